@@ -0,0 +1,42 @@
+//! Benchmarks for reading logs, which dominates the time needed to reprocess
+//! lots of saved logs. Run with `--features serde-parse` to compare against
+//! the typed parser.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mixes_db::{Log, LogId};
+
+const TEST_LOG: &str = include_str!("../test_data/log_3094861.json");
+
+fn parse(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("parse");
+
+    group.bench_function("json", |b| {
+        b.iter(|| {
+            let json = json::parse(black_box(TEST_LOG)).unwrap();
+            Log::from_json(LogId(3094861), &json).unwrap()
+        })
+    });
+
+    let json = json::parse(TEST_LOG).unwrap();
+    group.bench_function("json_from_document", |b| {
+        b.iter(|| Log::from_json(LogId(3094861), black_box(&json)).unwrap())
+    });
+
+    #[cfg(feature = "serde-parse")]
+    group.bench_function("typed", |b| {
+        b.iter(|| Log::parse_typed(LogId(3094861), black_box(TEST_LOG)).unwrap())
+    });
+
+    #[cfg(feature = "serde-parse")]
+    group.bench_function("typed_reader", |b| {
+        b.iter(|| Log::from_reader(LogId(3094861), black_box(TEST_LOG.as_bytes())).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+//! Update the database with the newest logs of all registered players. Run
+//! with `RUST_LOG=info` or `RUST_LOG=debug` to follow the progress.
+
+use mixes_db::sql_db::SQLDb;
+use mixes_db::Database;
+
+fn main()
+{
+    env_logger::init();
+
+    let mut db = SQLDb::start().expect("Unable to connect to the database");
+    db.update(0.6, 12..=13)
+        .expect("Unable to update the database");
+}
@@ -0,0 +1,394 @@
+//! Command line interface to the mixes database, for administration and quick
+//! looks at the stats. Only uses the [`Database`] trait, so everything it does
+//! is possible with the public interface of the library.
+//!
+//! Built with the `cli` feature: `cargo run --features cli --bin mixes-cli`.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use mixes_db::sql_db::SQLDb;
+use mixes_db::{
+    Class, Database, ImportMode, LogId, LogMetadata, LogPerformance, MixesDbError, QueryOptions,
+    RoundStats, SteamID,
+};
+
+/// Exit code when the database could not be used.
+const EXIT_DATABASE: u8 = 1;
+/// Exit code when writing the output failed.
+const EXIT_OUTPUT: u8 = 3;
+/// Exit code when reading an input file failed.
+const EXIT_INPUT: u8 = 4;
+
+#[derive(Parser)]
+#[command(
+    name = "mixes-cli",
+    about = "Manage and query the mixes stats database"
+)]
+struct Cli
+{
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command
+{
+    /// Register a player as a mixes player.
+    Register
+    {
+        /// Steam id of the player, in any format or as a link to the profile.
+        #[arg(value_parser = SteamID::from_reference)]
+        steam_id:   SteamID,
+        discord_id: u64,
+    },
+    /// Download the newest logs of all registered players.
+    Update
+    {
+        /// The fraction of players in a log that must be registered.
+        #[arg(long, default_value_t = 0.6, value_parser = parse_ratio)]
+        ratio:   f32,
+        /// The number of players a log must have, like `12..13`.
+        #[arg(long, default_value = "12..13", value_parser = parse_player_range)]
+        players: RangeInclusive<u16>,
+    },
+    /// Show the latest performances of a player on a class.
+    Stats
+    {
+        #[arg(value_parser = SteamID::from_reference)]
+        steam_id: SteamID,
+        class:    Class,
+        /// The number of logs to show.
+        #[arg(long, default_value_t = 20)]
+        limit:    usize,
+    },
+    /// Export the stats of all registered players as CSV.
+    Export
+    {
+        #[arg(long, value_enum)]
+        table: Table,
+        /// The file to write to.
+        #[arg(long)]
+        out:   PathBuf,
+    },
+    /// Write a snapshot of the whole database to a file.
+    Backup
+    {
+        /// The file to write to.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Load a snapshot written by `backup`, replacing everything in the
+    /// database.
+    Restore
+    {
+        /// The snapshot to load.
+        file:  PathBuf,
+        /// Only add what is not in the database yet instead of replacing it.
+        #[arg(long)]
+        merge: bool,
+    },
+}
+
+/// The kind of performances to export.
+#[derive(Copy, Clone, ValueEnum)]
+enum Table
+{
+    Overall,
+    Dm,
+    Med,
+}
+
+fn parse_ratio(ratio: &str) -> Result<f32, String>
+{
+    match ratio.parse::<f32>() {
+        Ok(ratio) if (0. ..=1.).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("`{}` is not a number between 0 and 1", ratio)),
+    }
+}
+
+fn parse_player_range(range: &str) -> Result<RangeInclusive<u16>, String>
+{
+    let error = || format!("`{}` is not a range of players like `12..13`", range);
+
+    let (start, end) = range.split_once("..").ok_or_else(error)?;
+    let start: u16 = start.parse().map_err(|_| error())?;
+    let end: u16 = end.trim_start_matches('=').parse().map_err(|_| error())?;
+    if start > end {
+        return Err(error());
+    }
+
+    Ok(start..=end)
+}
+
+/// Anything that makes a command fail.
+enum CliError
+{
+    Database(MixesDbError),
+    Input(io::Error),
+    Output(io::Error),
+}
+
+impl CliError
+{
+    fn database(e: impl Into<MixesDbError>) -> Self { Self::Database(e.into()) }
+
+    fn report(&self) -> ExitCode
+    {
+        match self {
+            Self::Database(e) => {
+                eprintln!("Database error: {}", e);
+                ExitCode::from(EXIT_DATABASE)
+            },
+            Self::Input(e) => {
+                eprintln!("Unable to read input: {}", e);
+                ExitCode::from(EXIT_INPUT)
+            },
+            Self::Output(e) => {
+                eprintln!("Unable to write output: {}", e);
+                ExitCode::from(EXIT_OUTPUT)
+            },
+        }
+    }
+}
+
+fn run(db: &mut dyn Database, command: Command) -> Result<(), CliError>
+{
+    match command {
+        Command::Register {
+            steam_id,
+            discord_id,
+        } => {
+            if db
+                .add_user(steam_id, discord_id)
+                .map_err(CliError::database)?
+            {
+                println!("Registered {}", steam_id.id64());
+            }
+            else {
+                println!(
+                    "{} or discord id {} is already registered",
+                    steam_id.id64(),
+                    discord_id
+                );
+            }
+        },
+        Command::Update { ratio, players } => {
+            let report = db.update(ratio, players).map_err(CliError::database)?;
+            println!("Added {} logs", report.added.len());
+            if let Some(run_id) = report.run_id {
+                println!("Recorded as update run {}", run_id.0);
+            }
+            for (id, reason) in &report.skipped {
+                println!("Skipped log {}: {}", id, reason);
+            }
+            if report.overflow != 0 {
+                println!(
+                    "Left out {} older logs of players with too many new logs",
+                    report.overflow
+                );
+            }
+        },
+        Command::Stats {
+            steam_id,
+            class,
+            limit,
+        } => {
+            let name = db.username(steam_id).map_err(CliError::database)?;
+            let performances = db
+                .get_class_performance_structured(steam_id, class, limit, None, None)
+                .map_err(CliError::database)?;
+            let rounds = db
+                .round_stats(steam_id, limit)
+                .map_err(CliError::database)?;
+
+            println!("{}", name.unwrap_or_else(|| steam_id.id64().to_string()));
+            print_stats(class, performances).map_err(CliError::Output)?;
+            print_round_stats(&rounds).map_err(CliError::Output)?;
+        },
+        Command::Export { table, out } => {
+            let file = File::create(out).map_err(CliError::Output)?;
+            export(db, table, BufWriter::new(file))?;
+        },
+        Command::Backup { out } => {
+            let file = File::create(out).map_err(CliError::Output)?;
+            let info = db
+                .export_snapshot(&mut BufWriter::new(file))
+                .map_err(CliError::database)?;
+            println!("Wrote {} rows", info.total_rows());
+        },
+        Command::Restore { file, merge } => {
+            let mode = if merge {
+                ImportMode::MergeSkipExisting
+            }
+            else {
+                ImportMode::ReplaceAll
+            };
+            let file = File::open(file).map_err(CliError::Input)?;
+            let info = db
+                .import_snapshot(&mut BufReader::new(file), mode)
+                .map_err(CliError::database)?;
+            println!(
+                "Loaded {} rows of a snapshot taken at {}",
+                info.total_rows(),
+                info.created_at
+            );
+        },
+    }
+
+    Ok(())
+}
+
+/// Print the performances on the class as a table, in the order they are given.
+fn print_stats(
+    class: Class,
+    performances: Vec<(LogId, LogMetadata, LogPerformance)>,
+) -> io::Result<()>
+{
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:>10} {:>7} {:>5} {:>6} {:>7} {:>6} {:>7}",
+        "log", "rounds", "kills", "deaths", "damage", "dpm", "minutes"
+    )?;
+    for (id, _, performances) in performances {
+        let rounds = performances
+            .overall
+            .as_ref()
+            .map(|overall| format!("{}/{}", overall.won_rounds, overall.num_rounds))
+            .unwrap_or_default();
+        match performances.class(class) {
+            Some(dm) => {
+                let minutes = dm.time_played_secs as f32 / 60.;
+                let dpm = if minutes > 0. {
+                    dm.damage as f32 / minutes
+                }
+                else {
+                    0.
+                };
+                writeln!(
+                    out,
+                    "{:>10} {:>7} {:>5} {:>6} {:>7} {:>6.1} {:>7.1}",
+                    id, rounds, dm.kills, dm.deaths, dm.damage, dpm, minutes
+                )?;
+            },
+            None => writeln!(out, "{:>10} {:>7}", id, rounds)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Print how close the games of the player have been, below their stats.
+fn print_round_stats(rounds: &RoundStats) -> io::Result<()>
+{
+    let mut out = io::stdout().lock();
+    if let Some(differential) = rounds.round_differential {
+        writeln!(
+            out,
+            "Round differential: {:+.2} per game over {} games",
+            differential, rounds.num_games
+        )?;
+    }
+    if let Some(comeback_wins) = rounds.comeback_wins {
+        writeln!(
+            out,
+            "Comeback wins: {} of {} games with known rounds",
+            comeback_wins, rounds.num_timelines
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the performances of all registered players as CSV, with one line per
+/// performance, ordered by player and newest log first.
+fn export(db: &mut dyn Database, table: Table, mut out: impl Write) -> Result<(), CliError>
+{
+    let header = match table {
+        Table::Overall => "log_id,steam_id,won_rounds,num_rounds,damage,damage_taken,kills,deaths",
+        Table::Dm => "log_id,steam_id,class,kills,assists,deaths,damage,time_played_secs",
+        Table::Med => {
+            "log_id,steam_id,healing,num_ubers,num_kritz,num_drops,advantages_lost,deaths,\
+             time_played_secs"
+        },
+    };
+    writeln!(out, "{}", header).map_err(CliError::Output)?;
+
+    for user in db.users().map_err(CliError::database)? {
+        let logs = db
+            .get_classes_performance(user, &Class::ALL, usize::MAX, &QueryOptions::default())
+            .map_err(CliError::database)?;
+        for (id, _, performances) in logs {
+            let rows: Vec<String> = match table {
+                Table::Overall => performances
+                    .overall
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{},{},{},{},{},{}",
+                            p.won_rounds, p.num_rounds, p.damage, p.damage_taken, p.kills, p.deaths
+                        )
+                    })
+                    .collect(),
+                Table::Dm => performances
+                    .dm
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{},{},{},{},{},{}",
+                            format!("{:?}", p.class).to_lowercase(),
+                            p.kills,
+                            p.assists,
+                            p.deaths,
+                            p.damage,
+                            p.time_played_secs
+                        )
+                    })
+                    .collect(),
+                Table::Med => performances
+                    .medic
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{},{},{},{},{},{},{}",
+                            p.healing,
+                            p.num_ubers,
+                            p.num_kritz,
+                            p.num_drops,
+                            p.advantages_lost,
+                            p.deaths,
+                            p.time_played_secs
+                        )
+                    })
+                    .collect(),
+            };
+
+            for stats in rows {
+                writeln!(out, "{},{},{}", id, user.id64(), stats).map_err(CliError::Output)?;
+            }
+        }
+    }
+
+    out.flush().map_err(CliError::Output)
+}
+
+fn main() -> ExitCode
+{
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let mut db = match SQLDb::start() {
+        Ok(db) => db,
+        Err(e) => return CliError::database(e).report(),
+    };
+
+    match run(&mut db, cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => e.report(),
+    }
+}
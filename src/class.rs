@@ -3,8 +3,10 @@ use std::str::FromStr;
 
 use num_derive::FromPrimitive;
 
+use crate::ClassPolicy;
+
 /// All TF2 classes.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive)]
 pub enum Class
 {
     Demoman,
@@ -21,20 +23,29 @@ pub enum Class
 
 impl Class
 {
+    /// Every class that can be played, leaving out [`Class::Unknown`], in the
+    /// order of the class selection menu.
+    pub const ALL: [Class; 9] = [
+        Class::Scout,
+        Class::Soldier,
+        Class::Pyro,
+        Class::Demoman,
+        Class::Heavy,
+        Class::Engineer,
+        Class::Medic,
+        Class::Sniper,
+        Class::Spy,
+    ];
+
     /// Check if the class is considered a "main" class, which means it is under
     /// consideration for main (most) played class during a game.
     ///
     /// # Returns
-    /// `true` if the class is main-classeable, currently that includes
+    /// `true` if the class is main-classeable in sixes, which includes
     /// `Demoman`, `Scout`, `Soldier` and `Medic`. `false` for all other
     /// classes.
-    pub fn is_main_class(self) -> bool
-    {
-        match self {
-            Self::Demoman | Self::Medic | Self::Scout | Self::Soldier => true,
-            _ => false,
-        }
-    }
+    #[deprecated(note = "use `ClassPolicy::is_main_class`, which covers other game modes as well")]
+    pub fn is_main_class(self) -> bool { ClassPolicy::sixes().is_main_class(self) }
 }
 
 /// When creating identifying a class from a string, the class may be unknown in
@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Class, GameMode};
+
+/// Which classes are considered "main" classes in a game mode, for instance
+/// to find the class a player has mainly played in a log or how much of the
+/// game they have spent offclassing. Sixes, highlander and ultiduo each have
+/// a preset, other game modes can build their own with [`ClassPolicy::new`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassPolicy
+{
+    main_classes:    HashSet<Class>,
+    min_time_shares: HashMap<Class, f32>,
+}
+
+impl ClassPolicy
+{
+    /// Create a policy where exactly the `main_classes` are main classes,
+    /// without any minimum time share.
+    pub fn new(main_classes: impl IntoIterator<Item = Class>) -> Self
+    {
+        Self {
+            main_classes:    main_classes.into_iter().collect(),
+            min_time_shares: HashMap::new(),
+        }
+    }
+
+    /// The main classes of sixes: scout, soldier, demoman and medic.
+    pub fn sixes() -> Self
+    {
+        Self::new([Class::Scout, Class::Soldier, Class::Demoman, Class::Medic])
+    }
+
+    /// Every class is a main class in highlander.
+    pub fn highlander() -> Self
+    {
+        Self::new([
+            Class::Scout,
+            Class::Soldier,
+            Class::Pyro,
+            Class::Demoman,
+            Class::Heavy,
+            Class::Engineer,
+            Class::Medic,
+            Class::Sniper,
+            Class::Spy,
+        ])
+    }
+
+    /// The main classes of ultiduo: soldier and medic.
+    pub fn ultiduo() -> Self { Self::new([Class::Soldier, Class::Medic]) }
+
+    /// Guess the policy of the game mode of a log from its number of players,
+    /// see [`GameMode::from_num_players`].
+    pub fn for_player_count(num_players: u16) -> Self
+    {
+        GameMode::from_num_players(num_players).class_policy()
+    }
+
+    /// Set the share of the playtime of a player the `class` needs to have
+    /// been played for to count as a main class in a log. Time on the class
+    /// in logs where it has been played for less counts as offclass time.
+    pub fn min_time_share(mut self, class: Class, share: f32) -> Self
+    {
+        self.min_time_shares.insert(class, share);
+        self
+    }
+
+    /// Check if the `class` is a main class of this policy, regardless of the
+    /// time it has been played for.
+    pub fn is_main_class(&self, class: Class) -> bool { self.main_classes.contains(&class) }
+
+    /// The main class a player has played the longest, given the time they
+    /// have spent on each class in a log, like returned by
+    /// [`Log::class_times`](crate::Log::class_times). Ties go to the class
+    /// listed first.
+    ///
+    /// # Returns
+    /// `None` if the player has not played any main class long enough.
+    pub fn main_class(&self, class_times: &[(Class, u32)]) -> Option<Class>
+    {
+        let total_time = class_times.iter().map(|(_, time)| time).sum();
+
+        class_times
+            .iter()
+            .filter(|&&(class, time)| self.counts_as_main(class, time, total_time))
+            .rev()
+            .max_by_key(|(_, time)| time)
+            .map(|&(class, _)| class)
+    }
+
+    /// The fraction of the playtime that has been spent on classes that do
+    /// not count as main classes, given the time spent on each class in a log.
+    ///
+    /// # Returns
+    /// A value between `0.0` and `1.0`. If there is no playtime at all, `0.0`
+    /// is returned.
+    pub fn offclass_fraction(&self, class_times: &[(Class, u32)]) -> f32
+    {
+        let total_time: u32 = class_times.iter().map(|(_, time)| time).sum();
+        if total_time == 0 {
+            return 0.0;
+        }
+
+        let offclass_time: u32 = class_times
+            .iter()
+            .filter(|&&(class, time)| !self.counts_as_main(class, time, total_time))
+            .map(|(_, time)| time)
+            .sum();
+
+        offclass_time as f32 / total_time as f32
+    }
+
+    /// Whether the `time` spent on the `class` counts as main class time in a
+    /// log with `total_time` played.
+    fn counts_as_main(&self, class: Class, time: u32, total_time: u32) -> bool
+    {
+        self.is_main_class(class)
+            && match self.min_time_shares.get(&class) {
+                Some(&min_share) => time as f32 >= min_share * total_time as f32,
+                None => true,
+            }
+    }
+}
+
+impl Default for ClassPolicy
+{
+    fn default() -> Self { Self::sixes() }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn presets()
+    {
+        assert!(ClassPolicy::sixes().is_main_class(Class::Demoman));
+        assert!(!ClassPolicy::sixes().is_main_class(Class::Sniper));
+        assert!(ClassPolicy::highlander().is_main_class(Class::Sniper));
+        assert!(!ClassPolicy::highlander().is_main_class(Class::Unknown));
+        assert!(!ClassPolicy::ultiduo().is_main_class(Class::Scout));
+
+        assert_eq!(ClassPolicy::for_player_count(4), ClassPolicy::ultiduo());
+        assert_eq!(ClassPolicy::for_player_count(12), ClassPolicy::sixes());
+        assert_eq!(ClassPolicy::for_player_count(18), ClassPolicy::highlander());
+        assert_eq!(ClassPolicy::for_player_count(0), ClassPolicy::sixes());
+    }
+
+    #[test]
+    fn main_class()
+    {
+        let class_times = [
+            (Class::Scout, 100),
+            (Class::Sniper, 800),
+            (Class::Soldier, 100),
+        ];
+
+        assert_eq!(
+            ClassPolicy::sixes().main_class(&class_times),
+            Some(Class::Scout)
+        );
+        assert_eq!(
+            ClassPolicy::highlander().main_class(&class_times),
+            Some(Class::Sniper)
+        );
+        assert_eq!(ClassPolicy::sixes().offclass_fraction(&class_times), 0.8);
+        assert_eq!(
+            ClassPolicy::highlander().offclass_fraction(&class_times),
+            0.0
+        );
+
+        // Neither scout nor soldier has been played for a fifth of the game.
+        let policy = ClassPolicy::sixes()
+            .min_time_share(Class::Scout, 0.2)
+            .min_time_share(Class::Soldier, 0.2);
+        assert_eq!(policy.main_class(&class_times), None);
+        assert_eq!(policy.offclass_fraction(&class_times), 1.0);
+        assert_eq!(policy.offclass_fraction(&[]), 0.0);
+    }
+}
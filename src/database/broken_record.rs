@@ -0,0 +1,16 @@
+use super::RecordEntry;
+use crate::SteamID;
+
+/// A personal record that has been beaten by a newly added log, as part of
+/// [`DbEvent::LogAdded`](super::DbEvent::LogAdded) and
+/// [`UpdateReport::records_broken`](super::UpdateReport::records_broken).
+/// Records that have been set for the first time have not been broken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BrokenRecord
+{
+    pub player:   SteamID,
+    /// The record set in the new log.
+    pub record:   RecordEntry,
+    /// The record it has replaced.
+    pub previous: RecordEntry,
+}
@@ -0,0 +1,37 @@
+use crate::SteamID;
+
+/// What [`Database::add_users_bulk`](super::Database::add_users_bulk) has done
+/// with each of the given users.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BulkAddReport
+{
+    /// The outcome for every entry, in the order the entries have been given.
+    pub outcomes: Vec<BulkAddOutcome>,
+}
+
+impl BulkAddReport
+{
+    /// The number of users that have been added.
+    pub fn num_added(&self) -> usize
+    {
+        self.outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome, BulkAddOutcome::Added(_)))
+            .count()
+    }
+}
+
+/// The outcome of adding a single user in bulk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BulkAddOutcome
+{
+    /// The user has been added with the steam id.
+    Added(SteamID),
+    /// There already is a user with the steam id.
+    DuplicateSteamId(SteamID),
+    /// There already is a user with the discord id. Contains the steam id the
+    /// user would have been added with.
+    DuplicateDiscordId(SteamID),
+    /// The steam id could not be read, with a description of the problem.
+    Unparseable(String),
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Handle to stop a running update, set with
+/// [`UpdateOptionsBuilder::cancel_token`](super::UpdateOptionsBuilder::cancel_token).
+/// Clones share the same state, so a clone kept by another thread, for instance
+/// a signal handler, can cancel the update.
+///
+/// The update checks the token between the searches of the players and between
+/// the logs it downloads. The log that is being added when the update is
+/// cancelled is still added completely.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken
+{
+    pub fn new() -> Self { Self::default() }
+
+    /// Ask the update to stop as soon as possible.
+    pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed) }
+
+    /// Whether [`CancelToken::cancel`] has been called on the token or one of
+    /// its clones.
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn clones_share_state()
+    {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(clone.is_cancelled());
+    }
+}
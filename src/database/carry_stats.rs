@@ -0,0 +1,16 @@
+/// How much a player has contributed to the stats of their team, averaged over
+/// their recent games, as returned by
+/// [`Database::carry_stats`](super::Database::carry_stats).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CarryStats
+{
+    /// The number of games the averages have been taken over.
+    pub num_games:          u32,
+    /// The average fraction of the damage of their team the player has dealt.
+    /// `None` if it is not known for any of the games.
+    pub damage_share:       Option<f32>,
+    /// The average fraction of the kills of their team the player has taken
+    /// part in with a kill or an assist. `None` if it is not known for any of
+    /// the games.
+    pub kill_participation: Option<f32>,
+}
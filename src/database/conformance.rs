@@ -0,0 +1,498 @@
+//! Scenarios every implementation of [`Database`](super::Database) has to
+//! handle in the same way. Backends should call [`run_conformance_suite`] in
+//! their tests, so that their behaviour does not drift apart.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use super::{DynDatabase, Metric};
+use crate::score::Team;
+use crate::test_support::LogBuilder;
+use crate::{Class, Log, LogId, LogPerformance, MixesDbError, SteamID};
+
+/// The test log, which is bundled so the suite can run from any directory.
+const TEST_LOG: &str = include_str!("../../test_data/log_3094861.json");
+/// Player in the test log who played scout, engineer and pyro.
+const SCOUT: &str = "[U:1:886717065]";
+
+/// Run all scenarios against the database implementation. The `factory` is
+/// called once per scenario and must return a new, empty database each time.
+/// The scenarios only use the database as a trait object, like code that
+/// chooses its backend at runtime.
+///
+/// # Panics
+/// If the database does not behave as described by the
+/// [`Database`](super::Database) trait, with a message describing the
+/// violation.
+pub fn run_conformance_suite(factory: impl Fn() -> DynDatabase)
+{
+    duplicate_add_user(factory());
+    remove_unknown_user(factory());
+    add_log_idempotent(factory());
+    add_log_from_json(factory());
+    player_log_performance(factory());
+    class_performance_limit(factory());
+    performance_order(factory());
+    season_boundaries(factory());
+    metric_percentiles(factory());
+    normalized_leaderboard(factory());
+}
+
+/// The test log, pretending it has the provided id and was played at the given
+/// unix timestamp.
+fn test_log(id: u32, timestamp: i64) -> Log
+{
+    let mut json = json::parse(TEST_LOG).expect("Unable to parse test log");
+    json["info"]["date"] = timestamp.into();
+
+    Log::from_json(LogId(id), &json).unwrap()
+}
+
+/// A log in which only the given players have played, all of them as scouts in
+/// the red team, each with the given damage and time played in seconds.
+fn scout_log(id: u32, players: &[(&str, u32, u32)]) -> Log
+{
+    let players: Vec<_> = players
+        .iter()
+        .map(|&(player, damage, time_played_secs)| (player, Class::Scout, damage, time_played_secs))
+        .collect();
+
+    class_log(id, &players)
+}
+
+/// A log in which only the given players have played in the red team, each on
+/// the given class with the given damage and time played in seconds.
+fn class_log(id: u32, players: &[(&str, Class, u32, u32)]) -> Log
+{
+    players
+        .iter()
+        .fold(
+            LogBuilder::new(LogId(id)),
+            |log, &(player, class, damage, time_played_secs)| {
+                log.player(steam_id(player), |player| {
+                    player
+                        .class(class, 0, 0, damage, time_played_secs)
+                        .team(Team::Red)
+                })
+            },
+        )
+        .build()
+}
+
+fn at(timestamp: i64) -> DateTime<Utc> { Utc.timestamp_opt(timestamp, 0).unwrap() }
+
+fn steam_id(id3: &str) -> SteamID { SteamID::from_str(id3).expect("Invalid test steam id") }
+
+/// Count the overall, dm and medic performances.
+fn count_performances(performances: &LogPerformance) -> (usize, usize, usize)
+{
+    (
+        performances.overall.is_some() as usize,
+        performances.dm.len(),
+        performances.medic.is_some() as usize,
+    )
+}
+
+fn duplicate_add_user(mut db: DynDatabase)
+{
+    let first = steam_id("[U:1:1]");
+    let second = steam_id("[U:1:2]");
+
+    assert!(
+        db.add_user(first, 1).unwrap(),
+        "Adding a new user must succeed"
+    );
+    assert!(
+        !db.add_user(first, 2).unwrap(),
+        "Adding a user with an already registered steam id must return false"
+    );
+    assert!(
+        !db.add_user(second, 1).unwrap(),
+        "Adding a user with an already registered discord id must return false"
+    );
+    assert_eq!(
+        db.users().unwrap(),
+        vec![first],
+        "Rejected users must not be registered"
+    );
+}
+
+fn remove_unknown_user(mut db: DynDatabase)
+{
+    let user = steam_id("[U:1:1]");
+
+    assert!(
+        !db.remove_user(user).unwrap(),
+        "Removing a user that was never added must return false"
+    );
+    assert!(
+        db.add_user(user, 1).unwrap(),
+        "Adding a new user must succeed"
+    );
+    assert!(
+        db.remove_user(user).unwrap(),
+        "Removing a registered user must return true"
+    );
+    assert!(
+        !db.remove_user(user).unwrap(),
+        "Removing a user twice must return false the second time"
+    );
+    assert!(
+        db.users().unwrap().is_empty(),
+        "Removed users must not be listed anymore"
+    );
+}
+
+fn add_log_idempotent(mut db: DynDatabase)
+{
+    assert!(
+        !db.has_log(LogId(1)).unwrap(),
+        "An empty database must not have any logs"
+    );
+    assert!(
+        db.add_log(test_log(1, 1000)).unwrap(),
+        "Adding a new log must succeed"
+    );
+    assert!(
+        db.has_log(LogId(1)).unwrap(),
+        "A log that has been added must be present"
+    );
+    assert!(
+        !db.add_log(test_log(1, 1000)).unwrap(),
+        "Adding a log that is already present must return false"
+    );
+
+    let performances = db
+        .get_class_performance_structured(steam_id(SCOUT), Class::Scout, 10, None, None)
+        .unwrap();
+    assert_eq!(
+        performances.len(),
+        1,
+        "A log added twice must only be stored once"
+    );
+    assert_eq!(
+        count_performances(&performances[0].2),
+        (1, 3, 0),
+        "A log added twice must not duplicate performances"
+    );
+}
+
+fn add_log_from_json(mut db: DynDatabase)
+{
+    let mut without_players = json::parse(TEST_LOG).expect("Unable to parse test log");
+    without_players["players"] = json::JsonValue::new_object();
+
+    assert!(
+        db.add_log_from_json(LogId(1), &without_players.dump())
+            .is_err(),
+        "Adding a log without players must fail"
+    );
+    assert!(
+        db.add_log_from_json(LogId(1), "{").is_err(),
+        "Adding a log that is not valid json must fail"
+    );
+    assert!(
+        db.add_log_from_json(LogId(1), TEST_LOG).unwrap(),
+        "Adding a valid log must succeed"
+    );
+    assert_eq!(
+        db.get_class_performance_structured(steam_id(SCOUT), Class::Scout, 10, None, None)
+            .unwrap()
+            .len(),
+        1,
+        "A log added from json must be readable"
+    );
+}
+
+fn player_log_performance(mut db: DynDatabase)
+{
+    assert!(
+        matches!(
+            db.get_player_log_performance(LogId(1), steam_id(SCOUT)),
+            Err(MixesDbError::NotFound)
+        ),
+        "Getting a performance in a log that is not present must fail with NotFound"
+    );
+
+    db.add_log(test_log(1, 1000)).unwrap();
+    let scout = db
+        .get_player_log_performance(LogId(1), steam_id(SCOUT))
+        .unwrap()
+        .expect("A player of a log must have a performance in it");
+    assert_eq!(
+        count_performances(&scout),
+        (1, 3, 0),
+        "The performance of a player must contain all classes they have played"
+    );
+    assert_eq!(
+        scout.dm[0].class,
+        Class::Scout,
+        "The class played the longest must come first"
+    );
+    let medic = db
+        .get_player_log_performance(LogId(1), steam_id("[U:1:71020853]"))
+        .unwrap()
+        .expect("A player of a log must have a performance in it");
+    assert_eq!(
+        count_performances(&medic),
+        (1, 1, 1),
+        "The performance of a medic must contain the medic stats"
+    );
+    assert_eq!(
+        db.get_player_log_performance(LogId(1), steam_id("[U:1:1]"))
+            .unwrap(),
+        None,
+        "A player that has not played in a log must not have a performance in it"
+    );
+}
+
+fn class_performance_limit(mut db: DynDatabase)
+{
+    for id in 1..=3 {
+        db.add_log(test_log(id, 1000 * id as i64)).unwrap();
+    }
+
+    let performances = db
+        .get_class_performance_structured(steam_id(SCOUT), Class::Scout, 2, None, None)
+        .unwrap();
+    assert_eq!(
+        performances
+            .iter()
+            .map(|(id, ..)| *id)
+            .collect::<Vec<LogId>>(),
+        vec![LogId(3), LogId(2)],
+        "Only the newest logs up to the limit must be returned"
+    );
+    for (_, _, log_performances) in &performances {
+        assert_eq!(
+            count_performances(log_performances),
+            (1, 3, 0),
+            "Only the performances of the requested player must be returned"
+        );
+    }
+
+    assert!(
+        db.get_class_performance_structured(steam_id(SCOUT), Class::Scout, 0, None, None)
+            .unwrap()
+            .is_empty(),
+        "No logs must be returned with a limit of zero"
+    );
+    assert!(
+        db.get_class_performance_structured(steam_id(SCOUT), Class::Medic, 10, None, None)
+            .unwrap()
+            .is_empty(),
+        "Logs where the player has not played the class must not be returned"
+    );
+}
+
+fn performance_order(mut db: DynDatabase)
+{
+    // Log ids are not in the order the logs have been played in.
+    db.add_log(test_log(1, 3000)).unwrap();
+    db.add_log(test_log(2, 1000)).unwrap();
+    db.add_log(test_log(3, 2000)).unwrap();
+    db.add_log(test_log(4, 2000)).unwrap();
+
+    let logs = |db: &mut DynDatabase, limit| {
+        db.get_class_performance_structured(steam_id(SCOUT), Class::Scout, limit, None, None)
+            .unwrap()
+            .into_iter()
+            .map(|(id, meta, _)| {
+                assert_eq!(id, meta.id, "The metadata must be that of the log");
+                (id, meta.date_time)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        logs(&mut db, 10),
+        vec![
+            (LogId(1), at(3000)),
+            (LogId(4), at(2000)),
+            (LogId(3), at(2000)),
+            (LogId(2), at(1000)),
+        ],
+        "Logs must be ordered by date and then by id, newest first"
+    );
+    assert_eq!(
+        logs(&mut db, 2),
+        vec![(LogId(1), at(3000)), (LogId(4), at(2000))],
+        "The limit must apply to the newest logs by date"
+    );
+}
+
+fn season_boundaries(mut db: DynDatabase)
+{
+    let first = db.create_season("First", at(1000), Some(at(2000))).unwrap();
+    let second = db.create_season("Second", at(2000), None).unwrap();
+    assert!(
+        first.is_some() && second.is_some(),
+        "Creating seasons that only touch must succeed"
+    );
+    assert_eq!(
+        db.create_season("Overlapping", at(1999), Some(at(2001)))
+            .unwrap(),
+        None,
+        "Creating overlapping seasons must fail"
+    );
+
+    db.add_log(test_log(1, 999)).unwrap();
+    db.add_log(test_log(2, 1000)).unwrap();
+    db.add_log(test_log(3, 1999)).unwrap();
+    db.add_log(test_log(4, 2000)).unwrap();
+
+    let logs_in = |db: &mut DynDatabase, season| {
+        db.get_class_performance_structured(steam_id(SCOUT), Class::Scout, 10, season, None)
+            .unwrap()
+            .into_iter()
+            .map(|(id, ..)| id)
+            .collect::<HashSet<LogId>>()
+    };
+    assert_eq!(
+        logs_in(&mut db, first),
+        HashSet::from([LogId(2), LogId(3)]),
+        "The start of a season must be inclusive and the end exclusive"
+    );
+    assert_eq!(
+        logs_in(&mut db, second),
+        HashSet::from([LogId(4)]),
+        "A log at the end of a season must belong to the next season"
+    );
+    assert_eq!(
+        logs_in(&mut db, None),
+        HashSet::from([LogId(1), LogId(2), LogId(3), LogId(4)]),
+        "Without a season, all logs must be considered"
+    );
+}
+
+fn metric_percentiles(mut db: DynDatabase)
+{
+    let (first, second, third, unregistered) = ("[U:1:1]", "[U:1:2]", "[U:1:3]", "[U:1:4]");
+    for (discord_id, player) in [first, second, third].into_iter().enumerate() {
+        db.add_user(steam_id(player), discord_id as u64).unwrap();
+    }
+    // The first two players have 100 damage per minute, the third has 300 in the
+    // first log and 100 in the second, which makes 200 overall.
+    db.add_log(scout_log(
+        1,
+        &[
+            (first, 1000, 600),
+            (second, 1000, 600),
+            (third, 3000, 600),
+            (unregistered, 5000, 600),
+        ],
+    ))
+    .unwrap();
+    db.add_log(scout_log(2, &[(third, 1000, 600)])).unwrap();
+
+    let percentile = |db: &mut DynDatabase, player, class, metric, min_games| {
+        db.percentile_for_metric(steam_id(player), class, metric, min_games, false, 0)
+            .unwrap()
+    };
+    let dpm = Metric::DamagePerMinute;
+    assert_eq!(
+        percentile(&mut db, third, Class::Scout, dpm, 1),
+        Some(1.),
+        "The best player must be above all other players"
+    );
+    assert_eq!(
+        (
+            percentile(&mut db, first, Class::Scout, dpm, 1),
+            percentile(&mut db, second, Class::Scout, dpm, 1)
+        ),
+        (Some(0.), Some(0.)),
+        "Tied players must only count players strictly below them"
+    );
+    assert_eq!(
+        percentile(&mut db, third, Class::Scout, dpm, 2),
+        Some(0.),
+        "Players with too few games must not be compared"
+    );
+    assert_eq!(
+        percentile(&mut db, first, Class::Scout, dpm, 2),
+        None,
+        "A player with too few games must not have a percentile"
+    );
+    assert_eq!(
+        percentile(&mut db, unregistered, Class::Scout, dpm, 1),
+        None,
+        "Unregistered players must not have a percentile"
+    );
+    assert_eq!(
+        percentile(&mut db, first, Class::Soldier, dpm, 1),
+        None,
+        "A player must not have a percentile on a class they have not played"
+    );
+    assert_eq!(
+        percentile(&mut db, first, Class::Scout, Metric::HealingPerMinute, 1),
+        None,
+        "Healing must only be available for medics"
+    );
+}
+
+fn normalized_leaderboard(mut db: DynDatabase)
+{
+    let scouts = ["[U:1:1]", "[U:1:2]", "[U:1:3]"];
+    let soldiers = ["[U:1:4]", "[U:1:5]"];
+    let (pyro, unregistered) = ("[U:1:6]", "[U:1:7]");
+    for (discord_id, player) in scouts.iter().chain(&soldiers).chain([&pyro]).enumerate() {
+        db.add_user(steam_id(player), discord_id as u64).unwrap();
+    }
+    // The scouts have 100, 200 and 300 damage per minute, the soldiers 400 and
+    // 600. The first scout has played soldier as well, but less than scout.
+    db.add_log(class_log(
+        1,
+        &[
+            (scouts[0], Class::Scout, 1000, 600),
+            (scouts[1], Class::Scout, 2000, 600),
+            (scouts[2], Class::Scout, 3000, 600),
+            (soldiers[0], Class::Soldier, 4000, 600),
+            (soldiers[1], Class::Soldier, 6000, 600),
+            (pyro, Class::Pyro, 5000, 600),
+            (unregistered, Class::Scout, 9000, 600),
+        ],
+    ))
+    .unwrap();
+    db.add_log(class_log(2, &[(scouts[0], Class::Scout, 1000, 600)]))
+        .unwrap();
+    db.add_log(class_log(3, &[(scouts[0], Class::Soldier, 9000, 600)]))
+        .unwrap();
+
+    let leaderboard =
+        |db: &mut DynDatabase, metric, min_games, limit| -> Vec<(SteamID, Class, i64)> {
+            db.normalized_leaderboard(metric, min_games, limit)
+                .unwrap()
+                .into_iter()
+                .map(|(player, class, z_score)| (player, class, (z_score * 1000.).round() as i64))
+                .collect()
+        };
+    let dpm = Metric::DamagePerMinute;
+    assert_eq!(
+        leaderboard(&mut db, dpm, 1, 10),
+        vec![
+            (steam_id(scouts[2]), Class::Scout, 1000),
+            (steam_id(soldiers[1]), Class::Soldier, 707),
+            (steam_id(scouts[1]), Class::Scout, 0),
+            (steam_id(soldiers[0]), Class::Soldier, -707),
+            (steam_id(scouts[0]), Class::Scout, -1000),
+        ],
+        "Players must be ranked by the z-score on their primary class, leaving out classes with a \
+         single player"
+    );
+    assert_eq!(
+        leaderboard(&mut db, dpm, 1, 2).len(),
+        2,
+        "The leaderboard must be limited"
+    );
+    assert_eq!(
+        leaderboard(&mut db, dpm, 2, 10),
+        Vec::new(),
+        "Players with too few games must not be ranked"
+    );
+    assert_eq!(
+        leaderboard(&mut db, Metric::KillDeathRatio, 1, 10),
+        Vec::new(),
+        "Metrics that are not per minute must not be ranked"
+    );
+}
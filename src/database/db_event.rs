@@ -0,0 +1,35 @@
+use super::BrokenRecord;
+use crate::{LogId, SteamID};
+
+/// A change to the database, passed to the handler set with
+/// [`Database::set_event_handler`](super::Database::set_event_handler) after
+/// the change has been saved.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DbEvent
+{
+    UserAdded(SteamID),
+    UserRemoved(SteamID),
+    /// A log has been added, with the players whose stats have been saved,
+    /// ordered by their steam id, and the personal records the log has broken.
+    LogAdded
+    {
+        log_id:         LogId,
+        players:        Vec<SteamID>,
+        records_broken: Vec<BrokenRecord>,
+    },
+    LogRemoved(LogId),
+    /// An update has finished, with the number of logs it has added.
+    UpdateCompleted
+    {
+        added: usize,
+    },
+    /// An update has been stopped with a [`CancelToken`](super::CancelToken),
+    /// with the number of logs it has added until then.
+    UpdateCancelled
+    {
+        added: usize,
+    },
+}
+
+/// Function that is called with every [`DbEvent`].
+pub type EventHandler = Box<dyn Fn(DbEvent) + Send>;
@@ -0,0 +1,12 @@
+use super::League;
+
+/// An official match of a league that a stored log has been played in, see
+/// [`Database::link_external_match`](super::Database::link_external_match).
+/// A match played over several maps can have a log for each of them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExternalMatch
+{
+    pub league:   League,
+    /// The id of the match on the website of the league.
+    pub match_id: u64,
+}
@@ -0,0 +1,28 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// How up to date the stored data is, as returned by
+/// [`Database::data_freshness`](super::Database::data_freshness), for
+/// instance to show when the stats have last been updated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Freshness
+{
+    /// When the last update has finished successfully, `None` if there has
+    /// not been an update yet.
+    pub last_update:     Option<DateTime<Utc>>,
+    /// Date of the newest log, `None` if there are no logs.
+    pub newest_log:      Option<DateTime<Utc>>,
+    /// The number of registered players whose newest stored log is older than
+    /// the threshold asked for. Players without any stored log are not
+    /// counted.
+    pub num_stale_users: u64,
+}
+
+impl Freshness
+{
+    /// How long ago the last update has finished at the time `now`, `None` if
+    /// there has not been an update yet.
+    pub fn age(&self, now: DateTime<Utc>) -> Option<Duration>
+    {
+        self.last_update.map(|last_update| now - last_update)
+    }
+}
@@ -0,0 +1,54 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+
+use crate::{Class, LogId};
+
+/// How a game has turned out for a player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameOutcome
+{
+    Win,
+    Loss,
+    Tie,
+}
+
+/// A game of a player in short, as listed by
+/// [`Database::recent_games`](super::Database::recent_games).
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSummary
+{
+    pub log_id:           LogId,
+    pub date_time:        DateTime<Utc>,
+    pub map:              String,
+    /// The class the player has played the longest. `None` if no class stats
+    /// are stored for them.
+    pub main_class:       Option<Class>,
+    /// The number of rounds the team of the player has won.
+    pub won_rounds:       u8,
+    /// The number of rounds the other team has won.
+    pub lost_rounds:      u8,
+    /// The damage per minute on the main class. `None` if the main class has
+    /// not been played for any time.
+    pub dpm:              Option<f32>,
+    /// The kills per death on the main class. A game without deaths counts as
+    /// if the player had died once, like with
+    /// [`Metric::KillDeathRatio`](super::Metric::KillDeathRatio).
+    pub kill_death_ratio: Option<f32>,
+    /// The healing done, only if the main class is medic.
+    pub healing:          Option<u32>,
+}
+
+impl GameSummary
+{
+    /// Whether the team of the player has won more or fewer rounds than the
+    /// other team.
+    pub fn outcome(&self) -> GameOutcome
+    {
+        match self.won_rounds.cmp(&self.lost_rounds) {
+            Ordering::Greater => GameOutcome::Win,
+            Ordering::Less => GameOutcome::Loss,
+            Ordering::Equal => GameOutcome::Tie,
+        }
+    }
+}
@@ -0,0 +1,13 @@
+/// What [`Database::import_snapshot`](super::Database::import_snapshot) does
+/// with the data that is already in the database.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImportMode
+{
+    /// Remove everything before loading the snapshot, so the database ends up
+    /// with exactly what the snapshot holds.
+    ReplaceAll,
+    /// Only add what is not in the database yet. Users, seasons, logs, names
+    /// and update runs that are already stored are kept as they are, and so
+    /// are the stats, tags and chat of logs that are already stored.
+    MergeSkipExisting,
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::{LogId, SteamIDError};
+
+/// Something that is wrong with the stored data, as found by
+/// [`Database::check_integrity`](super::Database::check_integrity). Players
+/// are given by the steamID64 as it is stored, since it may not be valid in a
+/// corrupted database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityProblem
+{
+    /// Rows of the `table` belong to a log that is not stored.
+    OrphanRows
+    {
+        table:  &'static str,
+        log_id: LogId,
+        rows:   u64,
+    },
+    /// The log has no stats of any player.
+    EmptyLog(LogId),
+    /// The duration of the log is negative, missing, or zero or longer than the
+    /// allowed maximum without the log being marked as suspect.
+    InvalidDuration
+    {
+        log_id:        LogId,
+        duration_secs: Option<i32>,
+    },
+    /// The players of the log do not agree on the number of rounds that have
+    /// been played.
+    InconsistentRounds
+    {
+        log_id:     LogId,
+        min_rounds: i16,
+        max_rounds: i16,
+    },
+    /// The class stats of the player are stored for a class that does not
+    /// exist.
+    InvalidClass
+    {
+        log_id: LogId,
+        player: u64,
+        class:  i16,
+    },
+    /// The stats of the player are stored more than once in the `table`. For
+    /// the class stats, the class they have been stored more than once for.
+    DuplicateRows
+    {
+        table:  &'static str,
+        log_id: LogId,
+        player: u64,
+        class:  Option<i16>,
+        rows:   u64,
+    },
+    /// A registered player has a steam id that is not valid, so they are left
+    /// out of [`Database::users`](super::Database::users).
+    InvalidUser
+    {
+        steam_id: u64,
+        error:    SteamIDError,
+    },
+}
+
+/// What [`Database::check_integrity`](super::Database::check_integrity) has
+/// found and repaired.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport
+{
+    /// Every problem that has been found, including the repaired ones.
+    pub problems:      Vec<IntegrityProblem>,
+    /// The number of orphaned rows that have been removed.
+    pub repaired_rows: u64,
+}
+
+impl IntegrityReport
+{
+    /// Whether no problem has been found.
+    pub fn is_ok(&self) -> bool { self.problems.is_empty() }
+}
+
+impl fmt::Display for IntegrityProblem
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::OrphanRows {
+                table,
+                log_id,
+                rows,
+            } => write!(
+                f,
+                "{} rows in {} belong to the unknown log {}",
+                rows, table, log_id
+            ),
+            Self::EmptyLog(log_id) => write!(f, "Log {} has no stats", log_id),
+            Self::InvalidDuration {
+                log_id,
+                duration_secs: Some(duration_secs),
+            } => write!(
+                f,
+                "Log {} has an invalid duration of {}s",
+                log_id, duration_secs
+            ),
+            Self::InvalidDuration {
+                log_id,
+                duration_secs: None,
+            } => write!(f, "Log {} has no duration", log_id),
+            Self::InconsistentRounds {
+                log_id,
+                min_rounds,
+                max_rounds,
+            } => write!(
+                f,
+                "The players of log {} have played between {} and {} rounds",
+                log_id, min_rounds, max_rounds
+            ),
+            Self::InvalidClass {
+                log_id,
+                player,
+                class,
+            } => write!(
+                f,
+                "Player {} has stats for the unknown class {} in log {}",
+                player, class, log_id
+            ),
+            Self::DuplicateRows {
+                table,
+                log_id,
+                player,
+                class,
+                rows,
+            } => {
+                write!(
+                    f,
+                    "Player {} has {} rows in {} for log {}",
+                    player, rows, table, log_id
+                )?;
+                match class {
+                    Some(class) => write!(f, " with class {}", class),
+                    None => Ok(()),
+                }
+            },
+            Self::InvalidUser { steam_id, error } => write!(
+                f,
+                "The registered player {} has an invalid steam id: {}",
+                steam_id, error
+            ),
+        }
+    }
+}
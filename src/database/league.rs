@@ -0,0 +1,129 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest the name of a league other than the known ones may be, in
+/// characters.
+pub const MAX_LEAGUE_LEN: usize = 32;
+
+/// A league that organizes official matches, see
+/// [`Database::link_external_match`](super::Database::link_external_match).
+/// Leagues are parsed without regard to case, so `ETF2L` and `etf2l` are the
+/// same league. Any other league is kept trimmed and in lowercase.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum League
+{
+    Etf2l,
+    Rgl,
+    Ozfortress,
+    /// A league that is not known to the crate, like a local cup.
+    Other(String),
+}
+
+/// The reason a text is not a valid [`League`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LeagueError
+{
+    /// The league is empty or only consists of whitespace.
+    Empty,
+    /// The league is longer than [`MAX_LEAGUE_LEN`] characters. Contains the
+    /// league.
+    TooLong(String),
+}
+
+impl League
+{
+    /// Parse the `league`, which is trimmed and converted to lowercase first.
+    ///
+    /// # Returns
+    /// An error if the league is empty or too long after trimming it.
+    pub fn new(league: &str) -> Result<Self, LeagueError>
+    {
+        let league = league.trim().to_lowercase();
+        match league.as_str() {
+            "" => Err(LeagueError::Empty),
+            "etf2l" => Ok(Self::Etf2l),
+            "rgl" => Ok(Self::Rgl),
+            "ozfortress" => Ok(Self::Ozfortress),
+            _ if league.chars().count() > MAX_LEAGUE_LEN => Err(LeagueError::TooLong(league)),
+            _ => Ok(Self::Other(league)),
+        }
+    }
+
+    /// The name of the league as it is stored, like `etf2l`.
+    pub fn as_str(&self) -> &str
+    {
+        match self {
+            Self::Etf2l => "etf2l",
+            Self::Rgl => "rgl",
+            Self::Ozfortress => "ozfortress",
+            Self::Other(league) => league,
+        }
+    }
+}
+
+impl FromStr for League
+{
+    type Err = LeagueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::new(s) }
+}
+
+impl fmt::Display for League
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Etf2l => write!(f, "ETF2L"),
+            Self::Rgl => write!(f, "RGL"),
+            Self::Ozfortress => write!(f, "ozfortress"),
+            Self::Other(league) => write!(f, "{}", league),
+        }
+    }
+}
+
+impl fmt::Display for LeagueError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Empty => write!(f, "Leagues must not be empty"),
+            Self::TooLong(league) => write!(
+                f,
+                "The league `{}` is longer than {} characters",
+                league, MAX_LEAGUE_LEN
+            ),
+        }
+    }
+}
+
+impl Error for LeagueError {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse()
+    {
+        assert_eq!(League::new(" ETF2L "), Ok(League::Etf2l));
+        assert_eq!(League::new("rgl"), Ok(League::Rgl));
+        assert_eq!("OzFortress".parse(), Ok(League::Ozfortress));
+        assert_eq!(
+            League::new("Local Cup"),
+            Ok(League::Other("local cup".to_owned()))
+        );
+        assert_eq!(League::new("  "), Err(LeagueError::Empty));
+        assert_eq!(
+            League::new(&"A".repeat(MAX_LEAGUE_LEN + 1)),
+            Err(LeagueError::TooLong("a".repeat(MAX_LEAGUE_LEN + 1)))
+        );
+
+        // The stored name parses to the same league.
+        for league in [League::Etf2l, League::Rgl, League::Other("cup".to_owned())] {
+            assert_eq!(League::new(league.as_str()), Ok(league));
+        }
+        assert_eq!(League::Etf2l.to_string(), "ETF2L");
+    }
+}
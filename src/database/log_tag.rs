@@ -0,0 +1,95 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest a tag may be, in characters.
+pub const MAX_TAG_LEN: usize = 32;
+
+/// A label attached to logs, like `official` or `fun`, to tell apart the logs
+/// that should count differently. Tags are free-form, but are trimmed and
+/// converted to lowercase, so `Scrim` and `scrim ` are the same tag.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LogTag(String);
+
+/// The reason a text is not a valid [`LogTag`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogTagError
+{
+    /// The tag is empty or only consists of whitespace.
+    Empty,
+    /// The tag is longer than [`MAX_TAG_LEN`] characters. Contains the tag.
+    TooLong(String),
+}
+
+impl LogTag
+{
+    /// Normalize the `tag` by trimming it and converting it to lowercase.
+    ///
+    /// # Returns
+    /// An error if the tag is empty or too long after trimming it.
+    pub fn new(tag: &str) -> Result<Self, LogTagError>
+    {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            Err(LogTagError::Empty)
+        }
+        else if tag.chars().count() > MAX_TAG_LEN {
+            Err(LogTagError::TooLong(tag))
+        }
+        else {
+            Ok(Self(tag))
+        }
+    }
+
+    pub fn as_str(&self) -> &str { &self.0 }
+}
+
+impl FromStr for LogTag
+{
+    type Err = LogTagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::new(s) }
+}
+
+impl fmt::Display for LogTag
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+impl fmt::Display for LogTagError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Empty => write!(f, "Tags must not be empty"),
+            Self::TooLong(tag) => write!(
+                f,
+                "The tag `{}` is longer than {} characters",
+                tag, MAX_TAG_LEN
+            ),
+        }
+    }
+}
+
+impl Error for LogTagError {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn normalization()
+    {
+        assert_eq!(LogTag::new(" Official ").unwrap().as_str(), "official");
+        assert_eq!(LogTag::new("Scrim"), LogTag::new("scrim"));
+        assert_eq!(LogTag::new("   "), Err(LogTagError::Empty));
+
+        let longest = "ä".repeat(MAX_TAG_LEN);
+        assert_eq!(LogTag::new(&longest).unwrap().as_str(), longest);
+        assert_eq!(
+            LogTag::new(&"A".repeat(MAX_TAG_LEN + 1)),
+            Err(LogTagError::TooLong("a".repeat(MAX_TAG_LEN + 1)))
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::medic_performance::medic_efficiency;
+use crate::{Class, LogPerformance};
+
+/// A stat of a player, which can be compared with that of other players. The
+/// same metric is used to look up, rank and compute the stats, so that every
+/// part of the crate agrees on what e.g. the damage per minute is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Metric
+{
+    DamagePerMinute,
+    KillsPerMinute,
+    AssistsPerMinute,
+    /// Deaths per minute. Unlike with the other metrics, a lower value is
+    /// better.
+    DeathsPerMinute,
+    /// Deaths per 10 minutes, like [`Metric::DeathsPerMinute`] on a scale that
+    /// is easier to read.
+    DeathsPer10Min,
+    /// Healing per minute, which is only recorded for medics.
+    HealingPerMinute,
+    /// Damage taken per minute. Unlike with the other metrics, a lower value
+    /// is better. logs.tf only reports the damage taken of a player for the
+    /// whole game, so it is always relative to the whole duration of the
+    /// games, even when looking at a single class.
+    DamageTakenPerMinute,
+    KillsPerGame,
+    /// Kills per death. A game without deaths counts as if the player had died
+    /// once.
+    KillDeathRatio,
+    /// Kills and assists per death, like [`Metric::KillDeathRatio`].
+    KillsAssistsPerDeath,
+    /// `1` for a won game, `0.5` for a tie and `0` for a lost game.
+    WinRate,
+    /// The fraction of ubers that have been dropped instead of used, only for
+    /// medics. Unlike with the other metrics, a lower value is better.
+    DropRate,
+    /// Ubers used per 5 minutes, only for medics.
+    UberRate,
+    /// Damage per healing received, a measure of how efficiently a player uses
+    /// the heals of their medic, like a pocket soldier. A game without any
+    /// healing received counts as if one hp had been received. Medics are
+    /// hardly ever healed, so the metric does not apply to them.
+    DamagePerHeal,
+}
+
+/// A string could not be read as a metric. Contains the string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownMetricError
+{
+    metric: String,
+}
+
+impl Metric
+{
+    /// Every metric, in the order they are declared.
+    pub const ALL: [Metric; 14] = [
+        Metric::DamagePerMinute,
+        Metric::KillsPerMinute,
+        Metric::AssistsPerMinute,
+        Metric::DeathsPerMinute,
+        Metric::DeathsPer10Min,
+        Metric::HealingPerMinute,
+        Metric::DamageTakenPerMinute,
+        Metric::KillsPerGame,
+        Metric::KillDeathRatio,
+        Metric::KillsAssistsPerDeath,
+        Metric::WinRate,
+        Metric::DropRate,
+        Metric::UberRate,
+        Metric::DamagePerHeal,
+    ];
+
+    /// The short name of the metric, which it is read from again by
+    /// [`Metric::from_str`](FromStr::from_str) and stored as.
+    pub fn short_name(&self) -> &'static str
+    {
+        match self {
+            Self::DamagePerMinute => "dpm",
+            Self::KillsPerMinute => "kpm",
+            Self::AssistsPerMinute => "apm",
+            Self::DeathsPerMinute => "dapm",
+            Self::DeathsPer10Min => "d10",
+            Self::HealingPerMinute => "hpm",
+            Self::DamageTakenPerMinute => "dtm",
+            Self::KillsPerGame => "kills",
+            Self::KillDeathRatio => "kd",
+            Self::KillsAssistsPerDeath => "kad",
+            Self::WinRate => "wr",
+            Self::DropRate => "drops",
+            Self::UberRate => "ubers",
+            Self::DamagePerHeal => "dph",
+        }
+    }
+
+    /// Whether the metric can be computed for the `class`. Some stats are only
+    /// recorded for medics.
+    pub fn applicable_to(&self, class: Class) -> bool
+    {
+        match self {
+            Self::HealingPerMinute | Self::DropRate | Self::UberRate => class == Class::Medic,
+            Self::DamagePerHeal => class != Class::Medic,
+            _ => true,
+        }
+    }
+
+    /// Whether a lower value of the metric is better, like fewer deaths.
+    pub fn lower_is_better(&self) -> bool
+    {
+        matches!(
+            self,
+            Self::DeathsPerMinute
+                | Self::DeathsPer10Min
+                | Self::DamageTakenPerMinute
+                | Self::DropRate
+        )
+    }
+
+    /// Whether the `value` of the metric is better than the value it is
+    /// compared `to`, which depends on whether [lower is
+    /// better](Metric::lower_is_better). Equal values are not better.
+    pub fn is_better(&self, value: f64, to: f64) -> bool
+    {
+        if self.lower_is_better() {
+            value < to
+        }
+        else {
+            value > to
+        }
+    }
+
+    /// Compute the metric from the `performances` of a player in a single log,
+    /// which has lasted `duration_secs`. The medic metrics are relative to the
+    /// time played as medic instead.
+    ///
+    /// # Returns
+    /// `None` if the stats the metric is computed from are missing in the log,
+    /// or if nothing has been played.
+    pub fn compute(&self, performances: &LogPerformance, duration_secs: u32) -> Option<f64>
+    {
+        let per = |period_secs: f64, value: f64, secs: u32| match secs {
+            0 => None,
+            secs => Some(value * period_secs / secs as f64),
+        };
+        let per_minute = |value: f64, secs: u32| per(60., value, secs);
+        let overall = performances.overall.as_ref();
+        let medic = performances.medic.as_ref();
+        let assists: u32 = performances.dm.iter().map(|dm| dm.assists as u32).sum();
+
+        match self {
+            Self::DamagePerMinute => per_minute(overall?.damage as f64, duration_secs),
+            Self::KillsPerMinute => per_minute(overall?.kills as f64, duration_secs),
+            Self::AssistsPerMinute => per_minute(assists as f64, duration_secs),
+            Self::DeathsPerMinute => per_minute(overall?.deaths as f64, duration_secs),
+            Self::DeathsPer10Min => per(600., overall?.deaths as f64, duration_secs),
+            Self::HealingPerMinute => per_minute(medic?.healing as f64, medic?.time_played_secs),
+            Self::DamageTakenPerMinute => per_minute(overall?.damage_taken as f64, duration_secs),
+            Self::KillsPerGame => Some(overall?.kills as f64),
+            Self::KillDeathRatio => {
+                let overall = overall?;
+                Some(overall.kills as f64 / overall.deaths.max(1) as f64)
+            },
+            Self::KillsAssistsPerDeath => {
+                let overall = overall?;
+                Some((overall.kills as u32 + assists) as f64 / overall.deaths.max(1) as f64)
+            },
+            Self::WinRate => {
+                let overall = overall?;
+                let lost_rounds = overall.num_rounds.saturating_sub(overall.won_rounds);
+                Some(match overall.won_rounds.cmp(&lost_rounds) {
+                    Ordering::Greater => 1.,
+                    Ordering::Equal => 0.5,
+                    Ordering::Less => 0.,
+                })
+            },
+            Self::DropRate => {
+                let medic = medic?;
+                match medic.num_ubers as u32 + medic.num_drops as u32 {
+                    0 => None,
+                    charges => Some(medic.num_drops as f64 / charges as f64),
+                }
+            },
+            Self::UberRate => {
+                let medic = medic?;
+                medic_efficiency(medic, medic.time_played_secs)
+                    .map(|efficiency| efficiency.ubers_per_5_min as f64)
+            },
+            Self::DamagePerHeal => {
+                let overall = overall?;
+                Some(overall.damage as f64 / overall.heals_received.max(1) as f64)
+            },
+        }
+    }
+}
+
+impl fmt::Display for Metric
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let name = match self {
+            Self::DamagePerMinute => "damage per minute",
+            Self::KillsPerMinute => "kills per minute",
+            Self::AssistsPerMinute => "assists per minute",
+            Self::DeathsPerMinute => "deaths per minute",
+            Self::DeathsPer10Min => "deaths per 10 minutes",
+            Self::HealingPerMinute => "healing per minute",
+            Self::DamageTakenPerMinute => "damage taken per minute",
+            Self::KillsPerGame => "kills per game",
+            Self::KillDeathRatio => "K/D",
+            Self::KillsAssistsPerDeath => "KA/D",
+            Self::WinRate => "win rate",
+            Self::DropRate => "drop rate",
+            Self::UberRate => "ubers per 5 minutes",
+            Self::DamagePerHeal => "damage per heal",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Metric
+{
+    type Err = UnknownMetricError;
+
+    /// Read the metric from its short name as used in chat commands, like `dpm`
+    /// or `k/d`, ignoring the case.
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s.trim().to_lowercase().as_str() {
+            "dpm" | "damage" => Ok(Self::DamagePerMinute),
+            "kpm" => Ok(Self::KillsPerMinute),
+            "apm" => Ok(Self::AssistsPerMinute),
+            "deaths" | "dapm" => Ok(Self::DeathsPerMinute),
+            "d10" | "deaths10" => Ok(Self::DeathsPer10Min),
+            "hpm" | "heals" | "healing" => Ok(Self::HealingPerMinute),
+            "dtm" | "dt" | "damage_taken" => Ok(Self::DamageTakenPerMinute),
+            "kills" => Ok(Self::KillsPerGame),
+            "kd" | "k/d" | "kdr" => Ok(Self::KillDeathRatio),
+            "kad" | "ka/d" => Ok(Self::KillsAssistsPerDeath),
+            "wr" | "winrate" | "win_rate" => Ok(Self::WinRate),
+            "drops" | "droprate" | "drop_rate" => Ok(Self::DropRate),
+            "ubers" | "uberrate" | "uber_rate" => Ok(Self::UberRate),
+            "dph" | "dmg/heal" | "damage_per_heal" => Ok(Self::DamagePerHeal),
+            _ => Err(UnknownMetricError {
+                metric: s.to_owned(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for UnknownMetricError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "Unknown metric `{}`", &self.metric)
+    }
+}
+
+impl std::error::Error for UnknownMetricError {}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{Log, LogId, SteamID};
+
+    #[test]
+    fn from_str()
+    {
+        assert_eq!(Metric::from_str("dpm"), Ok(Metric::DamagePerMinute));
+        assert_eq!(Metric::from_str("DPM"), Ok(Metric::DamagePerMinute));
+        assert_eq!(Metric::from_str("kd"), Ok(Metric::KillDeathRatio));
+        assert_eq!(Metric::from_str("k/d"), Ok(Metric::KillDeathRatio));
+        assert_eq!(Metric::from_str("ka/d"), Ok(Metric::KillsAssistsPerDeath));
+        assert_eq!(Metric::from_str(" hpm "), Ok(Metric::HealingPerMinute));
+        assert_eq!(Metric::from_str("dtm"), Ok(Metric::DamageTakenPerMinute));
+        assert_eq!(Metric::from_str("d10"), Ok(Metric::DeathsPer10Min));
+        assert_eq!(Metric::from_str("dph"), Ok(Metric::DamagePerHeal));
+        assert_eq!(
+            Metric::from_str("airshots"),
+            Err(UnknownMetricError {
+                metric: "airshots".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn applicable_to()
+    {
+        assert!(Metric::HealingPerMinute.applicable_to(Class::Medic));
+        assert!(!Metric::HealingPerMinute.applicable_to(Class::Scout));
+        assert!(!Metric::DropRate.applicable_to(Class::Soldier));
+        assert!(Metric::DamagePerMinute.applicable_to(Class::Scout));
+        assert!(Metric::DamagePerMinute.applicable_to(Class::Medic));
+        assert!(Metric::DamagePerHeal.applicable_to(Class::Soldier));
+        assert!(!Metric::DamagePerHeal.applicable_to(Class::Medic));
+    }
+
+    #[test]
+    fn lower_is_better()
+    {
+        assert!(Metric::DeathsPerMinute.lower_is_better());
+        assert!(Metric::DeathsPer10Min.lower_is_better());
+        assert!(Metric::DamageTakenPerMinute.lower_is_better());
+        assert!(Metric::DropRate.lower_is_better());
+        assert!(!Metric::DamagePerMinute.lower_is_better());
+        assert!(!Metric::UberRate.lower_is_better());
+    }
+
+    #[test]
+    fn is_better()
+    {
+        assert!(Metric::DamagePerMinute.is_better(300., 250.));
+        assert!(!Metric::DamagePerMinute.is_better(250., 300.));
+        assert!(Metric::DeathsPerMinute.is_better(0.5, 0.7));
+        assert!(!Metric::DeathsPerMinute.is_better(0.7, 0.5));
+        assert!(!Metric::KillDeathRatio.is_better(2., 2.));
+    }
+
+    #[test]
+    fn short_name()
+    {
+        for metric in Metric::ALL {
+            assert_eq!(Metric::from_str(metric.short_name()), Ok(metric));
+        }
+    }
+
+    #[test]
+    fn compute()
+    {
+        let json = fs::read_to_string("test_data/log_3094861.json").expect("Unable to read log");
+        let log = Log::parse(LogId(3094861), &json).expect("Unable to parse log");
+        let performance = |player: &str| {
+            let player = SteamID::from_str(player).unwrap();
+            LogPerformance::from(log.performance_of(player).unwrap().clone())
+        };
+        let scout = performance("[U:1:886717065]");
+        let medic = performance("[U:1:71020853]");
+        let duration = log.duration_secs();
+        let compute =
+            |metric: Metric, performance: &LogPerformance| metric.compute(performance, duration);
+
+        let overall = scout.overall.as_ref().unwrap();
+        assert_eq!(
+            compute(Metric::DamagePerMinute, &scout),
+            Some(overall.damage as f64 * 60. / duration as f64)
+        );
+        assert_eq!(
+            compute(Metric::KillsPerGame, &scout),
+            Some(overall.kills as f64)
+        );
+        assert_eq!(
+            compute(Metric::KillDeathRatio, &scout),
+            Some(overall.kills as f64 / overall.deaths as f64)
+        );
+        assert!(
+            compute(Metric::KillsAssistsPerDeath, &scout).unwrap()
+                > compute(Metric::KillDeathRatio, &scout).unwrap()
+        );
+        // The scout has taken 6573 damage and died 17 times in the 1738 seconds
+        // of the log.
+        assert_eq!((overall.damage_taken, overall.deaths), (6573, 17));
+        assert_eq!(
+            compute(Metric::DamageTakenPerMinute, &scout),
+            Some(6573. * 60. / 1738.)
+        );
+        assert_eq!(
+            compute(Metric::DeathsPer10Min, &scout),
+            Some(17. * 600. / 1738.)
+        );
+        assert_eq!(
+            Metric::DeathsPer10Min.compute(&scout, 0),
+            None,
+            "A log without a duration has no rate"
+        );
+        // The scout has dealt 7090 damage with 4934 healing received.
+        assert_eq!(overall.heals_received, 4934);
+        assert_eq!(compute(Metric::DamagePerHeal, &scout), Some(7090. / 4934.));
+        // The medic has not been healed at all, which counts as one hp.
+        assert_eq!(
+            compute(Metric::DamagePerHeal, &medic),
+            Some(medic.overall.as_ref().unwrap().damage as f64)
+        );
+        // The log has ended in a 3:3 tie.
+        assert_eq!(compute(Metric::WinRate, &scout), Some(0.5));
+        assert_eq!(compute(Metric::HealingPerMinute, &scout), None);
+
+        let med = medic.medic.as_ref().unwrap();
+        assert_eq!(
+            compute(Metric::HealingPerMinute, &medic),
+            Some(med.healing as f64 * 60. / med.time_played_secs as f64)
+        );
+        assert_eq!(
+            compute(Metric::DropRate, &medic),
+            Some(med.num_drops as f64 / (med.num_ubers + med.num_drops) as f64)
+        );
+        assert_eq!(
+            compute(Metric::UberRate, &medic),
+            Some(med.uber_rate(med.time_played_secs) as f64)
+        );
+        assert_eq!(compute(Metric::UberRate, &scout), None);
+        assert_eq!(
+            compute(Metric::DamagePerMinute, &LogPerformance::default()),
+            None
+        );
+    }
+}
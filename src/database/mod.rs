@@ -0,0 +1,825 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::ops::RangeInclusive;
+
+mod broken_record;
+mod bulk_add_report;
+mod cancel_token;
+mod carry_stats;
+#[cfg(any(test, feature = "test-util"))]
+pub mod conformance;
+mod db_event;
+mod external_match;
+mod freshness;
+mod game_summary;
+mod import_mode;
+mod integrity_report;
+mod league;
+mod log_tag;
+mod metric;
+mod official_filter;
+mod participation_stats;
+mod purge_report;
+mod query_options;
+mod record_entry;
+mod retention_policy;
+mod retention_report;
+mod round_stats;
+mod snapshot_info;
+mod status;
+mod tag_filter;
+mod teammate_record;
+mod title_filter;
+mod update_options;
+mod update_report;
+mod update_run;
+mod user_record;
+mod users_report;
+
+pub use broken_record::*;
+pub use bulk_add_report::*;
+pub use cancel_token::*;
+pub use carry_stats::*;
+use chrono::{DateTime, Duration, Utc};
+pub use db_event::*;
+pub use external_match::*;
+pub use freshness::*;
+pub use game_summary::*;
+pub use import_mode::*;
+pub use integrity_report::*;
+pub use league::*;
+pub use log_tag::*;
+pub use metric::*;
+use num_derive::FromPrimitive;
+pub use official_filter::*;
+pub use participation_stats::*;
+pub use purge_report::*;
+pub use query_options::*;
+pub use record_entry::*;
+pub use retention_policy::*;
+pub use retention_report::*;
+pub use round_stats::*;
+pub use snapshot_info::*;
+pub use status::*;
+pub use tag_filter::*;
+pub use teammate_record::*;
+pub use title_filter::*;
+pub use update_options::*;
+pub use update_report::*;
+pub use update_run::*;
+pub use user_record::*;
+pub use users_report::*;
+
+use crate::logs_tf::LogSource;
+use crate::score::Team;
+use crate::{
+    ChatMessage, Class, Log, LogId, LogMetadata, LogPerformance, MixesDbResult, Performance,
+    Season, SeasonId, SteamID, TeamSummary,
+};
+
+/// Why a log has been added to the database.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum IngestReason
+{
+    /// The log is a mix, with enough registered players in it. Logs added
+    /// directly with [`Database::add_log`] are considered mixes as well.
+    Mix,
+    /// The log is part of the history of a single player, added by
+    /// [`Database::backfill_player`]. It may well be a public game, so it
+    /// should not be used for comparing players.
+    PersonalBackfill,
+}
+
+/// What [`Database::ingest_log_by_ref`] has done with the requested log.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IngestOutcome
+{
+    /// The log already is in the database and has not been downloaded.
+    AlreadyPresent,
+    /// The log has been downloaded and added, with the number of players that
+    /// have stats in it.
+    Added
+    {
+        players: usize
+    },
+}
+
+/// A chat message found by [`Database::search_chat`], together with the game it
+/// was written in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatSearchResult
+{
+    pub log_id:    LogId,
+    pub date_time: DateTime<Utc>,
+    pub map:       String,
+    pub message:   ChatMessage,
+}
+
+/// A log together with the performances of every player stored for it, as
+/// returned by [`Database::log_performances_between`].
+pub type LogWithPerformances = (LogMetadata, HashMap<SteamID, LogPerformance>);
+
+/// A database backend chosen at runtime, for instance from a config file.
+pub type DynDatabase = Box<dyn Database + Send>;
+
+/// Where the stats are stored. The trait is object safe, so the backend can be
+/// chosen at runtime with a [`DynDatabase`]. Every backend reports its errors
+/// as a [`MixesDbError`](crate::MixesDbError) and has its own constructors,
+/// like [`SQLDb::start`](crate::sql_db::SQLDb::start).
+pub trait Database
+{
+    /// Set the `handler` that is called after every change to the database,
+    /// once the change has been saved, replacing the previous handler. Useful
+    /// for invalidating caches of the data. Databases that cannot report their
+    /// changes ignore the handler.
+    fn set_event_handler(&mut self, _handler: EventHandler) {}
+
+    /// Add a user to be tracked as a mixes player.
+    ///
+    /// # Returns
+    /// `true` if the player was successfully added, `false` if there is already
+    /// a player with the same `steam_id` or `discord_id`. Returns an Error if
+    /// anything during registering goes wrong.
+    fn add_user(&mut self, steam_id: SteamID, discord_id: u64) -> MixesDbResult<bool>;
+
+    /// Add many users at once, for instance when a community that already
+    /// exists starts using the database. Every entry contains the discord id of
+    /// the user and their steam id in any of the forms accepted by
+    /// [`SteamID::from_reference`]. Entries that cannot be added are skipped,
+    /// the others are added, all in a single transaction.
+    ///
+    /// # Returns
+    /// What has been done with each of the entries. Returns an Error only if
+    /// something goes wrong with the database itself, in which case no user is
+    /// added.
+    fn add_users_bulk(&mut self, entries: &[(u64, &str)]) -> MixesDbResult<BulkAddReport>;
+
+    /// Remove a mixes user from the database.
+    /// This does not remove all the data already saved in the database, it just
+    /// means that there will be no further attempt to collect data concerning
+    /// this user.
+    ///
+    /// # Returns
+    /// `true` if the user was removed, `false` if there was no such user.
+    fn remove_user(&mut self, steam_id: SteamID) -> MixesDbResult<bool>;
+
+    /// Look through the stored data for problems that can be left behind by
+    /// an interrupted write or a change to the tables by hand, like stats of
+    /// logs that are not stored or the stats of a player being stored twice.
+    /// With `repair`, the stats of logs that are not stored are removed. Either
+    /// all of them or none are removed.
+    ///
+    /// # Returns
+    /// Every problem that has been found and how many rows have been removed.
+    fn check_integrity(&mut self, repair: bool) -> MixesDbResult<IntegrityReport>;
+
+    /// Remove the player and everything that has been stored about them from
+    /// the database, for instance when they have asked for their data to be
+    /// deleted. The logs they have played in are kept for the other players,
+    /// unless `remove_sole_logs` is set, in which case the logs where they have
+    /// been the only registered player are removed entirely. The logs they
+    /// have uploaded are kept without an uploader. Either everything or
+    /// nothing is removed.
+    ///
+    /// # Returns
+    /// How much has been removed of the player.
+    fn purge_player_data(
+        &mut self,
+        steam_id: SteamID,
+        remove_sole_logs: bool,
+    ) -> MixesDbResult<PurgeReport>;
+
+    /// Remove the logs that are no longer needed according to the `policy`,
+    /// together with all stats, chat and tags stored for them. The logs are
+    /// removed in batches of [`RetentionPolicy::batch_size`], each in its own
+    /// transaction, so an error may leave some of the logs removed.
+    ///
+    /// # Returns
+    /// How many rows have been removed from each table, or would be removed in
+    /// a [dry run](RetentionPolicy::dry_run).
+    fn apply_retention(&mut self, policy: RetentionPolicy) -> MixesDbResult<RetentionReport>;
+
+    /// Get a list of users registered as mixes players in the database.
+    ///
+    /// # Returns
+    /// A vector containing all `SteamID`s registered as mixes players, ordered
+    /// by steam id. Players whose stored steam id is not valid are left out,
+    /// see [`Database::users_checked`].
+    fn users(&mut self) -> MixesDbResult<Vec<SteamID>>
+    {
+        Ok(self
+            .user_records()?
+            .into_iter()
+            .map(|record| record.steam_id)
+            .collect())
+    }
+
+    /// Like [`Database::users`], but also report the players whose stored
+    /// steam id is not valid, instead of leaving them out. Backends that can
+    /// only store valid steam ids report none.
+    fn users_checked(&mut self) -> MixesDbResult<UsersReport>
+    {
+        Ok(UsersReport {
+            valid:   self.users()?,
+            invalid: Vec::new(),
+        })
+    }
+
+    /// Get everything stored about the registered players, for instance to
+    /// look up their discord ids without asking for each player separately.
+    ///
+    /// # Returns
+    /// The records of all registered players with a valid steam id, ordered by
+    /// steam id.
+    fn user_records(&mut self) -> MixesDbResult<Vec<UserRecord>>;
+
+    /// Get everything stored about a single registered player.
+    ///
+    /// # Returns
+    /// The record of the player, or `None` if they are not registered.
+    fn user_record(&mut self, steam_id: SteamID) -> MixesDbResult<Option<UserRecord>>;
+
+    /// Mark a registered player as active or inactive, for instance when they
+    /// take a break from the mixes.
+    ///
+    /// # Returns
+    /// `true` if the player is registered, `false` otherwise.
+    fn set_user_active(&mut self, steam_id: SteamID, active: bool) -> MixesDbResult<bool>;
+
+    /// Get the corresponding username of the player with the given steam id.
+    ///
+    /// # Returns
+    /// The name of the player, which should be the same as the player's name on
+    /// ETF2L. If the player name is not in the database, `None` is returned.
+    fn username(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>;
+
+    /// Set the username of the player with the given steam id, replacing the
+    /// previous one, if any.
+    ///
+    /// # Returns
+    /// `true` if the username was set, `false` if there is no such user.
+    fn set_username(&mut self, steam_id: SteamID, username: &str) -> MixesDbResult<bool>;
+
+    /// Search for registered players whose username contains `query`, ignoring
+    /// case. If there are players whose username is exactly the query (still
+    /// ignoring case), only they are returned. Players are ordered by the date
+    /// of the newest log they have stats in, players that have never been seen
+    /// come last. Remaining ties are ordered by steam id.
+    ///
+    /// # Returns
+    /// At most `limit` players with their usernames. Returns an Error if the
+    /// query is empty.
+    fn find_players_by_name(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, String)>>;
+
+    /// Get the in-game name of the player with the given steam id, as it is
+    /// listed by the newest log stored with their name. Unlike the
+    /// [username](Database::username), it is taken from the logs and changes
+    /// whenever the player does. Names of unregistered players are only kept
+    /// if their stats are, see
+    /// [`SQLDb::store_unregistered`](crate::sql_db::SQLDb::store_unregistered).
+    /// The name is removed together with the log it has been taken from.
+    ///
+    /// # Returns
+    /// The name, or `None` if no log with the player's name has been stored.
+    fn player_name(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>;
+
+    /// Add a log to the database, including the performances of all players
+    /// in it, whether they are registered as mixes players or not.
+    ///
+    /// # Returns
+    /// `true` if the log was added, `false` if there already is a log with the
+    /// same id in the database, in which case nothing is changed.
+    fn add_log(&mut self, log: Log) -> MixesDbResult<bool>;
+
+    /// Add a log that is present as json text in the format of the logs.tf API,
+    /// for instance a log saved to a file or created by a custom log parser.
+    /// The log is saved with the given `id`.
+    ///
+    /// # Returns
+    /// The same as [`Database::add_log`]. Returns an Error if the text is not
+    /// a valid log or does not contain any players.
+    fn add_log_from_json(&mut self, id: LogId, json: &str) -> MixesDbResult<bool>;
+
+    /// Check whether the log with the given id is in the database.
+    fn has_log(&mut self, id: LogId) -> MixesDbResult<bool>;
+
+    /// The highest id of all logs that have ever been added to the database.
+    /// Since logs.tf hands out ids in ascending order, every log with a higher
+    /// id is newer than all logs in the database. Logs that have been removed
+    /// still count.
+    ///
+    /// # Returns
+    /// `None` if no log has been added yet.
+    fn max_log_id(&mut self) -> MixesDbResult<Option<LogId>>;
+
+    /// Attach the `tag` to the stored log, for instance to mark it as an
+    /// official match, so that queries can include or leave out the log by
+    /// its tags with a [`TagFilter`].
+    ///
+    /// # Returns
+    /// `true` if the tag has been attached, `false` if the log already has the
+    /// tag or is not in the database.
+    fn tag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>;
+
+    /// Remove the `tag` from the log.
+    ///
+    /// # Returns
+    /// `true` if the log has had the tag, `false` otherwise.
+    fn untag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>;
+
+    /// Get all logs that have the `tag`.
+    ///
+    /// # Returns
+    /// The ids of the logs, newest log first. Logs played at the same time are
+    /// ordered by id, highest first.
+    fn logs_with_tag(&mut self, tag: &LogTag) -> MixesDbResult<Vec<LogId>>;
+
+    /// Link the stored log to the official match with the `match_id` on the
+    /// website of the `league`, so that its stats can be joined with the
+    /// results of the league and queries can tell officials from mixes with
+    /// an [`OfficialFilter`]. A log is linked to at most one match, linking it
+    /// again replaces the previous match.
+    ///
+    /// # Returns
+    /// `true` if the log has been linked, `false` if it is not in the
+    /// database.
+    fn link_external_match(
+        &mut self,
+        id: LogId,
+        league: &League,
+        match_id: u64,
+    ) -> MixesDbResult<bool>;
+
+    /// Get the official match the log has been linked to with
+    /// [`Database::link_external_match`].
+    ///
+    /// # Returns
+    /// `None` if the log is not linked to a match or not in the database, or
+    /// if its league has been stored in a way that cannot be read.
+    fn external_match(&mut self, id: LogId) -> MixesDbResult<Option<ExternalMatch>>;
+
+    /// Get all logs linked to the match with the `match_id` of the `league`,
+    /// like the logs of each map of the match.
+    ///
+    /// # Returns
+    /// The ids of the logs, newest log first. Logs played at the same time are
+    /// ordered by id, highest first.
+    fn logs_for_match(&mut self, league: &League, match_id: u64) -> MixesDbResult<Vec<LogId>>;
+
+    /// Add a single log chosen by hand, for instance a match an admin wants to
+    /// be included, no matter who played in it. The `log_ref` may be the id of
+    /// the log, the id with a leading `#` or a link to the log on logs.tf, as
+    /// accepted by the [`FromStr`](std::str::FromStr) implementation of
+    /// [`LogId`]. The log is only downloaded from the `source` if it is not in
+    /// the database yet. It is added like with [`Database::add_log`].
+    ///
+    /// # Returns
+    /// Whether the log has been added or was already present. Returns an Error
+    /// if the `log_ref` does not name a log or the log could not be downloaded.
+    fn ingest_log_by_ref(
+        &mut self,
+        source: &dyn LogSource,
+        log_ref: &str,
+    ) -> MixesDbResult<IngestOutcome>;
+
+    /// Retrieve the latest logs of the mixes players from the `source`, with
+    /// the `options` deciding which logs are added and how. Ignores games that
+    /// do not contain enough mixes players. Logs that do not report any players
+    /// are always skipped. Logs added by [`Database::backfill_player`] that
+    /// turn out to be mixes are marked as such. Once the players have been
+    /// searched, their newest logs are searched again, so that logs uploaded
+    /// in the meantime are not missed, see [`UpdateReport::reconciled`].
+    ///
+    /// # Returns
+    /// Which logs have been added and which have been skipped for what reason.
+    fn update_with(
+        &mut self,
+        source: &dyn LogSource,
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>;
+
+    /// Retrieve the latest logs of only the `users` from the `source`, like
+    /// [`Database::update_with`], for instance right after they have
+    /// registered. The ratio of mixes players in a log is still taken of all
+    /// registered players, so the same logs are added as by a full update.
+    /// The time of the last update is left as it is.
+    ///
+    /// # Returns
+    /// Which logs have been added and which have been skipped for what reason.
+    /// Returns [`MixesDbError::UnregisteredPlayers`](crate::MixesDbError::UnregisteredPlayers)
+    /// if any of the `users` is not registered, in which case nothing is
+    /// searched, or
+    /// [`MixesDbError::InvalidInput`](crate::MixesDbError::InvalidInput) if
+    /// no `users` are given.
+    fn update_players(
+        &mut self,
+        source: &dyn LogSource,
+        users: &[SteamID],
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>;
+
+    /// Retrieve the latest logs of the mixes players from logs.tf. Ignores
+    /// games that do not contain enough mixes players. The amount of mixes
+    /// players needed in one game is governed by the `min_ratio` variable,
+    /// which must be between `0` (include all logs) and `1` (include only logs
+    /// where all players are registered as mixes players).
+    /// `num_players` determines the number of players that must be present for
+    /// this log to count. This is a shorthand for [`Database::update_with`]
+    /// with these [`UpdateOptions`].
+    ///
+    /// # Returns
+    /// Which logs have been added and which have been skipped for what reason,
+    /// or [`MixesDbError::InvalidInput`](crate::MixesDbError::InvalidInput) if
+    /// the options are not valid.
+    fn update(
+        &mut self,
+        min_ratio: f32,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>;
+
+    /// Search the `source` for new logs like [`Database::update_with`] does
+    /// with the same `options`, but stop before downloading them, so nothing
+    /// is written to the database. The health of the source is checked, the
+    /// logs are filtered and the newest logs are searched again the same way
+    /// as by an update. The progress and the tag of the options are not used.
+    ///
+    /// # Returns
+    /// The logs that would be downloaded, how many of them each registered
+    /// player has played in and the logs that would be skipped.
+    fn update_dry_run(
+        &mut self,
+        source: &dyn LogSource,
+        options: &UpdateOptions,
+    ) -> MixesDbResult<UpdatePlan>;
+
+    /// Get the most recent runs of [`Database::update_with`], newest run first,
+    /// to see which settings the logs in the database have been added with. At
+    /// most `limit` runs are returned. Runs that have failed or are still
+    /// running are included, but not marked as completed.
+    fn update_history(&mut self, limit: usize) -> MixesDbResult<Vec<UpdateRunRecord>>;
+
+    /// Add the history of a single player, for instance when they have just
+    /// registered and there are no mixes with them in the database yet. Up to
+    /// `max_logs` of the newest logs of the player are searched in the
+    /// `source`. Unlike with [`Database::update`], the logs are only filtered
+    /// by the number of players, not by the number of mixes players in
+    /// them. The logs are added with [`IngestReason::PersonalBackfill`],
+    /// logs that are already in the database are left untouched. With a
+    /// `max_logs` of 0, nothing is searched.
+    ///
+    /// # Returns
+    /// Which logs have been added and which have been skipped for what reason.
+    fn backfill_player(
+        &mut self,
+        source: &dyn LogSource,
+        user: SteamID,
+        max_logs: usize,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>;
+
+    /// Get an overview of what is stored in the database, for instance to
+    /// check that updates are running.
+    fn status(&mut self) -> MixesDbResult<DbStatus>;
+
+    /// Write a snapshot of everything stored in the database to the `writer`,
+    /// for instance as a backup before trying something out. The snapshot is
+    /// consistent even while the database is being written to, and can be
+    /// loaded again with [`Database::import_snapshot`]. It starts with the
+    /// version of the schema, so it can also be loaded into a database of a
+    /// later version of this crate. The aggregates are left out, they are
+    /// rebuilt when importing.
+    ///
+    /// # Returns
+    /// What has been written.
+    fn export_snapshot(&mut self, writer: &mut dyn Write) -> MixesDbResult<SnapshotInfo>;
+
+    /// Load a snapshot written by [`Database::export_snapshot`] from the
+    /// `reader`. All of it is loaded in a single transaction, so nothing
+    /// changes if it fails, even with [`ImportMode::ReplaceAll`].
+    ///
+    /// # Returns
+    /// What has been added, or an error if the snapshot is not valid or has
+    /// been taken of a database with a newer schema, like
+    /// [`DbError::SchemaTooNew`](crate::sql_db::DbError::SchemaTooNew).
+    fn import_snapshot(
+        &mut self,
+        reader: &mut dyn Read,
+        mode: ImportMode,
+    ) -> MixesDbResult<SnapshotInfo>;
+
+    /// Find out how up to date the stored data is. Registered players whose
+    /// newest stored log has been played more than `stale_after` ago are
+    /// counted as stale, which suggests that updates do not run often enough
+    /// for them or that they have stopped playing.
+    fn data_freshness(&mut self, stale_after: Duration) -> MixesDbResult<Freshness>;
+
+    /// Get all performances of a single player in a single log, for instance
+    /// to show how they have done in a log that is linked in a chat.
+    ///
+    /// # Returns
+    /// The performances of the player, `None` if they have not played in the
+    /// log, or [`MixesDbError::NotFound`](crate::MixesDbError::NotFound) if
+    /// there is no log with the id.
+    fn get_player_log_performance(
+        &mut self,
+        log_id: LogId,
+        user: SteamID,
+    ) -> MixesDbResult<Option<LogPerformance>>;
+
+    /// Get the players of each team in the log, for instance to weigh a game by
+    /// the strength of the opposing team. Players whose team has not been
+    /// stored are left out.
+    ///
+    /// # Returns
+    /// The players of each team, ordered by steam id. Empty if there is no log
+    /// with the id.
+    fn log_rosters(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, Vec<SteamID>>>;
+
+    /// Sum up the damage, healing and ubers of each team in the log from the
+    /// stored stats, together with the winners of the rounds, for a report of
+    /// the whole match. Gives the same as [`Log::team_summary`] for the log it
+    /// has been stored from, as long as the stats of all players are stored.
+    ///
+    /// # Returns
+    /// The summaries of the teams that have stored players. Empty if there is
+    /// no log with the id.
+    fn team_summary(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, TeamSummary>>;
+
+    /// Get all logs played between `from` (inclusive) and `to` (exclusive), for
+    /// instance to replay them in the order they have been played.
+    ///
+    /// # Returns
+    /// The ids of the logs, ordered by the date they were played, oldest
+    /// first. Logs played at the same time are ordered by id.
+    fn logs_between(&mut self, from: DateTime<Utc>, to: DateTime<Utc>)
+        -> MixesDbResult<Vec<LogId>>;
+
+    /// Like [`Database::logs_between`], but with the metadata of the logs and
+    /// the performances of every player stored for them, for instance to sum
+    /// up what has happened during a week.
+    ///
+    /// # Returns
+    /// The logs in the same order as [`Database::logs_between`], each with the
+    /// performances of the players in it.
+    fn log_performances_between(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<Vec<LogWithPerformances>>;
+
+    /// Sum up how often the registered players have played between `from`
+    /// (inclusive) and `to` (exclusive), for instance to see if the mixes are
+    /// played by the same few people all the time. Only the logs stored in
+    /// the database count.
+    fn participation_stats(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<ParticipationStats>;
+
+    /// Get the most recent performance records (stats) of the player described
+    /// by the `user`. Only logs where the player has played any of the
+    /// `classes` are included. The damage/healing stats are accurate for each
+    /// class, however the win-rate is over the entire log, not only for those
+    /// classes. The `limit` describes the number of most recent logs that
+    /// should be included in the report, therefore the result will contain at
+    /// most `limit` logs. A log where the player has played several of the
+    /// classes only counts once. The logs can be further filtered with the
+    /// `options`. A log only counts if the player has played
+    /// one of the classes for at least
+    /// [`min_class_time_secs`](QueryOptions::min_class_time_secs) and, unless
+    /// [`only_primary_class`](QueryOptions::only_primary_class) is disabled,
+    /// has played one of them the longest. The limit is applied after that.
+    ///
+    /// # Returns
+    /// All performances of the player in each of the latest logs they have
+    /// played any of the given classes in, together with the metadata of the
+    /// log. The newest log comes first, logs played at the same time are
+    /// ordered by id, highest first.
+    fn get_classes_performance(
+        &mut self,
+        user: SteamID,
+        classes: &[Class],
+        limit: usize,
+        options: &QueryOptions,
+    ) -> MixesDbResult<Vec<(LogId, LogMetadata, LogPerformance)>>;
+
+    /// Like [`Database::get_classes_performance`], for a single class, which
+    /// counts if it has been played the longest of all classes.
+    fn get_class_performance_structured(
+        &mut self,
+        user: SteamID,
+        class: Class,
+        limit: usize,
+        season: Option<SeasonId>,
+        max_offclass_fraction: Option<f32>,
+    ) -> MixesDbResult<Vec<(LogId, LogMetadata, LogPerformance)>>
+    {
+        let options = QueryOptions {
+            season,
+            max_offclass_fraction,
+            ..QueryOptions::default()
+        };
+        self.get_classes_performance(user, &[class], limit, &options)
+    }
+
+    /// Like [`Database::get_class_performance_structured`], but with the
+    /// performances of each log in a single vector, in no particular order.
+    #[deprecated(note = "Use `get_class_performance_structured` instead")]
+    fn get_class_performance(
+        &mut self,
+        user: SteamID,
+        class: Class,
+        limit: usize,
+        season: Option<SeasonId>,
+        max_offclass_fraction: Option<f32>,
+    ) -> MixesDbResult<HashMap<LogId, Vec<Performance>>>
+    {
+        Ok(self
+            .get_class_performance_structured(user, class, limit, season, max_offclass_fraction)?
+            .into_iter()
+            .map(|(id, _, performances)| (id, performances.into()))
+            .collect())
+    }
+
+    /// Find out how the `metric` of the `user` on the `class` compares to that
+    /// of the other registered players. Only players that have played the class
+    /// in at least `min_games` games are compared. The percentile is the
+    /// fraction of the other compared players whose metric is strictly lower,
+    /// so players with the same value share the same percentile. The best
+    /// player has a percentile of `1`, unless they are the only one compared.
+    /// Only metrics per minute played can be compared, weighted by the time
+    /// played in each game. The damage taken is only known for whole games, so
+    /// it is weighted by the duration of the games and only counts for the
+    /// primary class of each game. Logs whose duration is likely wrong, for
+    /// instance because the server crashed, are left out unless
+    /// `include_suspect` is set. Games where a player has played the class
+    /// for less than `min_class_time_secs` are left out as well, and so are the
+    /// logs added for [`IngestReason::PersonalBackfill`].
+    ///
+    /// # Returns
+    /// The percentile between `0` and `1`, or `None` if the user is not
+    /// registered or has not played the class in enough games, or if the
+    /// metric cannot be compared or is not [applicable](Metric::applicable_to)
+    /// to the class.
+    fn percentile_for_metric(
+        &mut self,
+        user: SteamID,
+        class: Class,
+        metric: Metric,
+        min_games: u32,
+        include_suspect: bool,
+        min_class_time_secs: u32,
+    ) -> MixesDbResult<Option<f32>>;
+
+    /// Rank the registered players by how far their `metric` is above the
+    /// average of the players of the same class, so that players of classes
+    /// with naturally high values, like the damage of demomen, do not fill
+    /// the whole leaderboard. Every player is only ranked on their primary
+    /// class, the class they have mostly played in their games, and only if
+    /// they have played it in at least `min_games` games. The metric of a
+    /// player is the z-score of their value among all ranked players of their
+    /// class, which is the number of standard deviations it is above their
+    /// mean. The z-score is negated if [`Metric::lower_is_better`], so a
+    /// higher score is always better. Only metrics per minute played can be
+    /// ranked, like with [`Database::percentile_for_metric`], and logs whose
+    /// duration is likely wrong or that have only been added to the history
+    /// of a single player are left out.
+    ///
+    /// # Returns
+    /// Up to `limit` players together with their primary class and z-score,
+    /// best first. Ties are ordered by steam id. Classes with fewer than two
+    /// ranked players, or where all of them have the same value, are left out,
+    /// since their z-scores are not defined. Empty if the metric cannot be
+    /// ranked.
+    fn normalized_leaderboard(
+        &mut self,
+        metric: Metric,
+        min_games: u32,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, Class, f64)>>;
+
+    /// Get the healing the medic has done to each teammate, summed up over the
+    /// `limit` most recent logs the player has played medic in.
+    ///
+    /// # Returns
+    /// The teammates together with the healing they received, most healed
+    /// teammate first. Ties are ordered by steam id.
+    fn get_heal_spread(
+        &mut self,
+        medic: SteamID,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, u64)>>;
+
+    /// Get the share of the damage and kills of their team the `user` has had,
+    /// averaged over their `limit` most recent games. Games in which the
+    /// shares are not known, for instance because they have been stored
+    /// before the shares were recorded, are left out of the averages.
+    fn carry_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<CarryStats>;
+
+    /// Get how close the `limit` most recent games of the `user` have been,
+    /// by the difference of the rounds won and lost and by the games won after
+    /// being behind, see [`RoundStats`]. Comebacks need the winner of each
+    /// round, so games stored before the rounds were are left out of them.
+    fn round_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<RoundStats>;
+
+    /// Get the personal records of the `user`, the best value of every metric
+    /// they have reached on each class in a single log. A log counts for the
+    /// class the player has played the longest in it, and logs with a suspect
+    /// duration do not count at all. There is no record of the
+    /// [`Metric::WinRate`], since a single game is either won or not.
+    ///
+    /// # Returns
+    /// The records ordered by class and metric, empty if the `user` has not
+    /// played any games.
+    fn personal_records(&mut self, user: SteamID) -> MixesDbResult<Vec<RecordEntry>>;
+
+    /// Get a summary of each of the `limit` most recent games of the `user`,
+    /// like the map, the outcome and how they have done on their main class.
+    /// Only games in logs that pass the `tags` filter are considered.
+    ///
+    /// # Returns
+    /// The summaries, newest game first. Games played at the same time are
+    /// ordered by log id, highest first.
+    fn recent_games(
+        &mut self,
+        user: SteamID,
+        limit: usize,
+        tags: &TagFilter,
+    ) -> MixesDbResult<Vec<GameSummary>>;
+
+    /// Get how the games of the `user` have turned out for every player they
+    /// have played on the same team with in at least `min_shared_games` games.
+    /// Only stats that have been stored together with the team of the players
+    /// are considered.
+    ///
+    /// # Returns
+    /// The records of the teammates, the teammate with the most shared games
+    /// first. Ties are ordered by steam id.
+    fn teammate_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>;
+
+    /// Like [`Database::teammate_winrates`], but for the players the `user` has
+    /// played against, so on the other team.
+    fn opponent_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>;
+
+    /// Create a new season starting at `start`. If no `end` is given, the
+    /// season is open-ended and becomes the current season until it is closed.
+    /// Logs already in the database are not assigned to the new season, use
+    /// [`Database::assign_unmatched_logs_to_seasons`] for that.
+    ///
+    /// # Returns
+    /// The id of the newly created season, or `None` if the season would
+    /// overlap with a season that already exists. Returns
+    /// [`MixesDbError::InvalidInput`](crate::MixesDbError::InvalidInput) if
+    /// `end` is not after `start`.
+    fn create_season(
+        &mut self,
+        name: &str,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> MixesDbResult<Option<SeasonId>>;
+
+    /// Close the current season, so that it ends at `end` (exclusive). Logs
+    /// that have been assigned to the season, but were played at or after
+    /// `end` are unassigned again.
+    ///
+    /// # Returns
+    /// `true` if the current season was closed, `false` if there is no current
+    /// season or it starts after `end`.
+    fn close_season(&mut self, end: DateTime<Utc>) -> MixesDbResult<bool>;
+
+    /// Get the season that is currently running, which is the season that has
+    /// not been closed yet.
+    fn current_season(&mut self) -> MixesDbResult<Option<Season>>;
+
+    /// Assign all logs that are not part of any season yet to the season they
+    /// were played in, if any. Useful after creating a season that lies in the
+    /// past.
+    ///
+    /// # Returns
+    /// The number of logs that were assigned to a season.
+    fn assign_unmatched_logs_to_seasons(&mut self) -> MixesDbResult<u64>;
+
+    /// Search the chat of the stored logs for messages containing `pattern`,
+    /// ignoring case. Only logs that have been added while chat storage was
+    /// enabled in the database can be found, since chat is not stored by
+    /// default.
+    ///
+    /// # Returns
+    /// At most `limit` messages, from the newest games first and in the order
+    /// they were written within a game. Returns an Error if the pattern is
+    /// empty.
+    fn search_chat(&mut self, pattern: &str, limit: usize) -> MixesDbResult<Vec<ChatSearchResult>>;
+}
@@ -0,0 +1,15 @@
+/// Which logs a query considers based on whether they are linked to an
+/// official match, see
+/// [`Database::link_external_match`](super::Database::link_external_match).
+/// The default considers every log.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OfficialFilter
+{
+    /// Consider officials and mixes alike.
+    #[default]
+    Include,
+    /// Only consider the logs that are not linked to an official match.
+    Exclude,
+    /// Only consider the logs of official matches.
+    Only,
+}
@@ -0,0 +1,27 @@
+/// How the registered players take part in the mixes during a period, as
+/// returned by
+/// [`Database::participation_stats`](super::Database::participation_stats), for
+/// instance to see if it is always the same few people that play.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticipationStats
+{
+    /// The number of registered players that have played at least once.
+    pub num_players:     u64,
+    /// The median number of games of the players that have played. `None` if
+    /// nobody has played.
+    pub median_games:    Option<f32>,
+    /// The number of players by the number of games they have played. Each
+    /// entry is the lower bound of a bucket of [`GAMES_BUCKETS`] with the
+    /// number of players in it.
+    pub games_histogram: Vec<(u32, u32)>,
+    /// The share of all games played that the most active fifth of the players
+    /// has played. Close to `0.2` if everyone plays about as often, close to
+    /// `1.0` if a few players make up almost all of the games. `None` if
+    /// nobody has played.
+    pub top_fifth_share: Option<f32>,
+}
+
+/// The lower bounds of the buckets of
+/// [`ParticipationStats::games_histogram`]. Each bucket ranges up to the lower
+/// bound of the next one, the last one is open.
+pub const GAMES_BUCKETS: [u32; 6] = [1, 2, 5, 10, 20, 50];
@@ -0,0 +1,23 @@
+/// What [`Database::purge_player_data`](super::Database::purge_player_data)
+/// has removed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PurgeReport
+{
+    /// Whether the player has been registered as a user.
+    pub user:          bool,
+    /// The number of logs that have been removed entirely, because the player
+    /// has been the only registered participant.
+    pub logs:          u64,
+    pub overall_stats: u64,
+    pub dm_stats:      u64,
+    pub med_stats:     u64,
+    /// Rows where the player has been either the medic or the target.
+    pub heal_spread:   u64,
+    pub chat:          u64,
+}
+
+impl PurgeReport
+{
+    /// Whether nothing has been stored about the player.
+    pub fn is_empty(&self) -> bool { *self == Self::default() }
+}
@@ -0,0 +1,51 @@
+use super::{IngestReason, OfficialFilter, TagFilter};
+use crate::SeasonId;
+
+/// Filters for the logs performances are looked up in, for instance with
+/// [`Database::get_classes_performance`](super::Database::get_classes_performance).
+/// The default includes all mixes where the class has been played the longest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryOptions
+{
+    /// Only include logs played during this season.
+    pub season: Option<SeasonId>,
+    /// Exclude logs where the player has spent a larger fraction of the game
+    /// on classes that are not main classes, see
+    /// [`Log::offclass_fraction`](crate::Log::offclass_fraction).
+    pub max_offclass_fraction: Option<f32>,
+    /// Only count a log for a class if the player has played the class for at
+    /// least this many seconds, so that short appearances on a class do not
+    /// count as a game of it.
+    pub min_class_time_secs: u32,
+    /// Only include logs that pass the filter by their tags.
+    pub tags: TagFilter,
+    /// Only include logs that pass the filter by whether they are linked to an
+    /// official match.
+    pub officials: OfficialFilter,
+    /// Only include logs where one of the classes asked for is the class the
+    /// player has played the longest, see
+    /// [`LogPerformance::primary_class`](crate::LogPerformance::primary_class).
+    /// Otherwise, a medic who has played scout for the last round would have
+    /// their game counted as a scout game. Enabled by default.
+    pub only_primary_class: bool,
+    /// Only include logs that have been added for this reason, or logs added
+    /// for any reason if `None`. Only mixes by default, since the logs from
+    /// the history of a single player may well be public games.
+    pub ingest_reason: Option<IngestReason>,
+}
+
+impl Default for QueryOptions
+{
+    fn default() -> Self
+    {
+        Self {
+            season: None,
+            max_offclass_fraction: None,
+            min_class_time_secs: 0,
+            tags: TagFilter::default(),
+            officials: OfficialFilter::default(),
+            only_primary_class: true,
+            ingest_reason: Some(IngestReason::Mix),
+        }
+    }
+}
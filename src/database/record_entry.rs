@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+
+use super::Metric;
+use crate::{Class, LogId};
+
+/// The best value of a metric a player has reached on a class in a single
+/// log, as returned by
+/// [`Database::personal_records`](super::Database::personal_records). A log
+/// counts for the class the player has played the longest in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordEntry
+{
+    pub class:  Class,
+    pub metric: Metric,
+    pub value:  f64,
+    /// The log the record has been set in.
+    pub log_id: LogId,
+    /// The date of the log.
+    pub date:   DateTime<Utc>,
+}
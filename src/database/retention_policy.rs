@@ -0,0 +1,44 @@
+use super::LogTag;
+
+/// Which logs [`Database::apply_retention`](super::Database::apply_retention)
+/// removes, so that the database does not grow forever. The default keeps
+/// everything, only the rules that are set remove logs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy
+{
+    /// Remove logs played more than this many days ago.
+    pub max_age_days:           Option<u32>,
+    /// For every registered player, only keep this many of the newest logs
+    /// added from their history with
+    /// [`Database::backfill_player`](super::Database::backfill_player). A log
+    /// is kept as long as it is one of the newest of any registered player in
+    /// it.
+    pub max_backfill_logs:      Option<usize>,
+    /// Logs with any of these tags are never removed, like the logs of
+    /// official games or of season awards. Contains `official` by default.
+    pub protected_tags:         Vec<LogTag>,
+    /// Never remove logs that are linked to an official match, see
+    /// [`Database::link_external_match`](super::Database::link_external_match),
+    /// whether they are tagged or not. Enabled by default.
+    pub protect_linked_matches: bool,
+    /// The number of logs removed in each transaction, so the tables are not
+    /// locked for the whole time it takes to remove many logs.
+    pub batch_size:             usize,
+    /// Only count what would be removed, without removing anything.
+    pub dry_run:                bool,
+}
+
+impl Default for RetentionPolicy
+{
+    fn default() -> Self
+    {
+        Self {
+            max_age_days:           None,
+            max_backfill_logs:      None,
+            protected_tags:         vec![LogTag::new("official").unwrap()],
+            protect_linked_matches: true,
+            batch_size:             500,
+            dry_run:                false,
+        }
+    }
+}
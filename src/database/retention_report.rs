@@ -0,0 +1,19 @@
+/// What [`Database::apply_retention`](super::Database::apply_retention) has
+/// removed, or would remove in a dry run, by table.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionReport
+{
+    pub logs:          u64,
+    pub overall_stats: u64,
+    pub dm_stats:      u64,
+    pub med_stats:     u64,
+    pub heal_spread:   u64,
+    pub chat:          u64,
+    pub log_tags:      u64,
+}
+
+impl RetentionReport
+{
+    /// Whether nothing has been removed.
+    pub fn is_empty(&self) -> bool { *self == Self::default() }
+}
@@ -0,0 +1,20 @@
+/// How close the games of a player have been, taken over their recent games,
+/// as returned by [`Database::round_stats`](super::Database::round_stats).
+/// Unlike the win rate, it tells a narrow win apart from a stomp.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoundStats
+{
+    /// The number of games the stats have been taken over.
+    pub num_games:          u32,
+    /// The rounds won minus the rounds lost, averaged over the games. `None`
+    /// if there are no games.
+    pub round_differential: Option<f32>,
+    /// The number of games for which the winner of each round is known, which
+    /// the comebacks are looked for in. Games stored before the rounds were,
+    /// and games without a known team of the player, are left out.
+    pub num_timelines:      u32,
+    /// The number of games that have been won after being behind by at least
+    /// [`COMEBACK_DEFICIT`](crate::stats::COMEBACK_DEFICIT) rounds. `None` if
+    /// the rounds are not known for any of the games.
+    pub comeback_wins:      Option<u32>,
+}
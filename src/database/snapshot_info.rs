@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+
+/// What a snapshot of the whole database holds, as returned by
+/// [`Database::export_snapshot`](super::Database::export_snapshot) and
+/// [`Database::import_snapshot`](super::Database::import_snapshot).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotInfo
+{
+    /// The version of the schema of the database the snapshot has been taken
+    /// of, see [`SQLDb::schema_version`](crate::sql_db::SQLDb::schema_version).
+    pub schema_version: u32,
+    /// When the snapshot has been taken.
+    pub created_at:     DateTime<Utc>,
+    /// The number of rows of each table, in the order the tables are in the
+    /// snapshot. When importing, only the rows that have been added count.
+    pub num_rows:       Vec<(&'static str, u64)>,
+}
+
+impl SnapshotInfo
+{
+    /// The number of rows of all tables.
+    pub fn total_rows(&self) -> u64 { self.num_rows.iter().map(|(_, num_rows)| num_rows).sum() }
+}
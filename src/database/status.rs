@@ -0,0 +1,28 @@
+use chrono::{DateTime, Utc};
+
+/// Overview of what is stored in a database, as returned by
+/// [`Database::status`](super::Database::status).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DbStatus
+{
+    pub num_users:         u64,
+    pub num_logs:          u64,
+    /// Date of the newest log, `None` if there are no logs.
+    pub newest_log:        Option<DateTime<Utc>>,
+    /// Date of the oldest log, `None` if there are no logs.
+    pub oldest_log:        Option<DateTime<Utc>>,
+    pub num_overall_stats: u64,
+    pub num_dm_stats:      u64,
+    pub num_med_stats:     u64,
+    /// Space the database takes up, in bytes.
+    pub size_bytes:        u64,
+    /// When the last update has finished successfully, `None` if there has
+    /// not been an update yet.
+    pub last_update:       Option<DateTime<Utc>>,
+    /// The version of the schema the tables have been migrated to, see
+    /// [`SQLDb::schema_version`](crate::sql_db::SQLDb::schema_version).
+    pub schema_version:    u32,
+    /// The version of this crate the database is used with, its
+    /// [`VERSION`](crate::VERSION).
+    pub crate_version:     &'static str,
+}
@@ -0,0 +1,35 @@
+use super::LogTag;
+
+/// Which logs a query considers based on their tags, see
+/// [`Database::tag_log`](super::Database::tag_log). The default considers
+/// every log.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagFilter
+{
+    /// If not empty, only logs that have any of these tags are considered.
+    pub include: Vec<LogTag>,
+    /// Logs that have any of these tags are never considered, even if they
+    /// have one of the `include` tags as well.
+    pub exclude: Vec<LogTag>,
+}
+
+impl TagFilter
+{
+    /// Only consider the logs with any of the `tags`.
+    pub fn include(tags: Vec<LogTag>) -> Self
+    {
+        Self {
+            include: tags,
+            exclude: Vec::new(),
+        }
+    }
+
+    /// Consider every log, except those with any of the `tags`.
+    pub fn exclude(tags: Vec<LogTag>) -> Self
+    {
+        Self {
+            include: Vec::new(),
+            exclude: tags,
+        }
+    }
+}
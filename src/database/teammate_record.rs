@@ -0,0 +1,34 @@
+use crate::SteamID;
+
+/// How the games a player has played together with or against another player
+/// have turned out, as returned by
+/// [`Database::teammate_winrates`](super::Database::teammate_winrates) and
+/// [`Database::opponent_winrates`](super::Database::opponent_winrates). Wins
+/// and losses are seen from the player the records have been requested for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TeammateRecord
+{
+    /// The teammate or opponent.
+    pub player:    SteamID,
+    /// The number of games both players have played in, on the same team for
+    /// teammates or on different teams for opponents.
+    pub num_games: u32,
+    pub wins:      u32,
+    pub losses:    u32,
+    pub ties:      u32,
+}
+
+impl TeammateRecord
+{
+    /// The fraction of the shared games that have been won, between `0` and
+    /// `1`. Ties count as games that have not been won.
+    pub fn win_rate(&self) -> f32
+    {
+        if self.num_games == 0 {
+            0.
+        }
+        else {
+            self.wins as f32 / self.num_games as f32
+        }
+    }
+}
@@ -0,0 +1,58 @@
+/// Which logs an update adds based on their title, as set with
+/// [`SQLDb::title_filter`](crate::sql_db::SQLDb::title_filter). Titles match
+/// a pattern if they contain it, ignoring case. The default filter allows
+/// every log.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TitleFilter
+{
+    /// If not empty, only logs with a title that matches one of these are
+    /// allowed. Logs without a title are not allowed then.
+    pub allow: Vec<String>,
+    /// Logs with a title that matches one of these are never allowed, even if
+    /// they match the `allow` list.
+    pub deny:  Vec<String>,
+}
+
+impl TitleFilter
+{
+    /// Whether a log with the `title` passes the filter.
+    pub fn allows(&self, title: Option<&str>) -> bool
+    {
+        let title = match title {
+            Some(title) => title.to_lowercase(),
+            None => return self.allow.is_empty(),
+        };
+        let matches = |pattern: &String| title.contains(&pattern.to_lowercase());
+
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn allows()
+    {
+        let filter = TitleFilter {
+            allow: vec!["serveme.tf".to_owned(), "Mix".to_owned()],
+            deny:  vec!["free server".to_owned()],
+        };
+
+        assert!(filter.allows(Some("serveme.tf #1221399 - BLU vs RED")));
+        assert!(filter.allows(Some("MIXES #12")));
+        assert!(!filter.allows(Some("serveme.tf Free Server")));
+        assert!(!filter.allows(Some("Pug #4")));
+        assert!(!filter.allows(None));
+
+        let deny_only = TitleFilter {
+            allow: Vec::new(),
+            deny:  vec!["free server".to_owned()],
+        };
+        assert!(deny_only.allows(Some("Pug #4")));
+        assert!(deny_only.allows(None));
+        assert!(TitleFilter::default().allows(Some("serveme.tf Free Server")));
+    }
+}
@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::{CancelToken, LogTag};
+use crate::GameMode;
+
+/// The most players a game of TF2 can have, the number of slots of the largest
+/// servers.
+pub const MAX_NUM_PLAYERS: u16 = 100;
+
+/// Function that is called with the [`UpdateProgress`] of an update after each
+/// log it has looked at.
+pub type ProgressHandler = Box<dyn FnMut(UpdateProgress) + Send>;
+
+/// How far an update has gotten, passed to the function set with
+/// [`UpdateOptionsBuilder::progress`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UpdateProgress
+{
+    /// The number of logs found by the update that have been looked at, no
+    /// matter if they have been added or skipped.
+    pub done:      usize,
+    /// The number of logs found by the update that have to be looked at.
+    pub total:     usize,
+    /// Whether the update has been cancelled. This is the last progress of the
+    /// update, further logs are not looked at.
+    pub cancelled: bool,
+}
+
+/// How an update searches for new logs, passed to
+/// [`Database::update_with`](super::Database::update_with). Created with
+/// [`UpdateOptions::builder`], which checks that the options make sense.
+pub struct UpdateOptions
+{
+    pub(crate) min_ratio:    f32,
+    pub(crate) num_players:  RangeInclusive<u16>,
+    pub(crate) tag:          Option<LogTag>,
+    pub(crate) progress:     Option<ProgressHandler>,
+    pub(crate) cancel:       Option<CancelToken>,
+    pub(crate) health_check: bool,
+}
+
+/// Options of an update, created with [`UpdateOptions::builder`].
+pub struct UpdateOptionsBuilder
+{
+    min_ratio:    f32,
+    num_players:  RangeInclusive<u16>,
+    tag:          Option<LogTag>,
+    progress:     Option<ProgressHandler>,
+    cancel:       Option<CancelToken>,
+    health_check: bool,
+}
+
+/// The reason an [`UpdateOptionsBuilder`] does not describe a valid update.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpdateOptionsError
+{
+    /// The ratio of mixes players is not between `0` and `1`. Contains the
+    /// ratio.
+    MinRatio(f32),
+    /// The range of the number of players is empty, asks for fewer than two
+    /// players or for more than [`MAX_NUM_PLAYERS`]. Contains the range.
+    NumPlayers(RangeInclusive<u16>),
+}
+
+impl UpdateOptions
+{
+    /// Start building the options of an update. By default, logs with 12 or 13
+    /// players, the size of a sixes game with a spectator, are added if 60% of
+    /// their players are registered.
+    pub fn builder() -> UpdateOptionsBuilder
+    {
+        UpdateOptionsBuilder {
+            min_ratio:    0.6,
+            num_players:  12..=13,
+            tag:          None,
+            progress:     None,
+            cancel:       None,
+            health_check: false,
+        }
+    }
+
+    /// The ratio of registered players a log needs to be added.
+    pub fn min_ratio(&self) -> f32 { self.min_ratio }
+
+    /// The numbers of players a log needs to be added.
+    pub fn num_players(&self) -> &RangeInclusive<u16> { &self.num_players }
+
+    /// The tag the added logs are tagged with, if any.
+    pub fn tag(&self) -> Option<&LogTag> { self.tag.as_ref() }
+}
+
+impl UpdateOptionsBuilder
+{
+    /// Set the ratio of registered players a log needs to be added, between
+    /// `0` (add all logs) and `1` (only add logs where all players are
+    /// registered).
+    pub fn min_ratio(mut self, min_ratio: f32) -> Self
+    {
+        self.min_ratio = min_ratio;
+        self
+    }
+
+    /// Set the numbers of players a log needs to be added. Logs that do not
+    /// report any players are always skipped.
+    pub fn num_players(mut self, num_players: RangeInclusive<u16>) -> Self
+    {
+        self.num_players = num_players;
+        self
+    }
+
+    /// Look for full games of the `game_mode`, setting the numbers of players
+    /// to [`GameMode::num_players`].
+    pub fn game_mode(self, game_mode: GameMode) -> Self
+    {
+        self.num_players(game_mode.num_players())
+    }
+
+    /// Tag every log added by the update with the `tag`. Without it, the tag
+    /// set on the database is used, like
+    /// [`SQLDb::update_tag`](crate::sql_db::SQLDb::update_tag).
+    pub fn tag(mut self, tag: LogTag) -> Self
+    {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Call the `progress` function after each log the update has looked at,
+    /// for instance to show a progress bar.
+    pub fn progress(mut self, progress: impl FnMut(UpdateProgress) + Send + 'static) -> Self
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop the update early once the `token` is cancelled. The logs added
+    /// until then are kept and the report of the update is marked as
+    /// [`cancelled`](super::UpdateReport::cancelled).
+    pub fn cancel_token(mut self, token: CancelToken) -> Self
+    {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Check the [health](crate::logs_tf::LogSource::health) of the source of
+    /// the logs before starting the update. If it is down, the update fails
+    /// with [`DbError::UpstreamUnavailable`](crate::sql_db::DbError::UpstreamUnavailable)
+    /// without changing anything. If it is degraded, the update waits longer
+    /// between its requests and tries failed ones more often.
+    pub fn health_check(mut self) -> Self
+    {
+        self.health_check = true;
+        self
+    }
+
+    /// Check the options and finish building them.
+    pub fn build(self) -> Result<UpdateOptions, UpdateOptionsError>
+    {
+        if !(0. ..=1.).contains(&self.min_ratio) {
+            return Err(UpdateOptionsError::MinRatio(self.min_ratio));
+        }
+        if self.num_players.is_empty()
+            || *self.num_players.start() < 2
+            || *self.num_players.end() > MAX_NUM_PLAYERS
+        {
+            return Err(UpdateOptionsError::NumPlayers(self.num_players));
+        }
+
+        Ok(UpdateOptions {
+            min_ratio:    self.min_ratio,
+            num_players:  self.num_players,
+            tag:          self.tag,
+            progress:     self.progress,
+            cancel:       self.cancel,
+            health_check: self.health_check,
+        })
+    }
+}
+
+impl fmt::Debug for UpdateOptions
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        f.debug_struct("UpdateOptions")
+            .field("min_ratio", &self.min_ratio)
+            .field("num_players", &self.num_players)
+            .field("tag", &self.tag)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .field("health_check", &self.health_check)
+            .finish()
+    }
+}
+
+impl fmt::Display for UpdateOptionsError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::MinRatio(ratio) => {
+                write!(
+                    f,
+                    "The ratio of mixes players must be between 0 and 1, not {}",
+                    ratio
+                )
+            },
+            Self::NumPlayers(range) => write!(
+                f,
+                "The number of players must be between 2 and {}, not {} to {}",
+                MAX_NUM_PLAYERS,
+                range.start(),
+                range.end()
+            ),
+        }
+    }
+}
+
+impl Error for UpdateOptionsError {}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn validation()
+    {
+        let options = UpdateOptions::builder()
+            .min_ratio(0.5)
+            .num_players(2..=2)
+            .build()
+            .unwrap();
+        assert_eq!(options.min_ratio(), 0.5);
+        assert_eq!(options.num_players(), &(2..=2));
+        assert!(options.tag().is_none());
+        assert!(UpdateOptions::builder().min_ratio(0.).build().is_ok());
+        assert!(UpdateOptions::builder().min_ratio(1.).build().is_ok());
+        assert!(UpdateOptions::builder()
+            .num_players(18..=MAX_NUM_PLAYERS)
+            .build()
+            .is_ok());
+        let highlander = UpdateOptions::builder()
+            .game_mode(GameMode::Highlander)
+            .build()
+            .unwrap();
+        assert_eq!(highlander.num_players(), &(18..=19));
+
+        for ratio in [-0.1, 1.1, f32::NAN] {
+            let error = UpdateOptions::builder()
+                .min_ratio(ratio)
+                .build()
+                .unwrap_err();
+            assert!(matches!(error, UpdateOptionsError::MinRatio(_)));
+        }
+        #[allow(clippy::reversed_empty_ranges)]
+        for range in [13..=12, 0..=13, 1..=1, 12..=MAX_NUM_PLAYERS + 1] {
+            assert_eq!(
+                UpdateOptions::builder()
+                    .num_players(range.clone())
+                    .build()
+                    .unwrap_err(),
+                UpdateOptionsError::NumPlayers(range)
+            );
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use super::{BrokenRecord, UpdateRunId};
+use crate::logs_tf::Health;
+use crate::{LogId, LogMetadata, SteamID};
+
+/// Summary of what has been done by
+/// [`Database::update`](super::Database::update).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpdateReport
+{
+    /// Ids of the logs that have been added to the database.
+    pub added:          Vec<LogId>,
+    /// Ids of the logs that have only been added by the reconciliation at the
+    /// end of the update, since they have been uploaded while the players
+    /// were searched. They are part of [`UpdateReport::added`] as well.
+    pub reconciled:     Vec<LogId>,
+    /// Logs of the players that have been found, but were not added to the
+    /// database, together with the reason why.
+    pub skipped:        Vec<(LogId, SkipReason)>,
+    /// The number of logs that have not been considered because a player had
+    /// more new logs than the database looks at for a single player. The
+    /// newest logs of the player are considered. If this is not `0`, the
+    /// limit is too low for how long ago the last update has been.
+    pub overflow:       usize,
+    /// The run of the update as recorded in the history of the database, see
+    /// [`Database::update_history`](super::Database::update_history). `None`
+    /// for reports of other operations, like backfilling a player.
+    pub run_id:         Option<UpdateRunId>,
+    /// Whether the update has been stopped with its
+    /// [`CancelToken`](super::CancelToken) before it has looked at all logs.
+    /// The logs that have been added until then are still part of the report.
+    pub cancelled:      bool,
+    /// The health of the source of the logs before the update has started, if
+    /// it has been checked, see
+    /// [`UpdateOptionsBuilder::health_check`](super::UpdateOptionsBuilder::health_check).
+    pub health:         Option<Health>,
+    /// How long the update has taken, from its start until the report was
+    /// made. `None` for reports of other operations, like backfilling a player.
+    pub duration:       Option<Duration>,
+    /// The personal records that have been broken by the added logs, in the
+    /// order the logs have been added.
+    pub records_broken: Vec<BrokenRecord>,
+}
+
+impl UpdateReport
+{
+    /// The logs that have been found, but could not be downloaded or stored,
+    /// together with the reason.
+    pub fn failures(&self) -> impl Iterator<Item = &(LogId, SkipReason)>
+    {
+        self.skipped
+            .iter()
+            .filter(|(_, reason)| reason.is_failure())
+    }
+}
+
+/// What [`Database::update`](super::Database::update) would do, as determined
+/// by [`Database::update_dry_run`](super::Database::update_dry_run).
+#[derive(Clone, Debug, Default)]
+pub struct UpdatePlan
+{
+    /// The logs that would be downloaded and added, newest log first.
+    pub candidates:    Vec<LogMetadata>,
+    /// For every registered player, the number of candidates they have played
+    /// in. Empty if the logs of several players are searched at once, like
+    /// with [`SQLDb::search_batch_size`](crate::sql_db::SQLDb::search_batch_size),
+    /// since the players of a log are only known once it is downloaded.
+    pub contributions: HashMap<SteamID, usize>,
+    /// Logs of the players that have been found, but would not be added,
+    /// together with the reason why.
+    pub skipped:       Vec<(LogId, SkipReason)>,
+    /// The number of logs that would not be considered, see
+    /// [`UpdateReport::overflow`].
+    pub overflow:      usize,
+}
+
+impl UpdatePlan
+{
+    /// The number of logs not yet in the database that have been found for the
+    /// players, whether they would be added or not.
+    pub fn num_found(&self) -> usize { self.candidates.len() + self.skipped.len() }
+}
+
+/// The reason a log that has been found during an update has not been added.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason
+{
+    /// The log does not report any players, which happens with broken logs.
+    /// The ratio of mixes players cannot be determined for it.
+    NoPlayers,
+    /// The number of players in the log, which is not in the requested range.
+    PlayerCount(u16),
+    /// Not enough of the players in the log are registered as mixes players.
+    NotEnoughMixesPlayers,
+    /// The title of the log, which is not allowed by the
+    /// [`TitleFilter`](super::TitleFilter) of the database.
+    Title(Option<String>),
+    /// The log could not be downloaded, with a description of the problem.
+    DownloadFailed(String),
+    /// The log could not be stored, with a description of the problem. Nothing
+    /// of the log has been added.
+    IngestFailed(String),
+}
+
+impl SkipReason
+{
+    /// Whether the log has been skipped because something went wrong, instead
+    /// of because it is not wanted.
+    pub fn is_failure(&self) -> bool
+    {
+        matches!(self, Self::DownloadFailed(_) | Self::IngestFailed(_))
+    }
+}
+
+impl fmt::Display for SkipReason
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::NoPlayers => write!(f, "The log does not contain any players"),
+            Self::PlayerCount(num_players) => {
+                write!(
+                    f,
+                    "The log has an unwanted number of players ({})",
+                    num_players
+                )
+            },
+            Self::NotEnoughMixesPlayers => write!(f, "Not enough mixes players are in the log"),
+            Self::Title(Some(title)) => {
+                write!(f, "The title of the log is not allowed ({})", title)
+            },
+            Self::Title(None) => write!(f, "The log has no title"),
+            Self::DownloadFailed(e) => write!(f, "The log could not be downloaded: {}", e),
+            Self::IngestFailed(e) => write!(f, "The log could not be stored: {}", e),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::ops::RangeInclusive;
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::LogTag;
+
+/// Identifier of an update run as it is saved in the database, see
+/// [`UpdateReport::run_id`](super::UpdateReport::run_id).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UpdateRunId(pub i32);
+
+/// A run of [`Database::update_with`](super::Database::update_with), as
+/// returned by [`Database::update_history`](super::Database::update_history).
+/// Tells which settings the logs in the database have been added with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateRunRecord
+{
+    pub id:             UpdateRunId,
+    pub started_at:     DateTime<Utc>,
+    /// How long the run has taken. `None` if it is still running or has failed
+    /// before looking at any logs.
+    pub duration:       Option<Duration>,
+    /// The ratio of registered players a log needed to be added.
+    pub min_ratio:      f32,
+    /// The numbers of players a log needed to be added.
+    pub num_players:    RangeInclusive<u16>,
+    /// The tag the added logs have been tagged with, if any.
+    pub tag:            Option<LogTag>,
+    /// The number of logs that have been found and were to be downloaded.
+    pub num_candidates: usize,
+    /// The number of logs that have been added.
+    pub num_added:      usize,
+    /// The number of logs that could not be downloaded or stored.
+    pub num_failed:     usize,
+    /// Whether the run has finished. A run that is still running or has failed
+    /// before looking at any logs has not, and its numbers of logs are all
+    /// `0`. A run that could not add any of its logs has not completed either,
+    /// but its numbers of logs tell how many of them have failed.
+    pub completed:      bool,
+    /// Whether the run has been stopped with a
+    /// [`CancelToken`](super::CancelToken). A cancelled run has not
+    /// completed, but its numbers of logs tell how far it has gotten.
+    pub cancelled:      bool,
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+
+use crate::SteamID;
+
+/// A registered player, as returned by
+/// [`Database::user_records`](super::Database::user_records).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserRecord
+{
+    pub steam_id:      SteamID,
+    pub discord_id:    u64,
+    /// When the player has been registered. `None` for players that have
+    /// been registered before the date was recorded.
+    pub registered_at: Option<DateTime<Utc>>,
+    /// Whether the player currently takes part in the mixes. Inactive players
+    /// stay registered, so their stats are kept.
+    pub active:        bool,
+    pub username:      Option<String>,
+}
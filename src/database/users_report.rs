@@ -0,0 +1,15 @@
+use crate::{SteamID, SteamIDError};
+
+/// The registered players as found by
+/// [`Database::users_checked`](super::Database::users_checked), split by
+/// whether their stored steam id is valid.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsersReport
+{
+    /// The players with a valid steam id, ordered by steam id.
+    pub valid:   Vec<SteamID>,
+    /// The steamID64s that are stored, but are not valid steam ids, together
+    /// with the reason. They can only be stored by changing the database by
+    /// hand.
+    pub invalid: Vec<(i64, SteamIDError)>,
+}
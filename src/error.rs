@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::fmt;
+
+use postgres::Error as SqlError;
+
+use crate::database::{LogTagError, UpdateOptionsError};
+use crate::logs_tf::{LogParseError, QueryError};
+use crate::sql_db::DbError;
+use crate::{LogIdParseError, SteamID, SteamIdParseError};
+
+/// Any error that may occur in this crate, independent of the database backend
+/// it occurs in. Every method of [`Database`](crate::Database) returns it, so
+/// that code working with several backends or layers only has to handle one
+/// type of error.
+#[derive(Debug)]
+pub enum MixesDbError
+{
+    /// The backend of the database has failed, for instance because the
+    /// connection to it was lost. Contains the error of the backend.
+    Backend(Box<dyn Error + Send + Sync>),
+    /// logs.tf could not be queried.
+    LogsTf(QueryError),
+    /// A log could not be read.
+    Parse(LogParseError),
+    /// The arguments given do not make sense, for instance an empty search
+    /// string or a link that does not name a log. Contains a description of
+    /// the problem.
+    InvalidInput(String),
+    /// Something that has been asked for does not exist.
+    NotFound,
+    /// Players that have been asked for are not registered, like the players
+    /// of [`Database::update_players`](crate::Database::update_players).
+    /// Contains the players.
+    UnregisteredPlayers(Vec<SteamID>),
+}
+
+pub type MixesDbResult<T> = Result<T, MixesDbError>;
+
+impl From<QueryError> for MixesDbError
+{
+    fn from(e: QueryError) -> Self { Self::LogsTf(e) }
+}
+
+impl From<LogParseError> for MixesDbError
+{
+    fn from(e: LogParseError) -> Self { Self::Parse(e) }
+}
+
+impl From<LogIdParseError> for MixesDbError
+{
+    fn from(e: LogIdParseError) -> Self { Self::InvalidInput(e.to_string()) }
+}
+
+impl From<SteamIdParseError> for MixesDbError
+{
+    fn from(e: SteamIdParseError) -> Self { Self::InvalidInput(e.to_string()) }
+}
+
+impl From<LogTagError> for MixesDbError
+{
+    fn from(e: LogTagError) -> Self { Self::InvalidInput(e.to_string()) }
+}
+
+impl From<UpdateOptionsError> for MixesDbError
+{
+    fn from(e: UpdateOptionsError) -> Self { Self::InvalidInput(e.to_string()) }
+}
+
+impl From<SqlError> for MixesDbError
+{
+    fn from(e: SqlError) -> Self { Self::Backend(Box::new(e)) }
+}
+
+impl From<DbError> for MixesDbError
+{
+    fn from(e: DbError) -> Self
+    {
+        match e {
+            DbError::Sql(sql_e) => sql_e.into(),
+            #[cfg(feature = "pool")]
+            DbError::Pool(pool_e) => Self::Backend(Box::new(pool_e)),
+            DbError::Parse(parse_e) => Self::Parse(parse_e),
+            DbError::Query(query_e) => Self::LogsTf(query_e),
+            DbError::LogRef(ref_e) => ref_e.into(),
+            DbError::InvalidInput(e) => Self::InvalidInput(e),
+            e @ DbError::Overflow { .. } => Self::InvalidInput(e.to_string()),
+            DbError::Io(io_e) => Self::Backend(Box::new(io_e)),
+            e @ (DbError::UpstreamUnavailable(_) | DbError::SchemaTooNew { .. }) => {
+                Self::Backend(Box::new(e))
+            },
+        }
+    }
+}
+
+impl fmt::Display for MixesDbError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Backend(backend_e) => write!(f, "Database error: {}", backend_e),
+            Self::LogsTf(query_e) => write!(f, "Unable to query logs.tf: {}", query_e),
+            Self::Parse(parse_e) => write!(f, "Unable to read log: {}", parse_e),
+            Self::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            Self::NotFound => write!(f, "Not found"),
+            Self::UnregisteredPlayers(players) => {
+                let players: Vec<String> = players
+                    .iter()
+                    .map(|player| player.to_id64_string())
+                    .collect();
+                write!(f, "Not registered: {}", players.join(", "))
+            },
+        }
+    }
+}
+
+impl Error for MixesDbError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            Self::Backend(backend_e) => Some(backend_e.as_ref()),
+            Self::LogsTf(query_e) => Some(query_e),
+            Self::Parse(parse_e) => Some(parse_e),
+            Self::InvalidInput(_) | Self::NotFound | Self::UnregisteredPlayers(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn backend_source()
+    {
+        // Nothing listens on port 1, so connecting fails right away.
+        let sql_e = postgres::Client::connect("host=localhost port=1", postgres::NoTls)
+            .err()
+            .expect("Connected to a closed port");
+        let e = MixesDbError::from(DbError::Sql(sql_e));
+
+        assert!(matches!(e, MixesDbError::Backend(_)));
+        assert!(e
+            .source()
+            .unwrap()
+            .downcast_ref::<postgres::Error>()
+            .is_some());
+    }
+
+    #[test]
+    fn logs_tf_source()
+    {
+        let http_e = reqwest::blocking::get("not a url").expect_err("Requested an invalid url");
+        let e = MixesDbError::from(DbError::Query(QueryError::from(http_e)));
+
+        let query_e = e.source().unwrap();
+        assert!(query_e.downcast_ref::<QueryError>().is_some());
+        assert!(query_e
+            .source()
+            .unwrap()
+            .downcast_ref::<reqwest::Error>()
+            .is_some());
+    }
+
+    #[test]
+    fn invalid_input()
+    {
+        let e = MixesDbError::from(DbError::InvalidInput("empty name".to_owned()));
+        assert_eq!(e.to_string(), "Invalid input: empty name");
+        assert!(e.source().is_none());
+
+        let e = MixesDbError::from(DbError::Overflow {
+            stat:  "damage",
+            value: 1 << 32,
+        });
+        assert_eq!(
+            e.to_string(),
+            "Invalid input: A damage of 4294967296 is too large to be stored"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Formatting of stats for the messages of bots and commands, so that the same
+//! stat always looks the same, like `6.7k` damage or a K/D of `1.19`. All
+//! functions only build strings and round half away from zero.
+
+mod stat_line;
+
+pub use stat_line::*;
+
+/// Shown in place of a number that cannot be computed, like the ratio of zero
+/// to zero.
+pub const NOT_A_NUMBER: &str = "-";
+
+/// Format damage or healing, in thousands with one decimal from 1000 on, like
+/// `950` or `6.7k`.
+pub fn fmt_damage(damage: u32) -> String
+{
+    if damage < 1000 {
+        return damage.to_string();
+    }
+
+    let tenths = (damage as u64 + 50) / 100;
+    format!("{}.{}k", tenths / 10, tenths % 10)
+}
+
+/// Format a ratio, like kills per death, with two decimals. An infinite ratio,
+/// like kills without any deaths, is shown as `∞`.
+pub fn fmt_ratio(ratio: f64) -> String
+{
+    if ratio.is_nan() {
+        return NOT_A_NUMBER.to_owned();
+    }
+    if ratio.is_infinite() {
+        let sign = if ratio < 0. { "-" } else { "" };
+        return format!("{}∞", sign);
+    }
+
+    format!("{:.2}", (ratio * 100.).round() / 100.)
+}
+
+/// Format a number of seconds like a clock, as `27:45`, or as `1:02:03` from
+/// an hour on.
+pub fn fmt_duration_secs(secs: u32) -> String
+{
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, mins, secs) => format!("{}:{:02}", mins, secs),
+        (hours, mins, secs) => format!("{}:{:02}:{:02}", hours, mins, secs),
+    }
+}
+
+/// Format a fraction between `0` and `1`, like a percentile or a win rate, as
+/// a whole percentage, like `46%`.
+pub fn fmt_percent(fraction: f64) -> String
+{
+    if !fraction.is_finite() {
+        return NOT_A_NUMBER.to_owned();
+    }
+
+    format!("{}%", (fraction * 100.).round())
+}
+
+/// Format a rate, like damage per minute, as a whole number.
+pub fn fmt_rate(rate: f64) -> String
+{
+    if !rate.is_finite() {
+        return NOT_A_NUMBER.to_owned();
+    }
+
+    format!("{}", rate.round())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn damage()
+    {
+        assert_eq!(fmt_damage(0), "0");
+        assert_eq!(fmt_damage(999), "999");
+        assert_eq!(fmt_damage(1000), "1.0k");
+        assert_eq!(fmt_damage(6671), "6.7k");
+        assert_eq!(fmt_damage(6650), "6.7k");
+        assert_eq!(fmt_damage(6649), "6.6k");
+        assert_eq!(fmt_damage(22732), "22.7k");
+        assert_eq!(fmt_damage(9_999), "10.0k");
+        assert_eq!(fmt_damage(u32::MAX), "4294967.3k");
+    }
+
+    #[test]
+    fn ratio()
+    {
+        assert_eq!(fmt_ratio(19. / 16.), "1.19");
+        assert_eq!(fmt_ratio(2.), "2.00");
+        assert_eq!(fmt_ratio(0.125), "0.13");
+        assert_eq!(fmt_ratio(0.), "0.00");
+        assert_eq!(fmt_ratio(f64::INFINITY), "∞");
+        assert_eq!(fmt_ratio(f64::NEG_INFINITY), "-∞");
+        assert_eq!(fmt_ratio(f64::NAN), "-");
+    }
+
+    #[test]
+    fn duration()
+    {
+        assert_eq!(fmt_duration_secs(0), "0:00");
+        assert_eq!(fmt_duration_secs(59), "0:59");
+        assert_eq!(fmt_duration_secs(1665), "27:45");
+        assert_eq!(fmt_duration_secs(3599), "59:59");
+        assert_eq!(fmt_duration_secs(3600), "1:00:00");
+        assert_eq!(fmt_duration_secs(3723), "1:02:03");
+        assert_eq!(fmt_duration_secs(36_000), "10:00:00");
+    }
+
+    #[test]
+    fn percent_and_rate()
+    {
+        assert_eq!(fmt_percent(0.), "0%");
+        assert_eq!(fmt_percent(1.), "100%");
+        assert_eq!(fmt_percent(0.456), "46%");
+        assert_eq!(fmt_percent(0.005), "1%");
+        assert_eq!(fmt_percent(0.004), "0%");
+        assert_eq!(fmt_percent(0.999), "100%");
+        assert_eq!(fmt_percent(f64::NAN), "-");
+
+        assert_eq!(fmt_rate(280.4), "280");
+        assert_eq!(fmt_rate(280.5), "281");
+        assert_eq!(fmt_rate(0.), "0");
+        assert_eq!(fmt_rate(f64::INFINITY), "-");
+    }
+}
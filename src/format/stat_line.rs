@@ -0,0 +1,193 @@
+use super::{fmt_damage, fmt_duration_secs, fmt_rate, fmt_ratio};
+use crate::medic_performance::MedicPerformance;
+use crate::{ClassView, LogPerformance};
+
+/// Stats of a player put together for a message, either on a single line,
+/// like `6.7k dmg, 280 DPM, 19/16 K/D`, or with a line per stat for the field
+/// of a Discord embed. Stats are shown in the order they have been added.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatLine
+{
+    stats: Vec<Stat>,
+}
+
+/// A single stat of a [`StatLine`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Stat
+{
+    /// The name of the stat, used on its own line, like `Damage`.
+    name:  &'static str,
+    /// The short name, put after the value on a single line, like `dmg`.
+    short: &'static str,
+    value: String,
+}
+
+impl StatLine
+{
+    pub fn new() -> Self { Self::default() }
+
+    /// Add a stat with the `value` that has already been formatted. The `name`
+    /// is shown in front of it on its own line, the `short` name after it on
+    /// a single line.
+    pub fn stat(mut self, name: &'static str, short: &'static str, value: String) -> Self
+    {
+        self.stats.push(Stat { name, short, value });
+        self
+    }
+
+    /// The stats of a whole game that has lasted `duration_secs`: the damage,
+    /// the damage per minute over the whole game, the kills and deaths and,
+    /// if the player has played medic, their healing, ubers and drops.
+    pub fn for_performance(performance: &LogPerformance, duration_secs: u32) -> Self
+    {
+        let mut line = Self::new();
+        if let Some(overall) = &performance.overall {
+            line = line.stat("Damage", "dmg", fmt_damage(overall.damage));
+            if duration_secs > 0 {
+                let dpm = overall.damage as f64 * 60. / duration_secs as f64;
+                line = line.stat("DPM", "DPM", fmt_rate(dpm));
+            }
+            line = line.kills_deaths(overall.kills, overall.deaths);
+        }
+
+        match &performance.medic {
+            Some(medic) => line.medic(medic),
+            None => line,
+        }
+    }
+
+    /// The stats of a player on a single class of a game: the damage, the
+    /// damage per minute on the class, the kills and deaths, the time played
+    /// and, for medic, the healing, ubers and drops.
+    pub fn for_class(view: &ClassView<'_>) -> Self
+    {
+        let mut line = Self::new().stat("Damage", "dmg", fmt_damage(view.dm.damage));
+        if let Some(dpm) = view.damage_per_minute() {
+            line = line.stat("DPM", "DPM", fmt_rate(dpm as f64));
+        }
+        line = line.kills_deaths(view.dm.kills, view.dm.deaths).stat(
+            "Time",
+            "played",
+            fmt_duration_secs(view.dm.time_played_secs),
+        );
+
+        match view.medic {
+            Some(medic) => line.medic(medic),
+            None => line,
+        }
+    }
+
+    /// All stats on a single line, separated by commas.
+    pub fn to_line(&self) -> String
+    {
+        self.stats
+            .iter()
+            .map(|stat| format!("{} {}", stat.value, stat.short))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Every stat on its own line with its name in bold, for the value of a
+    /// field of a Discord embed.
+    pub fn to_field(&self) -> String
+    {
+        self.stats
+            .iter()
+            .map(|stat| format!("**{}:** {}", stat.name, stat.value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn kills_deaths(self, kills: u16, deaths: u16) -> Self
+    {
+        self.stat("K/D", "K/D", format!("{}/{}", kills, deaths))
+            .stat("K/D ratio", "KDR", fmt_ratio(kills as f64 / deaths as f64))
+    }
+
+    fn medic(self, medic: &MedicPerformance) -> Self
+    {
+        self.stat("Healing", "heals", fmt_damage(medic.healing))
+            .stat("Ubers", "ubers", medic.num_ubers.to_string())
+            .stat("Drops", "drops", medic.num_drops.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::test_support::LogBuilder;
+    use crate::{Class, Log, LogId, SteamID};
+
+    fn performance(log: &Log, steam_id: SteamID) -> LogPerformance
+    {
+        log.performance_of(steam_id).unwrap().clone().into()
+    }
+
+    #[test]
+    fn performance_line()
+    {
+        let scout = SteamID::from_str("[U:1:1]").unwrap();
+        let medic = SteamID::from_str("[U:1:2]").unwrap();
+        let log = LogBuilder::new(LogId(1))
+            .duration(1665)
+            .player(scout, |player| {
+                player
+                    .class(Class::Scout, 19, 16, 6671, 1618)
+                    .class(Class::Pyro, 0, 0, 120, 47)
+            })
+            .medic(medic, 22732, 12, 0)
+            .build();
+
+        let line = StatLine::for_performance(&performance(&log, scout), log.duration_secs());
+        assert_eq!(line.to_line(), "6.8k dmg, 245 DPM, 19/16 K/D, 1.19 KDR");
+        assert_eq!(
+            line.to_field(),
+            "**Damage:** 6.8k\n**DPM:** 245\n**K/D:** 19/16\n**K/D ratio:** 1.19"
+        );
+
+        // Without a duration there is no damage per minute.
+        let line = StatLine::for_performance(&performance(&log, medic), 0);
+        assert_eq!(
+            line.to_line(),
+            "0 dmg, 0/0 K/D, - KDR, 22.7k heals, 12 ubers, 0 drops"
+        );
+    }
+
+    #[test]
+    fn class_line()
+    {
+        let scout = SteamID::from_str("[U:1:1]").unwrap();
+        let log = LogBuilder::new(LogId(1))
+            .duration(4000)
+            .player(scout, |player| {
+                player
+                    .class(Class::Scout, 19, 0, 6671, 3723)
+                    .class(Class::Engineer, 0, 2, 0, 0)
+            })
+            .build();
+        let performance = performance(&log, scout);
+
+        let view = performance.for_class(Class::Scout).unwrap();
+        assert_eq!(
+            StatLine::for_class(&view).to_line(),
+            "6.7k dmg, 108 DPM, 19/0 K/D, ∞ KDR, 1:02:03 played"
+        );
+        // A class played for no time has no damage per minute.
+        let view = performance.for_class(Class::Engineer).unwrap();
+        assert_eq!(
+            StatLine::for_class(&view).to_field(),
+            "**Damage:** 0\n**K/D:** 0/2\n**K/D ratio:** 0.00\n**Time:** 0:00"
+        );
+
+        assert_eq!(StatLine::new().to_line(), "");
+        assert_eq!(
+            StatLine::new()
+                .stat("Games", "games", "3".to_owned())
+                .to_field(),
+            "**Games:** 3"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::ClassPolicy;
+
+/// The game modes mixes are played in, which decide how many players a game
+/// has and which classes are played.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GameMode
+{
+    /// One soldier and one medic per team.
+    Ultiduo,
+    /// Six players per team, mostly scouts, soldiers, a demoman and a medic.
+    Sixes,
+    /// Nine players per team, one of each class.
+    Highlander,
+}
+
+impl GameMode
+{
+    /// Guess the game mode of a log from its number of players. Logs with up
+    /// to 5 players are taken to be ultiduo, logs with 14 or more players
+    /// highlander and everything in between sixes. Logs that do not report any
+    /// players are taken to be sixes as well.
+    pub fn from_num_players(num_players: u16) -> Self
+    {
+        match num_players {
+            1..=5 => Self::Ultiduo,
+            14.. => Self::Highlander,
+            _ => Self::Sixes,
+        }
+    }
+
+    /// The number of players of a full game, allowing for one spectator or
+    /// substitute, like the numbers of players an update looks for.
+    pub fn num_players(self) -> RangeInclusive<u16>
+    {
+        match self {
+            Self::Ultiduo => 4..=5,
+            Self::Sixes => 12..=13,
+            Self::Highlander => 18..=19,
+        }
+    }
+
+    /// The policy for the main classes of the game mode.
+    pub fn class_policy(self) -> ClassPolicy
+    {
+        match self {
+            Self::Ultiduo => ClassPolicy::ultiduo(),
+            Self::Sixes => ClassPolicy::sixes(),
+            Self::Highlander => ClassPolicy::highlander(),
+        }
+    }
+}
+
+impl fmt::Display for GameMode
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let name = match self {
+            Self::Ultiduo => "Ultiduo",
+            Self::Sixes => "Sixes",
+            Self::Highlander => "Highlander",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn from_num_players()
+    {
+        for mode in [GameMode::Ultiduo, GameMode::Sixes, GameMode::Highlander] {
+            for num_players in mode.num_players() {
+                assert_eq!(GameMode::from_num_players(num_players), mode);
+            }
+        }
+        assert_eq!(GameMode::from_num_players(0), GameMode::Sixes);
+        assert_eq!(GameMode::from_num_players(11), GameMode::Sixes);
+        assert_eq!(GameMode::from_num_players(17), GameMode::Highlander);
+        assert_eq!(GameMode::from_num_players(24), GameMode::Highlander);
+    }
+}
@@ -4,13 +4,37 @@
 #![feature(hash_drain_filter)]
 
 pub mod class;
+pub mod class_policy;
 pub mod database;
-mod logs_tf;
+pub mod error;
+pub mod format;
+pub mod game_mode;
+pub mod log_id;
+pub mod logs_tf;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "webhook")]
+pub mod notify;
 pub mod performance;
+pub mod report;
+pub mod season;
 pub mod sql_db;
+pub mod stats;
 pub mod steam_id;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
 
 pub use class::*;
+pub use class_policy::*;
 pub use database::*;
+pub use error::*;
+pub use game_mode::*;
+pub use log_id::*;
+pub use logs_tf::{ChatMessage, Log, LogAnomaly, LogMetadata, TeamSummary, TeamTotals};
 pub use performance::*;
+pub use season::*;
 pub use steam_id::*;
+
+/// The version of this crate, to tell which version a program using it has
+/// been built with, see also [`DbStatus`].
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,186 @@
+//! Identifier of a log on logs.tf. A newtype, so that log ids are not mixed up
+//! with counts and limits, which are numbers as well.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// The id of a log, as it is used by logs.tf.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct LogId(pub u32);
+
+/// A string could not be read as a log id. Contains the string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogIdParseError
+{
+    input: String,
+}
+
+impl From<u32> for LogId
+{
+    fn from(id: u32) -> Self { Self(id) }
+}
+
+impl From<LogId> for u32
+{
+    fn from(id: LogId) -> Self { id.0 }
+}
+
+impl fmt::Display for LogId
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.0) }
+}
+
+impl FromStr for LogId
+{
+    type Err = LogIdParseError;
+
+    /// Read a log id from either the plain id, like `3094861`, the id with a
+    /// leading `#` or the URL of the log, like `https://logs.tf/3094861`.
+    /// Anything after the id in the URL, like a selected player, is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let error = || LogIdParseError {
+            input: s.to_owned(),
+        };
+
+        let reference = s.trim();
+        let reference = reference.strip_prefix('#').unwrap_or(reference);
+        let reference = reference
+            .split(['#', '?'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+
+        let id = match reference.rsplit_once('/') {
+            Some((host, id)) => {
+                let host = host
+                    .trim_start_matches("https://")
+                    .trim_start_matches("http://")
+                    .trim_start_matches("www.");
+                if host != "logs.tf" {
+                    return Err(error());
+                }
+
+                id
+            },
+            None => reference,
+        };
+
+        // Only plain digits, no signs or other things u32 parsing accepts
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(error());
+        }
+        id.parse().map(Self).map_err(|_| error())
+    }
+}
+
+impl fmt::Display for LogIdParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "`{}` is not a log, expected an id like `3094861` or `#3094861`, or a link like \
+             `https://logs.tf/3094861`",
+            self.input
+        )
+    }
+}
+
+impl Error for LogIdParseError {}
+
+impl ToSql for LogId
+{
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut)
+        -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    {
+        if *ty == Type::INT4 {
+            i32::try_from(self.0)?.to_sql(ty, out)
+        }
+        else {
+            self.0.to_sql(ty, out)
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool { *ty == Type::INT4 || <u32 as ToSql>::accepts(ty) }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for LogId
+{
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>>
+    {
+        if *ty == Type::INT4 {
+            Ok(Self(u32::try_from(i32::from_sql(ty, raw)?)?))
+        }
+        else {
+            u32::from_sql(ty, raw).map(Self)
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool { *ty == Type::INT4 || <u32 as FromSql>::accepts(ty) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn from_str()
+    {
+        for reference in [
+            "3094861",
+            " 3094861 ",
+            "#3094861",
+            "logs.tf/3094861",
+            "https://logs.tf/3094861",
+            "http://www.logs.tf/3094861/",
+            "https://logs.tf/3094861#76561198847002865",
+            "https://logs.tf/3094861?highlight=76561198847002865",
+        ] {
+            assert_eq!(
+                LogId::from_str(reference),
+                Ok(LogId(3094861)),
+                "{}",
+                reference
+            );
+        }
+
+        for reference in [
+            "",
+            "#",
+            "+3094861",
+            "-3094861",
+            "https://logs.tf/",
+            "https://example.com/3094861",
+            "https://logs.tf/json/abc",
+            "99999999999",
+        ] {
+            assert!(LogId::from_str(reference).is_err(), "{}", reference);
+        }
+
+        let message = LogId::from_str("logs.tf").unwrap_err().to_string();
+        assert!(message.contains("`logs.tf`"));
+        assert!(message.contains("`#3094861`"));
+        assert!(message.contains("`https://logs.tf/3094861`"));
+    }
+
+    #[test]
+    fn display_round_trip()
+    {
+        let id = LogId(3094861);
+
+        assert_eq!(id.to_string().parse(), Ok(id));
+    }
+}
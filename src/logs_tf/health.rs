@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use ::log::{info, trace};
+
+use super::{check_json_success, log_delay, LogsTf, QueryError, QueryResult};
+
+/// How well logs.tf is answering, as found by [`health_check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Health
+{
+    /// logs.tf has answered correctly and in time.
+    Up,
+    /// logs.tf is answering, but struggling. Requests to it are likely to fail
+    /// or take long.
+    Degraded(Degradation),
+    /// logs.tf can not be used right now, for instance because it is under
+    /// maintenance or cannot be reached. Contains a description of the
+    /// problem.
+    Down(String),
+}
+
+/// The way logs.tf is struggling when its [`Health`] is degraded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Degradation
+{
+    /// logs.tf has answered correctly, but has taken longer than
+    /// [`LogsTf::slow_after`]. Contains the time it has taken.
+    Slow(Duration),
+    /// logs.tf has answered with a server error. Contains the status code.
+    ServerError(u16),
+    /// logs.tf has refused to answer because too many requests have been made.
+    RateLimited,
+}
+
+/// Check whether logs.tf is up with a search for a single log, so that a long
+/// running task like an update can find out whether to start at all. The
+/// request is not retried and its result is not cached.
+///
+/// # Returns
+/// The health of the logs.tf API of the `source`. An error is only returned if
+/// the request could not be made at all, problems with logs.tf itself are
+/// reported as its health.
+pub fn health_check(source: &LogsTf) -> QueryResult<Health>
+{
+    let client = source.client()?;
+    let request = client
+        .get(&source.api_base)
+        .query(&[("limit", 1)])
+        .build()?;
+    log_delay();
+
+    trace!("Checking the health of logs.tf: GET {}", request.url());
+    let started = Instant::now();
+    let health = match client.execute(request) {
+        Err(e) => Health::Down(QueryError::from(e).to_string()),
+        Ok(response) if response.status().is_server_error() => {
+            Health::Degraded(Degradation::ServerError(response.status().as_u16()))
+        },
+        Ok(response) if response.status().as_u16() == 429 => {
+            Health::Degraded(Degradation::RateLimited)
+        },
+        Ok(response) => {
+            let answer = response
+                .text()
+                .map_err(QueryError::from)
+                .and_then(|text| Ok(json::parse(&text)?))
+                .and_then(|json| check_json_success(&json));
+            let latency = started.elapsed();
+            match answer {
+                Err(e) => Health::Down(e.to_string()),
+                Ok(()) if latency > source.slow_after => {
+                    Health::Degraded(Degradation::Slow(latency))
+                },
+                Ok(()) => Health::Up,
+            }
+        },
+    };
+    info!("logs.tf is {:?}", health);
+
+    Ok(health)
+}
@@ -0,0 +1,68 @@
+//! Lenient reading of numbers from the json of logs. Older logs on logs.tf
+//! sometimes have numbers written as strings, like `"total_time": "1618"`,
+//! which the accessors of [`JsonValue`] do not accept.
+
+use std::str::FromStr;
+
+use json::JsonValue;
+
+/// Read the `value` with the `native` accessor of [`JsonValue`], or parse it
+/// from a string if it is one.
+fn lenient<T: FromStr>(value: &JsonValue, native: fn(&JsonValue) -> Option<T>) -> Option<T>
+{
+    native(value).or_else(|| value.as_str()?.trim().parse().ok())
+}
+
+/// Read a number that fits into a `u8` from a json number or a string.
+///
+/// # Returns
+/// `None` if the value is neither, or if it is out of range.
+pub fn json_num_u8(value: &JsonValue) -> Option<u8> { lenient(value, JsonValue::as_u8) }
+
+/// Read a number that fits into a `u16` from a json number or a string, see
+/// [`json_num_u8`].
+pub fn json_num_u16(value: &JsonValue) -> Option<u16> { lenient(value, JsonValue::as_u16) }
+
+/// Read a number that fits into a `u32` from a json number or a string, see
+/// [`json_num_u8`].
+pub fn json_num_u32(value: &JsonValue) -> Option<u32> { lenient(value, JsonValue::as_u32) }
+
+/// Read a number that fits into an `i64` from a json number or a string, see
+/// [`json_num_u8`].
+pub fn json_num_i64(value: &JsonValue) -> Option<i64> { lenient(value, JsonValue::as_i64) }
+
+/// Read a finite number from a json number or a string. Strings like `NaN` or
+/// `inf` are not numbers in json, so they are not accepted either.
+pub fn json_num_f32(value: &JsonValue) -> Option<f32>
+{
+    lenient(value, JsonValue::as_f32).filter(|number| number.is_finite())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn numbers_and_strings()
+    {
+        assert_eq!(json_num_u32(&1618.into()), Some(1618));
+        assert_eq!(json_num_u32(&"1618".into()), Some(1618));
+        assert_eq!(json_num_u16(&" 12 ".into()), Some(12));
+        assert_eq!(json_num_u8(&"7".into()), Some(7));
+        assert_eq!(json_num_i64(&"-86400".into()), Some(-86_400));
+        assert_eq!(json_num_f32(&"6.875".into()), Some(6.875));
+        assert_eq!(json_num_f32(&6.5.into()), Some(6.5));
+
+        // Anything else is not a number.
+        assert_eq!(json_num_u32(&"16 min".into()), None);
+        assert_eq!(json_num_u32(&"".into()), None);
+        assert_eq!(json_num_u32(&JsonValue::Null), None);
+        assert_eq!(json_num_u32(&true.into()), None);
+        assert_eq!(json_num_u8(&"256".into()), None);
+        assert_eq!(json_num_u16(&"-1".into()), None);
+        assert_eq!(json_num_u32(&"1.5".into()), None);
+        assert_eq!(json_num_f32(&"NaN".into()), None);
+        assert_eq!(json_num_f32(&"inf".into()), None);
+    }
+}
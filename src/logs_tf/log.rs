@@ -1,110 +1,1223 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt;
 use std::str::FromStr;
 
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use json::JsonValue;
+use log::trace;
+use reqwest::blocking::Client;
 
-use super::{keep_trying, log_delay, QueryResult, LOGS_TF_API_BASE};
-use crate::score::Score;
-use crate::{Performance, SteamID};
+use super::{
+    json_num_i64, json_num_u16, json_num_u32, log_delay, LogAnomaly, LogParseError, LogSource,
+    LogsTf, QueryResult, TeamSummary, TeamTotals,
+};
+use crate::overall_performance::OverallPerformance;
+use crate::score::{MatchFormat, Score, Team};
+use crate::{merge_performances, Class, ClassPolicy, GameMode, LogId, Performance, SteamID};
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct LogMetadata
 {
-    pub id:          u32,
-    pub date_time:   DateTime<Utc>,
-    pub map:         String,
-    pub num_players: u8,
+    pub id:            LogId,
+    pub date_time:     DateTime<Utc>,
+    pub map:           String,
+    /// The number of players as reported by logs.tf. Broken logs may report
+    /// `0`.
+    pub num_players:   u16,
+    /// The title the log has been uploaded with, like `serveme.tf #1221399 -
+    /// BLU vs RED`. Missing in older logs.
+    pub title:         Option<String>,
+    /// The player that has uploaded the log, which is only known from the
+    /// whole log, not from search results.
+    pub uploader:      Option<SteamID>,
+    /// How long the game has lasted in seconds, see [`Log::duration_secs`].
+    /// Like the uploader, only known from the whole log.
+    pub duration_secs: Option<u32>,
+}
+
+/// A message written in the chat during a game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatMessage
+{
+    /// The player that has written the message. Messages of the server console
+    /// do not have a steam id.
+    pub steam_id: Option<SteamID>,
+    pub name:     String,
+    pub message:  String,
 }
 
 pub struct Log
 {
-    meta:          LogMetadata,
-    performances:  HashMap<SteamID, Vec<Performance>>,
-    duration_secs: u32,
+    meta:         LogMetadata,
+    performances: HashMap<SteamID, Vec<Performance>>,
+    spectators:   Vec<SteamID>,
+    chat:         Vec<ChatMessage>,
+    /// The in-game names of everyone listed in the log, spectators included.
+    names:        HashMap<SteamID, String>,
+    /// The winner of each round, `None` for rounds won by neither team.
+    rounds:       Vec<Option<Team>>,
+    anomalies:    Vec<LogAnomaly>,
 }
 
 impl LogMetadata
+{
+    /// Read the metadata of a log as listed in the results of a search.
+    ///
+    /// # Returns
+    /// A [`LogParseError`] if the id, the date or the map is missing or
+    /// invalid.
+    pub fn from_json(json: &JsonValue) -> Result<Self, LogParseError>
+    {
+        let id = json_num_u32(&json["id"]).ok_or(LogParseError::InvalidField("id"))?;
+        let map = json["map"]
+            .as_str()
+            .ok_or(LogParseError::InvalidField("map"))?;
+
+        Ok(Self {
+            id:            LogId(id),
+            date_time:     parse_date(&json["date"])?,
+            map:           map.to_owned(),
+            // Broken logs may be missing the number of players
+            num_players:   json_num_u16(&json["players"]).unwrap_or(0),
+            title:         json["title"].as_str().map(str::to_owned),
+            uploader:      None,
+            duration_secs: None,
+        })
+    }
+
+    /// How long the game has lasted, if it is known, see
+    /// [`LogMetadata::duration_secs`].
+    pub fn duration(&self) -> Option<Duration>
+    {
+        self.duration_secs
+            .map(|duration_secs| Duration::seconds(duration_secs as i64))
+    }
+
+    /// The date of the log as a unix timestamp, the number of seconds since
+    /// 1970-01-01 00:00 UTC, just like logs.tf reports it.
+    pub fn timestamp(&self) -> i64 { self.date_time.timestamp() }
+
+    /// How the game has been played, judging by the map.
+    pub fn format(&self) -> MatchFormat { MatchFormat::from_map(&self.map) }
+
+    /// The game mode of the log, judging by its number of players, see
+    /// [`GameMode::from_num_players`].
+    pub fn game_mode(&self) -> GameMode { GameMode::from_num_players(self.num_players) }
+
+    /// How long ago the log has been played, as of `now`. Negative if the log
+    /// is dated after `now`.
+    pub fn age(&self, now: DateTime<Utc>) -> Duration { now - self.date_time }
+
+    /// Check whether the log has been played at most the `window` before
+    /// `now`, for instance to only look at the games of the last week.
+    pub fn is_within(&self, window: Duration, now: DateTime<Utc>) -> bool
+    {
+        self.age(now) <= window
+    }
+}
+
+impl fmt::Display for LogMetadata
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "{} ({} players) — {}",
+            self.map,
+            self.num_players,
+            self.date_time.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+}
+
+impl ChatMessage
 {
     pub fn from_json(json: &JsonValue) -> Self
     {
         Self {
-            id:          json["id"].as_u32().unwrap(),
-            date_time:   DateTime::from_utc(
-                NaiveDateTime::from_timestamp(json["date"].as_i64().unwrap(), 0),
-                Utc,
-            ),
-            map:         json["map"].as_str().unwrap().to_owned(),
-            num_players: json["players"].as_u8().unwrap(),
+            steam_id: json["steamid"]
+                .as_str()
+                .and_then(|steam_id| SteamID::from_str(steam_id).ok()),
+            name:     json["name"].as_str().unwrap_or_default().to_owned(),
+            message:  json["msg"].as_str().unwrap_or_default().to_owned(),
         }
     }
 }
 
 impl Log
 {
-    fn download_once(id: u32) -> QueryResult<Self>
+    pub(super) fn download_once(client: &Client, api_base: &str, id: LogId) -> QueryResult<Self>
     {
-        let log = reqwest::blocking::get(format!("{}/{}", LOGS_TF_API_BASE, id))?
-            .text()
-            .expect("Unable to read response body");
+        log_delay();
 
-        let json = json::parse(&log)?;
-        super::check_json_success(&json)?;
+        let url = format!("{}/{}", api_base, id);
+        trace!("Downloading log: GET {}", url);
+        let response = client.get(url).send()?;
 
-        Ok(Self::from_json(id, &json))
+        #[cfg(feature = "serde-parse")]
+        {
+            Self::read_response(id, response)
+        }
+
+        #[cfg(not(feature = "serde-parse"))]
+        {
+            let json = json::parse(&response.text()?)?;
+            super::check_json_success(&json)?;
+
+            Self::from_json(id, &json).map_err(super::QueryError::InvalidLog)
+        }
     }
 
     /// Download the log with the given id from logs.tf and turn it into a
     /// format that can be processed by a rating system easily.
-    pub fn download(id: u32, num_retries: u8) -> QueryResult<Self>
+    ///
+    /// With the `serde-parse` feature, the response is streamed into the
+    /// typed parser, without holding the whole text of the log in memory.
+    pub fn download(id: LogId, num_retries: u8) -> QueryResult<Self>
     {
-        keep_trying(|| Self::download_once(id), num_retries)
+        LogsTf {
+            num_retries,
+            ..LogsTf::default()
+        }
+        .download_log(id)
+    }
+
+    /// Parse a log from the json text as it is returned by the logs.tf API,
+    /// for instance when it has been saved to a file. Rejects logs that do not
+    /// contain any players or have an invalid date.
+    ///
+    /// With the `serde-parse` feature, the faster [`Log::parse_typed`] is used.
+    pub fn parse(id: LogId, json: &str) -> Result<Self, LogParseError>
+    {
+        #[cfg(feature = "serde-parse")]
+        let parsed = Self::parse_typed(id, json);
+
+        #[cfg(not(feature = "serde-parse"))]
+        let parsed = json::parse(json)
+            .map_err(LogParseError::from)
+            .and_then(|json| {
+                if json["players"].is_empty() {
+                    return Err(LogParseError::NoPlayers);
+                }
+                parse_date(&json["info"]["date"])?;
+
+                Self::from_json(id, &json)
+            });
+
+        #[cfg(feature = "metrics")]
+        if parsed.is_err() {
+            crate::metrics::record_parse_failure();
+        }
+        parsed
+    }
+
+    /// Create a log from its already extracted parts. The `players` are the
+    /// performances of each entry of the players of the log, entries of the
+    /// same player are merged and recorded as an anomaly. Players that have
+    /// not played any class for any time are moved to the spectators, and the
+    /// number of players of the `meta` is set to the number of the remaining
+    /// players. The duration of the `meta` is set to `duration_secs`.
+    /// `names` are the in-game names of the log, and `rounds` are the winners
+    /// of the rounds in the order they were played.
+    pub(super) fn from_parts(
+        mut meta: LogMetadata,
+        players: impl IntoIterator<Item = (SteamID, Vec<Performance>)>,
+        duration_secs: u32,
+        chat: Vec<ChatMessage>,
+        names: HashMap<SteamID, String>,
+        rounds: Vec<Option<Team>>,
+    ) -> Self
+    {
+        let mut performances: HashMap<SteamID, Vec<Performance>> = HashMap::new();
+        let mut anomalies = Vec::new();
+        for (steam_id, player_performances) in players {
+            match performances.entry(steam_id) {
+                Entry::Vacant(entry) => {
+                    entry.insert(player_performances);
+                },
+                Entry::Occupied(mut entry) => {
+                    merge_performances(entry.get_mut(), player_performances);
+                    anomalies.push(LogAnomaly::DuplicatePlayer(steam_id));
+                },
+            }
+        }
+
+        let mut spectators: Vec<SteamID> = performances
+            .iter()
+            .filter(|(_, performances)| !has_playtime(performances))
+            .map(|(&steam_id, _)| steam_id)
+            .collect();
+        spectators.sort_unstable_by_key(|steam_id| steam_id.id64());
+        for spectator in &spectators {
+            performances.remove(spectator);
+        }
+        meta.num_players = u16::try_from(performances.len()).unwrap_or(u16::MAX);
+        meta.duration_secs = Some(duration_secs);
+
+        Self {
+            meta,
+            performances,
+            spectators,
+            chat,
+            names,
+            rounds,
+            anomalies,
+        }
     }
 
     /// Parse the json information as found on logs.tf into a format easily
     /// digestible by the rating system.
+    ///
+    /// # Returns
+    /// A [`LogParseError`] if the date or the stats of a player cannot be
+    /// read. Unlike [`Log::parse`], a log without players is accepted.
     // XXX: Check presumed logs.tf json for any format deviances
-    pub fn from_json(id: u32, json: &JsonValue) -> Self
+    pub fn from_json(id: LogId, json: &JsonValue) -> Result<Self, LogParseError>
     {
         let info = &json["info"];
-        let duration_secs = info["total_length"]
-            .as_u32()
-            .expect("Duration is not an unsigned int");
+        let duration_secs = duration_or_rounds(
+            json_num_u32(&info["total_length"]),
+            json["rounds"]
+                .members()
+                .map(|round| json_num_u32(&round["length"]).unwrap_or(0)),
+        );
         let map = info["map"]
             .as_str()
-            .expect("Unable to read map of log")
+            .ok_or(LogParseError::InvalidField("map"))?
             .to_owned();
-        let timestamp = info["date"]
-            .as_u32()
-            .expect("Unable to read date as Unix timestamp") as i64;
-        let date_time = DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), Utc);
-        let num_players = json["names"].members().len() as u8;
+        let date_time = parse_date(&info["date"])?;
+        let title = info["title"].as_str().map(str::to_owned);
+        let uploader = info["uploader"]["id"]
+            .as_str()
+            .and_then(|steam_id| SteamID::from_str(steam_id).ok());
 
         let meta = LogMetadata {
             id,
             date_time,
             map,
-            num_players,
+            // Counted from the players that have actually played.
+            num_players: 0,
+            title,
+            uploader,
+            // Set with the performances.
+            duration_secs: None,
         };
 
-        let score = Score::from_json(json);
+        let score = Score::from_json(json)?;
 
-        let mut performances = HashMap::new();
-        for (player_id, stats) in json["players"].entries() {
-            let player_id =
-                SteamID::from_str(player_id).expect("Player id is not a valid steam id");
+        let players = json["players"]
+            .entries()
+            .map(|(player_id, stats)| {
+                let heal_spread = &json["healspread"][player_id];
+                let player_id = SteamID::from_str(player_id)
+                    .map_err(|_| LogParseError::InvalidPlayerId(player_id.to_owned()))?;
 
-            let player_performances = Performance::extract_all_from_json(&score, stats);
-            performances.insert(player_id, player_performances);
-        }
+                Ok((
+                    player_id,
+                    Performance::extract_all_from_json(&score, stats, heal_spread)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, LogParseError>>()?;
 
-        Self {
+        let chat = json["chat"].members().map(ChatMessage::from_json).collect();
+        // Names are not needed to read the stats, so broken entries are left out.
+        let names = json["names"]
+            .entries()
+            .filter_map(|(steam_id, name)| {
+                Some((SteamID::from_str(steam_id).ok()?, name.as_str()?.to_owned()))
+            })
+            .collect();
+        let rounds = json["rounds"]
+            .members()
+            .map(|round| {
+                round["winner"]
+                    .as_str()
+                    .and_then(|winner| Team::from_str(winner).ok())
+            })
+            .collect();
+
+        Ok(Self::from_parts(
             meta,
-            performances,
+            players,
             duration_secs,
+            chat,
+            names,
+            rounds,
+        ))
+    }
+
+    /// Get the time the player has spent on each class in this log, in the
+    /// order the classes are listed in the log. Empty if the player is not
+    /// part of the log.
+    pub fn class_times(&self, player: SteamID) -> Vec<(Class, u32)>
+    {
+        self.performance_of(player)
+            .into_iter()
+            .flatten()
+            .filter_map(Performance::as_dm)
+            .map(|dm| (dm.class, dm.time_played_secs))
+            .collect()
+    }
+
+    /// Get the class the player has played the longest in this log, no matter
+    /// the game mode. Ties go to the class listed first.
+    ///
+    /// # Returns
+    /// `None` if the player is not part of the log or has no playtime in it.
+    pub fn primary_class(&self, player: SteamID) -> Option<Class>
+    {
+        self.class_times(player)
+            .into_iter()
+            .filter(|&(_, time)| time > 0)
+            .rev()
+            .max_by_key(|&(_, time)| time)
+            .map(|(class, _)| class)
+    }
+
+    /// The policy for the main classes of the game mode of the log, which is
+    /// guessed from its number of players, see [`LogMetadata::game_mode`].
+    pub fn class_policy(&self) -> ClassPolicy { self.meta.game_mode().class_policy() }
+
+    /// Get the class the player has mainly played, according to the `policy`.
+    /// See [`ClassPolicy::main_class`].
+    pub fn main_class(&self, player: SteamID, policy: &ClassPolicy) -> Option<Class>
+    {
+        policy.main_class(&self.class_times(player))
+    }
+
+    /// Get the fraction of the playtime of the player that was spent on classes
+    /// which are not main classes in the game mode of the log, as defined by
+    /// [`Log::class_policy`].
+    ///
+    /// # Returns
+    /// A value between `0.0` and `1.0`. If the player has no playtime in this
+    /// log, `0.0` is returned.
+    pub fn offclass_fraction(&self, player: SteamID) -> f32
+    {
+        self.class_policy()
+            .offclass_fraction(&self.class_times(player))
+    }
+
+    /// Add up the stats of the players of each team, for instance to see how
+    /// much a player has contributed to their team.
+    ///
+    /// # Returns
+    /// The totals of the teams that are part of the log. Players without a team
+    /// are left out.
+    pub fn team_totals(&self) -> HashMap<Team, TeamTotals>
+    {
+        let mut totals: HashMap<Team, TeamTotals> = HashMap::new();
+        for performance in self.performances.values().flatten() {
+            if let Performance::Overall(OverallPerformance {
+                team: Some(team),
+                damage,
+                kills,
+                ..
+            }) = performance
+            {
+                let team_totals = totals.entry(*team).or_default();
+                team_totals.damage += *damage as u64;
+                team_totals.kills += *kills as u32;
+            }
         }
+
+        totals
+    }
+
+    /// Sum up the damage, healing and ubers of each team and attach the
+    /// winners of the rounds, for a report of the whole match. Computes the
+    /// same as [`Database::team_summary`](crate::Database::team_summary) does
+    /// for the stored log.
+    ///
+    /// # Returns
+    /// The summaries of the teams that are part of the log. Players without a
+    /// team are left out.
+    pub fn team_summary(&self) -> HashMap<Team, TeamSummary>
+    {
+        let mut summaries: HashMap<Team, TeamSummary> = HashMap::new();
+        for performances in self.performances.values() {
+            let team = performances
+                .iter()
+                .find_map(Performance::as_overall)
+                .and_then(|overall| overall.team);
+            let summary = match team {
+                Some(team) => summaries.entry(team).or_insert_with(|| TeamSummary {
+                    rounds: self.rounds.clone(),
+                    ..TeamSummary::default()
+                }),
+                None => continue,
+            };
+
+            for performance in performances {
+                match performance {
+                    Performance::Overall(overall) => summary.damage += overall.damage as u64,
+                    Performance::Med(medic) => {
+                        summary.healing += medic.healing as u64;
+                        summary.num_ubers += medic.num_ubers as u32;
+                    },
+                    Performance::DM(_) => {},
+                }
+            }
+        }
+
+        summaries
+    }
+
+    /// Get the fraction of the damage of their team the player has dealt, and
+    /// the fraction of the kills of their team they have taken part in with a
+    /// kill or an assist.
+    ///
+    /// # Returns
+    /// The damage share and the kill participation. Either is `None` if the
+    /// player is not part of the log or if their team has not dealt any damage
+    /// or kills, which happens in broken logs.
+    pub fn team_shares(&self, player: SteamID) -> (Option<f32>, Option<f32>)
+    {
+        let performances = self
+            .performance_of(player)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let overall = performances.iter().find_map(Performance::as_overall);
+        let totals = overall
+            .and_then(|overall| overall.team)
+            .and_then(|team| self.team_totals().remove(&team));
+        let (overall, totals) = match (overall, totals) {
+            (Some(overall), Some(totals)) => (overall, totals),
+            _ => return (None, None),
+        };
+        let assists: u32 = performances
+            .iter()
+            .filter_map(Performance::as_dm)
+            .map(|dm| dm.assists as u32)
+            .sum();
+
+        (
+            totals.damage_share(overall.damage),
+            totals.kill_participation(overall.kills as u32, assists),
+        )
     }
 
     pub fn meta(&self) -> &LogMetadata { &self.meta }
-    pub fn duration_secs(&self) -> u32 { self.duration_secs }
+    pub fn duration_secs(&self) -> u32 { self.meta.duration_secs.unwrap_or_default() }
     pub fn performances(&self) -> &HashMap<SteamID, Vec<Performance>> { &self.performances }
+
+    /// The players that are part of the log without having played any class,
+    /// for instance because they have joined but never picked a class. They
+    /// have no performances and do not count towards the number of players.
+    /// Ordered by steam id.
+    pub fn spectators(&self) -> &[SteamID] { &self.spectators }
+
+    /// How long the game has lasted, see [`Log::duration_secs`].
+    pub fn duration(&self) -> Duration { Duration::seconds(self.duration_secs() as i64) }
+
+    /// Get the performances of the player, if they are part of the log. The
+    /// player is found by their account id, so the lookup succeeds even if
+    /// their steam id differs from the one in the log in the universe or
+    /// instance, which happens when it is not read from a steamID3.
+    pub fn performance_of(&self, player: SteamID) -> Option<&Vec<Performance>>
+    {
+        let mut normalized = player;
+        normalized.normalize();
+
+        self.performances.get(&normalized).or_else(|| {
+            self.performances
+                .iter()
+                .find(|(steam_id, _)| steam_id.account_id() == player.account_id())
+                .map(|(_, performances)| performances)
+        })
+    }
+    /// Get the messages written in the chat during the game, in the order they
+    /// were written.
+    pub fn chat(&self) -> &[ChatMessage] { &self.chat }
+
+    /// The in-game names of the players, keyed by their steam id. Spectators
+    /// that are named by the log are included, even though they have no
+    /// performances. Players whose name the log does not list are missing.
+    pub fn player_names(&self) -> &HashMap<SteamID, String> { &self.names }
+
+    /// The winner of each round in the order they were played, `None` for a
+    /// round that has been won by neither team. Empty if the log has no
+    /// rounds.
+    pub fn round_winners(&self) -> &[Option<Team>] { &self.rounds }
+
+    /// What has been unusual about the log and has been worked around while
+    /// reading it, in the order it has been found.
+    pub fn anomalies(&self) -> &[LogAnomaly] { &self.anomalies }
+}
+
+/// Whether any class has been played for any time in the `performances` of a
+/// player.
+fn has_playtime(performances: &[Performance]) -> bool
+{
+    performances
+        .iter()
+        .filter_map(Performance::as_dm)
+        .any(|dm| dm.time_played_secs > 0)
+}
+
+/// Read the date of a log from its Unix timestamp.
+///
+/// # Returns
+/// [`LogParseError::InvalidDate`] if the date is not a number or too far in
+/// the past or future to be represented.
+fn parse_date(timestamp: &JsonValue) -> Result<DateTime<Utc>, LogParseError>
+{
+    json_num_i64(timestamp)
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .ok_or(LogParseError::InvalidDate)
+}
+
+/// The duration of a log in seconds. Some logs, for instance of games where the
+/// server crashed, do not report their duration in the header. In that case it
+/// is the sum of the lengths of the rounds, if there are any.
+pub(super) fn duration_or_rounds(
+    total_length: Option<u32>,
+    round_lengths: impl Iterator<Item = u32>,
+) -> u32
+{
+    match total_length {
+        Some(total_length) if total_length > 0 => total_length,
+        _ => round_lengths.sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+
+    fn fixture_json() -> JsonValue
+    {
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+
+        json::parse(&json).expect("Unable to parse json")
+    }
+
+    #[test]
+    fn offclass_fraction()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        assert_eq!(
+            log.class_times(scout),
+            vec![
+                (Class::Scout, 1618),
+                (Class::Engineer, 99),
+                (Class::Pyro, 8)
+            ]
+        );
+        assert_eq!(log.offclass_fraction(scout), 107.0 / 1725.0);
+        assert_eq!(log.offclass_fraction(medic), 0.0);
+        assert_eq!(
+            log.offclass_fraction(SteamID::from_str("[U:1:1]").unwrap()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn primary_class()
+    {
+        let mut json = fixture_json();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        assert_eq!(log.primary_class(scout), Some(Class::Scout));
+        assert_eq!(
+            log.primary_class(SteamID::from_str("[U:1:1]").unwrap()),
+            None
+        );
+
+        for class_stats in json["players"]["[U:1:886717065]"]["class_stats"].members_mut() {
+            class_stats["total_time"] = match class_stats["type"].as_str() {
+                Some("scout") => 0.into(),
+                _ => 99.into(),
+            };
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        assert_eq!(log.primary_class(scout), Some(Class::Engineer));
+
+        for class_stats in json["players"]["[U:1:886717065]"]["class_stats"].members_mut() {
+            class_stats["total_time"] = 0.into();
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        assert_eq!(log.primary_class(scout), None);
+    }
+
+    #[test]
+    fn offclass_fraction_without_playtime()
+    {
+        let mut json = fixture_json();
+        for class_stats in json["players"]["[U:1:886717065]"]["class_stats"].members_mut() {
+            class_stats["total_time"] = 0.into();
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(log.offclass_fraction(scout), 0.0);
+    }
+
+    #[test]
+    fn spectators()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        assert!(log.spectators().is_empty());
+        assert_eq!(log.meta().num_players, 12);
+
+        // A player that has joined without picking a class, and one that has
+        // picked a class without ever spawning.
+        let mut json = fixture_json();
+        json["players"]["[U:1:886717065]"]["class_stats"] = json::array![];
+        for class_stats in json["players"]["[U:1:71020853]"]["class_stats"].members_mut() {
+            class_stats["total_time"] = 0.into();
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        assert_eq!(log.spectators(), &[medic, scout]);
+        assert!(log.performance_of(scout).is_none());
+        assert!(log.performance_of(medic).is_none());
+        assert_eq!(log.performances().len(), 10);
+        assert_eq!(log.meta().num_players, 10);
+    }
+
+    #[test]
+    fn duplicate_player()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        assert!(log.anomalies().is_empty());
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let original = log.performance_of(scout).unwrap().clone();
+
+        // The scout has rejoined and is listed a second time under their
+        // steamID64, with their scout stats and some time on soldier.
+        let mut json = fixture_json();
+        let mut rejoined = json["players"]["[U:1:886717065]"].clone();
+        let soldier = json::object! {
+            "type": "soldier", "kills": 2, "assists": 1, "deaths": 3, "dmg": 500,
+            "total_time": 300,
+        };
+        rejoined["class_stats"] = json::array![rejoined["class_stats"][0].clone(), soldier];
+        rejoined["dmg"] = 1000.into();
+        rejoined["kills"] = 5.into();
+        json["players"]["76561198846982793"] = rejoined;
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+
+        assert_eq!(log.anomalies(), &[LogAnomaly::DuplicatePlayer(scout)]);
+        assert_eq!(log.meta().num_players, 12);
+        let original_overall = original.iter().find_map(Performance::as_overall).unwrap();
+        let merged = log.performance_of(scout).unwrap();
+        let overall = merged.iter().find_map(Performance::as_overall).unwrap();
+        assert_eq!(overall.damage, original_overall.damage + 1000);
+        assert_eq!(overall.kills, original_overall.kills + 5);
+        assert_eq!(overall.deaths, 2 * original_overall.deaths);
+        assert_eq!(overall.won_rounds, original_overall.won_rounds);
+        assert_eq!(overall.team, original_overall.team);
+
+        // Classes played in both entries are summed up, new ones come last.
+        assert_eq!(
+            log.class_times(scout),
+            vec![
+                (Class::Scout, 2 * 1618),
+                (Class::Engineer, 99),
+                (Class::Pyro, 8),
+                (Class::Soldier, 300),
+            ]
+        );
+        let scout_stats = merged
+            .iter()
+            .filter_map(Performance::as_dm)
+            .find(|dm| dm.class == Class::Scout)
+            .unwrap();
+        assert_eq!(scout_stats.damage, 2 * 6671);
+        assert_eq!(scout_stats.kills, 2 * 19);
+    }
+
+    #[test]
+    fn main_class_by_policy()
+    {
+        let mut json = fixture_json();
+        for class_stats in json["players"]["[U:1:1028703238]"]["class_stats"].members_mut() {
+            class_stats["total_time"] = match class_stats["type"].as_str() {
+                Some("sniper") => 1597.into(),
+                _ => 107.into(),
+            };
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        let sniper = SteamID::from_str("[U:1:1028703238]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        assert_eq!(
+            log.main_class(sniper, &ClassPolicy::sixes()),
+            Some(Class::Scout)
+        );
+        assert_eq!(
+            log.main_class(sniper, &ClassPolicy::highlander()),
+            Some(Class::Sniper)
+        );
+        assert_eq!(
+            log.main_class(medic, &ClassPolicy::sixes()),
+            Some(Class::Medic)
+        );
+        assert_eq!(
+            log.main_class(medic, &ClassPolicy::highlander()),
+            Some(Class::Medic)
+        );
+
+        // The log has 12 players, so its offclass time is counted like in sixes.
+        assert_eq!(log.class_policy(), ClassPolicy::sixes());
+        assert_eq!(log.offclass_fraction(sniper), 1597.0 / 1704.0);
+        assert_eq!(
+            ClassPolicy::highlander().offclass_fraction(&log.class_times(sniper)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn highlander()
+    {
+        let mut json = String::new();
+        File::open("test_data/log_highlander.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let log = Log::from_json(LogId(1), &json::parse(&json).unwrap()).unwrap();
+
+        assert_eq!(log.meta().num_players, 18);
+        assert_eq!(log.meta().game_mode(), GameMode::Highlander);
+        assert_eq!(log.meta().format(), MatchFormat::Koth);
+        assert_eq!(log.class_policy(), ClassPolicy::highlander());
+
+        // Each team has one player of each class, so nobody is offclassing.
+        for (account_id, class) in (20000001..).zip(Class::ALL.iter().chain(&Class::ALL)) {
+            let player = SteamID::from_str(&format!("[U:1:{}]", account_id)).unwrap();
+            assert_eq!(log.class_times(player), vec![(*class, 1500)]);
+            assert_eq!(log.main_class(player, &log.class_policy()), Some(*class));
+            assert_eq!(log.offclass_fraction(player), 0.0);
+        }
+        assert_eq!(log.team_totals()[&Team::Red].kills, 127);
+    }
+
+    #[test]
+    fn team_totals()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        let totals = log.team_totals();
+
+        assert_eq!(
+            totals[&Team::Blue],
+            TeamTotals {
+                damage: 41358,
+                kills:  111,
+            }
+        );
+        assert_eq!(
+            totals[&Team::Red],
+            TeamTotals {
+                damage: 41060,
+                kills:  110,
+            }
+        );
+
+        // The scout is on Blue and has dealt 7090 damage with 20 kills and 16
+        // assists.
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(
+            log.team_shares(scout),
+            (Some(7090. / 41358.), Some(36. / 111.))
+        );
+        assert_eq!(
+            log.team_shares(SteamID::from_str("[U:1:1]").unwrap()),
+            (None, None)
+        );
+
+        // The damage of a team adds up to more than a single player can deal.
+        let mut json = fixture_json();
+        for (_, player) in json["players"].entries_mut() {
+            player["dmg"] = 2_000_000_000.into();
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        assert_eq!(log.team_totals()[&Team::Red].damage, 12_000_000_000);
+    }
+
+    #[test]
+    fn team_shares_without_damage()
+    {
+        let mut json = fixture_json();
+        for (_, player) in json["players"].entries_mut() {
+            player["dmg"] = 0.into();
+        }
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let (damage_share, kill_participation) = log.team_shares(scout);
+        assert_eq!(damage_share, None);
+        assert!(kill_participation.is_some());
+    }
+
+    #[test]
+    fn team_summary()
+    {
+        let rounds = vec![
+            Some(Team::Blue),
+            Some(Team::Red),
+            Some(Team::Blue),
+            Some(Team::Blue),
+            Some(Team::Red),
+            Some(Team::Red),
+            None,
+        ];
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        let summaries = log.team_summary();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(
+            summaries[&Team::Blue],
+            TeamSummary {
+                damage:    41358,
+                healing:   22732,
+                num_ubers: 12,
+                rounds:    rounds.clone(),
+            }
+        );
+        assert_eq!(
+            summaries[&Team::Red],
+            TeamSummary {
+                damage:    41060,
+                healing:   23463,
+                num_ubers: 15,
+                rounds:    rounds.clone(),
+            }
+        );
+        assert_eq!(log.round_winners(), rounds);
+
+        // The parser of the build agrees, and a log without rounds has no
+        // timeline.
+        let mut json = fixture_json();
+        assert_eq!(
+            Log::parse(LogId(3094861), &json.dump())
+                .unwrap()
+                .team_summary(),
+            summaries
+        );
+        json["rounds"] = JsonValue::new_array();
+        let log = Log::parse(LogId(3094861), &json.dump()).unwrap();
+        assert!(log.team_summary()[&Team::Red].rounds.is_empty());
+        assert_eq!(log.team_summary()[&Team::Red].damage, 41060);
+    }
+
+    #[test]
+    fn player_names()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        assert_eq!(log.player_names().len(), 12);
+        // The names are found by either format of the steam id.
+        for scout in ["[U:1:886717065]", "76561198846982793"] {
+            let scout = SteamID::from_str(scout).unwrap();
+            assert_eq!(log.player_names()[&scout], "cklips");
+        }
+
+        // A spectator can be named without being one of the players.
+        let mut json = fixture_json();
+        json["names"]["76561197960265729"] = "spectator".into();
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        let spectator = SteamID::from_str("[U:1:1]").unwrap();
+        assert_eq!(log.player_names()[&spectator], "spectator");
+        assert!(log.performance_of(spectator).is_none());
+        assert_eq!(log.meta().num_players, 12);
+
+        // The parser of the build reads the same names.
+        let parsed = Log::parse(LogId(3094861), &json.dump()).unwrap();
+        assert_eq!(parsed.player_names(), log.player_names());
+    }
+
+    #[test]
+    fn chat()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        let chat = log.chat();
+
+        assert_eq!(chat.len(), 123);
+        assert_eq!(
+            chat[0],
+            ChatMessage {
+                steam_id: None,
+                name:     "Console".to_owned(),
+                message:  "ETF2L config (2021-01-12) loaded.".to_owned(),
+            }
+        );
+        assert_eq!(
+            chat[5],
+            ChatMessage {
+                steam_id: Some(SteamID::from_str("[U:1:838576271]").unwrap()),
+                name:     "SobriJoskaMGETrainingV8".to_owned(),
+                message:  "we are coming".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn performance_of()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        let from_id3 = SteamID::from_str("[U:1:886717065]").unwrap();
+        // The same account, but in the unspecified universe
+        let from_id64 = SteamID::from_str("4503604809054857").unwrap();
+        assert_ne!(from_id64, from_id3);
+
+        let performances = log.performance_of(from_id3).unwrap();
+        assert_eq!(log.performance_of(from_id64), Some(performances));
+        assert_eq!(log.class_times(from_id64), log.class_times(from_id3));
+        assert_eq!(
+            log.performance_of(SteamID::from_str("[U:1:1]").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn duration()
+    {
+        assert_eq!(
+            Log::from_json(LogId(1), &fixture_json())
+                .unwrap()
+                .duration_secs(),
+            1738
+        );
+
+        // Without a duration, the rounds are used.
+        let mut json = fixture_json();
+        json["info"].remove("total_length");
+        assert_eq!(
+            Log::from_json(LogId(1), &json).unwrap().duration_secs(),
+            1738
+        );
+        json["info"]["total_length"] = 0.into();
+        assert_eq!(
+            Log::from_json(LogId(1), &json).unwrap().duration_secs(),
+            1738
+        );
+
+        json["rounds"] = json::JsonValue::new_array();
+        assert_eq!(Log::from_json(LogId(1), &json).unwrap().duration_secs(), 0);
+
+        // Absurd durations are kept as they are, they are only flagged when stored.
+        json["info"]["total_length"] = 100_000.into();
+        assert_eq!(
+            Log::from_json(LogId(1), &json).unwrap().duration_secs(),
+            100_000
+        );
+    }
+
+    #[test]
+    fn match_format()
+    {
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let rounds = |json: &JsonValue| {
+            let log = Log::from_json(LogId(1), json).unwrap();
+            log.performance_of(scout)
+                .unwrap()
+                .iter()
+                .find_map(|performance| match performance {
+                    Performance::Overall(overall) => {
+                        Some((log.meta().format(), overall.won_rounds, overall.num_rounds))
+                    },
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // The fixture is a 3:3 tie on a 5CP map.
+        let mut json = fixture_json();
+        assert_eq!(rounds(&json), (MatchFormat::FiveCp, 3, 6));
+
+        json["info"]["map"] = "koth_product_final".into();
+        json["teams"]["Blue"]["score"] = 4.into();
+        assert_eq!(rounds(&json), (MatchFormat::Koth, 4, 7));
+
+        // A stopwatch game is a single round, the scout is in the blue team.
+        json["info"]["map"] = "pl_upward_f10".into();
+        json["teams"]["Red"]["score"] = 2.into();
+        json["teams"]["Blue"]["score"] = 1.into();
+        assert_eq!(rounds(&json), (MatchFormat::Stopwatch, 0, 1));
+        json["teams"]["Blue"]["score"] = 3.into();
+        assert_eq!(rounds(&json), (MatchFormat::Stopwatch, 1, 1));
+    }
+
+    #[test]
+    fn dates_and_durations()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        assert_eq!(log.duration(), Duration::seconds(1738));
+
+        let meta = log.meta();
+        assert_eq!(
+            meta.to_string(),
+            "cp_sunshine (12 players) — 2021-12-22 20:48 UTC"
+        );
+        assert_eq!(meta.timestamp(), 1640206126);
+        let now = meta.date_time + Duration::days(3);
+        assert_eq!(meta.age(now), Duration::days(3));
+        assert!(meta.is_within(Duration::days(3), now));
+        assert!(!meta.is_within(Duration::days(2), now));
+    }
+
+    #[test]
+    fn invalid_date()
+    {
+        assert!(parse_date(&1640206126.into()).is_ok());
+        // Dates before 1970 are negative.
+        let before_epoch = parse_date(&(-86_400).into()).unwrap();
+        assert_eq!(before_epoch.to_string(), "1969-12-31 00:00:00 UTC");
+        let out_of_range = JsonValue::from(10_i64.pow(15));
+        for date in [out_of_range, "yesterday".into(), JsonValue::Null] {
+            assert!(matches!(parse_date(&date), Err(LogParseError::InvalidDate)));
+        }
+
+        let mut json = fixture_json();
+        json["info"]["date"] = 10_i64.pow(15).into();
+        assert!(Log::parse(LogId(1), &json.dump()).is_err());
+        #[cfg(not(feature = "serde-parse"))]
+        assert!(matches!(
+            Log::parse(LogId(1), &json.dump()),
+            Err(LogParseError::InvalidDate)
+        ));
+    }
+
+    #[test]
+    fn metadata_player_count()
+    {
+        let mut json = json::object! {
+            id: 3094861,
+            date: 1640206126,
+            map: "cp_sunshine",
+            players: 300,
+        };
+        assert_eq!(LogMetadata::from_json(&json).unwrap().num_players, 300);
+
+        json["players"] = 0.into();
+        assert_eq!(LogMetadata::from_json(&json).unwrap().num_players, 0);
+
+        json.remove("players");
+        assert_eq!(LogMetadata::from_json(&json).unwrap().num_players, 0);
+    }
+
+    /// Replace every number in the `json` with the same number as a string.
+    fn stringify_numbers(json: &mut JsonValue)
+    {
+        if json.is_number() {
+            *json = json.dump().into();
+        }
+        else if json.is_object() {
+            json.entries_mut()
+                .for_each(|(_, value)| stringify_numbers(value));
+        }
+        else {
+            json.members_mut().for_each(stringify_numbers);
+        }
+    }
+
+    #[test]
+    fn numbers_given_as_strings()
+    {
+        let json = fixture_json();
+        let mut stringified = json.clone();
+        stringify_numbers(&mut stringified);
+        let scout = &stringified["players"]["[U:1:886717065]"];
+        assert!(scout["class_stats"][0]["total_time"].is_string());
+
+        let log = Log::from_json(LogId(3094861), &json).unwrap();
+        let lenient = Log::from_json(LogId(3094861), &stringified).unwrap();
+        assert_eq!(lenient.meta(), log.meta());
+        assert_eq!(lenient.performances(), log.performances());
+        assert_eq!(lenient.team_summary(), log.team_summary());
+        assert_eq!(lenient.round_winners(), log.round_winners());
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(lenient.class_times(scout), log.class_times(scout));
+
+        let mut meta = json::object! {
+            id: "3094861",
+            date: " 1640206126 ",
+            map: "cp_sunshine",
+            players: "12",
+        };
+        let parsed = LogMetadata::from_json(&meta).unwrap();
+        assert_eq!(parsed.id, LogId(3094861));
+        assert_eq!(parsed.num_players, 12);
+        assert_eq!(parsed.date_time.timestamp(), 1640206126);
+
+        meta["players"] = "twelve".into();
+        assert_eq!(LogMetadata::from_json(&meta).unwrap().num_players, 0);
+    }
+
+    #[test]
+    fn non_numeric_strings()
+    {
+        let mut json = fixture_json();
+        json["players"]["[U:1:886717065]"]["class_stats"][0]["kills"] = "abc".into();
+        assert!(matches!(
+            Log::from_json(LogId(3094861), &json),
+            Err(LogParseError::InvalidField("kills"))
+        ));
+        // The typed parser rejects the log just the same.
+        assert!(Log::parse(LogId(3094861), &json.dump()).is_err());
+
+        let mut json = fixture_json();
+        json["teams"]["Red"]["score"] = "three".into();
+        assert!(matches!(
+            Log::from_json(LogId(3094861), &json),
+            Err(LogParseError::InvalidField("score"))
+        ));
+
+        let mut json = fixture_json();
+        json["players"]["[U:1:886717065]"]["class_stats"][0]["type"] = "pilot".into();
+        assert!(matches!(
+            Log::from_json(LogId(3094861), &json),
+            Err(LogParseError::InvalidField("class"))
+        ));
+        assert!(Log::parse(LogId(3094861), &json.dump()).is_err());
+
+        let meta = json::object! {id: "abc", date: 1640206126, map: "cp_sunshine", players: 12};
+        assert!(matches!(
+            LogMetadata::from_json(&meta),
+            Err(LogParseError::InvalidField("id"))
+        ));
+    }
+
+    #[test]
+    fn title_and_uploader()
+    {
+        let log = Log::from_json(LogId(3094861), &fixture_json()).unwrap();
+        assert_eq!(
+            log.meta().title.as_deref(),
+            Some("serveme.tf #1221399 - BLU vs RED")
+        );
+        assert_eq!(
+            log.meta().uploader,
+            Some(SteamID::new_checked(76561197960497430).unwrap())
+        );
+
+        // Search results have the title, but not who has uploaded the log.
+        let mut search_result = json::object! {
+            id: 3094861,
+            title: "serveme.tf #1221399 - BLU vs RED",
+            map: "cp_sunshine",
+            date: 1640206126,
+            views: 56,
+            players: 12,
+        };
+        let meta = LogMetadata::from_json(&search_result).unwrap();
+        assert_eq!(meta.title, log.meta().title);
+        assert_eq!(meta.uploader, None);
+
+        search_result.remove("title");
+        assert_eq!(LogMetadata::from_json(&search_result).unwrap().title, None);
+    }
 }
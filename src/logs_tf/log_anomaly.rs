@@ -0,0 +1,28 @@
+use std::fmt;
+
+use crate::SteamID;
+
+/// Something unusual that has been found and worked around while reading a
+/// log, see [`Log::anomalies`](super::Log::anomalies).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogAnomaly
+{
+    /// The player is listed more than once, under steam ids that are written
+    /// differently but belong to the same account, which happens when players
+    /// rejoin. Their entries have been merged into one.
+    DuplicatePlayer(SteamID),
+}
+
+impl fmt::Display for LogAnomaly
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::DuplicatePlayer(steam_id) => write!(
+                f,
+                "Player {} is listed more than once, their entries have been merged",
+                steam_id.id64()
+            ),
+        }
+    }
+}
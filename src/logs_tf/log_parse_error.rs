@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fmt;
+
+use json::JsonError;
+
+/// Any error that may occur when reading a log that is present as Json text.
+#[derive(Debug)]
+pub enum LogParseError
+{
+    /// The text is not valid Json.
+    Json(JsonError),
+    /// The log does not contain any players, so there are no stats in it.
+    NoPlayers,
+    /// The date of the log is missing or not a timestamp that can be
+    /// represented.
+    InvalidDate,
+    /// A field that is needed to read the stats is missing or does not have a
+    /// valid value, like a number given as text that is not numeric.
+    InvalidField(&'static str),
+    /// A player is listed under a key that is not a steam id.
+    InvalidPlayerId(String),
+    /// The text is not valid json or does not have the structure of a log.
+    /// Only returned by the typed parser of the `serde-parse` feature.
+    #[cfg(feature = "serde-parse")]
+    Typed(serde_json::Error),
+}
+
+impl From<JsonError> for LogParseError
+{
+    fn from(e: JsonError) -> Self { Self::Json(e) }
+}
+
+#[cfg(feature = "serde-parse")]
+impl From<serde_json::Error> for LogParseError
+{
+    fn from(e: serde_json::Error) -> Self { Self::Typed(e) }
+}
+
+impl fmt::Display for LogParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Json(json_e) => write!(f, "The log is not valid json: {}", json_e),
+            Self::NoPlayers => write!(f, "The log does not contain any players"),
+            Self::InvalidDate => write!(f, "The log does not have a valid date"),
+            Self::InvalidField(field) => write!(f, "The log has a missing or invalid {}", field),
+            Self::InvalidPlayerId(id) => write!(f, "The log has an invalid steam id: {}", id),
+            #[cfg(feature = "serde-parse")]
+            Self::Typed(serde_e) => write!(f, "The log could not be read: {}", serde_e),
+        }
+    }
+}
+
+impl Error for LogParseError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            Self::Json(json_e) => Some(json_e),
+            Self::NoPlayers
+            | Self::InvalidDate
+            | Self::InvalidField(_)
+            | Self::InvalidPlayerId(_) => None,
+            #[cfg(feature = "serde-parse")]
+            Self::Typed(serde_e) => Some(serde_e),
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Abstraction over where logs are searched and downloaded from, so that the
+//! database can be updated from something other than the logs.tf API, most
+//! importantly in tests.
+
+use std::thread;
+use std::time::Duration;
+
+use ::log::debug;
+use reqwest::blocking::Client;
+
+use super::search_cache::cached_search;
+use super::search_params::SearchParams;
+use super::{
+    health_check, keep_trying, search_logs_once, Health, Log, LogMetadata, QueryResult,
+    LOGS_TF_API_BASE,
+};
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, RequestKind};
+use crate::LogId;
+
+/// A place logs can be found in and downloaded from.
+pub trait LogSource
+{
+    /// Search for logs matching the `search_params`, newest log first.
+    fn search_logs(&self, search_params: SearchParams) -> QueryResult<Vec<LogMetadata>>;
+
+    /// Download the log with the given id.
+    fn download_log(&self, id: LogId) -> QueryResult<Log>;
+
+    /// Check whether the source can be used right now, like [`health_check`]
+    /// does for logs.tf. Sources that cannot be down are always up.
+    fn health(&self) -> QueryResult<Health> { Ok(Health::Up) }
+
+    /// Search like [`LogSource::search_logs`], but request the logs in pages of
+    /// `page_size` logs, which are handed to `on_page` one after another,
+    /// newest log first. Only one page is held at a time, so long histories do
+    /// not have to fit into memory at once. The limit of the `search_params`
+    /// applies to all pages together. The search stops early if `on_page`
+    /// returns `false`.
+    fn search_logs_paged(
+        &self,
+        search_params: SearchParams,
+        page_size: u16,
+        on_page: &mut dyn FnMut(Vec<LogMetadata>) -> bool,
+    ) -> QueryResult<()>
+    {
+        let page_size = page_size.max(1);
+        let mut remaining = search_params.limit;
+        let mut offset = search_params.offset.unwrap_or(0);
+        loop {
+            let size = match remaining {
+                Some(0) => return Ok(()),
+                Some(remaining) => remaining.min(page_size),
+                None => page_size,
+            };
+            let page =
+                self.search_logs(search_params.clone().add_limit(size).add_offset(offset))?;
+            let num_found = page.len();
+            offset += num_found as u32;
+            if let Some(remaining) = &mut remaining {
+                *remaining = remaining.saturating_sub(num_found as u16);
+            }
+
+            // A page that is not full is the last one.
+            if !on_page(page) || num_found < size as usize {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// User agent sent with requests to logs.tf, so the admins can tell where they
+/// come from.
+pub const DEFAULT_USER_AGENT: &str = concat!("mixes-db/", env!("CARGO_PKG_VERSION"));
+/// Time after which a request to logs.tf is given up on.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the result of a search is reused by default, see
+/// [`LogsTf::search_cache_ttl`].
+pub const DEFAULT_SEARCH_CACHE_TTL: Duration = Duration::from_secs(60);
+/// Time after which logs.tf counts as slow by default, see
+/// [`LogsTf::slow_after`].
+pub const DEFAULT_SLOW_AFTER: Duration = Duration::from_secs(5);
+
+/// The logs.tf API. Failed requests are retried `num_retries` times.
+#[derive(Clone, Debug)]
+pub struct LogsTf
+{
+    pub num_retries:      u8,
+    /// Requests that have not been answered after this time fail with
+    /// [`QueryError::Timeout`](super::QueryError::Timeout).
+    pub timeout:          Duration,
+    pub user_agent:       String,
+    /// Base url of the log API. Only needs to be changed to use a mirror.
+    pub api_base:         String,
+    /// How long the result of a search is reused for the same search, by any
+    /// [`LogsTf`] of the process. A search that is made while the same search
+    /// is still running waits for its result instead of asking logs.tf again.
+    /// `None` to always ask logs.tf.
+    pub search_cache_ttl: Option<Duration>,
+    /// logs.tf is counted as [degraded](super::Degradation::Slow) by a
+    /// [`health_check`] that takes longer than this to be answered.
+    pub slow_after:       Duration,
+}
+
+impl LogsTf
+{
+    /// The logs.tf API with a user agent that includes a way to `contact` the
+    /// operator, like an email address or a website.
+    pub fn with_contact(contact: &str) -> Self
+    {
+        Self {
+            user_agent: format!("{} (+{})", DEFAULT_USER_AGENT, contact),
+            ..Self::default()
+        }
+    }
+
+    /// Create the http client all requests are made with.
+    pub(super) fn client(&self) -> QueryResult<Client>
+    {
+        Ok(Client::builder()
+            .timeout(self.timeout)
+            .user_agent(&self.user_agent)
+            .build()?)
+    }
+}
+
+impl Default for LogsTf
+{
+    fn default() -> Self
+    {
+        Self {
+            num_retries:      5,
+            timeout:          DEFAULT_TIMEOUT,
+            user_agent:       DEFAULT_USER_AGENT.to_owned(),
+            api_base:         LOGS_TF_API_BASE.to_owned(),
+            search_cache_ttl: Some(DEFAULT_SEARCH_CACHE_TTL),
+            slow_after:       DEFAULT_SLOW_AFTER,
+        }
+    }
+}
+
+impl LogSource for LogsTf
+{
+    fn search_logs(&self, search_params: SearchParams) -> QueryResult<Vec<LogMetadata>>
+    {
+        search_params.validate()?;
+        cached_search(
+            &self.api_base,
+            &search_params,
+            self.search_cache_ttl,
+            || {
+                let client = self.client()?;
+                keep_trying(
+                    || {
+                        let result = search_logs_once(&client, &self.api_base, &search_params);
+                        #[cfg(feature = "metrics")]
+                        metrics::record_request(RequestKind::Search, &result);
+                        result
+                    },
+                    self.num_retries,
+                )
+            },
+        )
+    }
+
+    fn download_log(&self, id: LogId) -> QueryResult<Log>
+    {
+        let client = self.client()?;
+        keep_trying(
+            || {
+                let result = Log::download_once(&client, &self.api_base, id);
+                #[cfg(feature = "metrics")]
+                metrics::record_request(RequestKind::Download, &result);
+                result
+            },
+            self.num_retries,
+        )
+    }
+
+    fn health(&self) -> QueryResult<Health> { health_check(self) }
+}
+
+/// A [`LogSource`] that goes easy on another source that is struggling. It
+/// waits before every request and tries failed requests again, doubling the
+/// wait after every failed try.
+pub struct Backoff<'a, S: ?Sized>
+{
+    source:      &'a S,
+    delay:       Duration,
+    num_retries: u8,
+}
+
+impl<'a, S: LogSource + ?Sized> Backoff<'a, S>
+{
+    /// Make the requests to the `source` after waiting for the `delay`, and
+    /// try failed requests up to `num_retries` more times.
+    pub fn new(source: &'a S, delay: Duration, num_retries: u8) -> Self
+    {
+        Self {
+            source,
+            delay,
+            num_retries,
+        }
+    }
+
+    fn with_backoff<R>(&self, action: impl Fn() -> QueryResult<R>) -> QueryResult<R>
+    {
+        let mut delay = self.delay;
+        let mut num_tries: u16 = 0;
+        loop {
+            thread::sleep(delay);
+            let res = action();
+            num_tries += 1;
+
+            if res.is_ok() || num_tries > self.num_retries as u16 {
+                return res;
+            }
+            #[cfg(feature = "metrics")]
+            crate::metrics::record_retry();
+            delay *= 2;
+            debug!("Request failed, trying again in {} ms", delay.as_millis());
+        }
+    }
+}
+
+impl<S: LogSource + ?Sized> LogSource for Backoff<'_, S>
+{
+    fn search_logs(&self, search_params: SearchParams) -> QueryResult<Vec<LogMetadata>>
+    {
+        self.with_backoff(|| self.source.search_logs(search_params.clone()))
+    }
+
+    fn download_log(&self, id: LogId) -> QueryResult<Log>
+    {
+        self.with_backoff(|| self.source.download_log(id))
+    }
+
+    fn health(&self) -> QueryResult<Health> { self.source.health() }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::MockLogSource;
+
+#[cfg(any(test, feature = "test-util"))]
+mod mock
+{
+    use std::cell::{Cell, RefCell};
+
+    use super::LogSource;
+    use crate::logs_tf::search_params::SearchParams;
+    use crate::logs_tf::{Health, Log, LogMetadata, QueryError, QueryResult};
+    use crate::{LogId, SteamID};
+
+    /// Log source that serves logs from memory. Searches only consider the
+    /// player ids, the limit and the offset of the search parameters.
+    #[derive(Default)]
+    pub struct MockLogSource
+    {
+        /// The logs with their players, their json and the number of searches
+        /// after which they can be found.
+        logs:           Vec<(LogMetadata, Vec<SteamID>, String, usize)>,
+        downloaded:     RefCell<Vec<LogId>>,
+        largest_search: Cell<usize>,
+        num_searches:   Cell<usize>,
+        health:         Option<Health>,
+    }
+
+    impl MockLogSource
+    {
+        pub fn new() -> Self { Self::default() }
+
+        /// Add a log with the given metadata, which can be found by searching
+        /// for any of the `players` and is downloaded by parsing the `json`.
+        pub fn add_log(&mut self, meta: LogMetadata, players: Vec<SteamID>, json: String)
+        {
+            self.add_log_after(0, meta, players, json);
+        }
+
+        /// Add a log like [`MockLogSource::add_log`], which can only be found
+        /// once `num_searches` searches have been made, like a log that is
+        /// uploaded during an update. It can always be downloaded.
+        pub fn add_log_after(
+            &mut self,
+            num_searches: usize,
+            meta: LogMetadata,
+            players: Vec<SteamID>,
+            json: String,
+        )
+        {
+            self.logs.push((meta, players, json, num_searches));
+            self.logs
+                .sort_by_key(|(meta, ..)| std::cmp::Reverse(meta.id));
+        }
+
+        /// Ids of all logs that have been downloaded, in the order they have
+        /// been downloaded.
+        pub fn downloaded(&self) -> Vec<LogId> { self.downloaded.borrow().clone() }
+
+        /// The largest number of logs that has been returned by a single
+        /// search.
+        pub fn largest_search(&self) -> usize { self.largest_search.get() }
+
+        /// The number of searches that have been made, counting every page.
+        pub fn num_searches(&self) -> usize { self.num_searches.get() }
+
+        /// Report the `health` when asked for it, instead of being up.
+        pub fn set_health(&mut self, health: Health) { self.health = Some(health); }
+    }
+
+    impl LogSource for MockLogSource
+    {
+        fn search_logs(&self, search_params: SearchParams) -> QueryResult<Vec<LogMetadata>>
+        {
+            let logs: Vec<LogMetadata> = self
+                .logs
+                .iter()
+                .filter(|(_, players, _, after)| {
+                    *after <= self.num_searches.get()
+                        && (search_params.player_ids.is_empty()
+                            || search_params
+                                .player_ids
+                                .iter()
+                                .any(|id| players.contains(id)))
+                })
+                .map(|(meta, ..)| meta.clone())
+                .skip(search_params.offset.unwrap_or(0) as usize)
+                .take(search_params.limit.map(usize::from).unwrap_or(1000))
+                .collect();
+            self.largest_search
+                .set(self.largest_search.get().max(logs.len()));
+            self.num_searches.set(self.num_searches.get() + 1);
+
+            Ok(logs)
+        }
+
+        fn download_log(&self, id: LogId) -> QueryResult<Log>
+        {
+            self.downloaded.borrow_mut().push(id);
+
+            let (_, _, json, _) = self
+                .logs
+                .iter()
+                .find(|(meta, ..)| meta.id == id)
+                .ok_or_else(|| QueryError::Unsuccessful("Log not found".to_owned()))?;
+            let json = json::parse(json)?;
+
+            Log::from_json(id, &json).map_err(QueryError::InvalidLog)
+        }
+
+        fn health(&self) -> QueryResult<Health> { Ok(self.health.clone().unwrap_or(Health::Up)) }
+    }
+}
@@ -1,73 +1,120 @@
+pub mod health;
+pub mod json_num;
+pub mod log_anomaly;
+pub mod log_parse_error;
+pub mod log_source;
+#[cfg(feature = "serde-parse")]
+pub mod model;
 pub mod query_error;
+mod search_cache;
 pub mod search_params;
+pub mod search_params_error;
+pub mod team_summary;
+pub mod team_totals;
+#[cfg(feature = "serde-parse")]
+mod typed_log;
 use std::thread;
 use std::time::Duration;
 
+use ::log::trace;
+pub use health::*;
 use json::JsonValue;
+pub use json_num::*;
+pub use log_anomaly::*;
+pub use log_parse_error::*;
+pub use log_source::*;
 pub use query_error::*;
+pub use search_params_error::*;
+pub use team_summary::*;
+pub use team_totals::*;
 
 pub mod log;
 pub use log::*;
-use reqwest::blocking as reqwest;
+use reqwest::blocking::Client;
 
 use self::search_params::SearchParams;
 
 const LOGS_TF_API_BASE: &str = "https://logs.tf/api/v1/log";
+/// Time to wait before each request to logs.tf.
+const REQUEST_DELAY: Duration = Duration::from_millis(500);
 
 /// Function that tries to execute something that returns a result. If it does
 /// not work the first time, it will keep trying num_retries times until it
 /// either returns Ok() or all the tries have been used up.
-pub(self) fn keep_trying<A, R, E>(action: A, num_retries: u8) -> Result<R, E>
+pub(crate) fn keep_trying<A, R, E>(action: A, num_retries: u8) -> Result<R, E>
 where
     A: Fn() -> Result<R, E>,
 {
-    let mut num_tries = 0;
+    let mut num_tries: u16 = 0;
     loop {
         let res = action();
         num_tries += 1;
 
-        if res.is_ok() || num_tries > num_retries + 1 {
+        if res.is_ok() || num_tries > num_retries as u16 {
             return res;
         }
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_retry();
     }
 }
 
 /// Sleep for a little time before making a request to logs.tf. The API is very
 /// sensitive to quickly making queries to it and will respond with invalid
 /// responses otherwise.
-pub(self) fn log_delay() { thread::sleep(Duration::from_millis(500)) }
+pub(self) fn log_delay()
+{
+    trace!(
+        "Waiting {} ms before the next request to logs.tf",
+        REQUEST_DELAY.as_millis()
+    );
+    thread::sleep(REQUEST_DELAY)
+}
+
+/// The number of characters of a malformed response kept in the error.
+const MAX_RESPONSE_SNIPPET_LEN: usize = 200;
 
 /// Checks for the `"success": true` field in the json value, which is always
 /// set by logs.tf. If `"success": false` is set, it will parse the error and
-/// return a `QueryError`.
+/// return a `QueryError`. If the field is missing or not a boolean, the
+/// response is considered malformed.
 fn check_json_success(json: &JsonValue) -> QueryResult<()>
 {
-    let success = json["success"].as_bool().unwrap();
+    match (json["success"].as_bool(), json["error"].as_str()) {
+        (Some(true), _) => Ok(()),
+        (Some(false), Some(error)) => Err(QueryError::Unsuccessful(error.to_owned())),
+        _ => {
+            let mut snippet = json.dump();
+            if let Some((end, _)) = snippet.char_indices().nth(MAX_RESPONSE_SNIPPET_LEN) {
+                snippet.truncate(end);
+                snippet.push_str("...");
+            }
 
-    if success {
-        Ok(())
-    }
-    else {
-        let error = json["error"].as_str().unwrap();
-        Err(QueryError::Unsuccessful(error.to_owned()))
+            Err(QueryError::MalformedResponse(snippet))
+        },
     }
 }
 
-fn search_logs_once(search_params: &SearchParams) -> QueryResult<Vec<LogMetadata>>
+fn search_logs_once(
+    client: &Client,
+    api_base: &str,
+    search_params: &SearchParams,
+) -> QueryResult<Vec<LogMetadata>>
 {
     log_delay();
 
-    let request = reqwest::Client::builder().build()?.get(LOGS_TF_API_BASE);
-    let request = search_params.add_params_to_request(request);
+    let request = search_params
+        .add_params_to_request(client.get(api_base))
+        .build()?;
 
-    let response = request.send()?;
-    let json = json::parse(&(response.text()?)).unwrap();
+    trace!("Searching logs: GET {}", request.url());
+    let response = client.execute(request)?;
+    let json = json::parse(&(response.text()?))?;
     check_json_success(&json)?;
 
-    Ok(json["logs"]
+    json["logs"]
         .members()
-        .map(|meta| LogMetadata::from_json(&meta))
-        .collect())
+        .map(|meta| LogMetadata::from_json(meta).map_err(QueryError::InvalidLog))
+        .collect()
 }
 
 /// Query logs.tf for logs with the given parameters. Takes a number of retries.
@@ -78,5 +125,262 @@ fn search_logs_once(search_params: &SearchParams) -> QueryResult<Vec<LogMetadata
 /// The metadata of all logs that fit the search parameters
 pub fn search_logs(search_params: SearchParams, num_retries: u8) -> QueryResult<Vec<LogMetadata>>
 {
-    keep_trying(|| search_logs_once(&search_params), num_retries)
+    LogsTf {
+        num_retries,
+        ..LogsTf::default()
+    }
+    .search_logs(search_params)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::cell::Cell;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{self, Receiver};
+
+    use super::*;
+    use crate::{LogId, SteamID};
+
+    /// What the server has received of a request.
+    struct Request
+    {
+        /// The path and query of the request.
+        target:     String,
+        user_agent: String,
+    }
+
+    /// Start a server that answers the requests it receives in order with the
+    /// given responses, or never answers them if the response is `None`.
+    /// Sends each request it has received.
+    fn serve(responses: Vec<Option<&'static str>>) -> (String, Receiver<Request>)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut unanswered: Vec<TcpStream> = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut lines = BufReader::new(&stream).lines().map(Result::unwrap);
+                let request_line = lines.next().unwrap_or_default();
+                let target = request_line
+                    .split(' ')
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_owned();
+                let user_agent = lines
+                    .take_while(|line| !line.is_empty())
+                    .find_map(|line| line.strip_prefix("user-agent: ").map(str::to_owned))
+                    .unwrap_or_default();
+                sender.send(Request { target, user_agent }).unwrap();
+
+                match response {
+                    Some(body) => write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .unwrap(),
+                    None => unanswered.push(stream),
+                }
+            }
+
+            // Keep the unanswered connections open until the client gives up.
+            thread::sleep(Duration::from_secs(2));
+        });
+
+        (address, receiver)
+    }
+
+    fn test_source(api_base: String, num_retries: u8) -> LogsTf
+    {
+        LogsTf {
+            num_retries,
+            timeout: Duration::from_millis(200),
+            api_base,
+            ..LogsTf::with_contact("admin@example.com")
+        }
+    }
+
+    #[test]
+    fn timeouts_are_retried()
+    {
+        let (api_base, requests) = serve(vec![None, Some(r#"{"success":true,"logs":[]}"#)]);
+
+        let logs = test_source(api_base, 1)
+            .search_logs(SearchParams::limit(1))
+            .unwrap();
+        assert!(logs.is_empty());
+
+        let user_agents: Vec<String> = requests.try_iter().map(|r| r.user_agent).collect();
+        assert_eq!(user_agents.len(), 2);
+        assert_eq!(
+            user_agents[0],
+            format!(
+                "mixes-db/{} (+admin@example.com)",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn search_several_players()
+    {
+        let (api_base, requests) = serve(vec![Some(r#"{"success":true,"logs":[]}"#)]);
+
+        let players = vec![SteamID::new_checked(76561197960265729).unwrap()];
+        let search_params = SearchParams::player_ids(players)
+            .add_player_id(SteamID::new_checked(76561197960265730).unwrap())
+            .add_limit(10);
+        test_source(api_base, 0).search_logs(search_params).unwrap();
+
+        assert_eq!(
+            requests.recv().unwrap().target,
+            "/?player=76561197960265729%2C76561197960265730&limit=10"
+        );
+    }
+
+    #[test]
+    fn identical_searches_are_coalesced()
+    {
+        const EMPTY: &str = r#"{"success":true,"logs":[]}"#;
+        let (api_base, requests) = serve(vec![Some(EMPTY); 3]);
+        let source = test_source(api_base, 0);
+        let medic = SteamID::new_checked(76561198031286581).unwrap();
+        let scout = SteamID::new_checked(76561198847982793).unwrap();
+
+        // Concurrent searches wait for the one that is running.
+        let searches: Vec<_> = (0..3)
+            .map(|_| {
+                let source = source.clone();
+                thread::spawn(move || source.search_logs(SearchParams::player_id(medic)))
+            })
+            .collect();
+        for search in searches {
+            assert!(search.join().unwrap().unwrap().is_empty());
+        }
+        assert_eq!(
+            requests.recv().unwrap().target,
+            "/?player=76561198031286581"
+        );
+        assert!(requests.recv_timeout(Duration::from_millis(700)).is_err());
+
+        // The order of the players does not matter, but other parameters do.
+        let reordered = SearchParams::player_ids(vec![scout, medic]);
+        source.search_logs(reordered.clone()).unwrap();
+        source
+            .search_logs(SearchParams::player_ids(vec![medic, scout, medic]))
+            .unwrap();
+        source.search_logs(reordered.add_limit(5)).unwrap();
+        let targets: Vec<String> = requests.try_iter().map(|r| r.target).collect();
+        assert_eq!(
+            targets,
+            vec![
+                "/?player=76561198847982793%2C76561198031286581",
+                "/?player=76561198847982793%2C76561198031286581&limit=5"
+            ]
+        );
+    }
+
+    #[test]
+    fn search_cache_disabled()
+    {
+        const EMPTY: &str = r#"{"success":true,"logs":[]}"#;
+        let (api_base, requests) = serve(vec![Some(EMPTY), Some(EMPTY)]);
+        let source = LogsTf {
+            search_cache_ttl: None,
+            ..test_source(api_base, 0)
+        };
+
+        source.search_logs(SearchParams::limit(1)).unwrap();
+        source.search_logs(SearchParams::limit(1)).unwrap();
+        assert_eq!(requests.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn timeout_error()
+    {
+        let (api_base, requests) = serve(vec![None]);
+
+        let result = test_source(api_base, 0).download_log(LogId(1));
+        assert!(matches!(result, Err(QueryError::Timeout(_))));
+        assert_eq!(requests.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn json_success()
+    {
+        assert!(check_json_success(&json::object! { success: true, logs: [] }).is_ok());
+        assert!(matches!(
+            check_json_success(&json::object! { success: false, error: "Log not found" }),
+            Err(QueryError::Unsuccessful(error)) if error == "Log not found"
+        ));
+
+        for json in [
+            json::object! { error: "Service unavailable" },
+            json::object! { success: null },
+            json::object! { success: "true" },
+            json::object! { success: false },
+            json::JsonValue::new_array(),
+        ] {
+            assert!(
+                matches!(
+                    check_json_success(&json),
+                    Err(QueryError::MalformedResponse(_))
+                ),
+                "{}",
+                json
+            );
+        }
+
+        let long = json::object! { message: "a".repeat(1000) };
+        match check_json_success(&long) {
+            Err(QueryError::MalformedResponse(snippet)) => {
+                assert_eq!(snippet.chars().count(), MAX_RESPONSE_SNIPPET_LEN + 3);
+                assert!(snippet.starts_with("{\"message\":\"aaa"));
+            },
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn malformed_responses_are_retried()
+    {
+        let num_tries = Cell::new(0);
+        let result = keep_trying(
+            || {
+                num_tries.set(num_tries.get() + 1);
+                match num_tries.get() {
+                    1 => Err(QueryError::MalformedResponse(String::new())),
+                    _ => Ok(()),
+                }
+            },
+            5,
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(num_tries.get(), 2);
+    }
+
+    #[test]
+    fn retries_are_limited()
+    {
+        for num_retries in [0, 2, u8::MAX] {
+            let num_tries = Cell::new(0);
+            let result: Result<(), ()> = keep_trying(
+                || {
+                    num_tries.set(num_tries.get() + 1);
+                    Err(())
+                },
+                num_retries,
+            );
+
+            assert!(result.is_err());
+            assert_eq!(num_tries.get(), num_retries as u32 + 1);
+        }
+    }
 }
@@ -0,0 +1,571 @@
+//! Typed model of a log as it is returned by the logs.tf API, to be read with
+//! serde. Only the parts of the log that are of interest are modelled,
+//! everything else is skipped while reading. Values that may be missing in
+//! older or broken logs default to zero or empty, and numbers may be given as
+//! strings, just like when reading them from a json document. Turn a model
+//! into a [`Log`](super::Log) with [`Log::from_typed`](super::Log::from_typed).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use serde::Deserialize;
+
+use crate::score::Team;
+use crate::{Class, SteamID};
+
+/// A whole log. The players are keyed by their steam id, which is written as
+/// a steamID3 in the log. They are kept in the order they are listed, so that
+/// players listed more than once can be merged like [`Log::from_json`] does.
+///
+/// [`Log::from_json`]: crate::Log::from_json
+#[derive(Clone, Debug, Deserialize)]
+pub struct LogModel
+{
+    pub info:       Info,
+    pub teams:      Teams,
+    #[serde(deserialize_with = "steam_id_entries")]
+    pub players:    Vec<(SteamID, Player)>,
+    /// The names of every player that has taken part.
+    #[serde(default, deserialize_with = "steam_id_keys")]
+    pub names:      HashMap<SteamID, String>,
+    /// The healing every medic has done to each of their teammates.
+    #[serde(default, deserialize_with = "heal_spread")]
+    pub healspread: HashMap<SteamID, HashMap<SteamID, u32>>,
+    #[serde(default)]
+    pub chat:       Vec<ChatEntry>,
+    #[serde(default)]
+    pub rounds:     Vec<Round>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Info
+{
+    pub map:          String,
+    /// When the log has been uploaded, as a unix timestamp.
+    #[serde(deserialize_with = "number")]
+    pub date:         u32,
+    /// The length of the game in seconds. Missing in older logs.
+    #[serde(default, deserialize_with = "optional_number")]
+    pub total_length: Option<u32>,
+    #[serde(default)]
+    pub title:        Option<String>,
+    #[serde(default)]
+    pub uploader:     Option<Uploader>,
+}
+
+/// Who has uploaded the log.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Uploader
+{
+    /// The steamID64 of the uploader, as a string.
+    #[serde(default)]
+    pub id:   Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The plugin the log has been uploaded with.
+    #[serde(default)]
+    pub info: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Teams
+{
+    #[serde(rename = "Red")]
+    pub red:  TeamStats,
+    #[serde(rename = "Blue")]
+    pub blue: TeamStats,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TeamStats
+{
+    #[serde(deserialize_with = "number")]
+    pub score:     u8,
+    #[serde(default, deserialize_with = "number")]
+    pub kills:     u32,
+    #[serde(default, deserialize_with = "number")]
+    pub deaths:    u32,
+    #[serde(default, deserialize_with = "number")]
+    pub dmg:       u32,
+    #[serde(default, deserialize_with = "number")]
+    pub charges:   u32,
+    #[serde(default, deserialize_with = "number")]
+    pub drops:     u32,
+    #[serde(default, deserialize_with = "number")]
+    pub firstcaps: u32,
+    #[serde(default, deserialize_with = "number")]
+    pub caps:      u32,
+}
+
+/// Stats of a player over the whole game. The totals are wide enough for any
+/// value in a broken log, they are narrowed when turned into performances.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Player
+{
+    #[serde(deserialize_with = "team")]
+    pub team:          Team,
+    pub class_stats:   Vec<ClassStats>,
+    #[serde(default, deserialize_with = "number")]
+    pub kills:         u64,
+    #[serde(default, deserialize_with = "number")]
+    pub deaths:        u64,
+    #[serde(default, deserialize_with = "number")]
+    pub assists:       u64,
+    #[serde(default, deserialize_with = "number")]
+    pub suicides:      u64,
+    #[serde(default, deserialize_with = "number")]
+    pub dmg:           u64,
+    #[serde(default, deserialize_with = "number")]
+    pub dmg_real:      u64,
+    #[serde(default, deserialize_with = "number")]
+    pub dt:            u64,
+    #[serde(default, deserialize_with = "number")]
+    pub dt_real:       u64,
+    /// Healing received.
+    #[serde(default, deserialize_with = "number")]
+    pub hr:            u64,
+    /// Healing done, only for medics.
+    #[serde(default, deserialize_with = "number")]
+    pub heal:          u64,
+    #[serde(default, deserialize_with = "number")]
+    pub ubers:         u64,
+    #[serde(default)]
+    pub ubertypes:     UberTypes,
+    #[serde(default, deserialize_with = "number")]
+    pub drops:         u64,
+    #[serde(default, deserialize_with = "number")]
+    pub medkits:       u64,
+    #[serde(default, deserialize_with = "number")]
+    pub medkits_hp:    u64,
+    #[serde(default, deserialize_with = "number")]
+    pub backstabs:     u64,
+    #[serde(default, deserialize_with = "number")]
+    pub headshots:     u64,
+    #[serde(default, deserialize_with = "number")]
+    pub headshots_hit: u64,
+    #[serde(default, deserialize_with = "number")]
+    pub sentries:      u64,
+    /// Control points captured.
+    #[serde(default, deserialize_with = "number")]
+    pub cpc:           u64,
+    /// Only present for players that have played medic.
+    #[serde(default)]
+    pub medicstats:    Option<MedicStats>,
+}
+
+/// Stats of a player on a single class.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClassStats
+{
+    #[serde(rename = "type", deserialize_with = "class")]
+    pub class:      Class,
+    #[serde(default, deserialize_with = "number")]
+    pub kills:      u16,
+    #[serde(default, deserialize_with = "number")]
+    pub assists:    u16,
+    #[serde(default, deserialize_with = "number")]
+    pub deaths:     u16,
+    #[serde(default, deserialize_with = "number")]
+    pub dmg:        u32,
+    #[serde(default, deserialize_with = "number")]
+    pub total_time: u32,
+    /// Sentry kills of an engineer, only present in some logs.
+    #[serde(default, deserialize_with = "optional_number")]
+    pub sentries:   Option<u64>,
+    /// The stats of each weapon used on the class, keyed by the name of the
+    /// weapon. Not present in older logs.
+    #[serde(default)]
+    pub weapon:     Option<HashMap<String, WeaponStats>>,
+}
+
+/// Stats of a player with a single weapon.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WeaponStats
+{
+    #[serde(default, deserialize_with = "number")]
+    pub kills: u64,
+}
+
+/// The number of ubers of each kind a medic has used.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct UberTypes
+{
+    #[serde(default, deserialize_with = "number")]
+    pub medigun:    u8,
+    #[serde(default, deserialize_with = "number")]
+    pub kritzkrieg: u8,
+    #[serde(default, deserialize_with = "number")]
+    pub quickfix:   u8,
+    #[serde(default, deserialize_with = "number")]
+    pub vaccinator: u8,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MedicStats
+{
+    #[serde(default, deserialize_with = "optional_number")]
+    pub avg_uber_length:              Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub advantages_lost:              u8,
+    #[serde(default, deserialize_with = "number")]
+    pub biggest_advantage_lost:       f64,
+    #[serde(default, deserialize_with = "number")]
+    pub deaths_with_95_99_uber:       u8,
+    #[serde(default, deserialize_with = "number")]
+    pub deaths_within_20s_after_uber: u8,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub avg_time_before_healing:      Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub avg_time_to_build:            Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub avg_time_before_using:        Option<f64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Round
+{
+    #[serde(default, deserialize_with = "number")]
+    pub length: u32,
+    /// `None` if the round has not been won by either team.
+    #[serde(default, deserialize_with = "optional_team")]
+    pub winner: Option<Team>,
+}
+
+/// A message in the chat. The steam id is not a valid one for messages of the
+/// server, like `Console`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChatEntry
+{
+    #[serde(default)]
+    pub steamid: Option<String>,
+    #[serde(default)]
+    pub name:    Option<String>,
+    #[serde(default)]
+    pub msg:     Option<String>,
+}
+
+/// Visitor for values that are read from a string without keeping it around,
+/// so the string does not have to be allocated.
+struct FromStrVisitor<T>(&'static str, fn(&str) -> Option<T>);
+
+impl<'de, T> Visitor<'de> for FromStrVisitor<T>
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E>
+    {
+        (self.1)(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+/// A steam id used as a key in the log, in the steamID3 format.
+struct PlayerId(SteamID);
+
+/// A team as it is named in the log.
+struct TeamName(Team);
+
+/// A json object keyed by steam ids, as its entries in order.
+struct SteamIdMap<V>(Vec<(SteamID, V)>);
+
+/// A number that may also be given as a string, like `"1618"`, which some
+/// logs re-exported by other tools do. The string is trimmed before it is
+/// parsed, just like by [`json_num_u32`](super::json_num_u32).
+struct Number<T>(T);
+
+impl<'de> Deserialize<'de> for PlayerId
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_str(FromStrVisitor("a steam id", |s| {
+            SteamID::from_str(s).ok().map(PlayerId)
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for TeamName
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        deserializer.deserialize_str(FromStrVisitor("a team", |s| {
+            Team::from_str(s).ok().map(TeamName)
+        }))
+    }
+}
+
+impl<'de, T: Deserialize<'de> + FromStr> Deserialize<'de> for Number<T>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        struct NumberVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de> + FromStr> Visitor<'de> for NumberVisitor<T>
+        {
+            type Value = T;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+            {
+                write!(f, "a number or a string of a number")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E>
+            {
+                T::deserialize(v.into_deserializer())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E>
+            {
+                T::deserialize(v.into_deserializer())
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E>
+            {
+                T::deserialize(v.into_deserializer())
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E>
+            {
+                v.trim()
+                    .parse()
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer
+            .deserialize_any(NumberVisitor(PhantomData))
+            .map(Number)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for SteamIdMap<V>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        struct MapVisitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for MapVisitor<V>
+        {
+            type Value = SteamIdMap<V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+            {
+                write!(f, "an object keyed by steam ids")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error>
+            {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some((PlayerId(steam_id), value)) = map.next_entry()? {
+                    entries.push((steam_id, value));
+                }
+
+                Ok(SteamIdMap(entries))
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+fn number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+{
+    Number::deserialize(deserializer).map(|number| number.0)
+}
+
+fn optional_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+{
+    Option::<Number<T>>::deserialize(deserializer).map(|number| number.map(|number| number.0))
+}
+
+fn steam_id_keys<'de, D, V>(deserializer: D) -> Result<HashMap<SteamID, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    SteamIdMap::deserialize(deserializer).map(|map| map.0.into_iter().collect())
+}
+
+fn steam_id_entries<'de, D, V>(deserializer: D) -> Result<Vec<(SteamID, V)>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    SteamIdMap::deserialize(deserializer).map(|map| map.0)
+}
+
+fn heal_spread<'de, D>(deserializer: D) -> Result<HashMap<SteamID, HashMap<SteamID, u32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let heal_spread: SteamIdMap<SteamIdMap<Number<u32>>> = SteamIdMap::deserialize(deserializer)?;
+
+    Ok(heal_spread
+        .0
+        .into_iter()
+        .map(|(medic, targets)| {
+            let targets = targets
+                .0
+                .into_iter()
+                .map(|(target, healing)| (target, healing.0));
+            (medic, targets.collect())
+        })
+        .collect())
+}
+
+fn class<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Class, D::Error>
+{
+    deserializer.deserialize_str(FromStrVisitor("a class", |s| Class::from_str(s).ok()))
+}
+
+fn team<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Team, D::Error>
+{
+    TeamName::deserialize(deserializer).map(|team| team.0)
+}
+
+fn optional_team<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Team>, D::Error>
+{
+    Option::<TeamName>::deserialize(deserializer).map(|team| team.map(|team| team.0))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const TEST_LOG: &str = include_str!("../../test_data/log_3094861.json");
+
+    fn steam_id(id3: &str) -> SteamID { SteamID::from_str(id3).unwrap() }
+
+    /// The first entry of the player in the `model`.
+    fn player<'a>(model: &'a LogModel, id3: &str) -> &'a Player
+    {
+        let steam_id = steam_id(id3);
+        model
+            .players
+            .iter()
+            .find(|(player, _)| *player == steam_id)
+            .map(|(_, player)| player)
+            .unwrap()
+    }
+
+    #[test]
+    fn read_fixture()
+    {
+        let model: LogModel = serde_json::from_str(TEST_LOG).unwrap();
+
+        assert_eq!(model.info.map, "cp_sunshine");
+        assert_eq!(model.info.date, 1640206126);
+        assert_eq!(model.info.total_length, Some(1738));
+        assert_eq!((model.teams.red.score, model.teams.blue.score), (3, 3));
+        assert_eq!((model.teams.red.dmg, model.teams.blue.dmg), (41060, 41358));
+        assert_eq!(model.players.len(), 12);
+        assert_eq!(model.names.len(), 12);
+        assert_eq!(model.chat.len(), 123);
+        assert_eq!(
+            model
+                .rounds
+                .iter()
+                .map(|round| round.winner)
+                .collect::<Vec<_>>(),
+            [
+                Some(Team::Blue),
+                Some(Team::Red),
+                Some(Team::Blue),
+                Some(Team::Blue),
+                Some(Team::Red),
+                Some(Team::Red),
+                None,
+            ]
+        );
+
+        let medic = player(&model, "[U:1:71020853]");
+        assert_eq!(medic.team, Team::Blue);
+        assert_eq!(medic.heal, 22732);
+        assert_eq!(medic.ubertypes.medigun, 12);
+        assert_eq!(medic.class_stats.len(), 1);
+        assert_eq!(medic.class_stats[0].class, Class::Medic);
+        assert_eq!(medic.class_stats[0].total_time, 1738);
+        let medicstats = medic.medicstats.as_ref().unwrap();
+        assert_eq!(medicstats.avg_uber_length, Some(6.875));
+        assert_eq!(medicstats.advantages_lost, 1);
+
+        let heal_targets = &model.healspread[&steam_id("[U:1:71020853]")];
+        assert_eq!(heal_targets.len(), 5);
+        assert_eq!(heal_targets[&steam_id("[U:1:886717065]")], 4934);
+        assert_eq!(
+            model.names[&steam_id("[U:1:178408897]")],
+            "autunno".to_owned()
+        );
+    }
+
+    #[test]
+    fn missing_values_default()
+    {
+        let model: LogModel = serde_json::from_str(
+            r#"{
+                "info": {"map": "koth_product_final", "date": 1000},
+                "teams": {"Red": {"score": 2}, "Blue": {"score": 4}},
+                "players": {
+                    "[U:1:886717065]": {"team": "Blue", "class_stats": [{"type": "scout"}]}
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(model.info.total_length, None);
+        assert!(model.names.is_empty());
+        assert!(model.healspread.is_empty());
+        let scout = player(&model, "[U:1:886717065]");
+        assert_eq!(scout.dmg, 0);
+        assert_eq!(scout.class_stats[0].total_time, 0);
+        assert!(scout.medicstats.is_none());
+    }
+
+    #[test]
+    fn numbers_given_as_strings()
+    {
+        let mut json = json::parse(TEST_LOG).unwrap();
+        json["info"]["date"] = "1640206126".into();
+        json["info"]["total_length"] = " 1738 ".into();
+        json["teams"]["Red"]["score"] = "3".into();
+        let medic = &mut json["players"]["[U:1:71020853]"];
+        medic["heal"] = "22732".into();
+        medic["class_stats"][0]["total_time"] = "1738".into();
+        medic["medicstats"]["avg_uber_length"] = "6.875".into();
+
+        let model: LogModel = serde_json::from_str(&json.dump()).unwrap();
+        assert_eq!(model.info.date, 1640206126);
+        assert_eq!(model.info.total_length, Some(1738));
+        assert_eq!(model.teams.red.score, 3);
+        let medic = player(&model, "[U:1:71020853]");
+        assert_eq!(medic.heal, 22732);
+        assert_eq!(medic.class_stats[0].total_time, 1738);
+        let medicstats = medic.medicstats.as_ref().unwrap();
+        assert_eq!(medicstats.avg_uber_length, Some(6.875));
+
+        // Strings that are not numbers of the type are still rejected.
+        for total_time in ["", "a lot", "-5"] {
+            json["players"]["[U:1:71020853]"]["class_stats"][0]["total_time"] = total_time.into();
+            assert!(serde_json::from_str::<LogModel>(&json.dump()).is_err());
+        }
+    }
+
+    #[test]
+    fn reject_invalid_steam_ids()
+    {
+        let mut json = json::parse(TEST_LOG).unwrap();
+        json["players"]["medic"] = json["players"]["[U:1:71020853]"].clone();
+
+        assert!(serde_json::from_str::<LogModel>(&json.dump()).is_err());
+    }
+}
@@ -4,6 +4,8 @@ use std::fmt;
 use json::JsonError;
 use reqwest::Error as HttpError;
 
+use super::{LogParseError, SearchParamsError};
+
 /// Any error that may occur when querying data from logs.tf
 #[derive(Debug)]
 pub enum QueryError
@@ -11,6 +13,8 @@ pub enum QueryError
     /// An error that can occur when the connection to logs.tf is unstable or
     /// the service is down.
     HttpResponse(HttpError),
+    /// logs.tf has not answered in time. The query should be tried again.
+    Timeout(HttpError),
     /// If for whatever reason an invalid Json file is returned by logs.tf or it
     /// is corrupted.
     JsonParseError(JsonError),
@@ -18,18 +22,41 @@ pub enum QueryError
     /// `"success": false` to let the other party know if the query succeeded.
     /// If it is false, this error is returned.
     Unsuccessful(String),
+    /// The response is valid Json, but does not say whether the query
+    /// succeeded, which happens when logs.tf is under maintenance. Contains the
+    /// beginning of the response. The query should be tried again later.
+    MalformedResponse(String),
+    /// logs.tf has returned a log that could not be read, for instance because
+    /// it does not have the structure of a log or its stats are not numbers.
+    /// Also returned for a log listed in the results of a search that could
+    /// not be read.
+    InvalidLog(LogParseError),
+    /// The search does not make sense, so it has not been made.
+    InvalidParams(SearchParamsError),
 }
 
 pub type QueryResult<T> = Result<T, QueryError>;
 
 impl From<HttpError> for QueryError
 {
-    fn from(e: HttpError) -> Self { Self::HttpResponse(e) }
+    fn from(e: HttpError) -> Self
+    {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        }
+        else {
+            Self::HttpResponse(e)
+        }
+    }
 }
 impl From<JsonError> for QueryError
 {
     fn from(e: JsonError) -> Self { Self::JsonParseError(e) }
 }
+impl From<SearchParamsError> for QueryError
+{
+    fn from(e: SearchParamsError) -> Self { Self::InvalidParams(e) }
+}
 
 impl fmt::Display for QueryError
 {
@@ -39,6 +66,7 @@ impl fmt::Display for QueryError
             &Self::HttpResponse(http_e) => {
                 write!(f, "An error occured contacting logs.tf: {}", http_e)
             },
+            &Self::Timeout(http_e) => write!(f, "logs.tf did not answer in time: {}", http_e),
             &Self::JsonParseError(json_e) => {
                 write!(f, "logs.tf did not return valid json: {}", json_e)
             },
@@ -49,8 +77,31 @@ impl fmt::Display for QueryError
                     e
                 )
             },
+            &Self::MalformedResponse(response) => {
+                write!(f, "logs.tf returned an unexpected response: {}", response)
+            },
+            &Self::InvalidLog(parse_e) => {
+                write!(
+                    f,
+                    "logs.tf returned a log that could not be read: {}",
+                    parse_e
+                )
+            },
+            &Self::InvalidParams(params_e) => write!(f, "Invalid search: {}", params_e),
         }
     }
 }
 
-impl Error for QueryError {}
+impl Error for QueryError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            Self::HttpResponse(http_e) | Self::Timeout(http_e) => Some(http_e),
+            Self::JsonParseError(json_e) => Some(json_e),
+            Self::InvalidLog(parse_e) => Some(parse_e),
+            Self::InvalidParams(params_e) => Some(params_e),
+            Self::Unsuccessful(_) | Self::MalformedResponse(_) => None,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Cache of the results of searches on logs.tf, shared by the whole process.
+//! When several parts of a program, like the commands of a bot handled at the
+//! same time, make the same search within a short time, logs.tf is only asked
+//! once. Searches that are made while the same search is still running wait for
+//! its result instead of making their own request.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::search_params::SearchParams;
+use super::{LogMetadata, QueryResult};
+
+/// The result of a search, once it has finished. `None` if it has failed, in
+/// which case it is not reused.
+type Entry = Arc<OnceLock<Option<(Instant, Vec<LogMetadata>)>>>;
+
+/// The searches by the API they have been made on and their canonical
+/// parameters.
+static SEARCHES: OnceLock<Mutex<HashMap<(String, SearchParams), Entry>>> = OnceLock::new();
+
+/// Run the `search` for the `search_params` on the API at `api_base`, unless
+/// the same search has finished successfully less than `ttl` ago or is
+/// running right now. In that case, its result is returned instead. Without a
+/// `ttl`, the search is always run.
+pub(super) fn cached_search(
+    api_base: &str,
+    search_params: &SearchParams,
+    ttl: Option<Duration>,
+    search: impl Fn() -> QueryResult<Vec<LogMetadata>>,
+) -> QueryResult<Vec<LogMetadata>>
+{
+    let ttl = match ttl {
+        Some(ttl) => ttl,
+        None => return search(),
+    };
+
+    let entry = {
+        let mut searches = SEARCHES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Failed and outdated searches are never used again.
+        searches.retain(|_, entry| match entry.get() {
+            Some(Some((finished, _))) => finished.elapsed() < ttl,
+            Some(None) => false,
+            None => true,
+        });
+
+        let key = (api_base.to_owned(), search_params.canonicalized());
+        Arc::clone(searches.entry(key).or_default())
+    };
+
+    // Only one of the callers runs the search, the others wait for it to finish.
+    let mut error = None;
+    let result = entry.get_or_init(|| match search() {
+        Ok(logs) => Some((Instant::now(), logs)),
+        Err(e) => {
+            error = Some(e);
+            None
+        },
+    });
+
+    match (result, error) {
+        (_, Some(e)) => Err(e),
+        (Some((_, logs)), None) => Ok(logs.clone()),
+        // The search that has been waited for has failed, so try again without
+        // relying on anyone else.
+        (None, None) => search(),
+    }
+}
@@ -2,49 +2,68 @@ use std::cmp;
 
 use reqwest::blocking::RequestBuilder;
 
+use super::SearchParamsError;
 use crate::SteamID;
 
+/// The largest number of logs logs.tf returns for a search.
+const MAX_LIMIT: u16 = 10000;
+/// The longest title logs.tf searches for, in characters.
+const MAX_TITLE_LEN: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct SearchParams
 {
-    pub player_id: Option<SteamID>,
-    pub title:     Option<String>,
-    pub limit:     Option<u16>,
+    /// Only logs that contain any of the players are found. Empty to not
+    /// filter by player.
+    pub player_ids: Vec<SteamID>,
+    pub title:      Option<String>,
+    pub limit:      Option<u16>,
+    /// The number of matching logs to skip, to get the logs page by page.
+    pub offset:     Option<u32>,
 }
 
 impl SearchParams
 {
-    pub fn player_id(id: SteamID) -> Self
+    pub fn player_id(id: SteamID) -> Self { Self::player_ids(vec![id]) }
+
+    /// Search for the logs that contain any of the players, so the logs of
+    /// several players can be found with a single request.
+    pub fn player_ids(ids: Vec<SteamID>) -> Self
     {
         Self {
-            player_id: Some(id),
-            title:     None,
-            limit:     None,
+            player_ids: ids,
+            title:      None,
+            limit:      None,
+            offset:     None,
         }
     }
 
     pub fn log_title(title: String) -> Self
     {
         Self {
-            player_id: None,
-            title:     Some(title),
-            limit:     None,
+            player_ids: Vec::new(),
+            title:      Some(title),
+            limit:      None,
+            offset:     None,
         }
     }
 
     pub fn limit(limit: u16) -> Self
     {
-        let limit = cmp::min(limit, 10000);
+        let limit = cmp::min(limit, MAX_LIMIT);
 
         Self {
-            player_id: None,
-            title:     None,
-            limit:     Some(limit),
+            player_ids: Vec::new(),
+            title:      None,
+            limit:      Some(limit),
+            offset:     None,
         }
     }
 
+    /// Also find the logs that contain the player.
     pub fn add_player_id(mut self, id: SteamID) -> Self
     {
-        self.player_id.replace(id);
+        self.player_ids.push(id);
         self
     }
 
@@ -56,19 +75,79 @@ impl SearchParams
 
     pub fn add_limit(mut self, limit: u16) -> Self
     {
-        self.limit.replace(cmp::min(limit, 10000));
+        self.limit.replace(cmp::min(limit, MAX_LIMIT));
+        self
+    }
+
+    pub fn add_offset(mut self, offset: u32) -> Self
+    {
+        self.offset.replace(offset);
         self
     }
 
+    /// The same search with the player ids sorted and without duplicates and
+    /// the title trimmed, so searches that find the same logs compare as
+    /// equal.
+    pub fn canonicalized(&self) -> Self
+    {
+        let mut canonical = self.clone();
+        canonical.player_ids.sort_unstable_by_key(|id| id.id64());
+        canonical.player_ids.dedup();
+        if let Some(title) = &mut canonical.title {
+            *title = title.trim().to_owned();
+        }
+        canonical
+    }
+
+    /// Check that the search makes sense before it is made. The limit must be
+    /// between 1 and 10000, and the title, without surrounding whitespace,
+    /// must not be empty or longer than 100 characters. Players, a title or a
+    /// limit must be given, so a search never asks for all logs; a limit on
+    /// its own is meant to find the most recent logs. Players and a title are
+    /// never searched for together.
+    pub fn validate(&self) -> Result<(), SearchParamsError>
+    {
+        match self.limit {
+            Some(limit) if !(1..=MAX_LIMIT).contains(&limit) => {
+                return Err(SearchParamsError::LimitOutOfRange(limit));
+            },
+            _ => {},
+        }
+        if let Some(title) = &self.title {
+            let len = title.trim().chars().count();
+            if len == 0 {
+                return Err(SearchParamsError::EmptyTitle);
+            }
+            if len > MAX_TITLE_LEN {
+                return Err(SearchParamsError::TitleTooLong(len));
+            }
+        }
+        if self.player_ids.is_empty() && self.title.is_none() && self.limit.is_none() {
+            return Err(SearchParamsError::NoFilter);
+        }
+        if !self.player_ids.is_empty() && self.title.is_some() {
+            return Err(SearchParamsError::PlayersAndTitle);
+        }
+
+        Ok(())
+    }
+
     pub fn add_params_to_request(&self, request_builder: RequestBuilder) -> RequestBuilder
     {
-        let request_builder = match &self.player_id {
-            Some(id) => request_builder.query(&[("player", &id.to_id64_string())]),
-            None => request_builder,
+        let request_builder = if self.player_ids.is_empty() {
+            request_builder
+        }
+        else {
+            let ids: Vec<String> = self
+                .player_ids
+                .iter()
+                .map(|id| id.to_id64_string())
+                .collect();
+            request_builder.query(&[("player", &ids.join(","))])
         };
 
         let request_builder = match &self.title {
-            Some(name) => request_builder.query(&[("title", &name)]),
+            Some(name) => request_builder.query(&[("title", name.trim())]),
             None => request_builder,
         };
 
@@ -77,6 +156,98 @@ impl SearchParams
             None => request_builder,
         };
 
+        let request_builder = match &self.offset {
+            Some(offset) => request_builder.query(&[("offset", &offset.to_string())]),
+            None => request_builder,
+        };
+
         request_builder
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn scout() -> SteamID { SteamID::new_checked(76561198847982793).unwrap() }
+
+    #[test]
+    fn validate_limit()
+    {
+        assert_eq!(SearchParams::limit(1).validate(), Ok(()));
+        assert_eq!(SearchParams::limit(20000).validate(), Ok(()));
+        assert_eq!(
+            SearchParams::limit(0).validate(),
+            Err(SearchParamsError::LimitOutOfRange(0))
+        );
+        let too_many = SearchParams {
+            limit: Some(10001),
+            ..SearchParams::player_id(scout())
+        };
+        assert_eq!(
+            too_many.validate(),
+            Err(SearchParamsError::LimitOutOfRange(10001))
+        );
+    }
+
+    #[test]
+    fn validate_title()
+    {
+        assert_eq!(
+            SearchParams::log_title(" mix ".to_owned()).validate(),
+            Ok(())
+        );
+        assert_eq!(
+            SearchParams::log_title(" \t".to_owned()).validate(),
+            Err(SearchParamsError::EmptyTitle)
+        );
+        assert_eq!(
+            SearchParams::limit(5)
+                .add_log_title(String::new())
+                .validate(),
+            Err(SearchParamsError::EmptyTitle)
+        );
+        let longest = format!(" {} ", "ä".repeat(100));
+        assert_eq!(SearchParams::log_title(longest).validate(), Ok(()));
+        assert_eq!(
+            SearchParams::log_title("a".repeat(101)).validate(),
+            Err(SearchParamsError::TitleTooLong(101))
+        );
+    }
+
+    #[test]
+    fn validate_filter()
+    {
+        assert_eq!(SearchParams::player_id(scout()).validate(), Ok(()));
+        // The most recent logs.
+        assert_eq!(SearchParams::limit(5).validate(), Ok(()));
+        assert_eq!(
+            SearchParams::player_id(scout())
+                .add_log_title("mix".to_owned())
+                .validate(),
+            Err(SearchParamsError::PlayersAndTitle)
+        );
+        assert_eq!(
+            SearchParams::player_ids(Vec::new()).validate(),
+            Err(SearchParamsError::NoFilter)
+        );
+        assert_eq!(
+            SearchParams::player_ids(Vec::new())
+                .add_offset(10)
+                .validate(),
+            Err(SearchParamsError::NoFilter)
+        );
+    }
+
+    #[test]
+    fn canonical_title()
+    {
+        let params = SearchParams::log_title(" mix #1\n".to_owned());
+        assert_eq!(params.canonicalized().title.as_deref(), Some("mix #1"));
+        assert_eq!(
+            params.canonicalized(),
+            SearchParams::log_title("mix #1".to_owned()).canonicalized()
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fmt;
+
+/// A search that makes no sense, as returned by
+/// [`SearchParams::validate`](super::search_params::SearchParams::validate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchParamsError
+{
+    /// The limit is not between 1 and 10000. Contains the limit.
+    LimitOutOfRange(u16),
+    /// The title is empty or only whitespace.
+    EmptyTitle,
+    /// The title is longer than 100 characters. Contains its length.
+    TitleTooLong(usize),
+    /// Neither players, a title nor a limit are given.
+    NoFilter,
+    /// Both players and a title are given, which logs.tf does not combine.
+    PlayersAndTitle,
+}
+
+impl fmt::Display for SearchParamsError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::LimitOutOfRange(limit) => {
+                write!(f, "The limit must be between 1 and 10000, not {}", limit)
+            },
+            Self::EmptyTitle => write!(f, "The title must not be empty"),
+            Self::TitleTooLong(len) => {
+                write!(f, "The title must be at most 100 characters, not {}", len)
+            },
+            Self::NoFilter => write!(f, "No players, title or limit have been given"),
+            Self::PlayersAndTitle => {
+                write!(f, "Players and a title cannot be searched for together")
+            },
+        }
+    }
+}
+
+impl Error for SearchParamsError {}
@@ -0,0 +1,21 @@
+use crate::score::Team;
+
+/// The numbers of one team in a log for a match report, as returned by
+/// [`Log::team_summary`](super::Log::team_summary) and
+/// [`Database::team_summary`](crate::Database::team_summary). Only players
+/// whose team is known are counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TeamSummary
+{
+    /// The damage dealt by all players of the team.
+    pub damage:    u64,
+    /// The healing done by the medics of the team.
+    pub healing:   u64,
+    /// The ubers used by the medics of the team.
+    pub num_ubers: u32,
+    /// The winner of each round of the game in the order they have been
+    /// played, the same for both teams. A round that has not been won by
+    /// either team is `None`. Empty if the log has no rounds, or if it has
+    /// been stored before the rounds were.
+    pub rounds:    Vec<Option<Team>>,
+}
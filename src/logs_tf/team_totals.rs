@@ -0,0 +1,40 @@
+/// The stats of all players of one team in a log added up, as returned by
+/// [`Log::team_totals`](super::Log::team_totals). They put the stats of a
+/// single player into the context of their team. The damage of a team can be
+/// more than a single player can deal, so it is kept in a wider type.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TeamTotals
+{
+    pub damage: u64,
+    pub kills:  u32,
+}
+
+impl TeamTotals
+{
+    /// The fraction of the damage of the team that has been dealt by a player
+    /// with `damage`.
+    ///
+    /// # Returns
+    /// `None` if the team has not dealt any damage, which happens with broken
+    /// logs.
+    pub fn damage_share(&self, damage: u32) -> Option<f32>
+    {
+        match self.damage {
+            0 => None,
+            total => Some(damage as f32 / total as f32),
+        }
+    }
+
+    /// The fraction of the kills of the team a player with `kills` and
+    /// `assists` has taken part in.
+    ///
+    /// # Returns
+    /// `None` if the team has not killed anyone.
+    pub fn kill_participation(&self, kills: u32, assists: u32) -> Option<f32>
+    {
+        match self.kills {
+            0 => None,
+            total => Some((kills + assists) as f32 / total as f32),
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! Parser for logs that deserializes the json text directly into the typed
+//! [`model`](super::model) with serde. Everything in the log that is not
+//! needed is skipped without being allocated, which makes it a lot faster than
+//! building a json document first, when lots of logs have to be read.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read};
+use std::str::FromStr;
+
+use chrono::{TimeZone, Utc};
+
+use super::log::duration_or_rounds;
+use super::model::{ClassStats, LogModel, Player};
+use super::{ChatMessage, Log, LogMetadata, LogParseError, QueryError, QueryResult};
+use crate::dm_performance::{self, DMPerformance};
+use crate::medic_performance::{self, MedicPerformance};
+use crate::overall_performance::OverallPerformance;
+use crate::score::{MatchFormat, Score};
+use crate::{Class, LogId, Performance, SteamID};
+
+/// The number of bytes at the start of a response that are kept to find out
+/// why it is not a log. The errors logs.tf answers with are much shorter.
+const MAX_KEPT_RESPONSE_LEN: usize = 4096;
+
+/// A reader that keeps the first bytes read from it, so that a response that
+/// has been streamed into the parser can still be looked at if it turns out
+/// not to be a log.
+struct KeepStart<R>
+{
+    inner:     R,
+    start:     Vec<u8>,
+    /// Whether more than [`MAX_KEPT_RESPONSE_LEN`] bytes have been read.
+    truncated: bool,
+    /// Whether the end of the inner reader has been reached.
+    finished:  bool,
+}
+
+impl<R: Read> KeepStart<R>
+{
+    fn new(inner: R) -> Self
+    {
+        Self {
+            inner,
+            start: Vec::new(),
+            truncated: false,
+            finished: false,
+        }
+    }
+
+    /// The whole text that has been read, if it has been read to the end and
+    /// has been short enough to be kept.
+    fn whole_text(mut self) -> Option<String>
+    {
+        // Read the rest of a short response that the parser has given up on.
+        let mut rest = (&mut self).take(MAX_KEPT_RESPONSE_LEN as u64 + 1);
+        io::copy(&mut rest, &mut io::sink()).ok()?;
+
+        if self.finished && !self.truncated {
+            Some(String::from_utf8_lossy(&self.start).into_owned())
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl<R: Read> Read for KeepStart<R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        let num_read = self.inner.read(buf)?;
+        if num_read == 0 && !buf.is_empty() {
+            self.finished = true;
+        }
+        let num_kept = num_read.min(MAX_KEPT_RESPONSE_LEN - self.start.len());
+        self.start.extend_from_slice(&buf[..num_kept]);
+        self.truncated |= num_kept < num_read;
+
+        Ok(num_read)
+    }
+}
+
+impl Player
+{
+    fn into_performances(
+        self,
+        score: &Score,
+        heal_spread: Option<HashMap<SteamID, u32>>,
+    ) -> Vec<Performance>
+    {
+        let (won_rounds, num_rounds) = score.rounds(self.team);
+
+        let mut performances = vec![OverallPerformance {
+            team: Some(self.team),
+            won_rounds,
+            num_rounds,
+            damage: self.dmg.try_into().unwrap_or(0),
+            damage_taken: self.dt.try_into().unwrap_or(0),
+            kills: self.kills.try_into().unwrap_or(0),
+            deaths: self.deaths.try_into().unwrap_or(0),
+            num_medkits: self.medkits.try_into().unwrap_or(0),
+            medkits_hp: self.medkits_hp.try_into().unwrap_or(0),
+            captures: self.cpc.try_into().unwrap_or(0),
+            heals_received: self.hr.try_into().unwrap_or(0),
+        }
+        .into()];
+
+        for class_stats in &self.class_stats {
+            performances.push(
+                DMPerformance {
+                    class:            class_stats.class,
+                    kills:            class_stats.kills,
+                    assists:          class_stats.assists,
+                    deaths:           class_stats.deaths,
+                    damage:           class_stats.dmg,
+                    time_played_secs: class_stats.total_time,
+                    sentry_kills:     class_stats.sentry_kills(),
+                    team:             Some(self.team),
+                }
+                .into(),
+            );
+        }
+
+        let medic_stats = self
+            .class_stats
+            .iter()
+            .find(|class_stats| class_stats.class == Class::Medic);
+        if let (Some(medicstats), Some(class_stats)) = (self.medicstats, medic_stats) {
+            let mut heal_targets: Vec<(SteamID, u32)> = heal_spread.into_iter().flatten().collect();
+            medic_performance::sort_heal_targets(&mut heal_targets);
+
+            performances.push(
+                MedicPerformance {
+                    healing: self.heal.try_into().unwrap_or(0),
+                    average_uber_length_secs: medicstats.avg_uber_length.unwrap_or(0.0) as f32,
+                    num_ubers: self.ubers.try_into().unwrap_or(0),
+                    num_drops: self.drops.try_into().unwrap_or(0),
+                    num_kritz: self.ubertypes.kritzkrieg,
+                    advantages_lost: medicstats.advantages_lost,
+                    biggest_advantage_lost_secs: medicstats.biggest_advantage_lost as f32,
+                    deaths: class_stats.deaths,
+                    time_played_secs: class_stats.total_time,
+                    heal_targets,
+                    team: Some(self.team),
+                }
+                .into(),
+            );
+        }
+
+        performances
+    }
+}
+
+impl ClassStats
+{
+    /// The sentry kills of an engineer, read the same way as by
+    /// [`DMPerformance::extract_all_from_json`].
+    fn sentry_kills(&self) -> Option<u16>
+    {
+        if self.class != Class::Engineer {
+            return None;
+        }
+        if let Some(sentry_kills) = self.sentries.and_then(|kills| kills.try_into().ok()) {
+            return Some(sentry_kills);
+        }
+
+        self.weapon.as_ref().map(|weapons| {
+            weapons
+                .iter()
+                .filter(|(weapon, _)| dm_performance::is_sentry_weapon(weapon))
+                .map(|(_, stats)| u16::try_from(stats.kills).unwrap_or(0))
+                .sum()
+        })
+    }
+}
+
+impl Log
+{
+    /// Turn the typed model of a log into a log. Produces the same log as
+    /// [`Log::from_json`] does for the json document of the model.
+    pub fn from_typed(id: LogId, mut model: LogModel) -> Self
+    {
+        let meta = LogMetadata {
+            id,
+            date_time: Utc
+                .timestamp_opt(model.info.date as i64, 0)
+                .single()
+                .expect("Log date out of range"),
+            map: model.info.map,
+            // Counted from the players that have actually played.
+            num_players: 0,
+            title: model.info.title,
+            uploader: model
+                .info
+                .uploader
+                .and_then(|uploader| uploader.id)
+                .and_then(|steam_id| SteamID::from_str(&steam_id).ok()),
+            // Set with the performances.
+            duration_secs: None,
+        };
+
+        let format = MatchFormat::from_map(&meta.map);
+        let score = Score::new(model.teams.red.score, model.teams.blue.score, format);
+        // The heal spread is keyed by the steam id alone, so a medic listed more
+        // than once only has it for their first entry.
+        let players = model.players.into_iter().map(|(player_id, player)| {
+            let heal_spread = model.healspread.remove(&player_id);
+            (player_id, player.into_performances(&score, heal_spread))
+        });
+
+        let chat = model
+            .chat
+            .into_iter()
+            .map(|message| ChatMessage {
+                steam_id: message
+                    .steamid
+                    .and_then(|steam_id| SteamID::from_str(&steam_id).ok()),
+                name:     message.name.unwrap_or_default(),
+                message:  message.msg.unwrap_or_default(),
+            })
+            .collect();
+
+        let duration_secs = duration_or_rounds(
+            model.info.total_length,
+            model.rounds.iter().map(|round| round.length),
+        );
+        let rounds = model.rounds.iter().map(|round| round.winner).collect();
+
+        Self::from_parts(meta, players, duration_secs, chat, model.names, rounds)
+    }
+
+    /// Parse a log from the json text as it is returned by the logs.tf API,
+    /// without building a json document first. Produces the same log as
+    /// [`Log::from_json`], but is considerably faster. Rejects logs that do not
+    /// contain any players.
+    pub fn parse_typed(id: LogId, json: &str) -> Result<Self, LogParseError>
+    {
+        let model: LogModel = serde_json::from_str(json)?;
+        if model.players.is_empty() {
+            return Err(LogParseError::NoPlayers);
+        }
+
+        Ok(Self::from_typed(id, model))
+    }
+
+    /// Like [`Log::parse_typed`], but reads the json text from the `reader`
+    /// while parsing it, so that the text never has to be held in memory as a
+    /// whole. The reader is buffered, so it can read directly from a file or a
+    /// response.
+    pub fn from_reader(id: LogId, reader: impl Read) -> Result<Self, LogParseError>
+    {
+        let model: LogModel = serde_json::from_reader(BufReader::new(reader))?;
+        if model.players.is_empty() {
+            return Err(LogParseError::NoPlayers);
+        }
+
+        Ok(Self::from_typed(id, model))
+    }
+
+    /// Read the log from a `response` of the logs.tf API while it is being
+    /// downloaded. A response that is not a log, like an error, is classified
+    /// the same way as when it is read as a json document first.
+    pub(super) fn read_response(id: LogId, response: impl Read) -> QueryResult<Self>
+    {
+        let mut reader = BufReader::new(KeepStart::new(response));
+        let error = match serde_json::from_reader::<_, LogModel>(&mut reader) {
+            Ok(model) => return Ok(Self::from_typed(id, model)),
+            Err(e) => e,
+        };
+
+        if let Some(text) = reader.into_inner().whole_text() {
+            let json = json::parse(&text)?;
+            super::check_json_success(&json)?;
+        }
+
+        Err(QueryError::InvalidLog(error.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+
+    use super::*;
+
+    const TEST_LOG: &str = include_str!("../../test_data/log_3094861.json");
+
+    #[test]
+    fn same_as_json_parser()
+    {
+        let typed = Log::parse_typed(LogId(3094861), TEST_LOG).unwrap();
+        let json = Log::from_json(LogId(3094861), &json::parse(TEST_LOG).unwrap()).unwrap();
+
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.chat(), json.chat());
+        assert_eq!(typed.duration_secs(), json.duration_secs());
+
+        let mut without_duration = json::parse(TEST_LOG).unwrap();
+        without_duration["info"].remove("total_length");
+        assert_eq!(
+            Log::parse_typed(LogId(3094861), &without_duration.dump())
+                .unwrap()
+                .duration_secs(),
+            1738
+        );
+        assert_eq!(typed.meta().date_time, json.meta().date_time);
+        assert_eq!(typed.meta().map, json.meta().map);
+        assert_eq!(typed.meta().num_players, json.meta().num_players);
+        assert_eq!(typed.meta().title, json.meta().title);
+        assert_eq!(typed.meta().uploader, json.meta().uploader);
+    }
+
+    #[test]
+    fn highlander_same_as_json()
+    {
+        let highlander = include_str!("../../test_data/log_highlander.json");
+        let typed = Log::parse_typed(LogId(1), highlander).unwrap();
+        let json = Log::from_json(LogId(1), &json::parse(highlander).unwrap()).unwrap();
+
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.performances().len(), 18);
+        assert_eq!(typed.meta().num_players, json.meta().num_players);
+    }
+
+    #[test]
+    fn from_typed_same_as_json()
+    {
+        let model: LogModel = serde_json::from_str(TEST_LOG).unwrap();
+        let typed = Log::from_typed(LogId(3094861), model);
+        let json = Log::from_json(LogId(3094861), &json::parse(TEST_LOG).unwrap()).unwrap();
+
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.chat(), json.chat());
+        assert_eq!(typed.duration_secs(), json.duration_secs());
+        assert_eq!(typed.meta().num_players, json.meta().num_players);
+    }
+
+    /// Replace every number in the `json` with the same number as a string.
+    fn stringify_numbers(json: &mut json::JsonValue)
+    {
+        if json.is_number() {
+            *json = json.dump().into();
+        }
+        else if json.is_object() {
+            json.entries_mut()
+                .for_each(|(_, value)| stringify_numbers(value));
+        }
+        else {
+            json.members_mut().for_each(stringify_numbers);
+        }
+    }
+
+    #[test]
+    fn numbers_given_as_strings_same_as_json()
+    {
+        let mut stringified = json::parse(TEST_LOG).unwrap();
+        stringify_numbers(&mut stringified);
+
+        let typed = Log::parse_typed(LogId(3094861), &stringified.dump()).unwrap();
+        let json = Log::from_json(LogId(3094861), &json::parse(TEST_LOG).unwrap()).unwrap();
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.meta(), json.meta());
+        assert_eq!(typed.round_winners(), json.round_winners());
+    }
+
+    #[test]
+    fn sentry_kills_same_as_json()
+    {
+        let engineer = "[U:1:886717065]";
+        let mut with_sentries = json::parse(TEST_LOG).unwrap();
+        with_sentries["players"][engineer]["class_stats"][1]["sentries"] = 4.into();
+        let mut without_weapons = json::parse(TEST_LOG).unwrap();
+        without_weapons["players"][engineer]["class_stats"][1].remove("weapon");
+
+        for (json, sentry_kills) in [(with_sentries, Some(4)), (without_weapons, None)] {
+            let typed = Log::parse_typed(LogId(1), &json.dump()).unwrap();
+            let json = Log::from_json(LogId(1), &json).unwrap();
+            assert_eq!(typed.performances(), json.performances());
+
+            let steam_id = SteamID::from_str(engineer).unwrap();
+            let engi_perf = &typed.performances()[&steam_id]
+                .iter()
+                .filter_map(Performance::as_dm)
+                .find(|dm| dm.class == Class::Engineer)
+                .unwrap();
+            assert_eq!(engi_perf.sentry_kills, sentry_kills);
+        }
+    }
+
+    #[test]
+    fn spectators_same_as_json()
+    {
+        let mut json = json::parse(TEST_LOG).unwrap();
+        json["players"]["[U:1:886717065]"]["class_stats"] = json::array![];
+
+        let typed = Log::parse_typed(LogId(1), &json.dump()).unwrap();
+        let json = Log::from_json(LogId(1), &json).unwrap();
+        assert_eq!(typed.spectators(), json.spectators());
+        assert_eq!(typed.spectators().len(), 1);
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.meta().num_players, 11);
+    }
+
+    #[test]
+    fn duplicate_player_same_as_json()
+    {
+        let mut json = json::parse(TEST_LOG).unwrap();
+        json["players"]["76561198846982793"] = json["players"]["[U:1:886717065]"].clone();
+
+        let typed = Log::parse_typed(LogId(1), &json.dump()).unwrap();
+        let json = Log::from_json(LogId(1), &json).unwrap();
+        assert_eq!(typed.performances(), json.performances());
+        assert_eq!(typed.anomalies(), json.anomalies());
+        assert_eq!(typed.anomalies().len(), 1);
+        assert_eq!(typed.meta().num_players, 12);
+    }
+
+    #[test]
+    fn reject_invalid_logs()
+    {
+        let mut without_players = json::parse(TEST_LOG).unwrap();
+        without_players["players"] = json::JsonValue::new_object();
+        assert!(matches!(
+            Log::parse_typed(LogId(1), &without_players.dump()),
+            Err(LogParseError::NoPlayers)
+        ));
+
+        let mut unknown_class = json::parse(TEST_LOG).unwrap();
+        unknown_class["players"]["[U:1:886717065]"]["class_stats"][0]["type"] = "bard".into();
+        assert!(matches!(
+            Log::parse_typed(LogId(1), &unknown_class.dump()),
+            Err(LogParseError::Typed(_))
+        ));
+
+        assert!(matches!(
+            Log::parse_typed(LogId(1), "{"),
+            Err(LogParseError::Typed(_))
+        ));
+        assert!(matches!(
+            Log::from_reader(LogId(1), without_players.dump().as_bytes()),
+            Err(LogParseError::NoPlayers)
+        ));
+    }
+
+    #[test]
+    fn from_reader_same_as_json()
+    {
+        let highlander = include_str!("../../test_data/log_highlander.json");
+        for text in [TEST_LOG, highlander] {
+            let streamed = Log::from_reader(LogId(1), text.as_bytes()).unwrap();
+            let json = Log::from_json(LogId(1), &json::parse(text).unwrap()).unwrap();
+
+            assert_eq!(streamed.performances(), json.performances());
+            assert_eq!(streamed.chat(), json.chat());
+            assert_eq!(streamed.duration_secs(), json.duration_secs());
+            assert_eq!(streamed.meta(), json.meta());
+        }
+
+        let file = File::open("test_data/log_3094861.json").unwrap();
+        let streamed = Log::from_reader(LogId(1), file).unwrap();
+        let typed = Log::parse_typed(LogId(1), TEST_LOG).unwrap();
+        assert_eq!(streamed.performances(), typed.performances());
+        assert_eq!(streamed.meta(), typed.meta());
+    }
+
+    #[test]
+    fn response_errors()
+    {
+        let read = |response: &str| Log::read_response(LogId(1), response.as_bytes());
+
+        let json = Log::from_json(LogId(1), &json::parse(TEST_LOG).unwrap()).unwrap();
+        assert_eq!(read(TEST_LOG).unwrap().performances(), json.performances());
+        assert!(matches!(
+            read("Too Many Requests"),
+            Err(QueryError::JsonParseError(_))
+        ));
+        assert!(matches!(
+            read(r#"{"success":false,"error":"Log not found."}"#),
+            Err(QueryError::Unsuccessful(error)) if error == "Log not found."
+        ));
+        assert!(matches!(
+            read(r#"{"message":"logs.tf is down for maintenance"}"#),
+            Err(QueryError::MalformedResponse(response)) if response.contains("maintenance")
+        ));
+        assert!(matches!(
+            read(r#"{"success":true}"#),
+            Err(QueryError::InvalidLog(LogParseError::Typed(_)))
+        ));
+
+        // Responses that are too long to be kept are not looked at again.
+        let mut unknown_class = json::parse(TEST_LOG).unwrap();
+        unknown_class["players"]["[U:1:886717065]"]["class_stats"][0]["type"] = "bard".into();
+        assert!(matches!(
+            read(&unknown_class.dump()),
+            Err(QueryError::InvalidLog(LogParseError::Typed(_)))
+        ));
+        let truncated = &TEST_LOG[..MAX_KEPT_RESPONSE_LEN * 2];
+        assert!(matches!(
+            read(truncated),
+            Err(QueryError::InvalidLog(LogParseError::Typed(_)))
+        ));
+    }
+}
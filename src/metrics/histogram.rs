@@ -0,0 +1,92 @@
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The distribution of durations, counted into buckets with the upper
+/// `bounds` in seconds like a Prometheus histogram. Durations above the last
+/// bound are only part of the count.
+pub(super) struct Histogram<const N: usize>
+{
+    bounds:     [f64; N],
+    /// The number of durations that fall into each bucket, not including the
+    /// buckets below it.
+    buckets:    [AtomicU64; N],
+    count:      AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N>
+{
+    /// A histogram with the upper `bounds` of its buckets, in ascending order.
+    pub const fn new(bounds: [f64; N]) -> Self
+    {
+        Self {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration)
+    {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = self.bounds.iter().position(|&bound| secs <= bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Write the histogram with the `name` in the text exposition format, with
+    /// a cumulative count for every bucket.
+    pub fn render(&self, out: &mut String, name: &str, help: &str) -> fmt::Result
+    {
+        writeln!(out, "# HELP {} {}", name, help)?;
+        writeln!(out, "# TYPE {} histogram", name)?;
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative)?;
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count)?;
+        let sum_secs = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.;
+        writeln!(out, "{}_sum {}", name, sum_secs)?;
+        writeln!(out, "{}_count {}", name, count)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn buckets_are_cumulative()
+    {
+        let histogram = Histogram::new([1., 10.]);
+        histogram.observe(Duration::from_millis(500));
+        histogram.observe(Duration::from_secs(1));
+        histogram.observe(Duration::from_secs(5));
+        histogram.observe(Duration::from_secs(60));
+
+        let mut out = String::new();
+        histogram
+            .render(&mut out, "update_seconds", "Updates")
+            .unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                "# HELP update_seconds Updates",
+                "# TYPE update_seconds histogram",
+                "update_seconds_bucket{le=\"1\"} 2",
+                "update_seconds_bucket{le=\"10\"} 3",
+                "update_seconds_bucket{le=\"+Inf\"} 4",
+                "update_seconds_sum 66.5",
+                "update_seconds_count 4",
+            ]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Metrics about what the crate has done in this process, like the requests
+//! made to logs.tf and the logs that have been added, for operators who run
+//! updates as a service. The metrics are rendered by [`gather`] in the text
+//! format of Prometheus, to be served by the bot or written to a file. There
+//! is no HTTP server in the crate.
+
+mod histogram;
+
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use self::histogram::Histogram;
+use crate::logs_tf::{QueryError, QueryResult};
+
+/// What a request to logs.tf has been made for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum RequestKind
+{
+    Search,
+    Download,
+}
+
+const REQUEST_KINDS: [(RequestKind, &str); 2] = [
+    (RequestKind::Search, "search"),
+    (RequestKind::Download, "download"),
+];
+
+/// The outcomes of a request, the first one for success and the rest for each
+/// variant of [`QueryError`].
+const OUTCOMES: [&str; 8] = [
+    "success",
+    "http_error",
+    "timeout",
+    "invalid_json",
+    "unsuccessful",
+    "malformed",
+    "invalid_log",
+    "invalid_params",
+];
+
+/// The upper bounds of the buckets of the duration of updates, in seconds.
+const UPDATE_DURATION_BOUNDS: [f64; 9] = [1., 5., 15., 30., 60., 120., 300., 600., 1800.];
+
+/// All metrics of the process.
+static REGISTRY: Registry = Registry::new();
+
+struct Registry
+{
+    /// The requests by [`RequestKind`] and index of their outcome in
+    /// [`OUTCOMES`].
+    requests:        [[AtomicU64; OUTCOMES.len()]; REQUEST_KINDS.len()],
+    retries:         AtomicU64,
+    logs_ingested:   AtomicU64,
+    parse_failures:  AtomicU64,
+    update_duration: Histogram<{ UPDATE_DURATION_BOUNDS.len() }>,
+}
+
+impl Registry
+{
+    const fn new() -> Self
+    {
+        Self {
+            requests:        [const { Self::counters() }; REQUEST_KINDS.len()],
+            retries:         AtomicU64::new(0),
+            logs_ingested:   AtomicU64::new(0),
+            parse_failures:  AtomicU64::new(0),
+            update_duration: Histogram::new(UPDATE_DURATION_BOUNDS),
+        }
+    }
+
+    /// Counters that all start at zero.
+    const fn counters<const N: usize>() -> [AtomicU64; N] { [const { AtomicU64::new(0) }; N] }
+
+    fn record_request<T>(&self, kind: RequestKind, result: &QueryResult<T>)
+    {
+        let outcome = match result {
+            Ok(_) => 0,
+            Err(QueryError::HttpResponse(_)) => 1,
+            Err(QueryError::Timeout(_)) => 2,
+            Err(QueryError::JsonParseError(_)) => 3,
+            Err(QueryError::Unsuccessful(_)) => 4,
+            Err(QueryError::MalformedResponse(_)) => 5,
+            Err(QueryError::InvalidLog(_)) => {
+                self.parse_failures.fetch_add(1, Ordering::Relaxed);
+                6
+            },
+            Err(QueryError::InvalidParams(_)) => 7,
+        };
+        self.requests[kind as usize][outcome].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> Result<String, fmt::Error>
+    {
+        let mut out = String::new();
+        let name = "mixes_db_logs_tf_requests_total";
+        writeln!(
+            out,
+            "# HELP {} Requests made to logs.tf, by kind and outcome.",
+            name
+        )?;
+        writeln!(out, "# TYPE {} counter", name)?;
+        for (kind, kind_name) in REQUEST_KINDS {
+            for (outcome, count) in OUTCOMES.iter().zip(&self.requests[kind as usize]) {
+                writeln!(
+                    out,
+                    "{}{{kind=\"{}\",outcome=\"{}\"}} {}",
+                    name,
+                    kind_name,
+                    outcome,
+                    count.load(Ordering::Relaxed)
+                )?;
+            }
+        }
+
+        for (name, help, counter) in [
+            (
+                "mixes_db_retries_total",
+                "Failed requests that have been tried again.",
+                &self.retries,
+            ),
+            (
+                "mixes_db_logs_ingested_total",
+                "Logs that have been added to the database.",
+                &self.logs_ingested,
+            ),
+            (
+                "mixes_db_log_parse_failures_total",
+                "Logs that could not be read.",
+                &self.parse_failures,
+            ),
+        ] {
+            writeln!(out, "# HELP {} {}", name, help)?;
+            writeln!(out, "# TYPE {} counter", name)?;
+            writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed))?;
+        }
+
+        self.update_duration.render(
+            &mut out,
+            "mixes_db_update_duration_seconds",
+            "How long updates of the database have taken.",
+        )?;
+
+        Ok(out)
+    }
+}
+
+/// Render all metrics of the process in the text exposition format of
+/// Prometheus. Every metric is listed, even if nothing has been counted for it
+/// yet.
+pub fn gather() -> String { REGISTRY.render().expect("Writing to a string cannot fail") }
+
+/// Count a request to logs.tf of the `kind` with its `result`. A log that
+/// could not be read also counts as a parse failure.
+pub(crate) fn record_request<T>(kind: RequestKind, result: &QueryResult<T>)
+{
+    REGISTRY.record_request(kind, result)
+}
+
+/// Count a failed request that is tried again.
+pub(crate) fn record_retry() { REGISTRY.retries.fetch_add(1, Ordering::Relaxed); }
+
+/// Count a log that has been added to the database.
+pub(crate) fn record_ingested() { REGISTRY.logs_ingested.fetch_add(1, Ordering::Relaxed); }
+
+/// Count a log that could not be read.
+pub(crate) fn record_parse_failure() { REGISTRY.parse_failures.fetch_add(1, Ordering::Relaxed); }
+
+/// Note how long an update has taken.
+pub(crate) fn observe_update(duration: Duration) { REGISTRY.update_duration.observe(duration) }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn render()
+    {
+        let registry = Registry::new();
+        registry.record_request(RequestKind::Search, &Ok(()));
+        registry.record_request(RequestKind::Search, &Ok(()));
+        registry.record_request::<()>(
+            RequestKind::Download,
+            &Err(QueryError::Unsuccessful("Log not found.".to_owned())),
+        );
+        registry.retries.fetch_add(3, Ordering::Relaxed);
+        registry.update_duration.observe(Duration::from_secs(20));
+
+        let out = registry.render().unwrap();
+        for line in [
+            "# TYPE mixes_db_logs_tf_requests_total counter",
+            "mixes_db_logs_tf_requests_total{kind=\"search\",outcome=\"success\"} 2",
+            "mixes_db_logs_tf_requests_total{kind=\"download\",outcome=\"unsuccessful\"} 1",
+            "mixes_db_logs_tf_requests_total{kind=\"download\",outcome=\"success\"} 0",
+            "mixes_db_retries_total 3",
+            "mixes_db_logs_ingested_total 0",
+            "mixes_db_update_duration_seconds_bucket{le=\"15\"} 0",
+            "mixes_db_update_duration_seconds_bucket{le=\"30\"} 1",
+            "mixes_db_update_duration_seconds_count 1",
+        ] {
+            assert!(out.lines().any(|rendered| rendered == line), "{}", line);
+        }
+        // Every sample has a name and a value.
+        assert!(out
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .all(|line| line.split(' ').count() == 2));
+    }
+}
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use json::JsonValue;
+use log::{info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+
+use super::NotifyError;
+use crate::database::{DbEvent, EventHandler, UpdateReport};
+use crate::logs_tf::{keep_trying, DEFAULT_USER_AGENT};
+
+/// How long to wait for Discord to answer by default, see
+/// [`DiscordWebhook::timeout`].
+pub const DEFAULT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often a failed post is retried by default, see
+/// [`DiscordWebhook::num_retries`].
+pub const DEFAULT_WEBHOOK_RETRIES: u8 = 2;
+/// The most characters Discord allows in the description of an embed. The
+/// failures of an update are listed there, so that they are not bound by the
+/// 2000 characters of a plain message.
+pub const MAX_DESCRIPTION_CHARS: usize = 4096;
+/// The most characters a single failure takes up in the list of failures.
+/// Longer reasons are cut off.
+const MAX_FAILURE_CHARS: usize = 200;
+/// The most characters of a response kept in [`NotifyError::Rejected`].
+const MAX_RESPONSE_CHARS: usize = 200;
+
+const COLOR_SUCCESS: u32 = 0x2ecc71;
+const COLOR_FAILURES: u32 = 0xe67e22;
+const COLOR_CANCELLED: u32 = 0x95a5a6;
+
+/// A Discord webhook that updates are reported to, as an embed in the channel
+/// of the webhook. Failed posts are tried again `num_retries` times.
+#[derive(Clone, Debug)]
+pub struct DiscordWebhook
+{
+    /// The url of the webhook, as copied from the settings of the channel.
+    pub url:         String,
+    pub num_retries: u8,
+    /// Posts that have not been answered after this time fail with
+    /// [`NotifyError::Http`].
+    pub timeout:     Duration,
+}
+
+impl DiscordWebhook
+{
+    pub fn new(url: &str) -> Self
+    {
+        Self {
+            url:         url.to_owned(),
+            num_retries: DEFAULT_WEBHOOK_RETRIES,
+            timeout:     DEFAULT_WEBHOOK_TIMEOUT,
+        }
+    }
+
+    /// Post a summary of the update of the `report`: how many logs have been
+    /// added, which logs could not be added and how long the update has taken.
+    /// If too many logs have failed to list them all, the first ones are
+    /// listed together with the number of those left out.
+    pub fn notify_update(&self, report: &UpdateReport) -> Result<(), NotifyError>
+    {
+        let failures: Vec<String> = report
+            .failures()
+            .map(|(id, reason)| truncate(&format!("Log {}: {}", id, reason), MAX_FAILURE_CHARS))
+            .collect();
+
+        let mut embed = update_embed(report.cancelled, report.added.len(), failures.len());
+        if let Some(duration) = report.duration {
+            embed["fields"]
+                .push(field("Duration", format_duration(duration)))
+                .expect("The fields of an embed are an array");
+        }
+        if !failures.is_empty() {
+            embed["description"] = truncated_list(&failures, MAX_DESCRIPTION_CHARS).into();
+        }
+
+        self.post(embed)
+    }
+
+    /// Turn the webhook into a handler for
+    /// [`Database::set_event_handler`](crate::Database::set_event_handler),
+    /// which posts the number of logs added whenever an update has completed
+    /// or has been cancelled. The update waits for the post, but does not fail
+    /// with it, failed posts are only logged. Call
+    /// [`DiscordWebhook::notify_update`] with the report of the update for a
+    /// summary that includes the failures.
+    pub fn into_event_handler(self) -> EventHandler
+    {
+        Box::new(move |event| {
+            let embed = match event {
+                DbEvent::UpdateCompleted { added } => update_embed(false, added, 0),
+                DbEvent::UpdateCancelled { added } => update_embed(true, added, 0),
+                _ => return,
+            };
+            if let Err(e) = self.post(embed) {
+                warn!("Unable to notify Discord about the update: {}", e);
+            }
+        })
+    }
+
+    /// Post the `embed` to the webhook.
+    fn post(&self, embed: JsonValue) -> Result<(), NotifyError>
+    {
+        let client = Client::builder()
+            .timeout(self.timeout)
+            .user_agent(DEFAULT_USER_AGENT)
+            .build()?;
+        let payload = json::object! { embeds: [embed] }.dump();
+
+        keep_trying(
+            || {
+                let response = client
+                    .post(&self.url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(payload.clone())
+                    .send()?;
+                let status = response.status();
+                if status.is_success() {
+                    return Ok(());
+                }
+
+                let response = response.text().unwrap_or_default();
+                Err(NotifyError::Rejected(
+                    status.as_u16(),
+                    truncate(&response, MAX_RESPONSE_CHARS),
+                ))
+            },
+            self.num_retries,
+        )?;
+        info!("Notified Discord about the update");
+
+        Ok(())
+    }
+}
+
+/// The embed reporting an update with the number of logs that have been
+/// `added` and that have `failed`, without listing the failures.
+fn update_embed(cancelled: bool, added: usize, failed: usize) -> JsonValue
+{
+    let (title, color) = match (cancelled, failed) {
+        (true, _) => ("Update cancelled", COLOR_CANCELLED),
+        (false, 0) => ("Update completed", COLOR_SUCCESS),
+        (false, _) => ("Update completed with failures", COLOR_FAILURES),
+    };
+
+    json::object! {
+        title: title,
+        color: color,
+        fields: [field("Added", added), field("Failed", failed)],
+    }
+}
+
+/// A field of an embed, shown next to the other fields.
+fn field(name: &str, value: impl ToString) -> JsonValue
+{
+    json::object! {
+        name: name,
+        value: value.to_string(),
+        inline: true,
+    }
+}
+
+/// Show the `duration` in whole seconds, like `1h 2m 3s`.
+fn format_duration(duration: Duration) -> String
+{
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, secs) => format!("{}s", secs),
+        (0, mins, secs) => format!("{}m {}s", mins, secs),
+        (hours, mins, secs) => format!("{}h {}m {}s", hours, mins, secs),
+    }
+}
+
+/// Cut the `text` off so that it is at most `max_chars` characters long,
+/// marking that it has been cut off.
+fn truncate(text: &str, max_chars: usize) -> String
+{
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// List the `lines`, one per line, in at most `max_chars` characters. If not
+/// all of them fit, the last ones are left out and their number is told
+/// instead.
+fn truncated_list(lines: &[String], max_chars: usize) -> String
+{
+    let list = lines.join("\n");
+    if list.chars().count() <= max_chars {
+        return list;
+    }
+
+    let mut list = String::new();
+    let mut num_chars = 0;
+    for (shown, line) in lines.iter().enumerate() {
+        let separator = usize::from(shown > 0);
+        let line_chars = separator + line.chars().count();
+        // Not all lines fit, so there has to be room left to tell how many
+        // lines come after this one.
+        let num_after = lines.len() - shown - 1;
+        let reserved = match num_after {
+            0 => 0,
+            _ => 1 + more(num_after).chars().count(),
+        };
+        if num_chars + line_chars + reserved > max_chars {
+            if separator == 1 {
+                list.push('\n');
+            }
+            list.push_str(&more(lines.len() - shown));
+            return list;
+        }
+
+        if separator == 1 {
+            list.push('\n');
+        }
+        list.push_str(line);
+        num_chars += line_chars;
+    }
+
+    list
+}
+
+/// The line that tells how many lines have been left out of a list.
+fn more(num_left_out: usize) -> String { format!("… and {} more", num_left_out) }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn truncation()
+    {
+        assert_eq!(truncate("short", 5), "short");
+        assert_eq!(truncate("too long", 5), "too …");
+        // Characters are counted, not bytes.
+        assert_eq!(truncate("ééé", 3), "ééé");
+        assert_eq!(format_duration(Duration::from_millis(59_900)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(61)), "1m 1s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 2m 3s");
+
+        let lines: Vec<String> = (1..=5).map(|i| format!("line {}", i)).collect();
+        assert_eq!(truncated_list(&lines, 100), lines.join("\n"));
+        // Exactly enough room for all lines
+        assert_eq!(truncated_list(&lines, 34), lines.join("\n"));
+        assert_eq!(
+            truncated_list(&lines, 33),
+            "line 1\nline 2\nline 3\n… and 2 more"
+        );
+        assert_eq!(truncated_list(&lines, 20), "line 1\n… and 4 more");
+
+        let lines: Vec<String> = (0..1000).map(|i| format!("Log {}: failed", i)).collect();
+        let list = truncated_list(&lines, MAX_DESCRIPTION_CHARS);
+        assert!(list.chars().count() <= MAX_DESCRIPTION_CHARS);
+        let num_shown = list.lines().count() - 1;
+        assert!(list.ends_with(&format!("… and {} more", 1000 - num_shown)));
+    }
+}
@@ -0,0 +1,8 @@
+//! Tell the operators of the database what it has done, like that an update
+//! has finished, by posting to a chat.
+
+mod discord_webhook;
+mod notify_error;
+
+pub use discord_webhook::*;
+pub use notify_error::*;
@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt;
+
+use reqwest::Error as HttpError;
+
+/// Any error that may occur when sending a notification.
+#[derive(Debug)]
+pub enum NotifyError
+{
+    /// The notification could not be sent, for instance because the service
+    /// could not be reached or has not answered in time.
+    Http(HttpError),
+    /// The service has refused the notification, for instance because the
+    /// webhook has been deleted. Contains the status code and the beginning of
+    /// the response.
+    Rejected(u16, String),
+}
+
+impl From<HttpError> for NotifyError
+{
+    fn from(e: HttpError) -> Self { Self::Http(e) }
+}
+
+impl fmt::Display for NotifyError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Http(e) => write!(f, "Unable to send the notification: {}", e),
+            Self::Rejected(status, response) => write!(
+                f,
+                "The notification has been refused with status {}: {}",
+                status, response
+            ),
+        }
+    }
+}
+
+impl Error for NotifyError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::Rejected(..) => None,
+        }
+    }
+}
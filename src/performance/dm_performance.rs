@@ -3,39 +3,135 @@ use std::str::FromStr;
 use json::JsonValue;
 
 use super::Performance;
+use crate::logs_tf::{json_num_u16, json_num_u32, LogParseError};
+use crate::score::Team;
 use crate::Class;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct DMPerformance
 {
     pub class:            Class,
-    pub kills:            u8,
-    pub assists:          u8,
-    pub deaths:           u8,
+    pub kills:            u16,
+    pub assists:          u16,
+    pub deaths:           u16,
     pub damage:           u32,
     pub time_played_secs: u32,
+    /// The kills made by the sentries of an engineer. `None` for other classes
+    /// and for logs that do not say what the sentries have done.
+    pub sentry_kills:     Option<u16>,
+    /// The team the player has played the class in, the same as in their
+    /// [`OverallPerformance`](super::overall_performance::OverallPerformance).
+    /// `None` for stats that have been stored before the team was recorded
+    /// with the class.
+    pub team:             Option<Team>,
 }
 
 impl DMPerformance
 {
-    pub fn extract_all_from_json(json: &JsonValue) -> Vec<Self>
+    /// Extract the performances on each class from the json of a player, who
+    /// has played in the `team`.
+    ///
+    /// # Returns
+    /// [`LogParseError::InvalidField`] if a class is unknown or one of its
+    /// stats is missing or not a number.
+    pub fn extract_all_from_json(
+        json: &JsonValue,
+        team: Option<Team>,
+    ) -> Result<Vec<Self>, LogParseError>
     {
         json["class_stats"]
             .members()
-            .map(|class_stats| Self {
-                class:            Class::from_str(class_stats["type"].as_str().unwrap()).unwrap(),
-                kills:            class_stats["kills"].as_u8().unwrap(),
-                assists:          class_stats["assists"].as_u8().unwrap(),
-                deaths:           class_stats["deaths"].as_u8().unwrap(),
-                damage:           class_stats["dmg"].as_u32().unwrap(),
-                time_played_secs: class_stats["total_time"].as_u32().unwrap(),
+            .map(|class_stats| {
+                let class = class_stats["type"]
+                    .as_str()
+                    .and_then(|class| Class::from_str(class).ok())
+                    .ok_or(LogParseError::InvalidField("class"))?;
+                let field = |field: &'static str| {
+                    json_num_u32(&class_stats[field]).ok_or(LogParseError::InvalidField(field))
+                };
+                let small_field = |field: &'static str| {
+                    json_num_u16(&class_stats[field]).ok_or(LogParseError::InvalidField(field))
+                };
+
+                Ok(Self {
+                    class,
+                    kills: small_field("kills")?,
+                    assists: small_field("assists")?,
+                    deaths: small_field("deaths")?,
+                    damage: field("dmg")?,
+                    time_played_secs: field("total_time")?,
+                    sentry_kills: Self::sentry_kills_from_json(class, class_stats),
+                    team,
+                })
             })
             .collect()
     }
+
+    /// Add the stats of `other` on the same class, for instance those of a
+    /// second entry of the player in the same log.
+    pub fn merge(&mut self, other: &Self)
+    {
+        debug_assert_eq!(self.class, other.class, "Only the same class can be merged");
+        self.kills = self.kills.saturating_add(other.kills);
+        self.assists = self.assists.saturating_add(other.assists);
+        self.deaths = self.deaths.saturating_add(other.deaths);
+        self.damage = self.damage.saturating_add(other.damage);
+        self.time_played_secs = self.time_played_secs.saturating_add(other.time_played_secs);
+        self.sentry_kills = match (self.sentry_kills, other.sentry_kills) {
+            (Some(own), Some(other)) => Some(own.saturating_add(other)),
+            (sentry_kills, None) | (None, sentry_kills) => sentry_kills,
+        };
+        self.team = self.team.or(other.team);
+    }
+
+    /// Read the sentry kills from the stats of an engineer. They are taken from
+    /// the `sentries` entry if the log has one, otherwise they are summed up
+    /// from the kills of the sentry weapons.
+    fn sentry_kills_from_json(class: Class, class_stats: &JsonValue) -> Option<u16>
+    {
+        if class != Class::Engineer {
+            return None;
+        }
+        if let Some(sentry_kills) = json_num_u16(&class_stats["sentries"]) {
+            return Some(sentry_kills);
+        }
+        if !class_stats["weapon"].is_object() {
+            return None;
+        }
+
+        Some(
+            class_stats["weapon"]
+                .entries()
+                .filter(|(weapon, _)| is_sentry_weapon(weapon))
+                .map(|(_, stats)| json_num_u16(&stats["kills"]).unwrap_or(0))
+                .sum(),
+        )
+    }
+}
+
+/// Whether the `weapon`, as named in the weapon stats of a log, is a sentry
+/// gun of any level or a mini-sentry.
+pub(crate) fn is_sentry_weapon(weapon: &str) -> bool
+{
+    weapon.starts_with("obj_sentrygun") || weapon == "obj_minisentry"
 }
 
-impl Into<Performance> for DMPerformance
+impl From<DMPerformance> for Performance
 {
-    fn into(self) -> Performance { Performance::DM(self) }
+    fn from(performance: DMPerformance) -> Self { Self::DM(performance) }
+}
+
+impl TryFrom<Performance> for DMPerformance
+{
+    type Error = Performance;
+
+    fn try_from(performance: Performance) -> Result<Self, Self::Error>
+    {
+        match performance {
+            Performance::DM(performance) => Ok(performance),
+            performance => Err(performance),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -56,7 +152,11 @@ mod tests
             .expect("Unable to read file to string");
         let json = json::parse(&json).expect("Unable to parse json");
 
-        let perfs = DMPerformance::extract_all_from_json(&json["players"]["[U:1:886717065]"]);
+        let perfs = DMPerformance::extract_all_from_json(
+            &json["players"]["[U:1:886717065]"],
+            Some(Team::Blue),
+        )
+        .unwrap();
 
         assert_eq!(perfs.len(), 3);
         let scout_perf = &perfs[0];
@@ -76,5 +176,73 @@ mod tests
         assert_eq!(engi_perf.deaths, 0);
         assert_eq!(engi_perf.damage, 293);
         assert_eq!(engi_perf.time_played_secs, 99);
+        assert_eq!(engi_perf.sentry_kills, Some(0));
+        assert_eq!(scout_perf.sentry_kills, None);
+        assert!(perfs.iter().all(|perf| perf.team == Some(Team::Blue)));
+    }
+
+    #[test]
+    fn sentry_kills()
+    {
+        let engineer = json::object! {
+            "type": "engineer", "kills": 5, "assists": 1, "deaths": 2, "dmg": 900,
+            "total_time": 600,
+            "weapon": {
+                "obj_sentrygun3": {"kills": 3},
+                "obj_minisentry": {"kills": 1},
+                "shotgun_primary": {"kills": 1},
+            },
+        };
+        let perfs = DMPerformance::extract_all_from_json(
+            &json::object! {
+                "class_stats": [engineer.clone()],
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(perfs[0].sentry_kills, Some(4));
+
+        let mut with_sentries = engineer.clone();
+        with_sentries["sentries"] = 7.into();
+        let perfs = DMPerformance::extract_all_from_json(
+            &json::object! {
+                "class_stats": [with_sentries],
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(perfs[0].sentry_kills, Some(7));
+
+        // Older logs without weapon stats do not say anything about sentries.
+        let mut without_weapons = engineer;
+        without_weapons.remove("weapon");
+        let perfs = DMPerformance::extract_all_from_json(
+            &json::object! {
+                "class_stats": [without_weapons],
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(perfs[0].sentry_kills, None);
+    }
+
+    #[test]
+    fn more_than_255_kills()
+    {
+        let perfs = DMPerformance::extract_all_from_json(
+            &json::object! {
+                "class_stats": [{
+                    "type": "heavyweapons", "kills": 300, "assists": 256, "deaths": 280,
+                    "dmg": 90000, "total_time": 7200,
+                }],
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(perfs[0].class, Class::Heavy);
+        assert_eq!(
+            (perfs[0].kills, perfs[0].assists, perfs[0].deaths),
+            (300, 256, 280)
+        );
     }
 }
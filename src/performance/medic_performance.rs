@@ -3,47 +3,234 @@ use std::str::FromStr;
 use json::JsonValue;
 
 use super::Performance;
-use crate::Class;
+use crate::logs_tf::{json_num_f32, json_num_u16, json_num_u32, json_num_u8};
+use crate::score::Team;
+use crate::{Class, SteamID};
 
+/// The most healing the stock Medi Gun does per second, on a patient that has
+/// not been in combat for a while. Healing at this rate for the whole game
+/// gives a [`MedicEfficiency::healing_fraction`] of `1`.
+pub const MAX_HEALING_PER_SEC: f32 = 72.;
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct MedicPerformance
 {
     pub healing: u32,
     pub average_uber_length_secs: f32,
     pub num_ubers: u8,
     pub num_drops: u8,
-    pub deaths: u8,
+    /// How many of the ubers have been kritz ubers.
+    pub num_kritz: u8,
+    /// The number of times the team has lost an uber advantage, so the medic
+    /// had uber while the enemy medic did not, but used it later than them.
+    pub advantages_lost: u8,
+    pub biggest_advantage_lost_secs: f32,
+    pub deaths: u16,
     pub time_played_secs: u32,
+    /// The healing done to each teammate, sorted by the amount of healing,
+    /// most healed teammate first.
+    pub heal_targets: Vec<(SteamID, u32)>,
+    /// The team the medic has played in, the same as in their
+    /// [`OverallPerformance`](super::overall_performance::OverallPerformance).
+    /// `None` for stats that have been stored before the team was recorded
+    /// with the medic stats.
+    pub team: Option<Team>,
 }
 
 impl MedicPerformance
 {
-    pub fn extract_from_json(json: &JsonValue) -> Option<Self>
+    /// Extract the medic performance from the json of a player. `heal_spread`
+    /// is the entry of the player in the `healspread` object of the log, which
+    /// maps the steam ids of the healed teammates to the healing they received.
+    /// The medic has played in the `team`.
+    pub fn extract_from_json(
+        json: &JsonValue,
+        heal_spread: &JsonValue,
+        team: Option<Team>,
+    ) -> Option<Self>
     {
+        // Most players are not medics, so check for the medic stats before looking
+        // through the classes.
+        if !json.has_key("medicstats") {
+            return None;
+        }
+        // Unknown classes are reported by the class performances.
         let class_stats = json["class_stats"].members().find(|class_stats| {
-            Class::from_str(class_stats["type"].as_str().unwrap()).unwrap() == Class::Medic
-        });
+            class_stats["type"]
+                .as_str()
+                .is_some_and(|class| matches!(Class::from_str(class), Ok(Class::Medic)))
+        })?;
+
+        let mut heal_targets: Vec<(SteamID, u32)> = heal_spread
+            .entries()
+            .filter_map(|(target, healing)| {
+                Some((SteamID::from_str(target).ok()?, json_num_u32(healing)?))
+            })
+            .collect();
+        sort_heal_targets(&mut heal_targets);
+
+        Some(Self {
+            healing: json_num_u32(&json["heal"]).unwrap_or(0),
+            average_uber_length_secs: json_num_f32(&json["medicstats"]["avg_uber_length"])
+                .unwrap_or(0.0),
+            num_ubers: json_num_u8(&json["ubers"]).unwrap_or(0),
+            num_drops: json_num_u8(&json["drops"]).unwrap_or(0),
+            // Older logs do not have the types of ubers or advantages lost.
+            num_kritz: json_num_u8(&json["ubertypes"]["kritzkrieg"]).unwrap_or(0),
+            advantages_lost: json_num_u8(&json["medicstats"]["advantages_lost"]).unwrap_or(0),
+            biggest_advantage_lost_secs: json_num_f32(
+                &json["medicstats"]["biggest_advantage_lost"],
+            )
+            .unwrap_or(0.0),
+            deaths: json_num_u16(&class_stats["deaths"]).unwrap_or(0),
+            time_played_secs: json_num_u32(&class_stats["total_time"]).unwrap_or(0),
+            heal_targets,
+            team,
+        })
+    }
+
+    /// The number of ubers used per 5 minutes of the `duration_secs`, usually
+    /// the time played as medic. `0` if no time has been played.
+    pub fn uber_rate(&self, duration_secs: u32) -> f32
+    {
+        ubers_per_5_min(self.num_ubers as u32, duration_secs)
+    }
+
+    /// The number of ubers dropped per uber used. `0` if no uber has been used,
+    /// even if some have been dropped.
+    pub fn drop_rate(&self) -> f32 { drops_per_uber(self.num_drops as u32, self.num_ubers as u32) }
+
+    /// Add the stats of `other`, for instance those of a second entry of the
+    /// player in the same log. The average uber length is weighted by the
+    /// number of ubers and the healing of each teammate is summed up.
+    pub fn merge(&mut self, other: &Self)
+    {
+        let num_ubers = self.num_ubers as f32 + other.num_ubers as f32;
+        if num_ubers > 0. {
+            self.average_uber_length_secs = (self.average_uber_length_secs * self.num_ubers as f32
+                + other.average_uber_length_secs * other.num_ubers as f32)
+                / num_ubers;
+        }
+        self.healing = self.healing.saturating_add(other.healing);
+        self.num_ubers = self.num_ubers.saturating_add(other.num_ubers);
+        self.num_drops = self.num_drops.saturating_add(other.num_drops);
+        self.num_kritz = self.num_kritz.saturating_add(other.num_kritz);
+        self.advantages_lost = self.advantages_lost.saturating_add(other.advantages_lost);
+        self.biggest_advantage_lost_secs = self
+            .biggest_advantage_lost_secs
+            .max(other.biggest_advantage_lost_secs);
+        self.deaths = self.deaths.saturating_add(other.deaths);
+        self.time_played_secs = self.time_played_secs.saturating_add(other.time_played_secs);
+
+        for &(target, healing) in &other.heal_targets {
+            match self.heal_targets.iter_mut().find(|(own, _)| *own == target) {
+                Some((_, own_healing)) => *own_healing = own_healing.saturating_add(healing),
+                None => self.heal_targets.push((target, healing)),
+            }
+        }
+        sort_heal_targets(&mut self.heal_targets);
+        self.team = self.team.or(other.team);
+    }
+}
+
+/// How well a medic has made use of the time they played, combining their
+/// healing, ubers and drops. Created with [`medic_efficiency`] for a single
+/// game.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MedicEfficiency
+{
+    /// Ubers used per 5 minutes, see [`MedicPerformance::uber_rate`].
+    pub ubers_per_5_min:  f32,
+    /// Ubers dropped per uber used, see [`MedicPerformance::drop_rate`].
+    pub drops_per_uber:   f32,
+    /// The healing done as a fraction of healing at [`MAX_HEALING_PER_SEC`]
+    /// the whole time.
+    pub healing_fraction: f32,
+}
 
-        if !json.has_key("medicstats") || class_stats.is_none() {
+impl MedicEfficiency
+{
+    /// Compute the efficiency from stats summed up over any number of games
+    /// that together took `duration_secs`. Games therefore count by how long
+    /// they have lasted, not all the same.
+    ///
+    /// # Returns
+    /// `None` if no time has been played.
+    pub(crate) fn from_totals(
+        healing: u64,
+        num_ubers: u32,
+        num_drops: u32,
+        duration_secs: u32,
+    ) -> Option<Self>
+    {
+        if duration_secs == 0 {
             return None;
         }
-        let class_stats = class_stats.unwrap();
 
         Some(Self {
-            healing: json["heal"].as_u32().unwrap_or(0),
-            average_uber_length_secs: json["medicstats"]["avg_uber_length"]
-                .as_f32()
-                .unwrap_or(0.0),
-            num_ubers: json["ubers"].as_u8().unwrap_or(0),
-            num_drops: json["drops"].as_u8().unwrap_or(0),
-            deaths: class_stats["deaths"].as_u8().unwrap_or(0),
-            time_played_secs: class_stats["total_time"].as_u32().unwrap_or(0),
+            ubers_per_5_min:  ubers_per_5_min(num_ubers, duration_secs),
+            drops_per_uber:   drops_per_uber(num_drops, num_ubers),
+            healing_fraction: healing as f32 / (duration_secs as f32 * MAX_HEALING_PER_SEC),
         })
     }
 }
 
-impl Into<Performance> for MedicPerformance
+/// Compute the efficiency of the `medic` in a game, in which they have played
+/// medic for `duration_secs`.
+///
+/// # Returns
+/// `None` if no time has been played.
+pub fn medic_efficiency(medic: &MedicPerformance, duration_secs: u32) -> Option<MedicEfficiency>
+{
+    MedicEfficiency::from_totals(
+        medic.healing as u64,
+        medic.num_ubers as u32,
+        medic.num_drops as u32,
+        duration_secs,
+    )
+}
+
+fn ubers_per_5_min(num_ubers: u32, duration_secs: u32) -> f32
+{
+    match duration_secs {
+        0 => 0.,
+        secs => num_ubers as f32 * 300. / secs as f32,
+    }
+}
+
+fn drops_per_uber(num_drops: u32, num_ubers: u32) -> f32
 {
-    fn into(self) -> Performance { Performance::Med(self) }
+    match num_ubers {
+        0 => 0.,
+        ubers => num_drops as f32 / ubers as f32,
+    }
+}
+
+/// Sort the heal targets by the healing they received, most healed teammate
+/// first. Ties are ordered by steam id.
+pub(crate) fn sort_heal_targets(heal_targets: &mut [(SteamID, u32)])
+{
+    heal_targets.sort_by(|(a_id, a_healing), (b_id, b_healing)| {
+        b_healing.cmp(a_healing).then(a_id.id64().cmp(&b_id.id64()))
+    });
+}
+
+impl From<MedicPerformance> for Performance
+{
+    fn from(performance: MedicPerformance) -> Self { Self::Med(performance) }
+}
+
+impl TryFrom<Performance> for MedicPerformance
+{
+    type Error = Performance;
+
+    fn try_from(performance: Performance) -> Result<Self, Self::Error>
+    {
+        match performance {
+            Performance::Med(performance) => Ok(performance),
+            performance => Err(performance),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -64,13 +251,115 @@ mod tests
             .expect("Unable to read file to string");
         let json = json::parse(&json).expect("Unable to parse json");
 
-        let stats = MedicPerformance::extract_from_json(&json["players"]["[U:1:71020853]"])
-            .expect("Unable to find medic performance");
+        let stats = MedicPerformance::extract_from_json(
+            &json["players"]["[U:1:71020853]"],
+            &json["healspread"]["[U:1:71020853]"],
+            Some(Team::Blue),
+        )
+        .expect("Unable to find medic performance");
         assert_eq!(stats.healing, 22732);
         assert_eq!(stats.average_uber_length_secs, 6.875);
         assert_eq!(stats.num_ubers, 12);
         assert_eq!(stats.num_drops, 0);
+        assert_eq!(stats.num_kritz, 0);
+        assert_eq!(stats.advantages_lost, 1);
+        assert_eq!(stats.biggest_advantage_lost_secs, 36.0);
         assert_eq!(stats.deaths, 10);
         assert_eq!(stats.time_played_secs, 1738);
+        assert_eq!(stats.team, Some(Team::Blue));
+
+        let id = |id3| SteamID::from_str(id3).unwrap();
+        assert_eq!(
+            stats.heal_targets,
+            vec![
+                (id("[U:1:142158386]"), 7081),
+                (id("[U:1:886717065]"), 4934),
+                (id("[U:1:980899970]"), 4006),
+                (id("[U:1:178408897]"), 3739),
+                (id("[U:1:301530244]"), 2691),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_uber_types()
+    {
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let mut json = json::parse(&json).expect("Unable to parse json");
+        let medic = &mut json["players"]["[U:1:71020853]"];
+        medic["ubertypes"]["kritzkrieg"] = 3.into();
+
+        let stats = MedicPerformance::extract_from_json(medic, &JsonValue::Null, None)
+            .expect("Unable to find medic performance");
+        assert_eq!(stats.num_kritz, 3);
+
+        // Older logs without the types of ubers and advantages
+        medic.remove("ubertypes");
+        medic["medicstats"].remove("advantages_lost");
+        medic["medicstats"].remove("biggest_advantage_lost");
+        let stats = MedicPerformance::extract_from_json(medic, &JsonValue::Null, None)
+            .expect("Unable to find medic performance");
+        assert_eq!(stats.num_kritz, 0);
+        assert_eq!(stats.advantages_lost, 0);
+        assert_eq!(stats.biggest_advantage_lost_secs, 0.0);
+        assert_eq!(stats.num_ubers, 12);
+    }
+
+    #[test]
+    fn extract_without_heal_spread()
+    {
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let json = json::parse(&json).expect("Unable to parse json");
+
+        let stats = MedicPerformance::extract_from_json(
+            &json["players"]["[U:1:71020853]"],
+            &json["no_healspread"]["[U:1:71020853]"],
+            None,
+        )
+        .expect("Unable to find medic performance");
+        assert!(stats.heal_targets.is_empty());
+    }
+
+    #[test]
+    fn efficiency()
+    {
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let json = json::parse(&json).expect("Unable to parse json");
+        let mut stats = MedicPerformance::extract_from_json(
+            &json["players"]["[U:1:71020853]"],
+            &JsonValue::Null,
+            None,
+        )
+        .expect("Unable to find medic performance");
+
+        // 12 ubers, no drops and 22732 healing in 1738 seconds.
+        let efficiency = medic_efficiency(&stats, stats.time_played_secs).unwrap();
+        assert!((efficiency.ubers_per_5_min - 2.0713).abs() < 1e-4);
+        assert_eq!(efficiency.drops_per_uber, 0.);
+        assert!((efficiency.healing_fraction - 0.18166).abs() < 1e-4);
+        assert_eq!(stats.uber_rate(1738), efficiency.ubers_per_5_min);
+        assert_eq!(stats.uber_rate(600), 6.);
+
+        stats.num_drops = 3;
+        assert_eq!(stats.drop_rate(), 0.25);
+        assert_eq!(medic_efficiency(&stats, 1738).unwrap().drops_per_uber, 0.25);
+
+        // Neither are NaN without ubers or time played.
+        stats.num_ubers = 0;
+        assert_eq!(stats.drop_rate(), 0.);
+        assert_eq!(stats.uber_rate(0), 0.);
+        assert_eq!(medic_efficiency(&stats, 0), None);
     }
 }
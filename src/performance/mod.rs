@@ -3,17 +3,22 @@ pub mod medic_performance;
 pub mod overall_performance;
 pub mod score;
 
+use std::cmp::Reverse;
+
 use dm_performance::DMPerformance;
 use json::JsonValue;
 use medic_performance::MedicPerformance;
 use overall_performance::OverallPerformance;
 
 use self::score::Score;
+use crate::logs_tf::LogParseError;
+use crate::Class;
 
 /// A `Performance` contains what a player has done in the course of a game. It
 /// contains either a generic performance, where data is not available on a per
 /// class basis and the specific performance with information of that class,
 /// being either a DM class or the medic.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Performance
 {
     Overall(OverallPerformance),
@@ -23,11 +28,23 @@ pub enum Performance
 
 impl Performance
 {
-    pub fn extract_all_from_json(score: &Score, json: &JsonValue) -> Vec<Performance>
+    /// Extract all performances from the json of a player. `heal_spread` is the
+    /// entry of the player in the `healspread` object of the log, which is only
+    /// present for medics. The team of the player is read with the overall
+    /// performance and passed on to the class and medic performances.
+    ///
+    /// # Returns
+    /// A [`LogParseError`] if the stats of the player cannot be read.
+    pub fn extract_all_from_json(
+        score: &Score,
+        json: &JsonValue,
+        heal_spread: &JsonValue,
+    ) -> Result<Vec<Performance>, LogParseError>
     {
-        let overall_performance = OverallPerformance::from_json(score, json);
-        let dm_performances = DMPerformance::extract_all_from_json(json);
-        let med_performance = MedicPerformance::extract_from_json(json);
+        let overall_performance = OverallPerformance::from_json(score, json)?;
+        let team = overall_performance.team;
+        let dm_performances = DMPerformance::extract_all_from_json(json, team)?;
+        let med_performance = MedicPerformance::extract_from_json(json, heal_spread, team);
 
         let mut performances = vec![overall_performance.into()];
 
@@ -39,6 +56,322 @@ impl Performance
             performances.push(med_performance.into());
         }
 
+        Ok(performances)
+    }
+
+    /// Whether the performances are of the same kind and, for DM performances,
+    /// of the same class, so that they can be merged.
+    fn merges_with(&self, other: &Self) -> bool
+    {
+        match (self, other) {
+            (Self::Overall(_), Self::Overall(_)) | (Self::Med(_), Self::Med(_)) => true,
+            (Self::DM(own), Self::DM(other)) => own.class == other.class,
+            _ => false,
+        }
+    }
+
+    /// The performance as an [`OverallPerformance`], if it is one.
+    pub fn as_overall(&self) -> Option<&OverallPerformance>
+    {
+        match self {
+            Self::Overall(overall) => Some(overall),
+            _ => None,
+        }
+    }
+
+    /// The performance as a [`DMPerformance`], if it is one.
+    pub fn as_dm(&self) -> Option<&DMPerformance>
+    {
+        match self {
+            Self::DM(dm) => Some(dm),
+            _ => None,
+        }
+    }
+
+    /// The performance as a [`MedicPerformance`], if it is one.
+    pub fn as_med(&self) -> Option<&MedicPerformance>
+    {
+        match self {
+            Self::Med(medic) => Some(medic),
+            _ => None,
+        }
+    }
+}
+
+/// Merge the `other` performances of a player into their `performances`, like
+/// those of two entries of the same player in a log. The overall, medic and
+/// class performances are each summed up with their counterpart, see
+/// [`OverallPerformance::merge`] and the like. Classes only played in `other`
+/// are added after the classes already played, so the order they are listed
+/// in is kept.
+pub fn merge_performances(performances: &mut Vec<Performance>, other: Vec<Performance>)
+{
+    for performance in other {
+        let own = performances
+            .iter_mut()
+            .find(|own| own.merges_with(&performance));
+        match (own, performance) {
+            (Some(Performance::Overall(own)), Performance::Overall(other)) => own.merge(&other),
+            (Some(Performance::DM(own)), Performance::DM(other)) => own.merge(&other),
+            (Some(Performance::Med(own)), Performance::Med(other)) => own.merge(&other),
+            (_, performance) => performances.push(performance),
+        }
+    }
+}
+
+/// All performances of a player in a single log, sorted by their kind.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogPerformance
+{
+    pub overall: Option<OverallPerformance>,
+    /// The performances on each class the player has played, the class played
+    /// the longest first.
+    pub dm:      Vec<DMPerformance>,
+    pub medic:   Option<MedicPerformance>,
+}
+
+impl LogPerformance
+{
+    /// Get the performance on the class, if the player has played it.
+    pub fn class(&self, class: Class) -> Option<&DMPerformance>
+    {
+        self.dm.iter().find(|dm| dm.class == class)
+    }
+
+    /// Get the performance on the class together with the share of the time
+    /// the player has spent on it. The overall performance covers all classes,
+    /// so the view tells how much of it can be attributed to the class.
+    ///
+    /// # Returns
+    /// `None` if the player has not played the class.
+    pub fn for_class(&self, class: Class) -> Option<ClassView<'_>>
+    {
+        let dm = self.class(class)?;
+        let total_secs: u32 = self.dm.iter().map(|dm| dm.time_played_secs).sum();
+        let time_fraction = match total_secs {
+            0 => 0.,
+            secs => dm.time_played_secs as f32 / secs as f32,
+        };
+
+        Some(ClassView {
+            dm,
+            medic: self.medic.as_ref().filter(|_| class == Class::Medic),
+            time_fraction,
+        })
+    }
+
+    /// The class the player has played the longest, if they have played at
+    /// all. Logs are only counted for this class by default, see
+    /// [`QueryOptions::only_primary_class`](crate::QueryOptions::only_primary_class).
+    pub fn primary_class(&self) -> Option<Class>
+    {
+        self.dm
+            .first()
+            .filter(|dm| dm.time_played_secs > 0)
+            .map(|dm| dm.class)
+    }
+}
+
+/// The performance of a player on a single class of a log, created with
+/// [`LogPerformance::for_class`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClassView<'a>
+{
+    pub dm:            &'a DMPerformance,
+    /// The medic stats, only if the class is medic.
+    pub medic:         Option<&'a MedicPerformance>,
+    /// The fraction of the time the player has played in the log that has
+    /// been spent on the class, between `0` and `1`.
+    pub time_fraction: f32,
+}
+
+impl ClassView<'_>
+{
+    /// The damage per minute on the class, relative to the time played on it
+    /// instead of the length of the log. `None` if the class has been played
+    /// for no time.
+    pub fn damage_per_minute(&self) -> Option<f32>
+    {
+        match self.dm.time_played_secs {
+            0 => None,
+            secs => Some(self.dm.damage as f32 * 60. / secs as f32),
+        }
+    }
+}
+
+impl From<Vec<Performance>> for LogPerformance
+{
+    fn from(performances: Vec<Performance>) -> Self
+    {
+        let mut log_performance = Self::default();
+        for performance in performances {
+            match performance {
+                Performance::Overall(overall) => log_performance.overall = Some(overall),
+                Performance::DM(dm) => log_performance.dm.push(dm),
+                Performance::Med(medic) => log_performance.medic = Some(medic),
+            }
+        }
+        log_performance
+            .dm
+            .sort_by_key(|dm| Reverse(dm.time_played_secs));
+
+        log_performance
+    }
+}
+
+impl From<LogPerformance> for Vec<Performance>
+{
+    fn from(log_performance: LogPerformance) -> Self
+    {
+        let mut performances: Vec<Performance> = log_performance
+            .overall
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        performances.extend(log_performance.dm.into_iter().map(Into::into));
+        performances.extend(log_performance.medic.into_iter().map(Into::into));
+
         performances
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{Log, LogId, SteamID};
+
+    /// The performances of the `player` in the test log.
+    fn performances_of(player: &str) -> Vec<Performance>
+    {
+        let json = fs::read_to_string("test_data/log_3094861.json").expect("Unable to read file");
+        let json = json::parse(&json).expect("Unable to parse json");
+        let player = SteamID::from_str(player).unwrap();
+
+        Log::from_json(LogId(3094861), &json)
+            .unwrap()
+            .performance_of(player)
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn conversions()
+    {
+        // The overall, DM and medic performance of the medic of the test log.
+        let performances = performances_of("[U:1:71020853]");
+        let LogPerformance { overall, dm, medic } = performances.clone().into();
+        let (overall, dm, medic) = (overall.unwrap(), dm[0].clone(), medic.unwrap());
+
+        let performance = Performance::from(dm.clone());
+        assert_eq!(performance, Performance::DM(dm.clone()));
+        assert_eq!(performance.as_dm(), Some(&dm));
+        assert_eq!(performance.as_overall(), None);
+        assert_eq!(performance.as_med(), None);
+        let dm_perf: DMPerformance = performance.clone().try_into().unwrap();
+        assert_eq!(dm_perf, dm);
+        assert_eq!(
+            OverallPerformance::try_from(performance.clone()),
+            Err(performance.clone())
+        );
+        assert_eq!(
+            MedicPerformance::try_from(performance.clone()),
+            Err(performance)
+        );
+
+        let performance: Performance = overall.clone().into();
+        assert_eq!(performance.as_overall(), Some(&overall));
+        assert_eq!(performance.as_med(), None);
+        assert_eq!(OverallPerformance::try_from(performance), Ok(overall));
+
+        let performance: Performance = medic.clone().into();
+        assert_eq!(performance.as_med(), Some(&medic));
+        assert_eq!(performance.as_dm(), None);
+        assert_eq!(
+            DMPerformance::try_from(performance.clone()),
+            Err(performance)
+        );
+        assert_eq!(
+            performances
+                .iter()
+                .filter_map(Performance::as_med)
+                .collect::<Vec<_>>(),
+            vec![&medic]
+        );
+    }
+
+    #[test]
+    fn for_class()
+    {
+        // The scout has also played engineer and pyro.
+        let scout = LogPerformance::from(performances_of("[U:1:886717065]"));
+        let view = scout.for_class(Class::Scout).unwrap();
+        assert_eq!(view.dm.class, Class::Scout);
+        assert_eq!(view.dm.damage, 6671);
+        assert_eq!(view.medic, None);
+        let total_secs: u32 = scout.dm.iter().map(|dm| dm.time_played_secs).sum();
+        assert_eq!(view.time_fraction, 1618. / total_secs as f32);
+        assert_eq!(view.damage_per_minute(), Some(6671. * 60. / 1618.));
+
+        let engineer = scout.for_class(Class::Engineer).unwrap();
+        assert_eq!(engineer.dm.class, Class::Engineer);
+        assert!(engineer.time_fraction < view.time_fraction);
+        let fractions: f32 = [Class::Scout, Class::Engineer, Class::Pyro]
+            .into_iter()
+            .map(|class| scout.for_class(class).unwrap().time_fraction)
+            .sum();
+        assert!(fractions <= 1. + f32::EPSILON);
+        assert!(fractions > 0.99);
+        assert_eq!(scout.for_class(Class::Medic), None);
+
+        let medic = LogPerformance::from(performances_of("[U:1:71020853]"));
+        let view = medic.for_class(Class::Medic).unwrap();
+        assert_eq!(view.medic, medic.medic.as_ref());
+        assert_eq!(view.time_fraction, 1.);
+    }
+
+    #[test]
+    fn merge()
+    {
+        let medic = performances_of("[U:1:71020853]");
+        let mut merged = medic.clone();
+        merge_performances(&mut merged, medic.clone());
+        assert_eq!(merged.len(), medic.len());
+
+        let LogPerformance { overall, dm, medic } = medic.into();
+        let (overall, medic) = (overall.unwrap(), medic.unwrap());
+        let twice = LogPerformance::from(merged);
+        assert_eq!(twice.overall.as_ref().unwrap().damage, 2 * overall.damage);
+        assert_eq!(twice.overall.unwrap().won_rounds, overall.won_rounds);
+        assert_eq!(twice.dm[0].time_played_secs, 2 * dm[0].time_played_secs);
+        let twice = twice.medic.unwrap();
+        assert_eq!(twice.healing, 2 * medic.healing);
+        assert_eq!(twice.num_ubers, 2 * medic.num_ubers);
+        // The average and the longest of both are the same as for either.
+        assert_eq!(
+            twice.average_uber_length_secs,
+            medic.average_uber_length_secs
+        );
+        assert_eq!(
+            twice.biggest_advantage_lost_secs,
+            medic.biggest_advantage_lost_secs
+        );
+        let doubled: Vec<_> = medic
+            .heal_targets
+            .iter()
+            .map(|&(target, healing)| (target, 2 * healing))
+            .collect();
+        assert_eq!(twice.heal_targets, doubled);
+
+        // Classes not played before are added.
+        let mut scout = performances_of("[U:1:886717065]");
+        merge_performances(&mut scout, dm.into_iter().map(Into::into).collect());
+        assert_eq!(
+            scout.last().and_then(Performance::as_dm).unwrap().class,
+            Class::Medic
+        );
+    }
+}
@@ -2,39 +2,57 @@ use std::str::FromStr;
 
 use json::JsonValue;
 
+use crate::logs_tf::{json_num_u16, json_num_u32, LogParseError};
 use crate::score::{Score, Team};
 use crate::Performance;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct OverallPerformance
 {
-    pub won_rounds:   u8,
-    pub num_rounds:   u8,
-    pub damage:       u32,
-    pub damage_taken: u32,
-    pub kills:        u8,
-    pub deaths:       u8,
-    pub num_medkits:  u16,
-    pub medkits_hp:   u32,
+    /// The team the player has played in. `None` for stats that have been
+    /// stored before the team was recorded.
+    pub team:           Option<Team>,
+    pub won_rounds:     u8,
+    pub num_rounds:     u8,
+    pub damage:         u32,
+    pub damage_taken:   u32,
+    pub kills:          u16,
+    pub deaths:         u16,
+    pub num_medkits:    u16,
+    pub medkits_hp:     u32,
+    /// The number of control points the player has helped to capture.
+    pub captures:       u16,
+    /// The healing the player has received. `0` for stats that have been
+    /// stored before it was recorded.
+    pub heals_received: u32,
 }
 
 impl OverallPerformance
 {
-    pub fn from_json(score: &Score, json: &JsonValue) -> Self
+    /// Read the overall performance from the json of a player.
+    ///
+    /// # Returns
+    /// [`LogParseError::InvalidField`] if the team of the player is missing or
+    /// invalid.
+    pub fn from_json(score: &Score, json: &JsonValue) -> Result<Self, LogParseError>
     {
-        let team = Team::from_str(json["team"].as_str().unwrap()).unwrap();
-        let won_rounds = score.get_score(team);
-        let lost_rounds = score.get_score(team.other());
-        let num_rounds = won_rounds + lost_rounds;
-
-        let damage = json["dmg"].as_u32().unwrap_or(0);
-        let damage_taken = json["dt"].as_u32().unwrap_or(0);
-        let kills = json["kills"].as_u8().unwrap_or(0);
-        let deaths = json["deaths"].as_u8().unwrap_or(0);
-        let num_medkits = json["medkits"].as_u16().unwrap_or(0);
-        let medkits_hp = json["medkits_hp"].as_u32().unwrap_or(0);
-
-        Self {
+        let team = json["team"]
+            .as_str()
+            .and_then(|team| Team::from_str(team).ok())
+            .ok_or(LogParseError::InvalidField("team"))?;
+        let (won_rounds, num_rounds) = score.rounds(team);
+
+        let damage = json_num_u32(&json["dmg"]).unwrap_or(0);
+        let damage_taken = json_num_u32(&json["dt"]).unwrap_or(0);
+        let kills = json_num_u16(&json["kills"]).unwrap_or(0);
+        let deaths = json_num_u16(&json["deaths"]).unwrap_or(0);
+        let num_medkits = json_num_u16(&json["medkits"]).unwrap_or(0);
+        let medkits_hp = json_num_u32(&json["medkits_hp"]).unwrap_or(0);
+        let captures = json_num_u16(&json["cpc"]).unwrap_or(0);
+        let heals_received = json_num_u32(&json["hr"]).unwrap_or(0);
+
+        Ok(Self {
+            team: Some(team),
             won_rounds,
             num_rounds,
             damage,
@@ -43,11 +61,41 @@ impl OverallPerformance
             deaths,
             num_medkits,
             medkits_hp,
-        }
+            captures,
+            heals_received,
+        })
+    }
+
+    /// Add the stats of `other`, for instance those of a second entry of the
+    /// player in the same log. The team and the rounds follow from the score
+    /// of the log, so they are kept as they are.
+    pub fn merge(&mut self, other: &Self)
+    {
+        self.damage = self.damage.saturating_add(other.damage);
+        self.damage_taken = self.damage_taken.saturating_add(other.damage_taken);
+        self.kills = self.kills.saturating_add(other.kills);
+        self.deaths = self.deaths.saturating_add(other.deaths);
+        self.num_medkits = self.num_medkits.saturating_add(other.num_medkits);
+        self.medkits_hp = self.medkits_hp.saturating_add(other.medkits_hp);
+        self.captures = self.captures.saturating_add(other.captures);
+        self.heals_received = self.heals_received.saturating_add(other.heals_received);
     }
 }
 
-impl Into<Performance> for OverallPerformance
+impl From<OverallPerformance> for Performance
 {
-    fn into(self) -> Performance { Performance::Overall(self) }
+    fn from(performance: OverallPerformance) -> Self { Self::Overall(performance) }
+}
+
+impl TryFrom<Performance> for OverallPerformance
+{
+    type Error = Performance;
+
+    fn try_from(performance: Performance) -> Result<Self, Self::Error>
+    {
+        match performance {
+            Performance::Overall(performance) => Ok(performance),
+            performance => Err(performance),
+        }
+    }
 }
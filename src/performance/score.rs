@@ -1,14 +1,69 @@
 use std::str::FromStr;
 
 use json::JsonValue;
+use num_derive::FromPrimitive;
+
+use crate::logs_tf::{json_num_u8, LogParseError};
 
 pub struct Score
 {
-    red:  u8,
-    blue: u8,
+    red:    u8,
+    blue:   u8,
+    format: MatchFormat,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// How a game is played and scored, which decides what the score of a team
+/// means.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive)]
+pub enum MatchFormat
+{
+    /// Symmetrical control points, the score is the number of rounds won.
+    FiveCp,
+    /// Attack and defend maps, where both teams attack once and the faster
+    /// attack wins the map. The score does not count rounds.
+    Stopwatch,
+    /// King of the hill, the score is the number of rounds won.
+    Koth,
+    /// Any other game mode. The score is taken as the number of rounds won.
+    Other,
+}
+
+/// Control point maps that are played as attack and defend in stopwatch.
+const STOPWATCH_CP_MAPS: [&str; 10] = [
+    "cp_dustbowl",
+    "cp_egypt",
+    "cp_gorge",
+    "cp_gravelpit",
+    "cp_junction",
+    "cp_manor",
+    "cp_mercenarypark",
+    "cp_mountainlab",
+    "cp_snowplow",
+    "cp_steel",
+];
+
+impl MatchFormat
+{
+    /// Find out how a game on the map is played from the name of the map.
+    pub fn from_map(map: &str) -> Self
+    {
+        let map = map.trim().to_ascii_lowercase();
+        if map.starts_with("pl_") || STOPWATCH_CP_MAPS.iter().any(|cp| map.starts_with(cp)) {
+            Self::Stopwatch
+        }
+        else if map.starts_with("koth_") {
+            Self::Koth
+        }
+        else if map.starts_with("cp_") {
+            Self::FiveCp
+        }
+        else {
+            Self::Other
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive)]
 pub enum Team
 {
     Red,
@@ -17,14 +72,22 @@ pub enum Team
 
 impl Score
 {
-    pub fn new(red: u8, blue: u8) -> Self { Self { red, blue } }
+    pub fn new(red: u8, blue: u8, format: MatchFormat) -> Self { Self { red, blue, format } }
 
-    pub fn from_json(json: &JsonValue) -> Self
+    /// Read the score from the json of a log.
+    ///
+    /// # Returns
+    /// [`LogParseError::InvalidField`] if the score of a team is missing or
+    /// not a number.
+    pub fn from_json(json: &JsonValue) -> Result<Self, LogParseError>
     {
-        let red = json["teams"]["Red"]["score"].as_u8().unwrap();
-        let blue = json["teams"]["Blue"]["score"].as_u8().unwrap();
+        let score = |team: &str| {
+            json_num_u8(&json["teams"][team]["score"]).ok_or(LogParseError::InvalidField("score"))
+        };
+        let (red, blue) = (score("Red")?, score("Blue")?);
+        let format = MatchFormat::from_map(json["info"]["map"].as_str().unwrap_or_default());
 
-        Self { red, blue }
+        Ok(Self { red, blue, format })
     }
 
     pub fn get_score(&self, team: Team) -> u8
@@ -34,6 +97,23 @@ impl Score
             Team::Blue => self.blue,
         }
     }
+
+    pub fn format(&self) -> MatchFormat { self.format }
+
+    /// The number of rounds the `team` has won and the number of rounds that
+    /// have been played. A stopwatch game counts as a single round won by the
+    /// team with the higher score, or as no round at all if it is a tie.
+    pub fn rounds(&self, team: Team) -> (u8, u8)
+    {
+        let won = self.get_score(team);
+        let lost = self.get_score(team.other());
+
+        match self.format {
+            MatchFormat::Stopwatch if won == lost => (0, 0),
+            MatchFormat::Stopwatch => ((won > lost) as u8, 1),
+            _ => (won, won.saturating_add(lost)),
+        }
+    }
 }
 
 impl Team
@@ -53,10 +133,60 @@ impl FromStr for Team
 
     fn from_str(s: &str) -> Result<Self, Self::Err>
     {
-        match s.trim().to_lowercase().as_str() {
-            "red" => Ok(Self::Red),
-            "blue" => Ok(Self::Blue),
-            _ => Err(()),
+        // Called for every player of every log, so avoid allocating a lowercase copy.
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("red") {
+            Ok(Self::Red)
+        }
+        else if s.eq_ignore_ascii_case("blue") {
+            Ok(Self::Blue)
         }
+        else {
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn format_from_map()
+    {
+        assert_eq!(MatchFormat::from_map("cp_process_f12"), MatchFormat::FiveCp);
+        assert_eq!(
+            MatchFormat::from_map("cp_steel_f12"),
+            MatchFormat::Stopwatch
+        );
+        assert_eq!(
+            MatchFormat::from_map("pl_upward_f10"),
+            MatchFormat::Stopwatch
+        );
+        assert_eq!(
+            MatchFormat::from_map("koth_product_final"),
+            MatchFormat::Koth
+        );
+        assert_eq!(MatchFormat::from_map("KOTH_Bagel_rc5"), MatchFormat::Koth);
+        assert_eq!(MatchFormat::from_map("ultiduo_baloo"), MatchFormat::Other);
+        assert_eq!(MatchFormat::from_map(""), MatchFormat::Other);
+    }
+
+    #[test]
+    fn rounds()
+    {
+        let five_cp = Score::new(5, 3, MatchFormat::FiveCp);
+        assert_eq!(five_cp.rounds(Team::Red), (5, 8));
+        assert_eq!(five_cp.rounds(Team::Blue), (3, 8));
+
+        let koth = Score::new(2, 4, MatchFormat::Koth);
+        assert_eq!(koth.rounds(Team::Blue), (4, 6));
+
+        let stopwatch = Score::new(2, 1, MatchFormat::Stopwatch);
+        assert_eq!(stopwatch.rounds(Team::Red), (1, 1));
+        assert_eq!(stopwatch.rounds(Team::Blue), (0, 1));
+        let tie = Score::new(1, 1, MatchFormat::Stopwatch);
+        assert_eq!(tie.rounds(Team::Red), (0, 0));
     }
 }
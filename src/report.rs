@@ -0,0 +1,731 @@
+//! Summaries of what has happened on the mixes during a period of time, like
+//! the weekly recap posted by the admins.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::medic_performance::MedicEfficiency;
+use crate::{
+    Class, Database, LogId, LogMetadata, LogPerformance, MixesDbResult, QueryOptions, SteamID,
+};
+
+/// The number of single-game performances that are listed for each stat.
+pub const NUM_TOP_GAMES: usize = 3;
+/// The number of most active players listed in the markdown summary.
+const NUM_ACTIVE_PLAYERS: usize = 5;
+
+/// A stat of a player in a single game, like their damage per minute.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameStat
+{
+    pub log_id: LogId,
+    pub player: SteamID,
+    pub value:  f32,
+}
+
+/// What has happened during a period of time, as created by
+/// [`generate_period_report`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeriodReport
+{
+    /// Start of the period, inclusive.
+    pub from:            DateTime<Utc>,
+    /// End of the period, exclusive.
+    pub to:              DateTime<Utc>,
+    pub num_logs:        usize,
+    /// The number of the logs that are linked to an official match, see
+    /// [`Database::link_external_match`]. The other logs are mixes.
+    pub num_officials:   usize,
+    /// Every player that has played in the period with the number of games
+    /// they have played. The player with the most games comes first, ties are
+    /// ordered by steam id.
+    pub games_played:    Vec<(SteamID, u32)>,
+    /// The games with the highest damage per minute over all classes played,
+    /// best first.
+    pub best_dpm:        Vec<GameStat>,
+    /// The games with the most healing done, best first.
+    pub best_healing:    Vec<GameStat>,
+    /// The map that has been played the most together with the number of times
+    /// it has been played. Ties are broken alphabetically. `None` if nothing
+    /// has been played.
+    pub most_played_map: Option<(String, u32)>,
+    /// The player that has captured the most control points over all games of
+    /// the period, together with the number of captures. Ties go to the lower
+    /// steam id. `None` if nothing has been captured.
+    pub most_captures:   Option<(SteamID, u32)>,
+    /// The names of the players that have one in the database.
+    pub names:           HashMap<SteamID, String>,
+}
+
+/// The games a player has played in one sitting, like a mix night, as created
+/// by [`session_summary`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionSummary
+{
+    /// Start of the first game of the session.
+    pub start: DateTime<Utc>,
+    /// End of the game of the session that has ended last.
+    pub end:   DateTime<Utc>,
+    /// The logs of the games of the session, oldest first.
+    pub logs:  Vec<LogId>,
+    /// The number of games the team of the player has won.
+    pub wins:  u32,
+    /// The damage per minute over all classes except medic. `None` if only
+    /// medic has been played.
+    pub dpm:   Option<f32>,
+    /// The medic stats summed up over the session. `None` if medic has not
+    /// been played.
+    pub medic: Option<SessionMedicStats>,
+}
+
+/// The medic stats of a player summed up over a session, see
+/// [`SessionSummary::medic`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionMedicStats
+{
+    pub healing:          u64,
+    pub num_ubers:        u32,
+    pub num_drops:        u32,
+    pub time_played_secs: u32,
+}
+
+impl SessionSummary
+{
+    /// The number of games played in the session, which is never zero.
+    pub fn num_games(&self) -> usize { self.logs.len() }
+
+    /// The fraction of the games of the session that have been won, between
+    /// `0.0` and `1.0`.
+    pub fn win_rate(&self) -> f32 { self.wins as f32 / self.num_games() as f32 }
+}
+
+impl SessionMedicStats
+{
+    /// The efficiency over all medic games of the session. Longer games count
+    /// for more than short ones, as if the session had been a single game.
+    ///
+    /// # Returns
+    /// `None` if no time has been played as medic.
+    pub fn efficiency(&self) -> Option<MedicEfficiency>
+    {
+        MedicEfficiency::from_totals(
+            self.healing,
+            self.num_ubers,
+            self.num_drops,
+            self.time_played_secs,
+        )
+    }
+}
+
+/// Sum up the logs played between `from` (inclusive) and `to` (exclusive). Only
+/// the stats stored in the `db` are considered, so with a database that only
+/// keeps the stats of registered players, only they are part of the report.
+pub fn generate_period_report(
+    db: &mut dyn Database,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> MixesDbResult<PeriodReport>
+{
+    let logs = db.log_performances_between(from, to)?;
+
+    let mut games_played: HashMap<SteamID, u32> = HashMap::new();
+    let mut maps: HashMap<&str, u32> = HashMap::new();
+    let mut captures: HashMap<SteamID, u32> = HashMap::new();
+    let mut best_dpm = Vec::new();
+    let mut best_healing = Vec::new();
+    for (meta, performances) in &logs {
+        *maps.entry(&meta.map).or_default() += 1;
+        for (&player, performance) in performances {
+            *games_played.entry(player).or_default() += 1;
+            if let Some(overall) = &performance.overall {
+                *captures.entry(player).or_default() += overall.captures as u32;
+            }
+
+            let stat = |value| GameStat {
+                log_id: meta.id,
+                player,
+                value,
+            };
+            if let Some(dpm) = damage_per_minute(performance) {
+                best_dpm.push(stat(dpm));
+            }
+            if let Some(medic) = &performance.medic {
+                best_healing.push(stat(medic.healing as f32));
+            }
+        }
+    }
+
+    let mut games_played: Vec<(SteamID, u32)> = games_played.into_iter().collect();
+    games_played.sort_unstable_by_key(|&(player, games)| (Reverse(games), player.id64()));
+
+    let most_played_map = maps
+        .into_iter()
+        .min_by_key(|&(map, times)| (Reverse(times), map))
+        .map(|(map, times)| (map.to_owned(), times));
+    let most_captures = captures
+        .into_iter()
+        .filter(|&(_, captures)| captures > 0)
+        .min_by_key(|&(player, captures)| (Reverse(captures), player.id64()));
+
+    let mut num_officials = 0;
+    for (meta, _) in &logs {
+        if db.external_match(meta.id)?.is_some() {
+            num_officials += 1;
+        }
+    }
+
+    let mut names = HashMap::new();
+    for &(player, _) in &games_played {
+        if let Some(name) = db.username(player)? {
+            names.insert(player, name);
+        }
+    }
+
+    Ok(PeriodReport {
+        from,
+        to,
+        num_logs: logs.len(),
+        num_officials,
+        games_played,
+        best_dpm: top_games(best_dpm),
+        best_healing: top_games(best_healing),
+        most_played_map,
+        most_captures,
+        names,
+    })
+}
+
+/// Group the games of the `user` stored in the `db` into sessions, like the mix
+/// nights they have played in, and sum up each of them. A game starts at the
+/// date of its log and lasts as long as the player has played in it. A game
+/// that starts less than `session_gap` after the end of the previous games
+/// belongs to the same session as them, just like a game that overlaps with
+/// them, for instance because the log has been uploaded twice.
+///
+/// # Returns
+/// The sessions, the newest first.
+pub fn session_summary(
+    db: &mut dyn Database,
+    user: SteamID,
+    session_gap: Duration,
+) -> MixesDbResult<Vec<SessionSummary>>
+{
+    let options = QueryOptions {
+        only_primary_class: false,
+        ..QueryOptions::default()
+    };
+    let mut games = db.get_classes_performance(user, &Class::ALL, usize::MAX, &options)?;
+    games.sort_by_key(|(id, meta, _)| (meta.date_time, *id));
+
+    let spans: Vec<(DateTime<Utc>, DateTime<Utc>)> = games
+        .iter()
+        .map(|(_, meta, performance)| {
+            let secs: u32 = performance.dm.iter().map(|dm| dm.time_played_secs).sum();
+            let end = meta.date_time + Duration::seconds(secs as i64);
+            (meta.date_time, end)
+        })
+        .collect();
+
+    Ok(group_sessions(&spans, session_gap)
+        .into_iter()
+        .rev()
+        .map(|range| summarize_session(&games[range.clone()], &spans[range]))
+        .collect())
+}
+
+/// Split games into sessions, given the start and end of each game, ordered by
+/// their start. A game belongs to the session before it if it starts less than
+/// the `session_gap` after the latest end of the games in that session, which
+/// includes games that overlap with it.
+///
+/// # Returns
+/// The range of the indices of the games of each session, in order.
+fn group_sessions(
+    spans: &[(DateTime<Utc>, DateTime<Utc>)],
+    session_gap: Duration,
+) -> Vec<Range<usize>>
+{
+    let mut sessions = Vec::new();
+    let mut session_start = 0;
+    let mut session_end = None;
+    for (i, &(start, end)) in spans.iter().enumerate() {
+        match session_end {
+            Some(previous_end) if start - previous_end < session_gap => {
+                session_end = Some(end.max(previous_end));
+            },
+            Some(_) => {
+                sessions.push(session_start..i);
+                session_start = i;
+                session_end = Some(end);
+            },
+            None => session_end = Some(end),
+        }
+    }
+    if !spans.is_empty() {
+        sessions.push(session_start..spans.len());
+    }
+
+    sessions
+}
+
+/// Sum up the `games` of a session, with the start and end of each of them
+/// in `spans`.
+fn summarize_session(
+    games: &[(LogId, LogMetadata, LogPerformance)],
+    spans: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> SessionSummary
+{
+    let mut wins = 0;
+    let mut damage: u64 = 0;
+    let mut dm_secs = 0;
+    let mut medic: Option<SessionMedicStats> = None;
+    for (_, _, performance) in games {
+        if let Some(overall) = &performance.overall {
+            if overall.won_rounds as u32 * 2 > overall.num_rounds as u32 {
+                wins += 1;
+            }
+        }
+        for dm in performance.dm.iter().filter(|dm| dm.class != Class::Medic) {
+            damage += dm.damage as u64;
+            dm_secs += dm.time_played_secs;
+        }
+        if let Some(medic_performance) = &performance.medic {
+            let stats = medic.get_or_insert_with(SessionMedicStats::default);
+            stats.healing += medic_performance.healing as u64;
+            stats.num_ubers += medic_performance.num_ubers as u32;
+            stats.num_drops += medic_performance.num_drops as u32;
+            stats.time_played_secs += medic_performance.time_played_secs;
+        }
+    }
+
+    let (start, first_end) = spans[0];
+    let end = spans
+        .iter()
+        .map(|&(_, end)| end)
+        .fold(first_end, DateTime::max);
+
+    SessionSummary {
+        start,
+        end,
+        logs: games.iter().map(|&(id, ..)| id).collect(),
+        wins,
+        dpm: (dm_secs != 0).then(|| damage as f32 * 60. / dm_secs as f32),
+        medic,
+    }
+}
+
+/// The damage per minute over all classes played in the game. `None` if no
+/// class has been played.
+fn damage_per_minute(performance: &LogPerformance) -> Option<f32>
+{
+    let damage: u64 = performance.dm.iter().map(|dm| dm.damage as u64).sum();
+    let secs: u32 = performance.dm.iter().map(|dm| dm.time_played_secs).sum();
+
+    match secs {
+        0 => None,
+        secs => Some(damage as f32 * 60. / secs as f32),
+    }
+}
+
+/// Keep the [`NUM_TOP_GAMES`] best stats. Ties are ordered by log and then by
+/// player, so the result does not depend on the order the stats are read in.
+fn top_games(mut stats: Vec<GameStat>) -> Vec<GameStat>
+{
+    stats.sort_unstable_by(|a, b| {
+        b.value
+            .total_cmp(&a.value)
+            .then(a.log_id.cmp(&b.log_id))
+            .then(a.player.id64().cmp(&b.player.id64()))
+    });
+    stats.truncate(NUM_TOP_GAMES);
+
+    stats
+}
+
+/// Escape the characters Discord would read as formatting.
+fn escape_markdown(text: &str) -> String
+{
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '~' | '`' | '|' | '>' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+impl PeriodReport
+{
+    /// The number of different players that have played in the period.
+    pub fn num_participants(&self) -> usize { self.games_played.len() }
+
+    /// The name of the player as it is written in the markdown summary, or
+    /// their steamID64 if there is no name for them.
+    fn name_of(&self, player: SteamID) -> String
+    {
+        match self.names.get(&player) {
+            Some(name) => escape_markdown(name),
+            None => player.to_id64_string(),
+        }
+    }
+
+    /// Write the report as a summary that can be pasted into Discord.
+    pub fn to_markdown(&self) -> String
+    {
+        let mut out = String::new();
+        // Writing to a string cannot fail.
+        let _ = self.write_markdown(&mut out);
+
+        out
+    }
+
+    fn write_markdown(&self, out: &mut String) -> std::fmt::Result
+    {
+        writeln!(
+            out,
+            "**Mixes recap {} to {}**",
+            self.from.format("%Y-%m-%d"),
+            self.to.format("%Y-%m-%d")
+        )?;
+        if self.num_logs == 0 {
+            return writeln!(out, "No mixes have been played.");
+        }
+
+        write!(out, "Games played: {}", self.num_logs)?;
+        if self.num_officials > 0 {
+            write!(
+                out,
+                " (officials: {}, mixes: {})",
+                self.num_officials,
+                self.num_logs - self.num_officials
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "Players: {}", self.num_participants())?;
+        if let Some((map, times)) = &self.most_played_map {
+            writeln!(
+                out,
+                "Most played map: {} ({} games)",
+                escape_markdown(map),
+                times
+            )?;
+        }
+        if let Some((player, captures)) = self.most_captures {
+            writeln!(
+                out,
+                "Most captures: {} ({} caps)",
+                self.name_of(player),
+                captures
+            )?;
+        }
+
+        writeln!(out, "\n**Most active players**")?;
+        for (rank, &(player, games)) in self
+            .games_played
+            .iter()
+            .take(NUM_ACTIVE_PLAYERS)
+            .enumerate()
+        {
+            writeln!(
+                out,
+                "{}. {} ({} games)",
+                rank + 1,
+                self.name_of(player),
+                games
+            )?;
+        }
+
+        for (title, unit, precision, stats) in [
+            ("Best DPM", "DPM", 1, &self.best_dpm),
+            ("Best medic games", "healing", 0, &self.best_healing),
+        ] {
+            if stats.is_empty() {
+                continue;
+            }
+
+            writeln!(out, "\n**{}**", title)?;
+            for (rank, stat) in stats.iter().enumerate() {
+                writeln!(
+                    out,
+                    "{}. {}: {:.*} {} in <https://logs.tf/{}>",
+                    rank + 1,
+                    self.name_of(stat.player),
+                    precision,
+                    stat.value,
+                    unit,
+                    stat.log_id
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::str::FromStr;
+
+    use chrono::TimeZone;
+    use json::JsonValue;
+
+    use super::*;
+    use crate::sql_db::SQLDb;
+    use crate::{League, Log};
+
+    fn fixture_json(timestamp: i64) -> JsonValue
+    {
+        let json = fs::read_to_string("test_data/log_3094861.json").expect("Unable to read log");
+        let mut json = json::parse(&json).expect("Unable to parse json");
+        json["info"]["date"] = timestamp.into();
+
+        json
+    }
+
+    fn at(timestamp: i64) -> DateTime<Utc> { Utc.timestamp_opt(timestamp, 0).unwrap() }
+
+    /// Three logs played between `at(0)` and `at(4000)` and one played after.
+    fn seeded_db() -> SQLDb
+    {
+        let mut db = SQLDb::temporary("host=localhost user=mixes dbname=mixes-stats")
+            .expect("Unable to connect to the database");
+
+        let mut third = fixture_json(3000);
+        third["info"]["map"] = "cp_process_f12".into();
+        // One of the demomen has not played in the last game.
+        third["players"].remove("[U:1:57603258]");
+        for class_stats in third["players"]["[U:1:886717065]"]["class_stats"].members_mut() {
+            if class_stats["type"] == "scout" {
+                class_stats["dmg"] = 20000.into();
+            }
+        }
+
+        let mut second = fixture_json(2000);
+        second["players"]["[U:1:71020853]"]["heal"] = 25000.into();
+
+        for (id, json) in [
+            (1, fixture_json(1000)),
+            (2, second),
+            (3, third),
+            (4, fixture_json(4000)),
+        ] {
+            db.add_log(Log::from_json(LogId(id), &json).unwrap())
+                .unwrap();
+        }
+
+        for (discord_id, (player, name)) in [
+            ("[U:1:886717065]", "cklips"),
+            ("[U:1:125648953]", "plutia_"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let player = SteamID::from_str(player).unwrap();
+            db.add_user(player, discord_id as u64).unwrap();
+            db.set_username(player, name).unwrap();
+        }
+
+        db
+    }
+
+    #[test]
+    fn period_report()
+    {
+        let mut db = seeded_db();
+        let report = generate_period_report(&mut db, at(0), at(4000)).unwrap();
+
+        assert_eq!(report.num_logs, 3);
+        assert_eq!(report.num_officials, 0);
+        assert_eq!(report.num_participants(), 12);
+        assert_eq!(
+            report.games_played.last(),
+            Some(&(SteamID::from_str("[U:1:57603258]").unwrap(), 2))
+        );
+        assert_eq!(report.most_played_map, Some(("cp_sunshine".to_owned(), 2)));
+        // 12 captures in each of the games.
+        assert_eq!(
+            report.most_captures,
+            Some((SteamID::from_str("[U:1:838576271]").unwrap(), 36))
+        );
+        assert_eq!(report.best_dpm.len(), NUM_TOP_GAMES);
+        assert_eq!(report.best_dpm[0].log_id, LogId(3));
+        assert_eq!(report.best_healing.len(), NUM_TOP_GAMES);
+
+        // Players without a name are shown by their steamID64.
+        assert_eq!(
+            report.to_markdown(),
+            concat!(
+                "**Mixes recap 1970-01-01 to 1970-01-01**\n",
+                "Games played: 3\n",
+                "Players: 12\n",
+                "Most played map: cp\\_sunshine (2 games)\n",
+                "Most captures: 76561198798841999 (36 caps)\n",
+                "\n",
+                "**Most active players**\n",
+                "1. 76561198031286581 (3 games)\n",
+                "2. plutia\\_ (3 games)\n",
+                "3. 76561198102424114 (3 games)\n",
+                "4. 76561198138674625 (3 games)\n",
+                "5. 76561198203657571 (3 games)\n",
+                "\n",
+                "**Best DPM**\n",
+                "1. cklips: 709.5 DPM in <https://logs.tf/3>\n",
+                "2. 76561198017868986: 350.5 DPM in <https://logs.tf/1>\n",
+                "3. 76561198017868986: 350.5 DPM in <https://logs.tf/2>\n",
+                "\n",
+                "**Best medic games**\n",
+                "1. 76561198031286581: 25000 healing in <https://logs.tf/2>\n",
+                "2. plutia\\_: 23463 healing in <https://logs.tf/1>\n",
+                "3. plutia\\_: 23463 healing in <https://logs.tf/2>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn period_report_officials()
+    {
+        let mut db = seeded_db();
+        assert!(db
+            .link_external_match(LogId(2), &League::Etf2l, 1234)
+            .unwrap());
+        // Outside of the period.
+        assert!(db
+            .link_external_match(LogId(4), &League::Etf2l, 1234)
+            .unwrap());
+
+        let report = generate_period_report(&mut db, at(0), at(4000)).unwrap();
+        assert_eq!(report.num_logs, 3);
+        assert_eq!(report.num_officials, 1);
+        assert!(report
+            .to_markdown()
+            .contains("\nGames played: 3 (officials: 1, mixes: 2)\n"));
+    }
+
+    #[test]
+    fn session_grouping()
+    {
+        let game = |start, end| (at(start), at(end));
+        let sessions = |spans: &[(DateTime<Utc>, DateTime<Utc>)]| {
+            group_sessions(spans, Duration::seconds(600))
+        };
+
+        assert_eq!(sessions(&[]), Vec::<Range<usize>>::new());
+        assert_eq!(sessions(&[game(0, 100)]), vec![0..1]);
+        // A gap of exactly the session gap starts a new session.
+        assert_eq!(sessions(&[game(0, 100), game(699, 800)]), vec![0..2]);
+        assert_eq!(sessions(&[game(0, 100), game(700, 800)]), vec![0..1, 1..2]);
+        // Overlapping logs, like a game uploaded twice, are the same session.
+        assert_eq!(
+            sessions(&[game(0, 2000), game(10, 1990), game(2500, 3000)]),
+            vec![0..3]
+        );
+        // The gap is measured from the game that has ended last.
+        let spans = [
+            game(0, 3000),
+            game(100, 200),
+            game(3500, 4000),
+            game(5000, 5100),
+        ];
+        assert_eq!(sessions(&spans), vec![0..3, 3..4]);
+    }
+
+    #[test]
+    fn session_summaries()
+    {
+        let mut db = seeded_db();
+        let mut later = fixture_json(100_000);
+        later["teams"]["Blue"]["score"] = 4.into();
+        db.add_log(Log::from_json(LogId(5), &later).unwrap())
+            .unwrap();
+
+        let medic = SteamID::from_str("[U:1:125648953]").unwrap();
+        let sessions = session_summary(&mut db, medic, Duration::hours(1)).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].logs, vec![LogId(5)]);
+        assert_eq!(
+            sessions[1].logs,
+            vec![LogId(1), LogId(2), LogId(3), LogId(4)]
+        );
+        assert_eq!(sessions[1].start, at(1000));
+        assert_eq!(sessions[1].num_games(), 4);
+        // The fixture is a tie.
+        assert_eq!(sessions[1].win_rate(), 0.);
+        assert_eq!(sessions[1].dpm, None);
+        let medic_stats = sessions[1].medic.unwrap();
+        assert_eq!(medic_stats.healing, 4 * 23463);
+
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let sessions = session_summary(&mut db, scout, Duration::hours(1)).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions[1].dpm.is_some());
+        assert_eq!(sessions[1].medic, None);
+        assert!(sessions[1].end > at(4000));
+
+        // The scout is on blue, which has won the later game.
+        assert_eq!(sessions[0].wins, 1);
+        assert_eq!(sessions[0].win_rate(), 1.);
+    }
+
+    #[test]
+    fn session_medic_efficiency()
+    {
+        let json = fixture_json(1000);
+        let log = Log::from_json(LogId(1), &json).unwrap();
+        let medic = SteamID::from_str("[U:1:125648953]").unwrap();
+        let game = |id, secs, healing, num_ubers, num_drops| {
+            let mut performance = LogPerformance::from(log.performance_of(medic).unwrap().clone());
+            let stats = performance.medic.as_mut().unwrap();
+            stats.time_played_secs = secs;
+            stats.healing = healing;
+            stats.num_ubers = num_ubers;
+            stats.num_drops = num_drops;
+            (LogId(id), log.meta().clone(), performance)
+        };
+
+        // A short game with a lot of ubers and healing, and a long one with few.
+        let games = [game(1, 600, 12000, 4, 2), game(2, 1800, 18000, 6, 0)];
+        let spans = [(at(0), at(600)), (at(700), at(2500))];
+        let efficiency = summarize_session(&games, &spans)
+            .medic
+            .unwrap()
+            .efficiency()
+            .unwrap();
+
+        // 10 ubers in 40 minutes, not the mean of 2 and 1 ubers per 5 minutes.
+        assert_eq!(efficiency.ubers_per_5_min, 1.25);
+        // 2 drops per 10 ubers, not the mean of 0.5 and 0.
+        assert_eq!(efficiency.drops_per_uber, 0.2);
+        // 30000 healing in 2400 seconds, not the mean of 20 and 10 per second.
+        assert!((efficiency.healing_fraction - 30000. / (2400. * 72.)).abs() < 1e-6);
+
+        let no_time = SessionMedicStats {
+            num_ubers: 3,
+            ..SessionMedicStats::default()
+        };
+        assert_eq!(no_time.efficiency(), None);
+    }
+
+    #[test]
+    fn empty_period_report()
+    {
+        let mut db = seeded_db();
+        let report = generate_period_report(&mut db, at(5000), at(6000)).unwrap();
+
+        assert_eq!(report.num_logs, 0);
+        assert!(report.games_played.is_empty());
+        assert_eq!(report.most_played_map, None);
+        assert_eq!(report.most_captures, None);
+        assert_eq!(
+            report.to_markdown(),
+            "**Mixes recap 1970-01-01 to 1970-01-01**\nNo mixes have been played.\n"
+        );
+    }
+}
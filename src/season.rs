@@ -0,0 +1,91 @@
+//! Seasons group the stored logs into periods of time, so that stats can be
+//! looked at per season instead of over the entire history of the database.
+
+use chrono::{DateTime, Utc};
+
+/// Identifier of a season as it is saved in the database.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SeasonId(pub i32);
+
+/// A period of time logs are grouped into.
+///
+/// The start of a season is inclusive, while the end is exclusive. A log that
+/// was played at exactly the end of one season therefore belongs to the season
+/// that starts at that point in time. A season without an end is the current
+/// season, which is still running.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Season
+{
+    pub id:    SeasonId,
+    pub name:  String,
+    pub start: DateTime<Utc>,
+    pub end:   Option<DateTime<Utc>>,
+}
+
+impl Season
+{
+    /// Check if the point in time falls into this season, in accordance with
+    /// the inclusive start and exclusive end of a season.
+    pub fn contains(&self, date_time: DateTime<Utc>) -> bool
+    {
+        self.start <= date_time && self.end.map(|end| date_time < end).unwrap_or(true)
+    }
+
+    /// Check if a season spanning from `start` to `end` would share any point
+    /// in time with this season. An `end` of `None` means the other season is
+    /// open-ended.
+    pub fn overlaps(&self, start: DateTime<Utc>, end: Option<DateTime<Utc>>) -> bool
+    {
+        let starts_before_other_ends = end.map(|end| self.start < end).unwrap_or(true);
+        let other_starts_before_end = self.end.map(|self_end| start < self_end).unwrap_or(true);
+
+        starts_before_other_ends && other_starts_before_end
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn season(start: i64, end: Option<i64>) -> Season
+    {
+        Season {
+            id:    SeasonId(0),
+            name:  "Season".to_owned(),
+            start: Utc.timestamp_opt(start, 0).unwrap(),
+            end:   end.map(|end| Utc.timestamp_opt(end, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn contains_boundaries()
+    {
+        let season = season(100, Some(200));
+
+        assert!(!season.contains(Utc.timestamp_opt(99, 0).unwrap()));
+        assert!(season.contains(Utc.timestamp_opt(100, 0).unwrap()));
+        assert!(season.contains(Utc.timestamp_opt(199, 0).unwrap()));
+        assert!(!season.contains(Utc.timestamp_opt(200, 0).unwrap()));
+    }
+
+    #[test]
+    fn overlaps()
+    {
+        let closed = season(100, Some(200));
+        let open = season(300, None);
+        let at = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+
+        // Touching seasons do not overlap, since the end is exclusive.
+        assert!(!closed.overlaps(at(200), Some(at(300))));
+        assert!(!closed.overlaps(at(0), Some(at(100))));
+        assert!(closed.overlaps(at(150), Some(at(250))));
+        assert!(closed.overlaps(at(0), None));
+
+        assert!(!open.overlaps(at(200), Some(at(300))));
+        assert!(open.overlaps(at(1000), Some(at(2000))));
+        assert!(open.overlaps(at(0), None));
+    }
+}
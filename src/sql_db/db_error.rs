@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::{fmt, io};
+
+use postgres::Error as SqlError;
+
+use crate::logs_tf::{LogParseError, QueryError};
+use crate::LogIdParseError;
+
+/// Any error that may occur when working with the [`SQLDb`](super::SQLDb).
+#[derive(Debug)]
+pub enum DbError
+{
+    /// The connection to the database failed or a query could not be executed.
+    Sql(SqlError),
+    /// No connection of the pool of a [`PooledSqlDb`](super::PooledSqlDb)
+    /// has become free in time, or the pool could not be created.
+    #[cfg(feature = "pool")]
+    Pool(r2d2::Error),
+    /// A log that should be added could not be read.
+    Parse(LogParseError),
+    /// Logs could not be searched for, for instance because logs.tf could not
+    /// be reached.
+    Query(QueryError),
+    /// A reference to a log, like a link to it, does not name a log.
+    LogRef(LogIdParseError),
+    /// The arguments given to a database method do not make sense, for
+    /// instance an empty search string. Contains a description of the problem.
+    InvalidInput(String),
+    /// A snapshot of the database could not be written or read.
+    Io(io::Error),
+    /// The source of the logs is down, so an update that checks its health
+    /// has not been started. Contains a description of the problem.
+    UpstreamUnavailable(String),
+    /// The tables of the database have been migrated by a newer version of
+    /// this crate, so this version does not know the schema they have.
+    /// A stat of a log that should be added is too large for its column,
+    /// so it has not been stored instead of being stored wrapped around.
+    Overflow
+    {
+        /// The name of the stat, like `damage`.
+        stat:  &'static str,
+        value: u64,
+    },
+    SchemaTooNew
+    {
+        /// The version of the schema of the tables.
+        found:     u32,
+        /// The newest version this version of the crate supports, its
+        /// [`SCHEMA_VERSION`](super::SCHEMA_VERSION).
+        supported: u32,
+    },
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+impl From<SqlError> for DbError
+{
+    fn from(e: SqlError) -> Self { Self::Sql(e) }
+}
+
+impl From<io::Error> for DbError
+{
+    fn from(e: io::Error) -> Self { Self::Io(e) }
+}
+
+#[cfg(feature = "pool")]
+impl From<r2d2::Error> for DbError
+{
+    fn from(e: r2d2::Error) -> Self { Self::Pool(e) }
+}
+
+impl From<LogParseError> for DbError
+{
+    fn from(e: LogParseError) -> Self { Self::Parse(e) }
+}
+
+impl From<QueryError> for DbError
+{
+    fn from(e: QueryError) -> Self { Self::Query(e) }
+}
+
+impl From<LogIdParseError> for DbError
+{
+    fn from(e: LogIdParseError) -> Self { Self::LogRef(e) }
+}
+
+impl fmt::Display for DbError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Sql(sql_e) => write!(f, "Database error: {}", sql_e),
+            #[cfg(feature = "pool")]
+            Self::Pool(pool_e) => write!(f, "No database connection available: {}", pool_e),
+            Self::Parse(parse_e) => write!(f, "Unable to read log: {}", parse_e),
+            Self::Query(query_e) => write!(f, "Unable to search logs: {}", query_e),
+            Self::LogRef(ref_e) => write!(f, "Invalid log: {}", ref_e),
+            Self::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+            Self::Io(io_e) => write!(f, "Unable to write or read the snapshot: {}", io_e),
+            Self::UpstreamUnavailable(e) => write!(f, "logs.tf is unavailable: {}", e),
+            Self::Overflow { stat, value } => {
+                write!(f, "A {} of {} is too large to be stored", stat, value)
+            },
+            Self::SchemaTooNew { found, supported } => write!(
+                f,
+                "The database has schema version {}, but only versions up to {} are supported. \
+                 Update mixes-db to use it",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl Error for DbError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)>
+    {
+        match self {
+            Self::Sql(sql_e) => Some(sql_e),
+            #[cfg(feature = "pool")]
+            Self::Pool(pool_e) => Some(pool_e),
+            Self::Parse(parse_e) => Some(parse_e),
+            Self::Query(query_e) => Some(query_e),
+            Self::LogRef(ref_e) => Some(ref_e),
+            Self::Io(io_e) => Some(io_e),
+            Self::InvalidInput(_)
+            | Self::UpstreamUnavailable(_)
+            | Self::Overflow { .. }
+            | Self::SchemaTooNew { .. } => None,
+        }
+    }
+}
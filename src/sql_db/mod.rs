@@ -0,0 +1,8680 @@
+mod db_error;
+#[cfg(feature = "pool")]
+mod pooled;
+mod rows;
+mod snapshot;
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut, RangeInclusive};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+pub use db_error::*;
+use log::{debug, info, warn};
+use num_traits::FromPrimitive;
+#[cfg(feature = "pool")]
+pub use pooled::*;
+use postgres as sql;
+pub use rows::*;
+
+use crate::class::Class;
+use crate::database::{
+    BrokenRecord, BulkAddOutcome, BulkAddReport, CancelToken, CarryStats, ChatSearchResult,
+    Database, DbEvent, DbStatus, EventHandler, ExternalMatch, Freshness, GameSummary, ImportMode,
+    IngestOutcome, IngestReason, IntegrityProblem, IntegrityReport, League, LogTag,
+    LogWithPerformances, Metric, OfficialFilter, ParticipationStats, PurgeReport, QueryOptions,
+    RecordEntry, RetentionPolicy, RetentionReport, RoundStats, SkipReason, SnapshotInfo, TagFilter,
+    TeammateRecord, TitleFilter, UpdateOptions, UpdatePlan, UpdateProgress, UpdateReport,
+    UpdateRunId, UpdateRunRecord, UserRecord, UsersReport, GAMES_BUCKETS,
+};
+use crate::error::{MixesDbError, MixesDbResult};
+use crate::logs_tf::search_params::SearchParams;
+use crate::logs_tf::{
+    Backoff, ChatMessage, Health, Log, LogMetadata, LogSource, LogsTf, QueryResult, TeamSummary,
+};
+use crate::medic_performance::MedicPerformance;
+use crate::score::Team;
+use crate::season::{Season, SeasonId};
+use crate::steam_id::{SteamID, SteamIDError};
+use crate::{stats, LogId, LogPerformance, Performance};
+
+/// Abstraction over a Postgresql database containing the saved mixes stats.
+/// Requires a postgresql server to be running on the system. Make sure a role
+/// with the name `mixes` exists and the database `mixes-stats` is present.
+pub struct SQLDb
+{
+    client:             Connection,
+    store_chat:         bool,
+    store_unregistered: bool,
+    max_duration_secs:  u32,
+    max_player_logs:    usize,
+    search_batch_size:  usize,
+    title_filter:       TitleFilter,
+    update_tag:         Option<LogTag>,
+    event_handler:      Option<EventHandler>,
+}
+
+/// Connection parameters used by [`SQLDb::start`].
+pub const DEFAULT_CONNECTION_PARAMS: &str = "host=localhost user=mixes dbname=mixes-stats";
+/// Logs that are longer than this are considered broken by default, see
+/// [`SQLDb::max_duration_secs`].
+pub const DEFAULT_MAX_DURATION_SECS: u32 = 3 * 60 * 60;
+/// The number of new logs of a single player an update looks at by default, see
+/// [`SQLDb::max_player_logs`].
+pub const DEFAULT_MAX_PLAYER_LOGS: usize = 1000;
+/// The number of players whose logs an update searches with a single request
+/// by default, see [`SQLDb::search_batch_size`].
+pub const DEFAULT_SEARCH_BATCH_SIZE: usize = 10;
+/// The number of logs requested at once when searching the logs of a player.
+const SEARCH_PAGE_SIZE: u16 = 1000;
+/// The longest usernames and season names can be, in bytes, since that is all
+/// the database has room for. Databases with a multibyte encoding could store
+/// more, but counting bytes makes sure the names fit into any database.
+pub const MAX_NAME_LEN: usize = 50;
+/// The version of the schema of the database, see [`SQLDb::schema_version`].
+/// It is the number of migrations that have been applied to the tables since
+/// they were first created, so it increases with every change to the schema.
+pub const SCHEMA_VERSION: u32 = 26;
+/// The changes to the stored data that cannot simply be repeated on every
+/// connect, with the schema version that has introduced them. Each of them runs
+/// once, on databases that have been stored with an older version, after the
+/// tables have been created.
+const MIGRATIONS: &[(u32, &str)] = &[
+    // Map names of any length.
+    (14, "ALTER TABLE logs ALTER COLUMN map TYPE text;"),
+    // The class played the longest in the logs stored before it was saved.
+    (
+        15,
+        "UPDATE overall_stats SET primary_class = (SELECT class FROM dm_stats
+            WHERE dm_stats.log_id = overall_stats.log_id
+            AND dm_stats.steam_id = overall_stats.steam_id AND time_played_secs > 0
+            ORDER BY time_played_secs DESC, class LIMIT 1)
+            WHERE primary_class IS NULL;",
+    ),
+    // The team of the class and medic stats stored before it was saved.
+    (
+        26,
+        "UPDATE dm_stats SET team = overall_stats.team FROM overall_stats
+            WHERE overall_stats.log_id = dm_stats.log_id
+            AND overall_stats.steam_id = dm_stats.steam_id
+            AND dm_stats.team IS NULL AND overall_stats.team IS NOT NULL;
+        UPDATE med_stats SET team = overall_stats.team FROM overall_stats
+            WHERE overall_stats.log_id = med_stats.log_id
+            AND overall_stats.steam_id = med_stats.steam_id
+            AND med_stats.team IS NULL AND overall_stats.team IS NOT NULL;",
+    ),
+];
+/// How far below the highest log id in the database an update looks up the
+/// logs found by its searches in a set of recently added logs. Older logs are
+/// looked up in the database, which is rarely needed since the searches return
+/// the newest logs first.
+const RECENT_LOGS_MARGIN: u32 = 50_000;
+/// The number of newest logs of each player that the reconciliation at the end
+/// of an update searches for logs that have been uploaded while it ran.
+const RECONCILE_PLAYER_LOGS: usize = 50;
+/// How long an update waits before each request to a source whose health is
+/// degraded. The wait doubles after every failed try.
+const DEGRADED_REQUEST_DELAY: Duration = Duration::from_secs(1);
+/// How often an update tries failed requests to a degraded source again, on
+/// top of the retries of the source itself.
+const DEGRADED_NUM_RETRIES: u8 = 3;
+/// Run on every new connection. Dates are bound as `timestamptz` and keep their
+/// instant in any time zone, but casts to and from text and grouping by day
+/// depend on the time zone of the session, which is whatever the server has
+/// been configured with. Setting it to UTC makes them the same everywhere.
+const SESSION_SETUP: &str = "SET TIME ZONE 'UTC'";
+
+/// The connection a [`SQLDb`] works with, which is either its own or one that
+/// has been checked out of a connection pool.
+enum Connection
+{
+    Owned(sql::Client),
+    #[cfg(feature = "pool")]
+    Pooled(pooled::PooledConnection),
+}
+
+impl Deref for Connection
+{
+    type Target = sql::Client;
+
+    fn deref(&self) -> &Self::Target
+    {
+        match self {
+            Self::Owned(client) => client,
+            #[cfg(feature = "pool")]
+            Self::Pooled(connection) => connection,
+        }
+    }
+}
+
+impl DerefMut for Connection
+{
+    fn deref_mut(&mut self) -> &mut Self::Target
+    {
+        match self {
+            Self::Owned(client) => client,
+            #[cfg(feature = "pool")]
+            Self::Pooled(connection) => connection,
+        }
+    }
+}
+
+impl SQLDb
+{
+    /// Connect to the local database with [`DEFAULT_CONNECTION_PARAMS`] and
+    /// create the tables needed, in case they are not present yet.
+    pub fn start() -> DbResult<Self> { Self::connect(DEFAULT_CONNECTION_PARAMS) }
+
+    /// Connect to the postgresql database described by the connection `params`
+    /// and create the tables needed, in case they are not present yet.
+    pub fn connect(params: &str) -> DbResult<Self>
+    {
+        let client = connect_client(params)?;
+        let mut db = Self::with_connection(Connection::Owned(client));
+        db.init_tables()?;
+
+        Ok(db)
+    }
+
+    /// Connect to the postgresql database described by the connection `params`,
+    /// but keep all tables in the temporary schema of the connection. Nothing
+    /// that is already saved in the database is visible and everything is
+    /// dropped when the connection closes, which makes it useful for tests.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn temporary(params: &str) -> DbResult<Self>
+    {
+        let mut client = connect_client(params)?;
+        client.batch_execute("SET search_path TO pg_temp")?;
+        let mut db = Self::with_connection(Connection::Owned(client));
+        db.init_tables()?;
+
+        Ok(db)
+    }
+
+    /// Wrap the `client` with the default settings, without creating any
+    /// tables.
+    fn with_connection(client: Connection) -> Self
+    {
+        Self {
+            client,
+            store_chat: false,
+            store_unregistered: true,
+            max_duration_secs: DEFAULT_MAX_DURATION_SECS,
+            max_player_logs: DEFAULT_MAX_PLAYER_LOGS,
+            search_batch_size: DEFAULT_SEARCH_BATCH_SIZE,
+            title_filter: TitleFilter::default(),
+            update_tag: None,
+            event_handler: None,
+        }
+    }
+
+    /// Set whether the chat of logs is saved when they are added. Since the
+    /// chat contains messages of players that may not want them to be kept,
+    /// this is disabled by default. Logs added while it is disabled have no
+    /// chat saved and cannot be found with [`Database::search_chat`].
+    pub fn store_chat(mut self, store_chat: bool) -> Self
+    {
+        self.store_chat = store_chat;
+        self
+    }
+
+    /// Set whether the stats and in-game names of players that are not
+    /// registered are saved when logs are added. This is enabled by default,
+    /// disabling it keeps the database smaller and does not keep data of
+    /// players that have never agreed to being tracked. The heal spread of
+    /// registered medics still contains the players they have healed.
+    pub fn store_unregistered(mut self, store_unregistered: bool) -> Self
+    {
+        self.store_unregistered = store_unregistered;
+        self
+    }
+
+    /// Set the longest duration a log may have. Logs that are longer, or that
+    /// do not have a duration at all, are saved with their duration marked as
+    /// suspect, since it is most likely the result of a server crash. These
+    /// logs are left out of per-minute stats, unless they are explicitly
+    /// included. Defaults to [`DEFAULT_MAX_DURATION_SECS`].
+    pub fn max_duration_secs(mut self, max_duration_secs: u32) -> Self
+    {
+        self.max_duration_secs = max_duration_secs;
+        self
+    }
+
+    /// Set how many new logs of a single player an update looks at. Only logs
+    /// that are not in the database yet and have the requested number of
+    /// players count, the newest of them are kept. Logs of players beyond the
+    /// limit are counted in [`UpdateReport::overflow`]. Defaults to
+    /// [`DEFAULT_MAX_PLAYER_LOGS`].
+    pub fn max_player_logs(mut self, max_player_logs: usize) -> Self
+    {
+        self.max_player_logs = max_player_logs;
+        self
+    }
+
+    /// Set how many players an update searches the logs of with a single
+    /// request. logs.tf finds the logs of any of the players, which saves most
+    /// of the searches in large communities. Since it is not known which of
+    /// the players are part of the logs found, the ratio of mixes players is
+    /// only checked once a log is downloaded, so logs with too few mixes
+    /// players are downloaded as well. With a size of `1`, every player is
+    /// searched on their own and the ratio is checked before downloading.
+    /// Defaults to [`DEFAULT_SEARCH_BATCH_SIZE`].
+    pub fn search_batch_size(mut self, search_batch_size: usize) -> Self
+    {
+        self.search_batch_size = search_batch_size;
+        self
+    }
+
+    /// Set which logs an update adds based on their title, for instance to
+    /// leave out logs of public servers that have the same number of players
+    /// as mixes. Logs that are not allowed are reported as skipped. Defaults
+    /// to allowing every log.
+    pub fn title_filter(mut self, title_filter: TitleFilter) -> Self
+    {
+        self.title_filter = title_filter;
+        self
+    }
+
+    /// Set a tag that is attached to every log an update adds, for instance
+    /// to tell them apart from logs that have been added by hand. Logs that
+    /// are skipped or already stored as mixes are not tagged. Defaults to no
+    /// tag.
+    pub fn update_tag(mut self, update_tag: Option<LogTag>) -> Self
+    {
+        self.update_tag = update_tag;
+        self
+    }
+
+    /// Pass the `event` to the event handler, if one is set.
+    fn emit(&self, event: DbEvent)
+    {
+        if let Some(handler) = &self.event_handler {
+            handler(event);
+        }
+    }
+
+    /// Remove the stats and in-game names of all players that are not
+    /// registered, like they would not have been saved with
+    /// [`SQLDb::store_unregistered`] disabled. The logs themselves are kept.
+    /// Everything is removed in one transaction.
+    ///
+    /// # Returns
+    /// The number of stat rows that have been removed.
+    pub fn prune_unregistered(&mut self) -> DbResult<u64>
+    {
+        let mut transaction = self.client.transaction()?;
+
+        let mut num_removed = 0;
+        for (table, steam_id) in [
+            ("overall_stats", "steam_id"),
+            ("dm_stats", "steam_id"),
+            ("med_stats", "steam_id"),
+            ("heal_spread", "medic_id"),
+        ] {
+            num_removed += transaction.execute(
+                format!(
+                    "DELETE FROM {} WHERE {} NOT IN (SELECT steam_id FROM users)",
+                    table, steam_id
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
+        for table in ["aggregates_cache", "player_names", "personal_records"] {
+            transaction.execute(
+                format!(
+                    "DELETE FROM {} WHERE steam_id NOT IN (SELECT steam_id FROM users)",
+                    table
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
+
+        transaction.commit()?;
+        info!("Removed {} stats of unregistered players", num_removed);
+
+        Ok(num_removed)
+    }
+
+    /// The version of the schema this version of the crate migrates the
+    /// database to. Connecting to a database that has been migrated to a newer
+    /// version fails with [`DbError::SchemaTooNew`]. The tables and columns of
+    /// a version are a stable contract, which custom queries like those run
+    /// with [`SQLDb::query_raw`] can rely on. Migrations only ever add
+    /// tables and columns or widen their types and increase the version, so
+    /// queries written for one version keep working with later ones.
+    pub fn schema_version() -> u32 { SCHEMA_VERSION }
+
+    /// Recompute the aggregates of every player from the stored stats,
+    /// replacing all cached ones. The aggregates are kept up to date by
+    /// every change made through the database, so this is only needed after
+    /// the stats have been changed by hand.
+    ///
+    /// # Returns
+    /// The number of aggregates, one for each player, class and season.
+    pub fn rebuild_aggregates(&mut self) -> DbResult<u64>
+    {
+        let mut transaction = self.client.transaction()?;
+        let num_aggregates = replace_aggregates(&mut transaction)?;
+        transaction.commit()?;
+        info!("Rebuilt {} aggregates", num_aggregates);
+
+        Ok(num_aggregates)
+    }
+
+    /// Recompute the personal records of every player from the stored stats,
+    /// replacing all of them. Like the aggregates, the records are kept up to
+    /// date by every change made through the database.
+    ///
+    /// # Returns
+    /// The number of records, one for each player, class and metric.
+    pub fn rebuild_records(&mut self) -> DbResult<u64>
+    {
+        let mut transaction = self.client.transaction()?;
+        let num_records = replace_records(&mut transaction, None)?;
+        transaction.commit()?;
+        info!("Rebuilt {} personal records", num_records);
+
+        Ok(num_records)
+    }
+
+    /// Run a query that is not covered by the [`Database`] trait, like a custom
+    /// aggregate for analytics. The rows can be decoded with the helpers like
+    /// [`row_to_overall`] if the matching columns like [`OVERALL_COLUMNS`] are
+    /// selected.
+    ///
+    /// Nothing stops the query from writing to the database, which can break
+    /// the invariants the rest of the database relies on. Only read with it.
+    #[cfg(feature = "danger-raw-sql")]
+    pub fn query_raw(
+        &mut self,
+        sql: &str,
+        params: &[&(dyn sql::types::ToSql + Sync)],
+    ) -> DbResult<Vec<sql::Row>>
+    {
+        Ok(self.client.query(sql, params)?)
+    }
+
+    /// Create the necessary tables in the database, in case they are not yet
+    /// present, and run the [`MIGRATIONS`] the stored schema version still
+    /// lacks.
+    fn init_tables(&mut self) -> DbResult<()>
+    {
+        let stored_version = self.stored_schema_version()?;
+        if let Some(found) = stored_version {
+            if found > SCHEMA_VERSION {
+                return Err(DbError::SchemaTooNew {
+                    found,
+                    supported: SCHEMA_VERSION,
+                });
+            }
+        }
+
+        self.client.batch_execute(
+            "CREATE TABLE IF NOT EXISTS users (
+                steam_id bigint,
+                discord_id bigint NOT NULL UNIQUE,
+                username varchar(50),
+                PRIMARY KEY (steam_id)
+            );
+            CREATE TABLE IF NOT EXISTS logs (
+                log_id OID,
+                date timestamptz,
+                map varchar(50),
+                duration_secs int,
+                num_players smallint,
+                PRIMARY KEY (log_id)
+            );
+            CREATE TABLE IF NOT EXISTS heal_spread (
+                log_id OID,
+                medic_id bigint,
+                target_id bigint,
+                healing int
+            );
+            CREATE TABLE IF NOT EXISTS seasons (
+                season_id serial,
+                name varchar(50),
+                start_date timestamptz NOT NULL,
+                end_date timestamptz,
+                PRIMARY KEY (season_id)
+            );
+            CREATE TABLE IF NOT EXISTS overall_stats (
+                log_id OID,
+                steam_id bigint,
+                won_rounds smallint,
+                num_rounds smallint,
+                damage int,
+                damage_taken int,
+                kills smallint,
+                deaths smallint,
+                num_medkits smallint,
+                medkits_hp int
+            );
+            CREATE TABLE IF NOT EXISTS dm_stats (
+                log_id OID,
+                steam_id bigint,
+                class smallint,
+                damage int,
+                kills smallint,
+                assists smallint,
+                deaths smallint,
+                time_played_secs int
+            );
+            CREATE TABLE IF NOT EXISTS med_stats (
+                log_id OID,
+                steam_id bigint,
+                healing int,
+                average_uber_length_secs real,
+                num_ubers smallint,
+                num_drops smallint,
+                deaths smallint,
+                time_played_secs int
+            );
+            CREATE TABLE IF NOT EXISTS meta (
+                key text,
+                value text,
+                PRIMARY KEY (key)
+            );
+            CREATE TABLE IF NOT EXISTS chat (
+                log_id OID,
+                idx int,
+                steam_id bigint,
+                name text,
+                message text
+            );
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS season_id int;
+            ALTER TABLE overall_stats ADD COLUMN IF NOT EXISTS offclass_fraction real;
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS ingest_reason smallint NOT NULL DEFAULT 0;
+            ALTER TABLE overall_stats ADD COLUMN IF NOT EXISTS team smallint;
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS duration_suspect bool NOT NULL DEFAULT false;
+            ALTER TABLE med_stats
+                ADD COLUMN IF NOT EXISTS num_kritz smallint NOT NULL DEFAULT 0,
+                ADD COLUMN IF NOT EXISTS advantages_lost smallint NOT NULL DEFAULT 0,
+                ADD COLUMN IF NOT EXISTS biggest_advantage_lost_secs real NOT NULL DEFAULT 0;
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS format smallint;
+            ALTER TABLE overall_stats
+                ADD COLUMN IF NOT EXISTS damage_share real,
+                ADD COLUMN IF NOT EXISTS kill_participation real;
+            ALTER TABLE users
+                ADD COLUMN IF NOT EXISTS registered_at timestamptz,
+                ADD COLUMN IF NOT EXISTS active bool NOT NULL DEFAULT true;
+            ALTER TABLE logs
+                ADD COLUMN IF NOT EXISTS title text,
+                ADD COLUMN IF NOT EXISTS uploader bigint;
+            CREATE TABLE IF NOT EXISTS log_tags (
+                log_id OID,
+                tag varchar(32),
+                PRIMARY KEY (log_id, tag)
+            );
+            ALTER TABLE overall_stats ADD COLUMN IF NOT EXISTS captures smallint NOT NULL DEFAULT \
+             0;
+            ALTER TABLE dm_stats ADD COLUMN IF NOT EXISTS sentry_kills smallint;
+            ALTER TABLE overall_stats ADD COLUMN IF NOT EXISTS primary_class smallint;
+            INSERT INTO meta (key, value) SELECT 'max_log_id', max(log_id)::text FROM logs
+                HAVING max(log_id) IS NOT NULL ON CONFLICT (key) DO NOTHING;
+            CREATE TABLE IF NOT EXISTS update_runs (
+                run_id serial PRIMARY KEY,
+                started_at timestamptz NOT NULL DEFAULT now(),
+                finished_at timestamptz,
+                min_ratio real NOT NULL,
+                min_players smallint NOT NULL,
+                max_players smallint NOT NULL,
+                tag varchar(32),
+                num_candidates integer NOT NULL DEFAULT 0,
+                num_added integer NOT NULL DEFAULT 0,
+                num_failed integer NOT NULL DEFAULT 0
+            );
+            ALTER TABLE update_runs
+                ADD COLUMN IF NOT EXISTS cancelled boolean NOT NULL DEFAULT false;
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS duration interval
+                GENERATED ALWAYS AS (duration_secs * interval '1 second') STORED;
+            ALTER TABLE overall_stats
+                ADD COLUMN IF NOT EXISTS heals_received integer NOT NULL DEFAULT 0;
+            ALTER TABLE logs
+                ADD COLUMN IF NOT EXISTS league varchar(32),
+                ADD COLUMN IF NOT EXISTS match_id bigint;
+            CREATE INDEX IF NOT EXISTS logs_match ON logs (league, match_id)
+                WHERE match_id IS NOT NULL;
+            ALTER TABLE logs ADD COLUMN IF NOT EXISTS round_winners smallint[];
+            CREATE TABLE IF NOT EXISTS player_names (
+                steam_id bigint PRIMARY KEY,
+                name text NOT NULL,
+                log_id OID NOT NULL
+            );
+            ALTER TABLE dm_stats ADD COLUMN IF NOT EXISTS team smallint;
+            ALTER TABLE med_stats ADD COLUMN IF NOT EXISTS team smallint;
+            ",
+        )?;
+        // The cache is recreated when it lacks the latest of its columns, it
+        // can always be rebuilt from the stats.
+        let has_aggregates: bool = self
+            .client
+            .query_one(
+                "SELECT EXISTS (SELECT FROM pg_attribute WHERE attrelid = \
+                 to_regclass('aggregates_cache') AND attname = 'ingest_reason')",
+                &[],
+            )?
+            .get(0);
+        if !has_aggregates {
+            self.client.batch_execute(
+                "DROP TABLE IF EXISTS aggregates_cache;
+                CREATE TABLE aggregates_cache (
+                    steam_id bigint,
+                    class smallint,
+                    season_id int,
+                    ingest_reason smallint,
+                    games bigint NOT NULL,
+                    time_played_secs bigint NOT NULL,
+                    damage bigint NOT NULL,
+                    kills bigint NOT NULL,
+                    assists bigint NOT NULL,
+                    deaths bigint NOT NULL,
+                    med_games bigint NOT NULL,
+                    med_time_secs bigint NOT NULL,
+                    healing bigint NOT NULL,
+                    num_ubers bigint NOT NULL,
+                    primary_games bigint NOT NULL,
+                    dt_games bigint NOT NULL,
+                    duration_secs bigint NOT NULL,
+                    damage_taken bigint NOT NULL,
+                    primary_damage bigint NOT NULL,
+                    heals_received bigint NOT NULL,
+                    PRIMARY KEY (steam_id, class, season_id, ingest_reason)
+                );",
+            )?;
+            self.rebuild_aggregates()?;
+        }
+        // The records are computed from the stats, which may have been stored
+        // before the records were kept.
+        let has_records: bool = self
+            .client
+            .query_one("SELECT to_regclass('personal_records') IS NOT NULL", &[])?
+            .get(0);
+        if !has_records {
+            self.client.batch_execute(
+                "CREATE TABLE personal_records (
+                    steam_id bigint,
+                    class smallint,
+                    metric varchar(16),
+                    value float8 NOT NULL,
+                    log_id OID NOT NULL,
+                    date timestamptz NOT NULL,
+                    PRIMARY KEY (steam_id, class, metric)
+                );",
+            )?;
+            self.rebuild_records()?;
+        }
+        for &(version, migration) in MIGRATIONS {
+            if stored_version.is_none_or(|stored| stored < version) {
+                let mut transaction = self.client.transaction()?;
+                transaction.batch_execute(migration)?;
+                store_schema_version(&mut transaction, version)?;
+                transaction.commit()?;
+                info!("Migrated the database to schema version {}", version);
+            }
+        }
+        store_schema_version(&mut *self.client, SCHEMA_VERSION)?;
+
+        Ok(())
+    }
+
+    /// The version of the schema the tables of the database have been
+    /// migrated to, `None` if they have not been created yet or were last
+    /// migrated before the version was saved.
+    fn stored_schema_version(&mut self) -> DbResult<Option<u32>>
+    {
+        let has_meta: bool = self
+            .client
+            .query_one("SELECT to_regclass('meta') IS NOT NULL", &[])?
+            .get(0);
+        if !has_meta {
+            return Ok(None);
+        }
+
+        Ok(self
+            .client
+            .query_opt(
+                "SELECT value::oid FROM meta WHERE key = 'schema_version'",
+                &[],
+            )?
+            .map(|row| row.get(0)))
+    }
+
+    /// Look up the ids of all logs already saved in the database. Since the
+    /// data in them remains constant, they won't have to be queried again.
+    /// They are always ordered by log_id descending, which means the newest
+    /// logs are on the top. This is in accordance to the logs.tf API, which
+    /// orders in the same manner.
+    pub fn known_logs(&mut self) -> Result<Vec<LogId>, sql::Error>
+    {
+        Ok(self
+            .client
+            .query("SELECT log_id FROM logs ORDER BY log_id DESC", &[])?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    /// Get the records of all registered players, see
+    /// [`Database::user_records`].
+    fn query_user_records(&mut self) -> DbResult<Vec<UserRecord>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT steam_id, discord_id, registered_at, active, username FROM users ORDER BY \
+                 steam_id",
+                &[],
+            )?
+            .iter()
+            .filter_map(|row| user_record_from_row(row).ok())
+            .collect())
+    }
+
+    /// The steam ids of all registered players, ordered by steam id.
+    fn registered_ids(&mut self) -> DbResult<Vec<SteamID>>
+    {
+        Ok(self
+            .query_user_records()?
+            .into_iter()
+            .map(|record| record.steam_id)
+            .collect())
+    }
+
+    /// Get the highest id of all logs ever added, see [`Database::max_log_id`].
+    fn query_max_log_id(&mut self) -> DbResult<Option<LogId>>
+    {
+        let row = self.client.query_opt(
+            "SELECT value::bigint FROM meta WHERE key = 'max_log_id'",
+            &[],
+        )?;
+
+        Ok(row.map(|row| LogId(row.get::<_, i64>(0) as u32)))
+    }
+
+    /// Look up the ids of the logs that have been added for the `reason` and
+    /// have an id of at least `min_id`.
+    fn known_logs_since(&mut self, reason: IngestReason, min_id: LogId)
+        -> DbResult<HashSet<LogId>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT log_id FROM logs WHERE ingest_reason = $1 AND log_id >= $2",
+                &[&(reason as i16), &min_id],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    /// Add a log that has been downloaded from logs.tf for the `reason`, with
+    /// the `tag` if there is one, and record the outcome in the `report`. If
+    /// the download failed, for instance because the log is corrupt, the
+    /// log is skipped with a warning instead of failing the whole update.
+    fn add_downloaded_log(
+        &mut self,
+        id: LogId,
+        download: QueryResult<Log>,
+        reason: IngestReason,
+        tag: Option<&LogTag>,
+        report: &mut UpdateReport,
+    ) -> DbResult<()>
+    {
+        match download {
+            Ok(log) => {
+                if let Some(records_broken) = self.insert_log(log, reason, tag)? {
+                    report.added.push(id);
+                    report.records_broken.extend(records_broken);
+                }
+            },
+            Err(e) => {
+                warn!("Skipping log {}, which could not be downloaded: {}", id, e);
+                report
+                    .skipped
+                    .push((id, SkipReason::DownloadFailed(e.to_string())));
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Add a log like [`Database::add_log`], noting the `reason` it is added
+    /// for. The log is tagged with the `tag` in the same transaction, if there
+    /// is one.
+    ///
+    /// # Returns
+    /// The personal records the log has broken, `None` if the log was already
+    /// present.
+    fn insert_log(
+        &mut self,
+        log: Log,
+        reason: IngestReason,
+        tag: Option<&LogTag>,
+    ) -> DbResult<Option<Vec<BrokenRecord>>>
+    {
+        debug!("Registering log {}", log.meta().id);
+        // Everything concerning the log is added in one transaction, so a log is either
+        // added completely or not at all.
+        let duration_suspect =
+            log.duration_secs() == 0 || log.duration_secs() > self.max_duration_secs;
+        if duration_suspect {
+            warn!(
+                "Log {} has a duration of {}s, which is most likely wrong",
+                log.meta().id,
+                log.duration_secs()
+            );
+        }
+        for anomaly in log.anomalies() {
+            warn!("Log {}: {}", log.meta().id, anomaly);
+        }
+
+        let mut transaction = self.client.transaction()?;
+
+        // Add log metadata to the logs table, together with the season it was played in
+        let num_inserted = transaction.execute(
+            "INSERT INTO logs (log_id, date, map, duration_secs, num_players, ingest_reason, \
+             duration_suspect, format, title, uploader, round_winners, season_id) VALUES ($1, $2, \
+             $3, $4, $5, $6, $7, $8, $9, $10, $11, (SELECT season_id FROM seasons WHERE \
+             start_date <= $2 AND (end_date IS NULL OR $2 < end_date))) ON CONFLICT (log_id) DO \
+             NOTHING",
+            &[
+                &log.meta().id,
+                &log.meta().date_time,
+                &log.meta().map,
+                &int("duration", log.duration_secs())?,
+                // No game has that many players, but the column is only a smallint
+                &i16::try_from(log.meta().num_players).unwrap_or(i16::MAX),
+                &(reason as i16),
+                &duration_suspect,
+                &(log.meta().format() as i16),
+                &log.meta().title,
+                &log.meta().uploader.map(|uploader| uploader.id64() as i64),
+                &log.round_winners()
+                    .iter()
+                    .map(|winner| winner.map(|team| team as i16))
+                    .collect::<Vec<_>>(),
+            ],
+        )?;
+
+        if num_inserted == 0 {
+            debug!("Log {} is already known", log.meta().id);
+            return Ok(None);
+        }
+        transaction.execute(
+            "INSERT INTO meta (key, value) VALUES ('max_log_id', $1::bigint::text) ON CONFLICT \
+             (key) DO UPDATE SET value = GREATEST(meta.value::bigint, \
+             EXCLUDED.value::bigint)::text",
+            &[&(log.meta().id.0 as i64)],
+        )?;
+        if let Some(tag) = tag {
+            insert_tag(&mut transaction, log.meta().id, tag)?;
+        }
+
+        let registered: Option<HashSet<i64>> = if self.store_unregistered {
+            None
+        }
+        else {
+            Some(
+                transaction
+                    .query("SELECT steam_id FROM users ORDER BY steam_id", &[])?
+                    .iter()
+                    .map(|row| row.get(0))
+                    .collect(),
+            )
+        };
+
+        // Add all performances of all players in the log
+        let mut players = Vec::new();
+        for (steam_id, performances) in log.performances() {
+            if let Some(registered) = &registered {
+                if !registered.contains(&(steam_id.id64() as i64)) {
+                    continue;
+                }
+            }
+            players.push(*steam_id);
+
+            for performance in performances {
+                match &performance {
+                    Performance::Overall(perf) => {
+                        let (damage_share, kill_participation) = log.team_shares(*steam_id);
+                        transaction.execute(
+                            "INSERT INTO overall_stats (log_id, steam_id, won_rounds, num_rounds, \
+                             damage, damage_taken, kills, deaths, num_medkits, medkits_hp, \
+                             offclass_fraction, team, damage_share, kill_participation, captures, \
+                             primary_class, heals_received) VALUES ($1, $2, $3, $4, $5, $6, $7, \
+                             $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)",
+                            &[
+                                &log.meta().id,
+                                &(steam_id.id64() as i64),
+                                &(perf.won_rounds as i16),
+                                &(perf.num_rounds as i16),
+                                &int("damage", perf.damage)?,
+                                &int("damage taken", perf.damage_taken)?,
+                                &smallint("number of kills", perf.kills)?,
+                                &smallint("number of deaths", perf.deaths)?,
+                                &smallint("number of medkits", perf.num_medkits)?,
+                                &int("medkit healing", perf.medkits_hp)?,
+                                &log.offclass_fraction(*steam_id),
+                                &perf.team.map(|team| team as i16),
+                                &damage_share,
+                                &kill_participation,
+                                &smallint("number of captures", perf.captures)?,
+                                &log.primary_class(*steam_id).map(|class| class as i16),
+                                &int("healing received", perf.heals_received)?,
+                            ],
+                        )?;
+                    },
+                    Performance::DM(dm_perf) => {
+                        transaction.execute(
+                            "INSERT INTO dm_stats (log_id, steam_id, class, damage, kills, \
+                             assists, deaths, time_played_secs, sentry_kills, team) VALUES ($1, \
+                             $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+                            &[
+                                &log.meta().id,
+                                &(steam_id.id64() as i64),
+                                &(dm_perf.class as i16),
+                                &int("damage", dm_perf.damage)?,
+                                &smallint("number of kills", dm_perf.kills)?,
+                                &smallint("number of assists", dm_perf.assists)?,
+                                &smallint("number of deaths", dm_perf.deaths)?,
+                                &int("time played", dm_perf.time_played_secs)?,
+                                &dm_perf
+                                    .sentry_kills
+                                    .map(|kills| smallint("number of sentry kills", kills))
+                                    .transpose()?,
+                                &dm_perf.team.map(|team| team as i16),
+                            ],
+                        )?;
+                    },
+                    Performance::Med(med_perf) => {
+                        transaction.execute(
+                            "INSERT INTO med_stats (log_id, steam_id, healing, \
+                             average_uber_length_secs, num_ubers, num_drops, deaths, \
+                             time_played_secs, num_kritz, advantages_lost, \
+                             biggest_advantage_lost_secs, team) VALUES ($1, $2, $3, $4, $5, $6, \
+                             $7, $8, $9, $10, $11, $12)",
+                            &[
+                                &log.meta().id,
+                                &(steam_id.id64() as i64),
+                                &int("healing", med_perf.healing)?,
+                                &med_perf.average_uber_length_secs,
+                                &(med_perf.num_ubers as i16),
+                                &(med_perf.num_drops as i16),
+                                &smallint("number of deaths", med_perf.deaths)?,
+                                &int("time played", med_perf.time_played_secs)?,
+                                &(med_perf.num_kritz as i16),
+                                &(med_perf.advantages_lost as i16),
+                                &med_perf.biggest_advantage_lost_secs,
+                                &med_perf.team.map(|team| team as i16),
+                            ],
+                        )?;
+
+                        for (target_id, healing) in &med_perf.heal_targets {
+                            transaction.execute(
+                                "INSERT INTO heal_spread (log_id, medic_id, target_id, healing) \
+                                 VALUES ($1, $2, $3, $4)",
+                                &[
+                                    &log.meta().id,
+                                    &(steam_id.id64() as i64),
+                                    &(target_id.id64() as i64),
+                                    &int("healing", *healing)?,
+                                ],
+                            )?;
+                        }
+                    },
+                }
+            }
+        }
+
+        if self.store_chat {
+            for (idx, message) in log.chat().iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO chat (log_id, idx, steam_id, name, message) VALUES ($1, $2, $3, \
+                     $4, $5)",
+                    &[
+                        &log.meta().id,
+                        &(idx as i32),
+                        &message.steam_id.map(|steam_id| steam_id.id64() as i64),
+                        &message.name,
+                        &message.message,
+                    ],
+                )?;
+            }
+        }
+
+        // Spectators are named too. Older logs do not replace the name of a newer one.
+        for (steam_id, name) in log.player_names() {
+            let steam_id = steam_id.id64() as i64;
+            if let Some(registered) = &registered {
+                if !registered.contains(&steam_id) {
+                    continue;
+                }
+            }
+            transaction.execute(
+                "INSERT INTO player_names (steam_id, name, log_id) VALUES ($1, $2, $3) ON \
+                 CONFLICT (steam_id) DO UPDATE SET name = EXCLUDED.name, log_id = EXCLUDED.log_id \
+                 WHERE player_names.log_id <= EXCLUDED.log_id",
+                &[&steam_id, name, &log.meta().id],
+            )?;
+        }
+
+        players.sort_unstable_by_key(|steam_id| steam_id.id64());
+        // A suspect duration would make for records nobody can beat.
+        let records_broken = if duration_suspect {
+            Vec::new()
+        }
+        else {
+            update_records(&mut transaction, &log, &players)?
+        };
+        update_aggregates(&mut transaction, &[log.meta().id], 1)?;
+        transaction.commit()?;
+        debug!("Log {} registered", log.meta().id);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_ingested();
+
+        self.emit(DbEvent::LogAdded {
+            log_id: log.meta().id,
+            players,
+            records_broken: records_broken.clone(),
+        });
+
+        Ok(Some(records_broken))
+    }
+
+    /// Rank the `user` like [`Database::percentile_for_metric`] directly from
+    /// the stats, for the options the aggregates cache does not cover.
+    fn live_percentile(
+        &mut self,
+        columns: &MetricColumns,
+        user_id: i64,
+        class: Class,
+        min_games: i64,
+        include_suspect: bool,
+        min_class_time_secs: u32,
+    ) -> DbResult<Option<sql::Row>>
+    {
+        let min_class_time_secs = min_class_time_secs as i32;
+        let class = class as i16;
+        let mut params: Vec<&(dyn sql::types::ToSql + Sync)> =
+            vec![&user_id, &min_games, &include_suspect, &min_class_time_secs];
+        // Medic stats are not split up by class, they are all medic stats.
+        let class_condition = match columns.class {
+            Some(class_column) => {
+                params.push(&class);
+                format!("AND {} = $5", class_column)
+            },
+            None => String::new(),
+        };
+
+        Ok(self.client.query_opt(
+            format!(
+                "SELECT percentile FROM (SELECT stats.steam_id, percent_rank() OVER (ORDER BY \
+                 SUM(stats.{value}) * {per_secs}.0 / SUM({time})) AS percentile FROM {table} \
+                 stats JOIN users ON users.steam_id = stats.steam_id JOIN logs ON logs.log_id = \
+                 stats.log_id WHERE ($3 OR NOT logs.duration_suspect) AND {time_played} >= $4 AND \
+                 logs.ingest_reason = {mix} {class_condition} GROUP BY stats.steam_id HAVING \
+                 COUNT(DISTINCT stats.log_id) >= $2 AND SUM({time}) > 0) ranked WHERE steam_id = \
+                 $1",
+                value = columns.value,
+                per_secs = columns.per_secs,
+                time = columns.time,
+                time_played = columns.time_played,
+                table = columns.table,
+                mix = IngestReason::Mix as i16,
+                class_condition = class_condition,
+            )
+            .as_str(),
+            &params,
+        )?)
+    }
+
+    /// Search the logs of all registered players in the `source` and decide
+    /// which of them would be added by an update with the `options`, as
+    /// described in [`Database::update_dry_run`]. The source is checked and
+    /// searched the same way [`SQLDb::update_from`] does, but nothing is
+    /// downloaded.
+    fn plan_update(
+        &mut self,
+        source: &dyn LogSource,
+        options: &UpdateOptions,
+    ) -> DbResult<UpdatePlan>
+    {
+        let user_ids = self.registered_ids()?;
+        let (plan, _) = self.with_checked_source(source, options.health_check, |db, source| {
+            let cancel = options.cancel.as_ref();
+            // The database does not change while planning, so the highest id
+            // stored is the watermark of the reconciliation as well.
+            let watermark = db.query_max_log_id()?.unwrap_or(LogId(0));
+            let (scope, mut plan) = db.plan_scoped(source, &user_ids, options, cancel)?;
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                return Ok(plan);
+            }
+            let reconciled =
+                db.reconcile_candidates(source, watermark, options, &scope, &mut plan.skipped)?;
+            for meta in reconciled {
+                if !plan
+                    .candidates
+                    .iter()
+                    .any(|candidate| candidate.id == meta.id)
+                {
+                    plan.skipped.retain(|(id, _)| *id != meta.id);
+                    plan.candidates.push(meta);
+                }
+            }
+            plan.candidates.sort_by_key(|meta| Reverse(meta.id));
+
+            Ok(plan)
+        })?;
+
+        Ok(plan)
+    }
+
+    /// Decide how an update with the `options` checks the ratio of mixes
+    /// players in the logs of the `players`, and search their logs
+    /// accordingly.
+    ///
+    /// # Returns
+    /// The scope of the update and the logs it would download.
+    fn plan_scoped<'a>(
+        &mut self,
+        source: &dyn LogSource,
+        players: &'a [SteamID],
+        options: &UpdateOptions,
+        cancel: Option<&CancelToken>,
+    ) -> DbResult<(UpdateScope<'a>, UpdatePlan)>
+    {
+        let users: HashSet<SteamID> = self.registered_ids()?.into_iter().collect();
+        // Searching only some of the players does not tell how many of the
+        // registered players are in a log, and neither does searching several
+        // players at once. The ratio is checked once the log is known instead,
+        // from the players stored for it or the players of the downloaded log.
+        let scoped = players.len() < users.len();
+        let scope = UpdateScope {
+            players,
+            users,
+            min_ratio: options.min_ratio,
+            ratio_after_download: scoped || self.search_batch_size > 1,
+        };
+        let plan = self.plan_update_since(
+            source,
+            players,
+            scope.search_ratio(),
+            &options.num_players,
+            cancel,
+            None,
+        )?;
+
+        Ok((scope, plan))
+    }
+
+    /// Plan an update like [`SQLDb::plan_update`], but only search the logs of
+    /// the `players`. Logs are only filtered by the ratio of mixes players if
+    /// a `search_ratio` is given. If `newer_than` is set, only the newest few
+    /// logs of each player are searched, and only those with a higher id are
+    /// considered.
+    fn plan_update_since(
+        &mut self,
+        source: &dyn LogSource,
+        players: &[SteamID],
+        search_ratio: Option<f32>,
+        num_players: &RangeInclusive<u16>,
+        cancel: Option<&CancelToken>,
+        newer_than: Option<LogId>,
+    ) -> DbResult<UpdatePlan>
+    {
+        if let Some(min_ratio) = search_ratio.filter(|ratio| !(0. ..=1.).contains(ratio)) {
+            return Err(DbError::InvalidInput(format!(
+                "The ratio of mixes players must be between 0 and 1, not {}",
+                min_ratio
+            )));
+        }
+
+        // Logs only known from the history of a single player may have become mixes,
+        // since more players have registered. Only the recently added mixes are kept
+        // in memory, older logs found by the searches are looked up when they come up.
+        let recent_floor = match self.query_max_log_id()? {
+            Some(max_id) => LogId(max_id.0.saturating_sub(RECENT_LOGS_MARGIN)),
+            None => LogId(0),
+        };
+        let recent_mixes = self.known_logs_since(IngestReason::Mix, recent_floor)?;
+
+        // The logs are filtered page by page, so players with thousands of logs
+        // of other game modes do not have to be kept in memory.
+        let batch_size = self.search_batch_size.max(1);
+        let max_player_logs = self.max_player_logs;
+        let title_filter = &self.title_filter;
+        let client = &mut self.client;
+        let mut search_results = Vec::new();
+        let mut skipped = Vec::new();
+        let mut overflow = 0;
+        let mut lookup_error = None;
+        for batch in players.chunks(batch_size) {
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                info!("The update has been cancelled, the remaining players are not searched");
+                break;
+            }
+            let batch_ids: Vec<String> = batch.iter().map(|id| id.to_id64_string()).collect();
+            debug!("Checking the logs of {}", batch_ids.join(", "));
+            // A batch may find as many logs as its players would on their own.
+            let max_logs = max_player_logs * batch.len();
+            let limit = match newer_than {
+                Some(_) => u16::try_from(RECONCILE_PLAYER_LOGS * batch.len()).unwrap_or(u16::MAX),
+                None => 10000,
+            };
+            let mut recent_logs = Vec::new();
+            let mut batch_overflow = 0;
+            source.search_logs_paged(
+                SearchParams::player_ids(batch.to_vec()).add_limit(limit),
+                SEARCH_PAGE_SIZE,
+                &mut |mut page| {
+                    if let Some(watermark) = newer_than {
+                        page.retain(|meta| meta.id > watermark);
+                    }
+                    // Remove all logs that are already in the database
+                    let removed =
+                        remove_known_mixes(client, &mut page, &recent_mixes, recent_floor);
+                    if let Err(e) = removed {
+                        lookup_error = Some(e);
+                        return false;
+                    }
+                    for meta in page {
+                        if let Some(reason) = player_count_skip_reason(&meta, num_players) {
+                            skipped.push((meta.id, reason));
+                        }
+                        else if !title_filter.allows(meta.title.as_deref()) {
+                            skipped.push((meta.id, SkipReason::Title(meta.title)));
+                        }
+                        else if recent_logs.len() < max_logs {
+                            recent_logs.push(meta);
+                        }
+                        else {
+                            batch_overflow += 1;
+                        }
+                    }
+
+                    true
+                },
+            )?;
+            if let Some(e) = lookup_error {
+                return Err(e);
+            }
+
+            if batch_overflow != 0 {
+                warn!(
+                    "{} have more than {} new logs, {} older logs are left out",
+                    batch_ids.join(", "),
+                    max_logs,
+                    batch_overflow
+                );
+            }
+            overflow += batch_overflow;
+            search_results.push((batch[0], recent_logs));
+        }
+
+        let mut plan = match search_ratio {
+            Some(min_ratio) if batch_size == 1 => {
+                select_new_logs(search_results, min_ratio, num_players)
+            },
+            // The players of the logs are only known once they are downloaded, so
+            // the ratio of mixes players is checked by the update.
+            _ => merge_batch_results(search_results),
+        };
+        // A log may have been skipped in the searches of several players.
+        plan.skipped.extend(skipped);
+        plan.skipped.sort_by_key(|(id, _)| Reverse(*id));
+        plan.skipped.dedup_by_key(|(id, _)| *id);
+        plan.overflow = overflow;
+
+        Ok(plan)
+    }
+
+    /// Add the logs of the registered players from the `source`, as described
+    /// in [`Database::update`].
+    fn update_from(
+        &mut self,
+        source: &dyn LogSource,
+        options: UpdateOptions,
+    ) -> DbResult<UpdateReport>
+    {
+        let user_ids = self.registered_ids()?;
+        self.update_players_from(source, &user_ids, options)
+    }
+
+    /// Add the logs of the `players` from the `source`, as described in
+    /// [`Database::update_players`]. The `players` must be registered. If the
+    /// options ask for it, the health of the source is checked first. Nothing
+    /// is done if it is down, and the update goes easy on it if it is degraded.
+    fn update_players_from(
+        &mut self,
+        source: &dyn LogSource,
+        players: &[SteamID],
+        options: UpdateOptions,
+    ) -> DbResult<UpdateReport>
+    {
+        let (mut report, health) =
+            self.with_checked_source(source, options.health_check, |db, source| {
+                db.run_update(source, players, options)
+            })?;
+        report.health = health;
+
+        Ok(report)
+    }
+
+    /// Call `run` with the `source`, after checking its health if
+    /// `health_check` is set. Nothing is run if the source is down, and the
+    /// source `run` gets goes easy on it if it is degraded.
+    ///
+    /// # Returns
+    /// What `run` has returned and the health of the source, if it has been
+    /// checked.
+    fn with_checked_source<T>(
+        &mut self,
+        source: &dyn LogSource,
+        health_check: bool,
+        run: impl FnOnce(&mut Self, &dyn LogSource) -> DbResult<T>,
+    ) -> DbResult<(T, Option<Health>)>
+    {
+        let health = if health_check {
+            Some(source.health()?)
+        }
+        else {
+            None
+        };
+
+        let result = match &health {
+            Some(Health::Down(problem)) => {
+                warn!("Not updating, the source of the logs is down: {}", problem);
+                return Err(DbError::UpstreamUnavailable(problem.clone()));
+            },
+            Some(Health::Degraded(degradation)) => {
+                warn!(
+                    "The source of the logs is degraded ({:?}), the update will be slower",
+                    degradation
+                );
+                let source = Backoff::new(source, DEGRADED_REQUEST_DELAY, DEGRADED_NUM_RETRIES);
+                run(self, &source)?
+            },
+            Some(Health::Up) | None => run(self, source)?,
+        };
+
+        Ok((result, health))
+    }
+
+    /// Run an update of the `players` from the `source`, see
+    /// [`SQLDb::update_players_from`].
+    fn run_update(
+        &mut self,
+        source: &dyn LogSource,
+        players: &[SteamID],
+        mut options: UpdateOptions,
+    ) -> DbResult<UpdateReport>
+    {
+        info!("Updating database");
+        let started = Instant::now();
+        // Without a tag of its own, the update uses the tag of the database.
+        if options.tag.is_none() {
+            options.tag = self.update_tag.clone();
+        }
+        // The run is recorded before anything else, so runs that fail halfway are
+        // still part of the history.
+        let run_id = self.start_update_run(&options)?;
+        // Logs above the highest id stored before the searches may have been
+        // uploaded while they ran, and are looked at again at the end.
+        let watermark = self.query_max_log_id()?.unwrap_or(LogId(0));
+        let cancel = options.cancel.take();
+        let is_cancelled = || cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        let (scope, plan) = self.plan_scoped(source, players, &options, cancel.as_ref())?;
+        let scoped = players.len() < scope.users.len();
+        info!("{} logs need to be downloaded", plan.candidates.len());
+
+        let mut report = UpdateReport {
+            added:          Vec::new(),
+            reconciled:     Vec::new(),
+            skipped:        plan.skipped,
+            overflow:       plan.overflow,
+            run_id:         Some(run_id),
+            cancelled:      is_cancelled(),
+            health:         None,
+            duration:       None,
+            records_broken: Vec::new(),
+        };
+
+        // Download the new logs and add it to the database. A log that cannot be
+        // added does not stop the update, its transaction is rolled back and the
+        // next log is added as usual.
+        let num_candidates = plan.candidates.len();
+        let mut last_error = None;
+        let mut num_failed = 0;
+        for (done, meta) in plan.candidates.into_iter().enumerate() {
+            // Logs are only added as a whole, so the update can stop between them.
+            if report.cancelled || is_cancelled() {
+                info!(
+                    "The update has been cancelled after {} of {} logs",
+                    done, num_candidates
+                );
+                report.cancelled = true;
+                if let Some(progress) = &mut options.progress {
+                    progress(UpdateProgress {
+                        done,
+                        total: num_candidates,
+                        cancelled: true,
+                    });
+                }
+                break;
+            }
+            let ingested = self.ingest_candidate(
+                source,
+                &meta,
+                &scope.users,
+                scope.download_ratio(),
+                options.tag.as_ref(),
+                &mut report,
+            );
+            if let Err(e) = ingested {
+                if self.client.is_closed() {
+                    return Err(e);
+                }
+                warn!("Skipping log {}, which could not be added: {}", meta.id, e);
+                report
+                    .skipped
+                    .push((meta.id, SkipReason::IngestFailed(e.to_string())));
+                num_failed += 1;
+                last_error = Some(e);
+            }
+            if let Some(progress) = &mut options.progress {
+                progress(UpdateProgress {
+                    done:      done + 1,
+                    total:     num_candidates,
+                    cancelled: false,
+                });
+            }
+        }
+        if num_failed == num_candidates {
+            if let Some(e) = last_error {
+                warn!(
+                    "None of the {} logs of the update could be added",
+                    num_candidates
+                );
+                // The run is still finished, so its history tells which logs have failed.
+                self.finish_update_run(run_id, num_candidates, &report)?;
+                return Err(e);
+            }
+        }
+        let mut num_candidates = num_candidates;
+        if !report.cancelled && !is_cancelled() {
+            num_candidates +=
+                self.reconcile_update(source, watermark, &options, &scope, &mut report)?;
+        }
+        let duration = started.elapsed();
+        report.duration = Some(duration);
+        #[cfg(feature = "metrics")]
+        crate::metrics::observe_update(duration);
+        self.finish_update_run(run_id, num_candidates, &report)?;
+        if report.cancelled {
+            info!(
+                "Update cancelled, {} logs have been added",
+                report.added.len()
+            );
+            self.emit(DbEvent::UpdateCancelled {
+                added: report.added.len(),
+            });
+
+            return Ok(report);
+        }
+        // Only an update of all players brings the whole database up to date.
+        if !scoped {
+            self.client.execute(
+                "INSERT INTO meta (key, value) VALUES ('last_update', now()::text) ON CONFLICT \
+                 (key) DO UPDATE SET value = EXCLUDED.value",
+                &[],
+            )?;
+        }
+        info!(
+            "Update done, {} logs have been added, {} skipped",
+            report.added.len(),
+            report.skipped.len()
+        );
+        self.emit(DbEvent::UpdateCompleted {
+            added: report.added.len(),
+        });
+
+        Ok(report)
+    }
+
+    /// Search the newest logs of the players again at the end of an update, to
+    /// pick up the logs that have been uploaded while it ran, with the same
+    /// `options`. Only logs with a higher id than the `watermark` are looked
+    /// at. Their occurrences are counted anew, so a log that has been uploaded
+    /// between the searches of two of its players can now have enough mixes
+    /// players. Logs that the update has already added, or skipped for a
+    /// reason that still holds, are left alone.
+    ///
+    /// # Returns
+    /// The number of logs that have been tried to add.
+    fn reconcile_update(
+        &mut self,
+        source: &dyn LogSource,
+        watermark: LogId,
+        options: &UpdateOptions,
+        scope: &UpdateScope,
+        report: &mut UpdateReport,
+    ) -> DbResult<usize>
+    {
+        let candidates =
+            self.reconcile_candidates(source, watermark, options, scope, &mut report.skipped)?;
+        if !candidates.is_empty() {
+            info!(
+                "{} logs have been uploaded during the update",
+                candidates.len()
+            );
+        }
+
+        for meta in &candidates {
+            // The log is decided on anew, so it is no longer skipped by the first search.
+            report.skipped.retain(|(id, _)| *id != meta.id);
+            let num_added = report.added.len();
+            let ingested = self.ingest_candidate(
+                source,
+                meta,
+                &scope.users,
+                scope.download_ratio(),
+                options.tag.as_ref(),
+                report,
+            );
+            if let Err(e) = ingested {
+                if self.client.is_closed() {
+                    return Err(e);
+                }
+                warn!("Skipping log {}, which could not be added: {}", meta.id, e);
+                report
+                    .skipped
+                    .push((meta.id, SkipReason::IngestFailed(e.to_string())));
+            }
+            else if report.added.len() > num_added {
+                report.reconciled.push(meta.id);
+            }
+        }
+
+        Ok(candidates.len())
+    }
+
+    /// Search the newest logs of the players again for the reconciliation of an
+    /// update, see [`SQLDb::reconcile_update`]. The logs that are newly found
+    /// to be skipped are added to the `skipped` logs of the update.
+    ///
+    /// # Returns
+    /// The logs that have to be decided on anew.
+    fn reconcile_candidates(
+        &mut self,
+        source: &dyn LogSource,
+        watermark: LogId,
+        options: &UpdateOptions,
+        scope: &UpdateScope,
+        skipped: &mut Vec<(LogId, SkipReason)>,
+    ) -> DbResult<Vec<LogMetadata>>
+    {
+        let plan = self.plan_update_since(
+            source,
+            scope.players,
+            scope.search_ratio(),
+            &options.num_players,
+            None,
+            Some(watermark),
+        )?;
+
+        // If the ratio has been checked against the players of the log, which
+        // do not change, it still holds. Otherwise only the players that had
+        // found it were known.
+        let reconsidered = |reason: &SkipReason| {
+            !scope.ratio_after_download && *reason == SkipReason::NotEnoughMixesPlayers
+        };
+        let settled: HashSet<LogId> = skipped
+            .iter()
+            .filter(|(_, reason)| !reconsidered(reason))
+            .map(|(id, _)| *id)
+            .collect();
+        for (id, reason) in plan.skipped {
+            if !skipped.iter().any(|(known, _)| *known == id) {
+                skipped.push((id, reason));
+            }
+        }
+
+        Ok(plan
+            .candidates
+            .into_iter()
+            .filter(|meta| !settled.contains(&meta.id))
+            .collect())
+    }
+
+    /// Record the start of an update with the `options` in the history of
+    /// updates. The run is not completed until [`SQLDb::finish_update_run`] is
+    /// called for it.
+    fn start_update_run(&mut self, options: &UpdateOptions) -> DbResult<UpdateRunId>
+    {
+        let row = self.client.query_one(
+            "INSERT INTO update_runs (min_ratio, min_players, max_players, tag) VALUES ($1, $2, \
+             $3, $4) RETURNING run_id",
+            &[
+                &options.min_ratio,
+                &(*options.num_players.start() as i16),
+                &(*options.num_players.end() as i16),
+                &options.tag.as_ref().map(LogTag::as_str),
+            ],
+        )?;
+
+        Ok(UpdateRunId(row.get(0)))
+    }
+
+    /// Mark the update run as finished, with the number of logs it has
+    /// considered and the `report` of what it has done with them. A run that
+    /// has been cancelled is finished, but not completed.
+    fn finish_update_run(
+        &mut self,
+        run_id: UpdateRunId,
+        num_candidates: usize,
+        report: &UpdateReport,
+    ) -> DbResult<()>
+    {
+        let num_failed = report.failures().count();
+        self.client.execute(
+            "UPDATE update_runs SET finished_at = clock_timestamp(), num_candidates = $2, \
+             num_added = $3, num_failed = $4, cancelled = $5 WHERE run_id = $1",
+            &[
+                &run_id.0,
+                &(num_candidates as i32),
+                &(report.added.len() as i32),
+                &(num_failed as i32),
+                &report.cancelled,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Add a log found by an update to the database, as part of
+    /// [`SQLDb::update_from`]. Logs that are already present from the history
+    /// of a player do not need to be downloaded again. If `batched_ratio` is
+    /// set, the ratio of mixes players still has to be checked against it.
+    /// The log is tagged with the `tag` of the update as it is added.
+    fn ingest_candidate(
+        &mut self,
+        source: &dyn LogSource,
+        meta: &LogMetadata,
+        users: &HashSet<SteamID>,
+        batched_ratio: Option<f32>,
+        tag: Option<&LogTag>,
+        report: &mut UpdateReport,
+    ) -> DbResult<()>
+    {
+        // The ratio is taken of the players that have actually played, like the
+        // number of players stored for the log, not of those found by the search.
+        let enough_mixes_players =
+            |num_mixes_players: usize, num_players: usize| match batched_ratio {
+                Some(min_ratio) => num_mixes_players as f32 / num_players as f32 >= min_ratio,
+                None => true,
+            };
+
+        if batched_ratio.is_some() {
+            if let Some((num_mixes_players, num_players)) = self.stored_mixes_players(meta.id)? {
+                if !enough_mixes_players(num_mixes_players, num_players) {
+                    report
+                        .skipped
+                        .push((meta.id, SkipReason::NotEnoughMixesPlayers));
+                    return Ok(());
+                }
+            }
+        }
+
+        // A log that is stored from the history of a player only changes its
+        // reason, which moves its aggregates to those of the mixes.
+        let mut transaction = self.client.transaction()?;
+        update_aggregates(&mut transaction, &[meta.id], -1)?;
+        let num_updated = transaction.execute(
+            "UPDATE logs SET ingest_reason = $2 WHERE log_id = $1",
+            &[&meta.id, &(IngestReason::Mix as i16)],
+        )?;
+        if num_updated != 0 {
+            update_aggregates(&mut transaction, &[meta.id], 1)?;
+            if let Some(tag) = tag {
+                insert_tag(&mut transaction, meta.id, tag)?;
+            }
+            transaction.commit()?;
+            report.added.push(meta.id);
+            return Ok(());
+        }
+        drop(transaction);
+
+        let download = source.download_log(meta.id);
+        if let Ok(log) = &download {
+            let num_mixes_players = log
+                .performances()
+                .keys()
+                .filter(|id| users.contains(id))
+                .count();
+            if !enough_mixes_players(num_mixes_players, log.performances().len()) {
+                report
+                    .skipped
+                    .push((meta.id, SkipReason::NotEnoughMixesPlayers));
+                return Ok(());
+            }
+        }
+
+        self.add_downloaded_log(meta.id, download, IngestReason::Mix, tag, report)
+    }
+
+    /// Count the registered players that have stats stored for the log.
+    ///
+    /// # Returns
+    /// The number of registered players and the number of players stored for
+    /// the log, or `None` if the log is not stored.
+    fn stored_mixes_players(&mut self, id: LogId) -> DbResult<Option<(usize, usize)>>
+    {
+        let row = self.client.query_opt(
+            "SELECT (SELECT count(*) FROM overall_stats JOIN users ON users.steam_id = \
+             overall_stats.steam_id WHERE overall_stats.log_id = $1), num_players FROM logs WHERE \
+             log_id = $1",
+            &[&id],
+        )?;
+
+        Ok(row.map(|row| {
+            let num_players: i16 = row.get(1);
+            (row.get::<_, i64>(0) as usize, num_players as usize)
+        }))
+    }
+
+    /// Read all performances of the player with the `steam_id` in the log
+    /// back from the database.
+    fn stored_performance(&mut self, id: LogId, steam_id: i64) -> DbResult<LogPerformance>
+    {
+        let mut log_performances = Vec::new();
+
+        // Overall performance
+        log_performances.extend(
+            self.client
+                .query(
+                    format!(
+                        "SELECT {} FROM overall_stats WHERE log_id=$1 AND steam_id=$2",
+                        OVERALL_COLUMNS
+                    )
+                    .as_str(),
+                    &[&id, &steam_id],
+                )?
+                .iter()
+                .map(|row| row_to_overall(row).into()),
+        );
+
+        // DM performances
+        log_performances.extend(
+            self.client
+                .query(
+                    format!(
+                        "SELECT {} FROM dm_stats WHERE log_id=$1 AND steam_id=$2",
+                        DM_COLUMNS
+                    )
+                    .as_str(),
+                    &[&id, &steam_id],
+                )?
+                .iter()
+                .map(|row| row_to_dm(row).into()),
+        );
+
+        // Possible medic performance
+        let mut heal_targets = Some(self.heal_targets(id, steam_id)?);
+        log_performances.extend(
+            self.client
+                .query(
+                    format!(
+                        "SELECT {} FROM med_stats WHERE log_id=$1 AND steam_id=$2",
+                        MED_COLUMNS
+                    )
+                    .as_str(),
+                    &[&id, &steam_id],
+                )?
+                .iter()
+                .map(|row| {
+                    MedicPerformance {
+                        heal_targets: heal_targets.take().unwrap_or_default(),
+                        ..row_to_med(row)
+                    }
+                    .into()
+                }),
+        );
+
+        Ok(LogPerformance::from(log_performances))
+    }
+
+    /// Get the healing the medic has done to each teammate in the log, most
+    /// healed teammate first.
+    fn heal_targets(&mut self, log_id: LogId, medic_id: i64) -> DbResult<Vec<(SteamID, u32)>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT target_id, healing FROM heal_spread WHERE log_id=$1 AND medic_id=$2 ORDER \
+                 BY healing DESC, target_id",
+                &[&log_id, &medic_id],
+            )?
+            .iter()
+            .filter_map(|row| {
+                let target_id = stored_user_id(row.get(0)).ok()?;
+                let healing: i32 = row.get(1);
+
+                Some((target_id, healing as u32))
+            })
+            .collect())
+    }
+
+    /// Get the records of the `user` with all players that have been on the
+    /// same team (`same_team`) or on the other team in at least `min_games`
+    /// games. Rows are made distinct first, so a player is never counted twice
+    /// for the same log.
+    fn query_teammate_records(
+        &mut self,
+        user: SteamID,
+        min_games: u32,
+        same_team: bool,
+    ) -> DbResult<Vec<TeammateRecord>>
+    {
+        let team_condition = if same_team { "=" } else { "<>" };
+
+        Ok(self
+            .client
+            .query(
+                format!(
+                    "SELECT other.steam_id, COUNT(*), COUNT(*) FILTER (WHERE 2 * \
+                     user_stats.won_rounds > user_stats.num_rounds), COUNT(*) FILTER (WHERE 2 * \
+                     user_stats.won_rounds < user_stats.num_rounds), COUNT(*) FILTER (WHERE 2 * \
+                     user_stats.won_rounds = user_stats.num_rounds) FROM (SELECT DISTINCT log_id, \
+                     team, won_rounds, num_rounds FROM overall_stats WHERE steam_id = $1 AND team \
+                     IS NOT NULL) user_stats JOIN (SELECT DISTINCT log_id, steam_id, team FROM \
+                     overall_stats WHERE steam_id <> $1 AND team IS NOT NULL) other ON \
+                     other.log_id = user_stats.log_id AND other.team {} user_stats.team GROUP BY \
+                     other.steam_id HAVING COUNT(*) >= $2 ORDER BY COUNT(*) DESC, other.steam_id",
+                    team_condition
+                )
+                .as_str(),
+                &[&(user.id64() as i64), &(min_games as i64)],
+            )?
+            .iter()
+            .filter_map(|row| {
+                let count = |idx| {
+                    let count: i64 = row.get(idx);
+                    count as u32
+                };
+
+                Some(TeammateRecord {
+                    player:    stored_user_id(row.get(0)).ok()?,
+                    num_games: count(1),
+                    wins:      count(2),
+                    losses:    count(3),
+                    ties:      count(4),
+                })
+            })
+            .collect())
+    }
+
+    /// Find users where `condition` holds, which may use the `pattern` as `$1`.
+    /// The users are ordered by the date of their newest log, then by steam id.
+    fn query_players_by_name(
+        &mut self,
+        condition: &str,
+        pattern: &str,
+        limit: usize,
+    ) -> Result<Vec<(SteamID, String)>, DbError>
+    {
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        Ok(self
+            .client
+            .query(
+                format!(
+                    "SELECT users.steam_id, users.username FROM users LEFT JOIN (SELECT \
+                     overall_stats.steam_id, MAX(logs.date) AS last_seen FROM overall_stats JOIN \
+                     logs ON logs.log_id = overall_stats.log_id GROUP BY overall_stats.steam_id) \
+                     seen ON seen.steam_id = users.steam_id WHERE {} ORDER BY seen.last_seen DESC \
+                     NULLS LAST, users.steam_id LIMIT $2",
+                    condition
+                )
+                .as_str(),
+                &[&pattern, &limit],
+            )?
+            .iter()
+            .filter_map(|row| {
+                let steam_id = stored_user_id(row.get(0)).ok()?;
+                Some((steam_id, row.get(1)))
+            })
+            .collect())
+    }
+}
+
+impl Database for SQLDb
+{
+    fn set_event_handler(&mut self, handler: EventHandler) { self.event_handler = Some(handler); }
+
+    fn add_user(&mut self, steam_id: SteamID, discord_id: u64) -> MixesDbResult<bool>
+    {
+        if !steam_id.is_individual() {
+            return Err(MixesDbError::InvalidInput(format!(
+                "{} is not the steam id of a player",
+                steam_id.to_id64_string()
+            )));
+        }
+        let user = steam_id;
+        // Convert to bigint
+        let steam_id: i64 = steam_id.id64() as i64;
+        let discord_id: i64 = discord_id as i64;
+        // Check if the steam id or discord id is already in the database
+        if self
+            .client
+            .query(
+                "SELECT FROM users WHERE steam_id = $1 OR discord_id = $2",
+                &[&steam_id, &discord_id],
+            )?
+            .is_empty()
+        {
+            // No entries yet. Add user to the database.
+            self.client.execute(
+                "INSERT INTO users (steam_id, discord_id, registered_at) VALUES ($1, $2, now())",
+                &[&steam_id, &discord_id],
+            )?;
+            self.emit(DbEvent::UserAdded(user));
+
+            Ok(true)
+        }
+        else {
+            // Already in the database
+            Ok(false)
+        }
+    }
+
+    fn add_users_bulk(&mut self, entries: &[(u64, &str)]) -> MixesDbResult<BulkAddReport>
+    {
+        let mut transaction = self.client.transaction()?;
+
+        let mut report = BulkAddReport::default();
+        for &(discord_id, steam_reference) in entries {
+            let steam_id = match SteamID::from_reference(steam_reference) {
+                Ok(steam_id) if steam_id.is_individual() => steam_id,
+                Ok(_) => {
+                    report.outcomes.push(BulkAddOutcome::Unparseable(format!(
+                        "`{}` is not the steam id of a player",
+                        steam_reference
+                    )));
+                    continue;
+                },
+                Err(reason) => {
+                    report.outcomes.push(BulkAddOutcome::Unparseable(reason));
+                    continue;
+                },
+            };
+
+            let row = transaction.query_one(
+                "SELECT EXISTS (SELECT FROM users WHERE steam_id = $1), EXISTS (SELECT FROM users \
+                 WHERE discord_id = $2)",
+                &[&(steam_id.id64() as i64), &(discord_id as i64)],
+            )?;
+            let outcome = if row.get(0) {
+                BulkAddOutcome::DuplicateSteamId(steam_id)
+            }
+            else if row.get(1) {
+                BulkAddOutcome::DuplicateDiscordId(steam_id)
+            }
+            else {
+                transaction.execute(
+                    "INSERT INTO users (steam_id, discord_id, registered_at) VALUES ($1, $2, \
+                     now())",
+                    &[&(steam_id.id64() as i64), &(discord_id as i64)],
+                )?;
+                BulkAddOutcome::Added(steam_id)
+            };
+            report.outcomes.push(outcome);
+        }
+
+        transaction.commit()?;
+        info!(
+            "Added {} of {} users in bulk",
+            report.num_added(),
+            report.outcomes.len()
+        );
+        for outcome in &report.outcomes {
+            if let BulkAddOutcome::Added(steam_id) = outcome {
+                self.emit(DbEvent::UserAdded(*steam_id));
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn remove_user(&mut self, steam_id: SteamID) -> MixesDbResult<bool>
+    {
+        let user = steam_id;
+        let steam_id = steam_id.id64() as i64;
+        let user_exists = !self
+            .client
+            .query("SELECT FROM users WHERE steam_id = $1", &[&steam_id])?
+            .is_empty();
+
+        if user_exists {
+            self.client
+                .execute("DELETE FROM users WHERE steam_id = $1", &[&steam_id])?;
+            self.emit(DbEvent::UserRemoved(user));
+
+            Ok(true)
+        }
+        else {
+            Ok(false)
+        }
+    }
+
+    fn purge_player_data(
+        &mut self,
+        steam_id: SteamID,
+        remove_sole_logs: bool,
+    ) -> MixesDbResult<PurgeReport>
+    {
+        let user = steam_id;
+        let steam_id = steam_id.id64() as i64;
+        let mut transaction = self.client.transaction()?;
+        let mut report = PurgeReport::default();
+
+        let mut log_ids: Vec<LogId> = Vec::new();
+        if remove_sole_logs {
+            // Logs the player has played in, without any other registered player.
+            log_ids = transaction
+                .query(
+                    "SELECT log_id FROM overall_stats AS own WHERE steam_id = $1 AND NOT EXISTS \
+                     (SELECT FROM overall_stats JOIN users ON users.steam_id = \
+                     overall_stats.steam_id WHERE overall_stats.log_id = own.log_id AND \
+                     overall_stats.steam_id <> $1) ORDER BY log_id",
+                    &[&steam_id],
+                )?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            update_aggregates(&mut transaction, &log_ids, -1)?;
+            report.logs =
+                transaction.execute("DELETE FROM logs WHERE log_id = ANY($1)", &[&log_ids])?;
+            // Names are removed with the log they have been taken from.
+            for table in ["log_tags", "player_names"] {
+                transaction.execute(
+                    format!("DELETE FROM {} WHERE log_id = ANY($1)", table).as_str(),
+                    &[&log_ids],
+                )?;
+            }
+            for (table, removed) in [
+                ("overall_stats", &mut report.overall_stats),
+                ("dm_stats", &mut report.dm_stats),
+                ("med_stats", &mut report.med_stats),
+                ("heal_spread", &mut report.heal_spread),
+                ("chat", &mut report.chat),
+            ] {
+                *removed += transaction.execute(
+                    format!("DELETE FROM {} WHERE log_id = ANY($1)", table).as_str(),
+                    &[&log_ids],
+                )?;
+            }
+            refresh_records(&mut transaction, &log_ids)?;
+        }
+
+        report.user =
+            transaction.execute("DELETE FROM users WHERE steam_id = $1", &[&steam_id])? > 0;
+        for (table, removed) in [
+            ("overall_stats", &mut report.overall_stats),
+            ("dm_stats", &mut report.dm_stats),
+            ("med_stats", &mut report.med_stats),
+            ("chat", &mut report.chat),
+        ] {
+            *removed += transaction.execute(
+                format!("DELETE FROM {} WHERE steam_id = $1", table).as_str(),
+                &[&steam_id],
+            )?;
+        }
+        report.heal_spread += transaction.execute(
+            "DELETE FROM heal_spread WHERE medic_id = $1 OR target_id = $1",
+            &[&steam_id],
+        )?;
+        for table in ["aggregates_cache", "player_names", "personal_records"] {
+            transaction.execute(
+                format!("DELETE FROM {} WHERE steam_id = $1", table).as_str(),
+                &[&steam_id],
+            )?;
+        }
+        // The logs they have uploaded are kept without their uploader.
+        transaction.execute(
+            "UPDATE logs SET uploader = NULL WHERE uploader = $1",
+            &[&steam_id],
+        )?;
+
+        transaction.commit()?;
+        info!("Purged all data of player {}: {:?}", steam_id, report);
+
+        if report.user {
+            self.emit(DbEvent::UserRemoved(user));
+        }
+        for log_id in log_ids {
+            self.emit(DbEvent::LogRemoved(log_id));
+        }
+
+        Ok(report)
+    }
+
+    fn apply_retention(&mut self, policy: RetentionPolicy) -> MixesDbResult<RetentionReport>
+    {
+        let protected: Vec<&str> = policy.protected_tags.iter().map(LogTag::as_str).collect();
+        let mut log_ids: Vec<LogId> = Vec::new();
+        if let Some(max_age_days) = policy.max_age_days {
+            let cutoff = Utc::now() - chrono::Duration::days(max_age_days as i64);
+            log_ids.extend(
+                self.client
+                    .query(
+                        "SELECT log_id FROM logs WHERE date < $1 AND NOT EXISTS (SELECT FROM \
+                         log_tags WHERE log_tags.log_id = logs.log_id AND tag = ANY($2)) AND (NOT \
+                         $3 OR match_id IS NULL)",
+                        &[&cutoff, &protected, &policy.protect_linked_matches],
+                    )?
+                    .iter()
+                    .map(|row| row.get::<_, LogId>(0)),
+            );
+        }
+        if let Some(max_logs) = policy.max_backfill_logs {
+            // Backfilled logs that are not among the newest of any registered player
+            // in them.
+            log_ids.extend(
+                self.client
+                    .query(
+                        "SELECT log_id FROM logs WHERE ingest_reason = $1 AND NOT EXISTS (SELECT \
+                         FROM log_tags WHERE log_tags.log_id = logs.log_id AND tag = ANY($3)) AND \
+                         (NOT $4 OR match_id IS NULL) AND log_id NOT IN (SELECT log_id FROM \
+                         (SELECT stats.log_id, row_number() OVER (PARTITION BY stats.steam_id \
+                         ORDER BY backfilled.date DESC, backfilled.log_id DESC) AS newest FROM \
+                         overall_stats stats JOIN users ON users.steam_id = stats.steam_id JOIN \
+                         logs backfilled ON backfilled.log_id = stats.log_id WHERE \
+                         backfilled.ingest_reason = $1) ranked WHERE newest <= $2)",
+                        &[
+                            &(IngestReason::PersonalBackfill as i16),
+                            &i64::try_from(max_logs).unwrap_or(i64::MAX),
+                            &protected,
+                            &policy.protect_linked_matches,
+                        ],
+                    )?
+                    .iter()
+                    .map(|row| row.get::<_, LogId>(0)),
+            );
+        }
+        log_ids.sort_unstable();
+        log_ids.dedup();
+
+        let mut report = RetentionReport::default();
+        for batch in log_ids.chunks(policy.batch_size.max(1)) {
+            let mut transaction = self.client.transaction()?;
+            update_aggregates(&mut transaction, batch, -1)?;
+            report.logs +=
+                transaction.execute("DELETE FROM logs WHERE log_id = ANY($1)", &[&batch])?;
+            for (table, removed) in [
+                ("overall_stats", &mut report.overall_stats),
+                ("dm_stats", &mut report.dm_stats),
+                ("med_stats", &mut report.med_stats),
+                ("heal_spread", &mut report.heal_spread),
+                ("chat", &mut report.chat),
+                ("log_tags", &mut report.log_tags),
+            ] {
+                *removed += transaction.execute(
+                    format!("DELETE FROM {} WHERE log_id = ANY($1)", table).as_str(),
+                    &[&batch],
+                )?;
+            }
+            transaction.execute("DELETE FROM player_names WHERE log_id = ANY($1)", &[&batch])?;
+            refresh_records(&mut transaction, batch)?;
+
+            // A dry run removes the rows just the same, so it counts exactly what a
+            // real run would remove, but never commits.
+            if policy.dry_run {
+                transaction.rollback()?;
+            }
+            else {
+                transaction.commit()?;
+                // The batch stays removed even if a later one fails.
+                for &log_id in batch {
+                    self.emit(DbEvent::LogRemoved(log_id));
+                }
+            }
+        }
+
+        if policy.dry_run {
+            info!("Retention would remove: {:?}", report);
+        }
+        else {
+            info!("Retention has removed: {:?}", report);
+        }
+
+        Ok(report)
+    }
+
+    fn check_integrity(&mut self, repair: bool) -> MixesDbResult<IntegrityReport>
+    {
+        let mut transaction = self.client.transaction()?;
+        let mut report = IntegrityReport::default();
+
+        for table in [
+            "overall_stats",
+            "dm_stats",
+            "med_stats",
+            "heal_spread",
+            "chat",
+            "log_tags",
+        ] {
+            let orphaned = format!(
+                "NOT EXISTS (SELECT FROM logs WHERE logs.log_id = {}.log_id)",
+                table
+            );
+            for row in transaction.query(
+                format!(
+                    "SELECT log_id, COUNT(*) FROM {} WHERE {} GROUP BY log_id ORDER BY log_id",
+                    table, orphaned
+                )
+                .as_str(),
+                &[],
+            )? {
+                let rows: i64 = row.get(1);
+                report.problems.push(IntegrityProblem::OrphanRows {
+                    table,
+                    log_id: row.get(0),
+                    rows: rows as u64,
+                });
+            }
+
+            if repair {
+                report.repaired_rows += transaction.execute(
+                    format!("DELETE FROM {} WHERE {}", table, orphaned).as_str(),
+                    &[],
+                )?;
+            }
+        }
+
+        for row in transaction.query(
+            "SELECT log_id FROM logs WHERE NOT EXISTS (SELECT FROM overall_stats WHERE \
+             overall_stats.log_id = logs.log_id) AND NOT EXISTS (SELECT FROM dm_stats WHERE \
+             dm_stats.log_id = logs.log_id) ORDER BY log_id",
+            &[],
+        )? {
+            report.problems.push(IntegrityProblem::EmptyLog(row.get(0)));
+        }
+
+        for row in transaction.query(
+            "SELECT log_id, duration_secs FROM logs WHERE duration_secs IS NULL OR duration_secs \
+             < 0 OR (NOT duration_suspect AND (duration_secs = 0 OR duration_secs > $1)) ORDER BY \
+             log_id",
+            &[&(self.max_duration_secs as i32)],
+        )? {
+            report.problems.push(IntegrityProblem::InvalidDuration {
+                log_id:        row.get(0),
+                duration_secs: row.get(1),
+            });
+        }
+
+        for row in transaction.query(
+            "SELECT log_id, MIN(num_rounds), MAX(num_rounds) FROM overall_stats GROUP BY log_id \
+             HAVING MIN(num_rounds) <> MAX(num_rounds) ORDER BY log_id",
+            &[],
+        )? {
+            report.problems.push(IntegrityProblem::InconsistentRounds {
+                log_id:     row.get(0),
+                min_rounds: row.get(1),
+                max_rounds: row.get(2),
+            });
+        }
+
+        for row in transaction.query(
+            "SELECT log_id, steam_id, class FROM dm_stats WHERE class NOT BETWEEN 0 AND $1 ORDER \
+             BY log_id, steam_id, class",
+            &[&(Class::Unknown as i16)],
+        )? {
+            let player: i64 = row.get(1);
+            report.problems.push(IntegrityProblem::InvalidClass {
+                log_id: row.get(0),
+                player: player as u64,
+                class:  row.get(2),
+            });
+        }
+
+        for (table, class) in [
+            ("overall_stats", "NULL::smallint"),
+            ("dm_stats", "class"),
+            ("med_stats", "NULL::smallint"),
+        ] {
+            for row in transaction.query(
+                format!(
+                    "SELECT log_id, steam_id, {1}, COUNT(*) FROM {0} GROUP BY log_id, steam_id, \
+                     {1} HAVING COUNT(*) > 1 ORDER BY log_id, steam_id, {1}",
+                    table, class
+                )
+                .as_str(),
+                &[],
+            )? {
+                let player: i64 = row.get(1);
+                let rows: i64 = row.get(3);
+                report.problems.push(IntegrityProblem::DuplicateRows {
+                    table,
+                    log_id: row.get(0),
+                    player: player as u64,
+                    class: row.get(2),
+                    rows: rows as u64,
+                });
+            }
+        }
+
+        for row in transaction.query("SELECT steam_id FROM users ORDER BY steam_id", &[])? {
+            let steam_id: i64 = row.get(0);
+            if let Err(error) = SteamID::try_from(steam_id as u64) {
+                report.problems.push(IntegrityProblem::InvalidUser {
+                    steam_id: steam_id as u64,
+                    error,
+                });
+            }
+        }
+
+        transaction.commit()?;
+        if report.is_ok() {
+            info!("No problems found in the stored data");
+        }
+        else {
+            warn!(
+                "Found {} problems in the stored data, removed {} rows",
+                report.problems.len(),
+                report.repaired_rows
+            );
+        }
+
+        Ok(report)
+    }
+
+    fn users_checked(&mut self) -> MixesDbResult<UsersReport>
+    {
+        let mut report = UsersReport::default();
+        for row in self
+            .client
+            .query("SELECT steam_id FROM users ORDER BY steam_id", &[])?
+        {
+            let steam_id: i64 = row.get(0);
+            match stored_user_id(steam_id) {
+                Ok(user) => report.valid.push(user),
+                Err(error) => report.invalid.push((steam_id, error)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn user_records(&mut self) -> MixesDbResult<Vec<UserRecord>> { Ok(self.query_user_records()?) }
+
+    fn user_record(&mut self, steam_id: SteamID) -> MixesDbResult<Option<UserRecord>>
+    {
+        Ok(self
+            .client
+            .query_opt(
+                "SELECT steam_id, discord_id, registered_at, active, username FROM users WHERE \
+                 steam_id = $1",
+                &[&(steam_id.id64() as i64)],
+            )?
+            .and_then(|row| user_record_from_row(&row).ok()))
+    }
+
+    fn set_user_active(&mut self, steam_id: SteamID, active: bool) -> MixesDbResult<bool>
+    {
+        Ok(self.client.execute(
+            "UPDATE users SET active = $2 WHERE steam_id = $1",
+            &[&(steam_id.id64() as i64), &active],
+        )? != 0)
+    }
+
+    fn username(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>
+    {
+        let steam_id = steam_id.id64() as i64;
+
+        Ok(self
+            .client
+            .query("SELECT username FROM users WHERE steam_id=$1", &[&steam_id])?
+            .iter()
+            .find_map(|row| {
+                // Users that have never been given a name do not have one.
+                let username: Option<String> = row.get(0);
+                username
+            }))
+    }
+
+    fn set_username(&mut self, steam_id: SteamID, username: &str) -> MixesDbResult<bool>
+    {
+        check_length("username", username, MAX_NAME_LEN)?;
+        let steam_id = steam_id.id64() as i64;
+
+        Ok(self.client.execute(
+            "UPDATE users SET username = $2 WHERE steam_id = $1",
+            &[&steam_id, &username],
+        )? != 0)
+    }
+
+    fn find_players_by_name(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, String)>>
+    {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(MixesDbError::InvalidInput(
+                "Player name search must not be empty".to_owned(),
+            ));
+        }
+
+        // Exact matches take precedence, so that a player with a short name can always
+        // be found, even if it is part of lots of other names.
+        let exact_matches =
+            self.query_players_by_name("lower(users.username) = lower($1)", query, limit)?;
+        if !exact_matches.is_empty() {
+            return Ok(exact_matches);
+        }
+
+        let pattern = substring_pattern(query);
+        Ok(self.query_players_by_name("users.username ILIKE $1", &pattern, limit)?)
+    }
+
+    fn player_name(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>
+    {
+        Ok(self
+            .client
+            .query_opt(
+                "SELECT name FROM player_names WHERE steam_id = $1",
+                &[&(steam_id.id64() as i64)],
+            )?
+            .map(|row| row.get(0)))
+    }
+
+    fn add_log(&mut self, log: Log) -> MixesDbResult<bool>
+    {
+        Ok(self.insert_log(log, IngestReason::Mix, None)?.is_some())
+    }
+
+    fn add_log_from_json(&mut self, id: LogId, json: &str) -> MixesDbResult<bool>
+    {
+        let log = Log::parse(id, json)?;
+
+        self.add_log(log)
+    }
+
+    fn has_log(&mut self, id: LogId) -> MixesDbResult<bool>
+    {
+        Ok(self
+            .client
+            .query_opt("SELECT 1 FROM logs WHERE log_id = $1", &[&id])?
+            .is_some())
+    }
+
+    fn max_log_id(&mut self) -> MixesDbResult<Option<LogId>> { Ok(self.query_max_log_id()?) }
+
+    fn tag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>
+    {
+        Ok(insert_tag(&mut *self.client, id, tag)?)
+    }
+
+    fn untag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>
+    {
+        let num_removed = self.client.execute(
+            "DELETE FROM log_tags WHERE log_id = $1 AND tag = $2",
+            &[&id, &tag.as_str()],
+        )?;
+
+        Ok(num_removed > 0)
+    }
+
+    fn logs_with_tag(&mut self, tag: &LogTag) -> MixesDbResult<Vec<LogId>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT logs.log_id FROM log_tags JOIN logs ON logs.log_id = log_tags.log_id \
+                 WHERE tag = $1 ORDER BY logs.date DESC, logs.log_id DESC",
+                &[&tag.as_str()],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn link_external_match(
+        &mut self,
+        id: LogId,
+        league: &League,
+        match_id: u64,
+    ) -> MixesDbResult<bool>
+    {
+        let match_id = i64::try_from(match_id).map_err(|_| DbError::Overflow {
+            stat:  "match id",
+            value: match_id,
+        })?;
+        let num_linked = self.client.execute(
+            "UPDATE logs SET league = $2, match_id = $3 WHERE log_id = $1",
+            &[&id, &league.as_str(), &match_id],
+        )?;
+
+        Ok(num_linked > 0)
+    }
+
+    fn external_match(&mut self, id: LogId) -> MixesDbResult<Option<ExternalMatch>>
+    {
+        let row = self.client.query_opt(
+            "SELECT league, match_id FROM logs WHERE log_id = $1 AND match_id IS NOT NULL",
+            &[&id],
+        )?;
+
+        Ok(row.and_then(|row| {
+            let league: &str = row.get(0);
+            let match_id: i64 = row.get(1);
+            Some(ExternalMatch {
+                league:   stored_value("league", league, League::new(league).ok())?,
+                match_id: match_id as u64,
+            })
+        }))
+    }
+
+    fn logs_for_match(&mut self, league: &League, match_id: u64) -> MixesDbResult<Vec<LogId>>
+    {
+        let match_id = match i64::try_from(match_id) {
+            Ok(match_id) => match_id,
+            // Such a match cannot have been linked.
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        Ok(self
+            .client
+            .query(
+                "SELECT log_id FROM logs WHERE league = $1 AND match_id = $2 ORDER BY date DESC, \
+                 log_id DESC",
+                &[&league.as_str(), &match_id],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn ingest_log_by_ref(
+        &mut self,
+        source: &dyn LogSource,
+        log_ref: &str,
+    ) -> MixesDbResult<IngestOutcome>
+    {
+        let id: LogId = log_ref.parse()?;
+        if self.has_log(id)? {
+            return Ok(IngestOutcome::AlreadyPresent);
+        }
+
+        info!("Adding log {} by hand", id);
+        let log = source.download_log(id)?;
+        let players = log.performances().len();
+        // An update may have stored it in the meantime.
+        match self.insert_log(log, IngestReason::Mix, None)? {
+            Some(_) => Ok(IngestOutcome::Added { players }),
+            None => Ok(IngestOutcome::AlreadyPresent),
+        }
+    }
+
+    fn update_with(
+        &mut self,
+        source: &dyn LogSource,
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        Ok(self.update_from(source, options)?)
+    }
+
+    fn update_players(
+        &mut self,
+        source: &dyn LogSource,
+        users: &[SteamID],
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        if users.is_empty() {
+            return Err(MixesDbError::InvalidInput(
+                "No players to update have been given".to_owned(),
+            ));
+        }
+        let registered: HashSet<SteamID> = self.registered_ids()?.into_iter().collect();
+        let mut players = users.to_vec();
+        players.sort_unstable_by_key(|player| player.id64());
+        players.dedup();
+        let unregistered: Vec<SteamID> = players
+            .iter()
+            .filter(|player| !registered.contains(player))
+            .copied()
+            .collect();
+        if !unregistered.is_empty() {
+            return Err(MixesDbError::UnregisteredPlayers(unregistered));
+        }
+        Ok(self.update_players_from(source, &players, options)?)
+    }
+
+    fn update(
+        &mut self,
+        min_ratio: f32,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        let options = UpdateOptions::builder()
+            .min_ratio(min_ratio)
+            .num_players(num_players)
+            .build()?;
+        self.update_with(&LogsTf::default(), options)
+    }
+
+    fn update_dry_run(
+        &mut self,
+        source: &dyn LogSource,
+        options: &UpdateOptions,
+    ) -> MixesDbResult<UpdatePlan>
+    {
+        Ok(self.plan_update(source, options)?)
+    }
+
+    fn update_history(&mut self, limit: usize) -> MixesDbResult<Vec<UpdateRunRecord>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT run_id, started_at, finished_at, min_ratio, min_players, max_players, \
+                 tag, num_candidates, num_added, num_failed, cancelled FROM update_runs ORDER BY \
+                 run_id DESC LIMIT $1",
+                &[&i64::try_from(limit).unwrap_or(i64::MAX)],
+            )?
+            .iter()
+            .map(|row| {
+                let started_at: DateTime<Utc> = row.get(1);
+                let finished_at: Option<DateTime<Utc>> = row.get(2);
+                let min_players: i16 = row.get(4);
+                let max_players: i16 = row.get(5);
+                let tag: Option<String> = row.get(6);
+                let num_candidates: i32 = row.get(7);
+                let num_added: i32 = row.get(8);
+                let num_failed: i32 = row.get(9);
+                let cancelled: bool = row.get(10);
+
+                UpdateRunRecord {
+                    id: UpdateRunId(row.get(0)),
+                    started_at,
+                    duration: finished_at.map(|finished_at| finished_at - started_at),
+                    min_ratio: row.get(3),
+                    num_players: min_players as u16..=max_players as u16,
+                    tag: tag.and_then(|tag| stored_value("tag", &tag, LogTag::new(&tag).ok())),
+                    num_candidates: num_candidates as usize,
+                    num_added: num_added as usize,
+                    num_failed: num_failed as usize,
+                    completed: finished_at.is_some()
+                        && !cancelled
+                        && (num_failed < num_candidates || num_candidates == 0),
+                    cancelled,
+                }
+            })
+            .collect())
+    }
+
+    fn backfill_player(
+        &mut self,
+        source: &dyn LogSource,
+        user: SteamID,
+        max_logs: usize,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        // logs.tf does not search for no logs at all.
+        if max_logs == 0 {
+            return Ok(UpdateReport::default());
+        }
+
+        info!("Backfilling the logs of {}", user.id64());
+        let known_logs = self.known_logs()?;
+
+        let limit = u16::try_from(max_logs).unwrap_or(u16::MAX);
+        let mut logs = source.search_logs(SearchParams::player_id(user).add_limit(limit))?;
+        logs.truncate(max_logs);
+        remove_external_occurrences(&mut logs, &known_logs);
+
+        // Only the player count matters, not how many of the players are registered.
+        let plan = select_new_logs(vec![(user, logs)], 0., &num_players);
+        let mut report = UpdateReport {
+            added:          Vec::new(),
+            reconciled:     Vec::new(),
+            skipped:        plan.skipped,
+            overflow:       plan.overflow,
+            run_id:         None,
+            health:         None,
+            cancelled:      false,
+            duration:       None,
+            records_broken: Vec::new(),
+        };
+
+        for meta in plan.candidates {
+            let download = source.download_log(meta.id);
+            self.add_downloaded_log(
+                meta.id,
+                download,
+                IngestReason::PersonalBackfill,
+                None,
+                &mut report,
+            )?;
+        }
+        info!(
+            "Backfill done, {} logs have been added, {} skipped",
+            report.added.len(),
+            report.skipped.len()
+        );
+
+        Ok(report)
+    }
+
+    fn status(&mut self) -> MixesDbResult<DbStatus>
+    {
+        let row = self.client.query_one(
+            "SELECT (SELECT COUNT(*) FROM users), (SELECT COUNT(*) FROM logs), (SELECT MAX(date) \
+             FROM logs), (SELECT MIN(date) FROM logs), (SELECT COUNT(*) FROM overall_stats), \
+             (SELECT COUNT(*) FROM dm_stats), (SELECT COUNT(*) FROM med_stats), \
+             pg_database_size(current_database()), (SELECT value::timestamptz FROM meta WHERE key \
+             = 'last_update'), (SELECT value::oid FROM meta WHERE key = 'schema_version')",
+            &[],
+        )?;
+
+        let count = |idx| {
+            let count: i64 = row.get(idx);
+            count as u64
+        };
+        Ok(DbStatus {
+            num_users:         count(0),
+            num_logs:          count(1),
+            newest_log:        row.get(2),
+            oldest_log:        row.get(3),
+            num_overall_stats: count(4),
+            num_dm_stats:      count(5),
+            num_med_stats:     count(6),
+            size_bytes:        count(7),
+            last_update:       row.get(8),
+            schema_version:    row.get(9),
+            crate_version:     crate::VERSION,
+        })
+    }
+
+    fn export_snapshot(&mut self, writer: &mut dyn Write) -> MixesDbResult<SnapshotInfo>
+    {
+        Ok(self.write_snapshot(writer)?)
+    }
+
+    fn import_snapshot(
+        &mut self,
+        reader: &mut dyn Read,
+        mode: ImportMode,
+    ) -> MixesDbResult<SnapshotInfo>
+    {
+        Ok(self.read_snapshot(reader, mode)?)
+    }
+
+    fn data_freshness(&mut self, stale_after: chrono::Duration) -> MixesDbResult<Freshness>
+    {
+        let stale_before = Utc::now() - stale_after;
+        let row = self.client.query_one(
+            "SELECT (SELECT value::timestamptz FROM meta WHERE key = 'last_update'), (SELECT \
+             MAX(date) FROM logs), (SELECT COUNT(*) FROM (SELECT users.steam_id FROM users JOIN \
+             overall_stats ON overall_stats.steam_id = users.steam_id JOIN logs ON logs.log_id = \
+             overall_stats.log_id GROUP BY users.steam_id HAVING MAX(logs.date) < $1) stale)",
+            &[&stale_before],
+        )?;
+        let num_stale_users: i64 = row.get(2);
+
+        Ok(Freshness {
+            last_update:     row.get(0),
+            newest_log:      row.get(1),
+            num_stale_users: num_stale_users as u64,
+        })
+    }
+
+    fn get_player_log_performance(
+        &mut self,
+        log_id: LogId,
+        user: SteamID,
+    ) -> MixesDbResult<Option<LogPerformance>>
+    {
+        if !self.has_log(log_id)? {
+            return Err(MixesDbError::NotFound);
+        }
+
+        let performance = self.stored_performance(log_id, user.id64() as i64)?;
+        if performance.overall.is_none() && performance.dm.is_empty() && performance.medic.is_none()
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(performance))
+    }
+
+    fn log_rosters(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, Vec<SteamID>>>
+    {
+        let mut rosters: HashMap<Team, Vec<SteamID>> = HashMap::new();
+        for row in self.client.query(
+            "SELECT DISTINCT team, steam_id FROM overall_stats WHERE log_id = $1 AND team IS NOT \
+             NULL ORDER BY steam_id",
+            &[&log_id],
+        )? {
+            let team: i16 = row.get(0);
+            let team = stored_value("team", team, Team::from_i16(team));
+            if let (Some(team), Ok(steam_id)) = (team, stored_user_id(row.get(1))) {
+                rosters.entry(team).or_default().push(steam_id);
+            }
+        }
+
+        Ok(rosters)
+    }
+
+    fn team_summary(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, TeamSummary>>
+    {
+        let row = self.client.query_opt(
+            "SELECT round_winners FROM logs WHERE log_id = $1",
+            &[&log_id],
+        )?;
+        let rounds = match row {
+            Some(row) => row_to_round_winners(&row, 0).unwrap_or_default(),
+            None => return Ok(HashMap::new()),
+        };
+
+        // The medic stats are summed up per player first, so a player is joined
+        // with a single row.
+        let mut summaries: HashMap<Team, TeamSummary> = HashMap::new();
+        for row in self.client.query(
+            "SELECT overall.team, SUM(overall.damage), COALESCE(SUM(med.healing), 0)::bigint, \
+             COALESCE(SUM(med.num_ubers), 0)::bigint FROM overall_stats overall LEFT JOIN (SELECT \
+             steam_id, SUM(healing) AS healing, SUM(num_ubers) AS num_ubers FROM med_stats WHERE \
+             log_id = $1 GROUP BY steam_id) med ON med.steam_id = overall.steam_id WHERE \
+             overall.log_id = $1 AND overall.team IS NOT NULL GROUP BY overall.team",
+            &[&log_id],
+        )? {
+            let team: i16 = row.get(0);
+            let team = match stored_value("team", team, Team::from_i16(team)) {
+                Some(team) => team,
+                None => continue,
+            };
+            let damage: i64 = row.get(1);
+            let healing: i64 = row.get(2);
+            let num_ubers: i64 = row.get(3);
+
+            summaries.insert(
+                team,
+                TeamSummary {
+                    damage:    damage as u64,
+                    healing:   healing as u64,
+                    num_ubers: num_ubers as u32,
+                    rounds:    rounds.clone(),
+                },
+            );
+        }
+
+        Ok(summaries)
+    }
+
+    fn logs_between(&mut self, from: DateTime<Utc>, to: DateTime<Utc>)
+        -> MixesDbResult<Vec<LogId>>
+    {
+        Ok(self
+            .client
+            .query(
+                "SELECT log_id FROM logs WHERE $1 <= date AND date < $2 ORDER BY date, log_id",
+                &[&from, &to],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn log_performances_between(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<Vec<LogWithPerformances>>
+    {
+        let logs: Vec<LogMetadata> = self
+            .client
+            .query(
+                format!(
+                    "SELECT {} FROM logs WHERE $1 <= date AND date < $2 ORDER BY date, log_id",
+                    META_COLUMNS
+                )
+                .as_str(),
+                &[&from, &to],
+            )?
+            .into_iter()
+            .map(|row| row_to_meta(&row))
+            .collect();
+
+        let mut result = Vec::with_capacity(logs.len());
+        for meta in logs {
+            let players: Vec<i64> = self
+                .client
+                .query(
+                    "SELECT steam_id FROM overall_stats WHERE log_id = $1 UNION SELECT steam_id \
+                     FROM dm_stats WHERE log_id = $1",
+                    &[&meta.id],
+                )?
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+
+            let mut performances = HashMap::with_capacity(players.len());
+            for steam_id in players {
+                if let Ok(player) = stored_user_id(steam_id) {
+                    performances.insert(player, self.stored_performance(meta.id, steam_id)?);
+                }
+            }
+            result.push((meta, performances));
+        }
+
+        Ok(result)
+    }
+
+    fn participation_stats(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<ParticipationStats>
+    {
+        let games: Vec<u32> = self
+            .client
+            .query(
+                "SELECT COUNT(DISTINCT logs.log_id) FROM users JOIN overall_stats ON \
+                 overall_stats.steam_id = users.steam_id JOIN logs ON logs.log_id = \
+                 overall_stats.log_id WHERE $1 <= logs.date AND logs.date < $2 GROUP BY \
+                 users.steam_id",
+                &[&from, &to],
+            )?
+            .iter()
+            .map(|row| row.get::<_, i64>(0) as u32)
+            .collect();
+
+        Ok(ParticipationStats {
+            num_players:     games.len() as u64,
+            median_games:    stats::median(&games),
+            games_histogram: stats::histogram(&games, &GAMES_BUCKETS),
+            top_fifth_share: stats::top_share(&games, 0.2),
+        })
+    }
+
+    fn get_classes_performance(
+        &mut self,
+        user: SteamID,
+        classes: &[Class],
+        limit: usize,
+        options: &QueryOptions,
+    ) -> MixesDbResult<Vec<(LogId, LogMetadata, LogPerformance)>>
+    {
+        let steam_id: i64 = user.id64() as i64;
+        let classes: Vec<i16> = classes.iter().map(|&class| class as i16).collect();
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let season_id: Option<i32> = options.season.map(|season| season.0);
+        let min_class_time_secs = options.min_class_time_secs as i32;
+        let (include_tags, exclude_tags) = tag_params(&options.tags);
+
+        // Find the logs where the player has played any of the classes for at least the
+        // minimum amount of time. Ordered by date descending to get the newest
+        // logs at the top. If a season is given, only logs played in the date
+        // range of the season are considered.
+        let logs: Vec<LogMetadata> = self
+            .client
+            .query(
+                format!(
+                    "SELECT DISTINCT logs.log_id, logs.date, logs.map, logs.num_players, \
+                     logs.title, logs.uploader, logs.duration_secs FROM dm_stats JOIN logs ON \
+                     logs.log_id = dm_stats.log_id LEFT JOIN seasons ON seasons.season_id = $4 \
+                     LEFT JOIN overall_stats ON overall_stats.log_id = dm_stats.log_id AND \
+                     overall_stats.steam_id = dm_stats.steam_id WHERE dm_stats.steam_id=$1 AND \
+                     class=ANY($2) AND ($4::int IS NULL OR (logs.date >= seasons.start_date AND \
+                     (seasons.end_date IS NULL OR logs.date < seasons.end_date))) AND ($5::real \
+                     IS NULL OR overall_stats.offclass_fraction <= $5) AND \
+                     dm_stats.time_played_secs >= $6 AND (NOT $9 OR overall_stats.primary_class = \
+                     ANY($2)) AND ($10::smallint IS NULL OR logs.ingest_reason = $10) AND {} AND \
+                     {} ORDER BY logs.date DESC, logs.log_id DESC LIMIT $3",
+                    tag_condition(7, 8),
+                    official_condition(options.officials),
+                )
+                .as_str(),
+                &[
+                    &steam_id,
+                    &classes,
+                    &limit,
+                    &season_id,
+                    &options.max_offclass_fraction,
+                    &min_class_time_secs,
+                    &include_tags,
+                    &exclude_tags,
+                    &options.only_primary_class,
+                    &options.ingest_reason.map(|reason| reason as i16),
+                ],
+            )?
+            .into_iter()
+            .map(|row| row_to_meta(&row))
+            .collect();
+
+        // Get *all* performances of all classes of the player from that game.
+        let mut performances = Vec::with_capacity(logs.len());
+        for meta in logs {
+            let id = meta.id;
+            performances.push((id, meta, self.stored_performance(id, steam_id)?));
+        }
+
+        Ok(performances)
+    }
+
+    fn percentile_for_metric(
+        &mut self,
+        user: SteamID,
+        class: Class,
+        metric: Metric,
+        min_games: u32,
+        include_suspect: bool,
+        min_class_time_secs: u32,
+    ) -> MixesDbResult<Option<f32>>
+    {
+        if !metric.applicable_to(class) {
+            return Ok(None);
+        }
+        let columns = match metric_columns(metric) {
+            Some(columns) => columns,
+            None => return Ok(None),
+        };
+        let user_id = user.id64() as i64;
+        let min_games = min_games as i64;
+
+        // percent_rank gives tied values the same rank, which is the number of
+        // values strictly below them.
+        let row = if !include_suspect && min_class_time_secs == 0 {
+            // The aggregates only contain logs with a plausible duration, and
+            // medic stats are aggregated as those of the medic. Only mixes are
+            // compared.
+            let class = match columns.class {
+                Some(_) => class as i16,
+                None => Class::Medic as i16,
+            };
+            let (value, time, games) = columns.cached;
+            self.client.query_opt(
+                format!(
+                    "SELECT percentile FROM (SELECT cache.steam_id, percent_rank() OVER (ORDER BY \
+                     SUM(cache.{value}) * {per_secs}.0 / SUM(cache.{time})) AS percentile FROM \
+                     aggregates_cache cache JOIN users ON users.steam_id = cache.steam_id WHERE \
+                     cache.class = $3 AND cache.ingest_reason = $4 GROUP BY cache.steam_id HAVING \
+                     SUM(cache.{games})::bigint >= $2 AND SUM(cache.{time}) > 0) ranked WHERE \
+                     steam_id = $1",
+                    value = value,
+                    per_secs = columns.per_secs,
+                    time = time,
+                    games = games,
+                )
+                .as_str(),
+                &[&user_id, &min_games, &class, &(IngestReason::Mix as i16)],
+            )?
+        }
+        else {
+            self.live_percentile(
+                &columns,
+                user_id,
+                class,
+                min_games,
+                include_suspect,
+                min_class_time_secs,
+            )?
+        };
+
+        Ok(row.map(|row| {
+            let percentile: f64 = row.get(0);
+            percentile as f32
+        }))
+    }
+
+    fn normalized_leaderboard(
+        &mut self,
+        metric: Metric,
+        min_games: u32,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, Class, f64)>>
+    {
+        let columns = match metric_columns(metric) {
+            Some(columns) => columns,
+            None => return Ok(Vec::new()),
+        };
+        let min_games = min_games as i64;
+        let (value, time, games) = columns.cached;
+
+        // The primary class of a player is the one that has been the primary
+        // class of most of their games. Medic stats are aggregated as those of
+        // the medic, so they only count for players who mostly play medic. Only
+        // mixes are ranked.
+        let rows = self.client.query(
+            format!(
+                "WITH primary_classes AS (SELECT DISTINCT ON (cache.steam_id) cache.steam_id, \
+                 cache.class FROM aggregates_cache cache JOIN users ON users.steam_id = \
+                 cache.steam_id WHERE cache.ingest_reason = $2 GROUP BY cache.steam_id, \
+                 cache.class HAVING SUM(cache.primary_games) > 0 ORDER BY cache.steam_id, \
+                 SUM(cache.primary_games) DESC, cache.class) SELECT cache.steam_id, cache.class, \
+                 (SUM(cache.{value}) * {per_secs}.0 / SUM(cache.{time}))::float8 FROM \
+                 aggregates_cache cache JOIN primary_classes ON primary_classes.steam_id = \
+                 cache.steam_id AND primary_classes.class = cache.class WHERE cache.ingest_reason \
+                 = $2 GROUP BY cache.steam_id, cache.class HAVING SUM(cache.{games})::bigint >= \
+                 $1 AND SUM(cache.{time}) > 0",
+                value = value,
+                per_secs = columns.per_secs,
+                time = time,
+                games = games,
+            )
+            .as_str(),
+            &[&min_games, &(IngestReason::Mix as i16)],
+        )?;
+
+        let mut by_class: HashMap<Class, Vec<(SteamID, f64)>> = HashMap::new();
+        for row in rows {
+            let steam_id = match stored_user_id(row.get(0)) {
+                Ok(steam_id) => steam_id,
+                Err(_) => continue,
+            };
+            let class: i16 = row.get(1);
+            let class = match stored_value("class", class, Class::from_i16(class)) {
+                Some(class) => class,
+                None => continue,
+            };
+            // Players are only ranked on their primary class, where the metric
+            // may not mean anything.
+            if metric.applicable_to(class) {
+                by_class
+                    .entry(class)
+                    .or_default()
+                    .push((steam_id, row.get(2)));
+            }
+        }
+
+        let sign = if metric.lower_is_better() { -1. } else { 1. };
+        let mut leaderboard = Vec::new();
+        for (class, players) in by_class {
+            let values: Vec<f64> = players.iter().map(|&(_, value)| value).collect();
+            if let Some(z_scores) = stats::z_scores(&values) {
+                leaderboard.extend(
+                    players
+                        .into_iter()
+                        .zip(z_scores)
+                        .map(|((steam_id, _), z_score)| (steam_id, class, sign * z_score)),
+                );
+            }
+        }
+        leaderboard.sort_by(|(a_id, _, a_score), (b_id, _, b_score)| {
+            b_score
+                .total_cmp(a_score)
+                .then(a_id.id64().cmp(&b_id.id64()))
+        });
+        leaderboard.truncate(limit);
+
+        Ok(leaderboard)
+    }
+
+    fn get_heal_spread(
+        &mut self,
+        medic: SteamID,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, u64)>>
+    {
+        let medic_id = medic.id64() as i64;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        Ok(self
+            .client
+            .query(
+                "SELECT target_id, SUM(healing) FROM heal_spread WHERE medic_id=$1 AND log_id IN \
+                 (SELECT log_id FROM med_stats WHERE steam_id=$1 ORDER BY log_id DESC LIMIT $2) \
+                 GROUP BY target_id ORDER BY SUM(healing) DESC, target_id",
+                &[&medic_id, &limit],
+            )?
+            .iter()
+            .filter_map(|row| {
+                let target_id = stored_user_id(row.get(0)).ok()?;
+                let healing: i64 = row.get(1);
+
+                Some((target_id, healing as u64))
+            })
+            .collect())
+    }
+
+    fn carry_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<CarryStats>
+    {
+        let user_id = user.id64() as i64;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        let row = self.client.query_one(
+            "SELECT COUNT(*), AVG(damage_share), AVG(kill_participation) FROM (SELECT \
+             damage_share, kill_participation FROM overall_stats JOIN logs ON logs.log_id = \
+             overall_stats.log_id WHERE steam_id = $1 ORDER BY logs.date DESC, logs.log_id DESC \
+             LIMIT $2) recent",
+            &[&user_id, &limit],
+        )?;
+        let num_games: i64 = row.get(0);
+        let damage_share: Option<f64> = row.get(1);
+        let kill_participation: Option<f64> = row.get(2);
+
+        Ok(CarryStats {
+            num_games:          num_games as u32,
+            damage_share:       damage_share.map(|share| share as f32),
+            kill_participation: kill_participation.map(|share| share as f32),
+        })
+    }
+
+    fn round_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<RoundStats>
+    {
+        let user_id = user.id64() as i64;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        let mut stats = RoundStats::default();
+        let mut differential = 0;
+        let mut comeback_wins = 0;
+        for row in self.client.query(
+            "SELECT overall_stats.won_rounds, overall_stats.num_rounds, overall_stats.team, \
+             logs.round_winners FROM overall_stats JOIN logs ON logs.log_id = \
+             overall_stats.log_id WHERE steam_id = $1 ORDER BY logs.date DESC, logs.log_id DESC \
+             LIMIT $2",
+            &[&user_id, &limit],
+        )? {
+            let won_rounds: i16 = row.get(0);
+            let num_rounds: i16 = row.get(1);
+            let team: Option<i16> = row.get(2);
+            let team = team.and_then(|team| stored_value("team", team, Team::from_i16(team)));
+            stats.num_games += 1;
+            differential += 2 * i64::from(won_rounds) - i64::from(num_rounds);
+
+            // Logs without rounds have an empty timeline, which is as good as none.
+            let winners = row_to_round_winners(&row, 3).filter(|winners| !winners.is_empty());
+            if let (Some(team), Some(winners)) = (team, winners) {
+                stats.num_timelines += 1;
+                comeback_wins += stats::is_comeback(&winners, team) as u32;
+            }
+        }
+        if stats.num_games > 0 {
+            stats.round_differential = Some(differential as f32 / stats.num_games as f32);
+        }
+        if stats.num_timelines > 0 {
+            stats.comeback_wins = Some(comeback_wins);
+        }
+
+        Ok(stats)
+    }
+
+    fn personal_records(&mut self, user: SteamID) -> MixesDbResult<Vec<RecordEntry>>
+    {
+        let mut records: Vec<RecordEntry> = self
+            .client
+            .query(
+                format!(
+                    "SELECT {} FROM personal_records WHERE steam_id = $1",
+                    RECORD_COLUMNS
+                )
+                .as_str(),
+                &[&(user.id64() as i64)],
+            )?
+            .iter()
+            .map(row_to_record)
+            .collect();
+        records.sort_by_key(|record| {
+            let metric = Metric::ALL
+                .iter()
+                .position(|metric| *metric == record.metric);
+            (record.class as i16, metric)
+        });
+
+        Ok(records)
+    }
+
+    fn recent_games(
+        &mut self,
+        user: SteamID,
+        limit: usize,
+        tags: &TagFilter,
+    ) -> MixesDbResult<Vec<GameSummary>>
+    {
+        let user_id = user.id64() as i64;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let (include_tags, exclude_tags) = tag_params(tags);
+
+        // The main class is the one with the most time played, ties are broken
+        // by the stored class number so that the result is stable.
+        Ok(self
+            .client
+            .query(
+                format!(
+                    "SELECT logs.log_id, logs.date, logs.map, overall_stats.won_rounds, \
+                     overall_stats.num_rounds, main.class, main.damage, main.kills, main.deaths, \
+                     main.time_played_secs, med_stats.healing FROM logs JOIN overall_stats ON \
+                     overall_stats.log_id = logs.log_id AND overall_stats.steam_id = $1 LEFT JOIN \
+                     (SELECT DISTINCT ON (log_id) log_id, class, damage, kills, deaths, \
+                     time_played_secs FROM dm_stats WHERE steam_id = $1 ORDER BY log_id, \
+                     time_played_secs DESC, class) main ON main.log_id = logs.log_id LEFT JOIN \
+                     med_stats ON med_stats.log_id = logs.log_id AND med_stats.steam_id = $1 \
+                     WHERE {} ORDER BY logs.date DESC, logs.log_id DESC LIMIT $2",
+                    tag_condition(3, 4)
+                )
+                .as_str(),
+                &[&user_id, &limit, &include_tags, &exclude_tags],
+            )?
+            .iter()
+            .filter_map(|row| {
+                let won_rounds: i16 = row.get(3);
+                let num_rounds: i16 = row.get(4);
+                let main_class: Option<i16> = row.get(5);
+                let main_class = match main_class {
+                    Some(class) => Some(stored_value("class", class, Class::from_i16(class))?),
+                    None => None,
+                };
+                let damage: Option<i32> = row.get(6);
+                let kills: Option<i16> = row.get(7);
+                let deaths: Option<i16> = row.get(8);
+                let time_played_secs: Option<i32> = row.get(9);
+                let healing: Option<i32> = row.get(10);
+
+                let dpm = match (damage, time_played_secs) {
+                    (Some(damage), Some(secs)) if secs > 0 => {
+                        Some(damage as f32 * 60. / secs as f32)
+                    },
+                    _ => None,
+                };
+                let kill_death_ratio = kills
+                    .zip(deaths)
+                    .map(|(kills, deaths)| kills as f32 / deaths.max(1) as f32);
+
+                Some(GameSummary {
+                    log_id: row.get(0),
+                    date_time: row.get(1),
+                    map: row.get(2),
+                    main_class,
+                    won_rounds: won_rounds as u8,
+                    lost_rounds: num_rounds.saturating_sub(won_rounds) as u8,
+                    dpm,
+                    kill_death_ratio,
+                    healing: healing
+                        .filter(|_| main_class == Some(Class::Medic))
+                        .map(|healing| healing as u32),
+                })
+            })
+            .collect())
+    }
+
+    fn teammate_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>
+    {
+        Ok(self.query_teammate_records(user, min_shared_games, true)?)
+    }
+
+    fn opponent_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>
+    {
+        Ok(self.query_teammate_records(user, min_shared_games, false)?)
+    }
+
+    fn create_season(
+        &mut self,
+        name: &str,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> MixesDbResult<Option<SeasonId>>
+    {
+        if end.is_some_and(|end| end <= start) {
+            return Err(MixesDbError::InvalidInput(
+                "A season must end after it starts".to_owned(),
+            ));
+        }
+        check_length("season name", name, MAX_NAME_LEN)?;
+
+        // Seasons that are created at the same time could each miss the other one
+        // when checking for overlaps, so they are created one after the other.
+        let mut transaction = self.client.transaction()?;
+        transaction.batch_execute("LOCK TABLE seasons IN SHARE ROW EXCLUSIVE MODE")?;
+
+        // Seasons are ranges with an inclusive start and exclusive end, so they overlap
+        // if each of them starts before the other one ends.
+        let overlapping = !transaction
+            .query(
+                "SELECT FROM seasons WHERE (end_date IS NULL OR $1 < end_date) AND \
+                 ($2::timestamptz IS NULL OR start_date < $2)",
+                &[&start, &end],
+            )?
+            .is_empty();
+
+        if overlapping {
+            return Ok(None);
+        }
+
+        let row = transaction.query_one(
+            "INSERT INTO seasons (name, start_date, end_date) VALUES ($1, $2, $3) RETURNING \
+             season_id",
+            &[&name, &start, &end],
+        )?;
+        transaction.commit()?;
+
+        Ok(Some(SeasonId(row.get(0))))
+    }
+
+    fn close_season(&mut self, end: DateTime<Utc>) -> MixesDbResult<bool>
+    {
+        let mut transaction = self.client.transaction()?;
+
+        let season_id: Option<i32> = transaction
+            .query_opt(
+                "UPDATE seasons SET end_date = $1 WHERE end_date IS NULL AND start_date < $1 \
+                 RETURNING season_id",
+                &[&end],
+            )?
+            .map(|row| row.get(0));
+
+        let season_id = match season_id {
+            Some(season_id) => season_id,
+            None => return Ok(false),
+        };
+
+        // Logs after the end of the season have been assigned while it was still
+        // running, but are not part of it anymore.
+        let log_ids: Vec<LogId> = transaction
+            .query(
+                "SELECT log_id FROM logs WHERE season_id = $1 AND date >= $2",
+                &[&season_id, &end],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        update_aggregates(&mut transaction, &log_ids, -1)?;
+        transaction.execute(
+            "UPDATE logs SET season_id = NULL WHERE log_id = ANY($1)",
+            &[&log_ids],
+        )?;
+        update_aggregates(&mut transaction, &log_ids, 1)?;
+        transaction.commit()?;
+
+        Ok(true)
+    }
+
+    fn current_season(&mut self) -> MixesDbResult<Option<Season>>
+    {
+        Ok(self
+            .client
+            .query_opt(
+                "SELECT season_id, name, start_date, end_date FROM seasons WHERE end_date IS NULL",
+                &[],
+            )?
+            .map(|row| Season {
+                id:    SeasonId(row.get(0)),
+                name:  row.get(1),
+                start: row.get(2),
+                end:   row.get(3),
+            }))
+    }
+
+    fn assign_unmatched_logs_to_seasons(&mut self) -> MixesDbResult<u64>
+    {
+        let mut transaction = self.client.transaction()?;
+
+        // The aggregates of the logs move from no season to their season.
+        let log_ids: Vec<LogId> = transaction
+            .query(
+                "SELECT logs.log_id FROM logs JOIN seasons ON logs.date >= seasons.start_date AND \
+                 (seasons.end_date IS NULL OR logs.date < seasons.end_date) WHERE logs.season_id \
+                 IS NULL",
+                &[],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        update_aggregates(&mut transaction, &log_ids, -1)?;
+        let num_assigned = transaction.execute(
+            "UPDATE logs SET season_id = seasons.season_id FROM seasons WHERE logs.log_id = \
+             ANY($1) AND logs.date >= seasons.start_date AND (seasons.end_date IS NULL OR \
+             logs.date < seasons.end_date)",
+            &[&log_ids],
+        )?;
+        update_aggregates(&mut transaction, &log_ids, 1)?;
+        transaction.commit()?;
+
+        Ok(num_assigned)
+    }
+
+    fn search_chat(&mut self, pattern: &str, limit: usize) -> MixesDbResult<Vec<ChatSearchResult>>
+    {
+        if pattern.is_empty() {
+            return Err(MixesDbError::InvalidInput(
+                "Chat search must not be empty".to_owned(),
+            ));
+        }
+
+        let pattern = substring_pattern(pattern);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        Ok(self
+            .client
+            .query(
+                "SELECT logs.log_id, logs.date, logs.map, chat.steam_id, chat.name, chat.message \
+                 FROM chat JOIN logs ON logs.log_id = chat.log_id WHERE chat.message ILIKE $1 \
+                 ORDER BY logs.date DESC, logs.log_id DESC, chat.idx LIMIT $2",
+                &[&pattern, &limit],
+            )?
+            .iter()
+            .map(|row| {
+                let steam_id: Option<i64> = row.get(3);
+
+                ChatSearchResult {
+                    log_id:    row.get(0),
+                    date_time: row.get(1),
+                    map:       row.get(2),
+                    message:   ChatMessage {
+                        steam_id: steam_id.and_then(|steam_id| stored_user_id(steam_id).ok()),
+                        name:     row.get(4),
+                        message:  row.get(5),
+                    },
+                }
+            })
+            .collect())
+    }
+}
+
+/// Connect to the database described by the connection `params` and set up the
+/// session with [`SESSION_SETUP`].
+fn connect_client(params: &str) -> DbResult<sql::Client>
+{
+    let mut client = sql::Client::connect(params, sql::NoTls)?;
+    client.batch_execute(SESSION_SETUP)?;
+
+    Ok(client)
+}
+
+/// Choose the logs that should be added from the logs found for each player.
+/// A log is only chosen if its number of players is in the `num_players` range
+/// and enough of the players have it in their `search_results`, in accordance
+/// with the `min_ratio`. Logs that report no players are never chosen, since
+/// the ratio cannot be determined for them.
+fn select_new_logs(
+    search_results: Vec<(SteamID, Vec<LogMetadata>)>,
+    min_ratio: f32,
+    num_players: &RangeInclusive<u16>,
+) -> UpdatePlan
+{
+    let mut plan = UpdatePlan::default();
+    let mut player_logs: Vec<(SteamID, Vec<LogId>)> = Vec::new();
+
+    // HashMap of logs to be added. First, all the logs found for any player are
+    // added in here, together with a counter showing how many (registered)
+    // players have an entry for that log, and have therefore participated.
+    let mut new_logs: HashMap<LogId, (LogMetadata, u16)> = HashMap::new();
+    for (player, logs) in search_results {
+        player_logs.push((player, logs.iter().map(|log| log.id).collect()));
+
+        for log in logs {
+            match new_logs.get_mut(&log.id) {
+                Some((_, ref mut occ)) => *occ += 1,
+                None => {
+                    new_logs.insert(log.id, (log, 1));
+                },
+            }
+        }
+    }
+
+    info!(
+        "Players have {} logs not in the database combined",
+        new_logs.len()
+    );
+
+    // Keep only the logs with the correct number of players (right game-type) where
+    // enough mixes players were there, in accordance with the ratio.
+    new_logs.drain_filter(|id, (meta, occ)| {
+        let skip_reason = player_count_skip_reason(meta, num_players).or_else(|| {
+            if (*occ as f32 / meta.num_players as f32) < min_ratio {
+                Some(SkipReason::NotEnoughMixesPlayers)
+            }
+            else {
+                None
+            }
+        });
+
+        match skip_reason {
+            Some(reason) => {
+                plan.skipped.push((*id, reason));
+                true
+            },
+            None => false,
+        }
+    });
+    plan.skipped.sort_by_key(|(id, _)| Reverse(*id));
+
+    for (player, logs) in player_logs {
+        let contribution = logs.iter().filter(|id| new_logs.contains_key(id)).count();
+        plan.contributions.insert(player, contribution);
+    }
+
+    plan.candidates = new_logs.into_values().map(|(meta, _)| meta).collect();
+    plan.candidates.sort_by_key(|meta| Reverse(meta.id));
+
+    plan
+}
+
+/// Merge the logs found by batched searches, which have already been filtered
+/// by their number of players. Every log is chosen, since it is not known yet
+/// how many mixes players it contains.
+fn merge_batch_results(search_results: Vec<(SteamID, Vec<LogMetadata>)>) -> UpdatePlan
+{
+    let mut candidates: Vec<LogMetadata> = search_results
+        .into_iter()
+        .flat_map(|(_, logs)| logs)
+        .collect();
+    candidates.sort_by_key(|meta| Reverse(meta.id));
+    candidates.dedup_by_key(|meta| meta.id);
+
+    UpdatePlan {
+        candidates,
+        ..UpdatePlan::default()
+    }
+}
+
+/// Check whether the log has the right number of players to be added.
+///
+/// # Returns
+/// Why the log is skipped, or `None` if it has the right number of players.
+fn player_count_skip_reason(
+    meta: &LogMetadata,
+    num_players: &RangeInclusive<u16>,
+) -> Option<SkipReason>
+{
+    if meta.num_players == 0 {
+        Some(SkipReason::NoPlayers)
+    }
+    else if !num_players.contains(&meta.num_players) {
+        Some(SkipReason::PlayerCount(meta.num_players))
+    }
+    else {
+        None
+    }
+}
+
+/// The columns of the aggregates cache that are summed up, see
+/// [`aggregate_contributions`].
+const AGGREGATE_COLUMNS: [&str; 16] = [
+    "games",
+    "time_played_secs",
+    "damage",
+    "kills",
+    "assists",
+    "deaths",
+    "med_games",
+    "med_time_secs",
+    "healing",
+    "num_ubers",
+    "primary_games",
+    "dt_games",
+    "duration_secs",
+    "damage_taken",
+    "primary_damage",
+    "heals_received",
+];
+
+/// Select what the logs that match the `condition` contribute to the
+/// aggregates cache, multiplied by the `sign`, in the order of
+/// [`AGGREGATE_COLUMNS`]. The condition is on the logs, which are queried as
+/// `logs`.
+///
+/// The cache is keyed by player, class, season and the reason the logs have
+/// been added for, with season `0` for logs outside of any season. Logs from
+/// the history of a single player are kept apart, so they are not compared. It
+/// holds the same sums the metrics of the players are compared with, see
+/// [`metric_columns`]. They only include logs with a plausible duration. The
+/// number of games each class has been the primary class in includes all logs,
+/// like the primary class of a player. The heals received are clamped like for
+/// [`Metric::DamagePerHeal`].
+fn aggregate_contributions(condition: &str, sign: i64) -> String
+{
+    format!(
+        "SELECT steam_id, class, season_id, ingest_reason, {sums} FROM (SELECT stats.steam_id, \
+         stats.class, COALESCE(logs.season_id, 0) AS season_id, logs.ingest_reason, 1 AS games, \
+         stats.time_played_secs, stats.damage, stats.kills, stats.assists, stats.deaths, 0 AS \
+         med_games, 0 AS med_time_secs, 0 AS healing, 0 AS num_ubers, 0 AS primary_games, 0 AS \
+         dt_games, 0 AS duration_secs, 0 AS damage_taken, 0 AS primary_damage, 0 AS \
+         heals_received FROM dm_stats stats JOIN logs ON logs.log_id = stats.log_id WHERE NOT \
+         logs.duration_suspect AND {condition} UNION ALL SELECT stats.steam_id, {medic}, \
+         COALESCE(logs.season_id, 0), logs.ingest_reason, 0, 0, 0, 0, 0, 0, 1, \
+         stats.time_played_secs, stats.healing, stats.num_ubers, 0, 0, 0, 0, 0, 0 FROM med_stats \
+         stats JOIN logs ON logs.log_id = stats.log_id WHERE NOT logs.duration_suspect AND \
+         {condition} UNION ALL SELECT stats.steam_id, stats.primary_class, \
+         COALESCE(logs.season_id, 0), logs.ingest_reason, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, \
+         {plausible}, {plausible} * logs.duration_secs, {plausible} * stats.damage_taken, \
+         {plausible} * stats.damage, {plausible} * GREATEST(stats.heals_received, 1) FROM \
+         overall_stats stats JOIN logs ON logs.log_id = stats.log_id WHERE stats.primary_class IS \
+         NOT NULL AND {condition}) contributions GROUP BY steam_id, class, season_id, \
+         ingest_reason",
+        sums = AGGREGATE_COLUMNS
+            .map(|column| format!("{} * SUM({})", sign, column))
+            .join(", "),
+        medic = Class::Medic as i16,
+        plausible = "(NOT logs.duration_suspect)::int",
+        condition = condition,
+    )
+}
+
+/// Convert a stat of a log to the value of an `int` column.
+///
+/// # Returns
+/// [`DbError::Overflow`] if the stat is too large for the column, which would
+/// otherwise be stored as a negative value.
+fn int(stat: &'static str, value: u32) -> DbResult<i32>
+{
+    i32::try_from(value).map_err(|_| DbError::Overflow {
+        stat,
+        value: value.into(),
+    })
+}
+
+/// Convert a stat of a log to the value of a `smallint` column, see [`int`].
+fn smallint(stat: &'static str, value: u16) -> DbResult<i16>
+{
+    i16::try_from(value).map_err(|_| DbError::Overflow {
+        stat,
+        value: value.into(),
+    })
+}
+
+/// Replace all aggregates in the cache with ones computed from the stored
+/// stats, see [`SQLDb::rebuild_aggregates`].
+fn replace_aggregates(transaction: &mut sql::Transaction) -> DbResult<u64>
+{
+    transaction.execute("DELETE FROM aggregates_cache", &[])?;
+
+    Ok(transaction.execute(
+        format!(
+            "INSERT INTO aggregates_cache (steam_id, class, season_id, ingest_reason, {}) {}",
+            AGGREGATE_COLUMNS.join(", "),
+            aggregate_contributions("TRUE", 1)
+        )
+        .as_str(),
+        &[],
+    )?)
+}
+
+/// Attach the `tag` to the log with the `id`, see [`Database::tag_log`].
+/// Nothing is done if the log is not stored.
+///
+/// # Returns
+/// Whether the tag has been attached, `false` if the log already had it.
+fn insert_tag(client: &mut impl sql::GenericClient, id: LogId, tag: &LogTag) -> DbResult<bool>
+{
+    let num_inserted = client.execute(
+        "INSERT INTO log_tags (log_id, tag) SELECT log_id, $2 FROM logs WHERE log_id = $1 ON \
+         CONFLICT (log_id, tag) DO NOTHING",
+        &[&id, &tag.as_str()],
+    )?;
+
+    Ok(num_inserted > 0)
+}
+
+/// Save the `version` of the schema the tables have been migrated to.
+fn store_schema_version(client: &mut impl sql::GenericClient, version: u32) -> DbResult<()>
+{
+    client.execute(
+        "INSERT INTO meta (key, value) VALUES ('schema_version', $1) ON CONFLICT (key) DO UPDATE \
+         SET value = excluded.value",
+        &[&version.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Add what the logs with the `log_ids` contribute to the aggregates cache,
+/// or subtract it with a negative `sign`, for instance right before the logs
+/// are removed. Aggregates that are left without any games are removed.
+fn update_aggregates(
+    transaction: &mut sql::Transaction,
+    log_ids: &[LogId],
+    sign: i64,
+) -> DbResult<()>
+{
+    transaction.execute(
+        format!(
+            "INSERT INTO aggregates_cache (steam_id, class, season_id, ingest_reason, {columns}) \
+             {contributions} ON CONFLICT (steam_id, class, season_id, ingest_reason) DO UPDATE \
+             SET {sums}",
+            columns = AGGREGATE_COLUMNS.join(", "),
+            contributions = aggregate_contributions("logs.log_id = ANY($1)", sign),
+            sums = AGGREGATE_COLUMNS
+                .map(|column| format!("{0} = aggregates_cache.{0} + EXCLUDED.{0}", column))
+                .join(", "),
+        )
+        .as_str(),
+        &[&log_ids],
+    )?;
+    transaction.execute(
+        "DELETE FROM aggregates_cache WHERE games = 0 AND med_games = 0 AND primary_games = 0",
+        &[],
+    )?;
+
+    Ok(())
+}
+
+/// The values of the metrics a player has reached in a log, which count
+/// towards their personal records on the `class` they have played the
+/// longest. A single game is either won or not, so there is no record of the
+/// win rate.
+fn record_values(
+    performance: &LogPerformance,
+    class: Class,
+    duration_secs: u32,
+) -> Vec<(Metric, f64)>
+{
+    Metric::ALL
+        .into_iter()
+        .filter(|metric| *metric != Metric::WinRate && metric.applicable_to(class))
+        .filter_map(|metric| Some((metric, metric.compute(performance, duration_secs)?)))
+        .filter(|(_, value)| value.is_finite())
+        .collect()
+}
+
+/// Insert the personal `records` of the players with their steam ids, replacing
+/// the records they have for the same class and metric.
+fn insert_records(
+    transaction: &mut sql::Transaction,
+    records: &[(i64, RecordEntry)],
+) -> DbResult<u64>
+{
+    let steam_ids: Vec<i64> = records.iter().map(|(steam_id, _)| *steam_id).collect();
+    let classes: Vec<i16> = records
+        .iter()
+        .map(|(_, record)| record.class as i16)
+        .collect();
+    let metrics: Vec<&str> = records
+        .iter()
+        .map(|(_, record)| record.metric.short_name())
+        .collect();
+    let values: Vec<f64> = records.iter().map(|(_, record)| record.value).collect();
+    let log_ids: Vec<LogId> = records.iter().map(|(_, record)| record.log_id).collect();
+    let dates: Vec<DateTime<Utc>> = records.iter().map(|(_, record)| record.date).collect();
+
+    Ok(transaction.execute(
+        "INSERT INTO personal_records (steam_id, class, metric, value, log_id, date) SELECT * \
+         FROM unnest($1::bigint[], $2::smallint[], $3::text[], $4::float8[], $5::oid[], \
+         $6::timestamptz[]) ON CONFLICT (steam_id, class, metric) DO UPDATE SET value = \
+         EXCLUDED.value, log_id = EXCLUDED.log_id, date = EXCLUDED.date",
+        &[&steam_ids, &classes, &metrics, &values, &log_ids, &dates],
+    )?)
+}
+
+/// Compare what the `players` have reached in the `log` with their personal
+/// records and replace the records they have beaten. Records of a class and
+/// metric the players have not had yet are set as well.
+///
+/// # Returns
+/// The records that have been beaten, ordered like the `players`.
+fn update_records(
+    transaction: &mut sql::Transaction,
+    log: &Log,
+    players: &[SteamID],
+) -> DbResult<Vec<BrokenRecord>>
+{
+    let steam_ids: Vec<i64> = players.iter().map(|player| player.id64() as i64).collect();
+    let current: HashMap<(i64, Class, Metric), RecordEntry> = transaction
+        .query(
+            format!(
+                "SELECT {}, steam_id FROM personal_records WHERE steam_id = ANY($1)",
+                RECORD_COLUMNS
+            )
+            .as_str(),
+            &[&steam_ids],
+        )?
+        .iter()
+        .map(|row| {
+            let record = row_to_record(row);
+            ((row.get(5), record.class, record.metric), record)
+        })
+        .collect();
+
+    let mut records = Vec::new();
+    let mut broken = Vec::new();
+    for (player, steam_id) in players.iter().zip(steam_ids) {
+        let class = match log.primary_class(*player) {
+            Some(class) => class,
+            None => continue,
+        };
+        let performance = match log.performances().get(player) {
+            Some(performances) => LogPerformance::from(performances.clone()),
+            None => continue,
+        };
+        for (metric, value) in record_values(&performance, class, log.duration_secs()) {
+            let record = RecordEntry {
+                class,
+                metric,
+                value,
+                log_id: log.meta().id,
+                date: log.meta().date_time,
+            };
+            match current.get(&(steam_id, class, metric)) {
+                Some(previous) if !metric.is_better(value, previous.value) => continue,
+                Some(previous) => broken.push(BrokenRecord {
+                    player:   *player,
+                    record:   record.clone(),
+                    previous: previous.clone(),
+                }),
+                None => {},
+            }
+            records.push((steam_id, record));
+        }
+    }
+    insert_records(transaction, &records)?;
+
+    Ok(broken)
+}
+
+/// Replace the personal records of the players with the `steam_ids`, or of
+/// all players if `None`, with ones computed from the stored stats, see
+/// [`SQLDb::rebuild_records`]. Of equal values, the one reached first is the
+/// record.
+fn replace_records(transaction: &mut sql::Transaction, steam_ids: Option<&[i64]>) -> DbResult<u64>
+{
+    transaction.execute(
+        "DELETE FROM personal_records WHERE $1::bigint[] IS NULL OR steam_id = ANY($1)",
+        &[&steam_ids],
+    )?;
+
+    // The games of each player in each log that counts towards the records.
+    let mut games: HashMap<(LogId, i64), RecordGame> = transaction
+        .query(
+            format!(
+                "SELECT {}, stats.log_id, stats.steam_id, stats.primary_class, \
+                 logs.duration_secs, logs.date FROM overall_stats stats JOIN logs ON logs.log_id \
+                 = stats.log_id WHERE NOT logs.duration_suspect AND stats.primary_class IS NOT \
+                 NULL AND ($1::bigint[] IS NULL OR stats.steam_id = ANY($1))",
+                OVERALL_COLUMNS
+            )
+            .as_str(),
+            &[&steam_ids],
+        )?
+        .iter()
+        .filter_map(|row| {
+            let class: i16 = row.get(13);
+            let duration_secs: Option<i32> = row.get(14);
+            let game = RecordGame {
+                class:         stored_value("class", class, Class::from_i16(class))?,
+                duration_secs: duration_secs.map_or(0, |secs| u32::try_from(secs).unwrap_or(0)),
+                date:          row.get(15),
+                performances:  vec![row_to_overall(row).into()],
+            };
+            Some(((row.get(11), row.get(12)), game))
+        })
+        .collect();
+    type ToPerformance = fn(&sql::Row) -> Performance;
+    let class_stats: [(&str, &str, ToPerformance); 2] = [
+        ("dm_stats", DM_COLUMNS, |row| row_to_dm(row).into()),
+        ("med_stats", MED_COLUMNS, |row| row_to_med(row).into()),
+    ];
+    for (table, columns, to_performance) in class_stats {
+        let num_columns = columns.split(',').count();
+        for row in transaction.query(
+            format!(
+                "SELECT {}, log_id, steam_id FROM {} WHERE $1::bigint[] IS NULL OR steam_id = \
+                 ANY($1)",
+                columns, table
+            )
+            .as_str(),
+            &[&steam_ids],
+        )? {
+            let key = (row.get(num_columns), row.get(num_columns + 1));
+            if let Some(game) = games.get_mut(&key) {
+                game.performances.push(to_performance(&row));
+            }
+        }
+    }
+
+    let mut games: Vec<_> = games.into_iter().collect();
+    games.sort_unstable_by_key(|((log_id, steam_id), game)| (game.date, *log_id, *steam_id));
+    let mut best: HashMap<(i64, Class, Metric), RecordEntry> = HashMap::new();
+    for ((log_id, steam_id), game) in games {
+        let class = game.class;
+        let performance = LogPerformance::from(game.performances);
+        for (metric, value) in record_values(&performance, class, game.duration_secs) {
+            let beaten = best
+                .get(&(steam_id, class, metric))
+                .is_none_or(|record| metric.is_better(value, record.value));
+            if beaten {
+                let record = RecordEntry {
+                    class,
+                    metric,
+                    value,
+                    log_id,
+                    date: game.date,
+                };
+                best.insert((steam_id, class, metric), record);
+            }
+        }
+    }
+    let records: Vec<(i64, RecordEntry)> = best
+        .into_iter()
+        .map(|((steam_id, ..), record)| (steam_id, record))
+        .collect();
+
+    insert_records(transaction, &records)
+}
+
+/// A game of a player that counts towards their personal records, see
+/// [`replace_records`].
+struct RecordGame
+{
+    /// The class the player has played the longest.
+    class:         Class,
+    duration_secs: u32,
+    date:          DateTime<Utc>,
+    performances:  Vec<Performance>,
+}
+
+/// Recompute the personal records of the players that have set a record in
+/// the logs with the `log_ids`, once the stats of the logs have been removed.
+fn refresh_records(transaction: &mut sql::Transaction, log_ids: &[LogId]) -> DbResult<()>
+{
+    let steam_ids: Vec<i64> = transaction
+        .query(
+            "SELECT DISTINCT steam_id FROM personal_records WHERE log_id = ANY($1)",
+            &[&log_ids],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if !steam_ids.is_empty() {
+        replace_records(transaction, Some(&steam_ids))?;
+    }
+
+    Ok(())
+}
+
+/// Whose logs an update searches and how it checks the ratio of mixes players
+/// in them, see [`SQLDb::run_update`].
+struct UpdateScope<'a>
+{
+    /// The players whose logs are searched.
+    players:              &'a [SteamID],
+    /// All registered players, which the ratio of mixes players is taken of.
+    users:                HashSet<SteamID>,
+    min_ratio:            f32,
+    /// Whether the ratio is checked once the players of a log are known,
+    /// because the searches do not tell how many registered players it has.
+    ratio_after_download: bool,
+}
+
+impl UpdateScope<'_>
+{
+    /// The ratio the search results are filtered by, if they are.
+    fn search_ratio(&self) -> Option<f32> { (!self.ratio_after_download).then_some(self.min_ratio) }
+
+    /// The ratio checked once the players of a log are known, if it is.
+    fn download_ratio(&self) -> Option<f32> { self.ratio_after_download.then_some(self.min_ratio) }
+}
+
+/// How a metric that is compared across players is summed up from the stats,
+/// see [`metric_columns`].
+struct MetricColumns
+{
+    /// The table of the stats, which are queried as `stats`.
+    table:       &'static str,
+    /// The stat that is summed up.
+    value:       &'static str,
+    /// What the stat is relative to, usually the time in seconds.
+    time:        &'static str,
+    /// The time played in seconds, which the minimum time on the class is
+    /// checked against.
+    time_played: &'static str,
+    /// The class of the stats, `None` for medic stats, which are all medic
+    /// stats.
+    class:       Option<&'static str>,
+    /// The number of seconds the metric is per, `1` for a metric that is not
+    /// relative to time.
+    per_secs:    u32,
+    /// The columns of the aggregates cache the stat, what it is relative to
+    /// and the number of games are summed up in.
+    cached:      (&'static str, &'static str, &'static str),
+}
+
+/// Where the `metric` is stored and how it is summed up.
+///
+/// # Returns
+/// `None` if the metric cannot be summed up over the games.
+fn metric_columns(metric: Metric) -> Option<MetricColumns>
+{
+    let dm = |value, per_secs| MetricColumns {
+        table: "dm_stats",
+        value,
+        time: "stats.time_played_secs",
+        time_played: "stats.time_played_secs",
+        class: Some("stats.class"),
+        per_secs,
+        cached: (value, "time_played_secs", "games"),
+    };
+    let med = |value, per_secs| MetricColumns {
+        table: "med_stats",
+        value,
+        time: "stats.time_played_secs",
+        time_played: "stats.time_played_secs",
+        class: None,
+        per_secs,
+        cached: (value, "med_time_secs", "med_games"),
+    };
+
+    match metric {
+        Metric::DamagePerMinute => Some(dm("damage", 60)),
+        Metric::KillsPerMinute => Some(dm("kills", 60)),
+        Metric::AssistsPerMinute => Some(dm("assists", 60)),
+        Metric::DeathsPerMinute => Some(dm("deaths", 60)),
+        Metric::DeathsPer10Min => Some(dm("deaths", 600)),
+        Metric::HealingPerMinute => Some(med("healing", 60)),
+        Metric::UberRate => Some(med("num_ubers", 300)),
+        // The damage taken is only known for the whole game, so it is relative
+        // to the duration of the game and belongs to the primary class.
+        Metric::DamageTakenPerMinute => Some(MetricColumns {
+            table:       "overall_stats",
+            value:       "damage_taken",
+            time:        "logs.duration_secs",
+            time_played: "logs.duration_secs",
+            class:       Some("stats.primary_class"),
+            per_secs:    60,
+            cached:      ("damage_taken", "duration_secs", "dt_games"),
+        }),
+        // Like the damage taken, the heals received are only known for the
+        // whole game. A game without any heals received counts as if one hp
+        // had been received, like in Metric::compute.
+        Metric::DamagePerHeal => Some(MetricColumns {
+            table:       "overall_stats",
+            value:       "damage",
+            time:        "GREATEST(stats.heals_received, 1)",
+            time_played: "logs.duration_secs",
+            class:       Some("stats.primary_class"),
+            per_secs:    1,
+            cached:      ("primary_damage", "heals_received", "dt_games"),
+        }),
+        _ => None,
+    }
+}
+
+/// The condition for the logs that pass a [`TagFilter`], given the numbers of
+/// the parameters the included and excluded tags are passed as, see
+/// [`tag_params`].
+fn tag_condition(include_param: usize, exclude_param: usize) -> String
+{
+    format!(
+        "(cardinality(${0}::text[]) = 0 OR EXISTS (SELECT FROM log_tags WHERE log_tags.log_id = \
+         logs.log_id AND tag = ANY(${0}))) AND NOT EXISTS (SELECT FROM log_tags WHERE \
+         log_tags.log_id = logs.log_id AND tag = ANY(${1}::text[]))",
+        include_param, exclude_param
+    )
+}
+
+/// The condition for the logs that pass an [`OfficialFilter`].
+fn official_condition(filter: OfficialFilter) -> &'static str
+{
+    match filter {
+        OfficialFilter::Include => "true",
+        OfficialFilter::Exclude => "logs.match_id IS NULL",
+        OfficialFilter::Only => "logs.match_id IS NOT NULL",
+    }
+}
+
+/// The included and excluded tags of the `filter` as query parameters.
+fn tag_params(filter: &TagFilter) -> (Vec<&str>, Vec<&str>)
+{
+    (
+        filter.include.iter().map(LogTag::as_str).collect(),
+        filter.exclude.iter().map(LogTag::as_str).collect(),
+    )
+}
+
+/// Make sure the `value` of the `field` fits into a column that holds at most
+/// `max_len` bytes, so it is rejected with a clear error instead of failing the
+/// query.
+fn check_length(field: &str, value: &str, max_len: usize) -> DbResult<()>
+{
+    if value.len() > max_len {
+        return Err(DbError::InvalidInput(format!(
+            "The {} must not be longer than {} bytes",
+            field, max_len
+        )));
+    }
+
+    Ok(())
+}
+
+/// Create an ILIKE pattern that matches any text containing `text`. The
+/// wildcard characters of ILIKE are escaped, so they are matched literally.
+fn substring_pattern(text: &str) -> String
+{
+    format!(
+        "%{}%",
+        text.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// Read a row of the users table, selected in the order of the fields of
+/// [`UserRecord`].
+fn user_record_from_row(row: &sql::Row) -> Result<UserRecord, SteamIDError>
+{
+    let discord_id: i64 = row.get(1);
+
+    Ok(UserRecord {
+        steam_id:      stored_user_id(row.get(0))?,
+        discord_id:    discord_id as u64,
+        registered_at: row.get(2),
+        active:        row.get(3),
+        username:      row.get(4),
+    })
+}
+
+/// Check the steam id of a player as it is stored in the database, like in
+/// the users table. An invalid one can only be stored by changing the tables
+/// by hand and is logged, so that the player can be left out instead of
+/// breaking everything that lists the players.
+fn stored_user_id(steam_id: i64) -> Result<SteamID, SteamIDError>
+{
+    SteamID::try_from(steam_id as u64).map_err(|error| {
+        warn!("Invalid steam id {} in the database: {}", steam_id, error);
+        error
+    })
+}
+
+/// Check a value as it is stored in the database, like the team of a player,
+/// once it has been decoded into the `value`. Like an invalid steam id, an
+/// invalid value is logged, so that its row can be left out.
+fn stored_value<T>(kind: &str, stored: impl fmt::Display, value: Option<T>) -> Option<T>
+{
+    if value.is_none() {
+        warn!("Invalid {} {} in the database", kind, stored);
+    }
+
+    value
+}
+
+/// Remove the logs that are already stored as mixes from a `page` of search
+/// results. Logs with an id of at least `recent_floor` are looked up in the
+/// `recent_mixes`, which are all mixes in that range. The few older logs are
+/// looked up in the database with a single query.
+fn remove_known_mixes(
+    client: &mut Connection,
+    page: &mut Vec<LogMetadata>,
+    recent_mixes: &HashSet<LogId>,
+    recent_floor: LogId,
+) -> DbResult<()>
+{
+    let old_ids: Vec<LogId> = page
+        .iter()
+        .map(|meta| meta.id)
+        .filter(|&id| id < recent_floor)
+        .collect();
+    let old_mixes: HashSet<LogId> = if old_ids.is_empty() {
+        HashSet::new()
+    }
+    else {
+        client
+            .query(
+                "SELECT log_id FROM logs WHERE log_id = ANY($1) AND ingest_reason = $2",
+                &[&old_ids, &(IngestReason::Mix as i16)],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    };
+
+    page.retain(|meta| !recent_mixes.contains(&meta.id) && !old_mixes.contains(&meta.id));
+
+    Ok(())
+}
+
+/// Takes two vectors, which are sorted in descending order and removes every
+/// item from the first vector, which is already in the second vector.
+fn remove_external_occurrences(target: &mut Vec<LogMetadata>, check: &[LogId])
+{
+    if check.is_empty() || target.is_empty() {
+        return;
+    }
+
+    // Walk through the fields from back to front and remove all items from target
+    // which are contained in check. Back to front is used to ensure very little
+    // vector reallocations should `check` be a superset of, or close to a superset
+    // of `target`.
+    //
+    // WARNING: The indexes are is ONE-INDEXED to make checking for a done state
+    // painless.
+    let mut check_i = check.len();
+    let mut target_i = target.len();
+    while target_i != 0 && check_i != 0 {
+        match target[target_i - 1].id.cmp(&check[check_i - 1]) {
+            Ordering::Equal => {
+                target.remove(target_i - 1);
+                target_i -= 1;
+            },
+            Ordering::Less => target_i -= 1,
+            Ordering::Greater => check_i -= 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Read;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use json::JsonValue;
+    use log::{Level, LevelFilter, Metadata, Record};
+    use num_traits::FromPrimitive;
+    use postgres::{Client, NoTls};
+
+    use super::{
+        remove_external_occurrences, select_new_logs, BulkAddOutcome, CarryStats, ChatSearchResult,
+        Database, DbError, DbEvent, DbStatus, ExternalMatch, ImportMode, IngestOutcome,
+        IngestReason, IntegrityProblem, IntegrityReport, League, LogTag, Metric, OfficialFilter,
+        PurgeReport, QueryOptions, SQLDb, SkipReason, TagFilter, TeammateRecord, TitleFilter,
+        UpdateOptions, UpdatePlan, UpdateProgress, UpdateReport, AGGREGATE_COLUMNS, MAX_NAME_LEN,
+        RECENT_LOGS_MARGIN, SCHEMA_VERSION, SEARCH_PAGE_SIZE,
+    };
+    use crate::database::conformance::run_conformance_suite;
+    use crate::database::{
+        CancelToken, DynDatabase, GameOutcome, GameSummary, RetentionPolicy, RoundStats,
+        UsersReport,
+    };
+    use crate::logs_tf::{
+        Degradation, Health, Log, LogMetadata, LogParseError, MockLogSource, QueryError,
+    };
+    use crate::score::{MatchFormat, Team};
+    use crate::test_support::LogBuilder;
+    use crate::{
+        report, ChatMessage, Class, GameMode, LogId, LogPerformance, MixesDbError, SteamID,
+    };
+
+    const TEST_DB_PARAMS: &str = "host=localhost user=mixes dbname=mixes-stats";
+
+    /// Load the test log json, pretending it was played at the given time.
+    fn fixture_json(date: DateTime<Utc>) -> JsonValue
+    {
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let mut json = json::parse(&json).expect("Unable to parse json");
+        json["info"]["date"] = date.timestamp().into();
+
+        json
+    }
+
+    /// Load the test log, pretending it has the provided id and was played at
+    /// the given time.
+    fn fixture_log(id: u32, date: DateTime<Utc>) -> Log
+    {
+        Log::from_json(LogId(id), &fixture_json(date)).unwrap()
+    }
+
+    /// The ids of the logs of performances returned by the database.
+    fn log_ids(performances: &[(LogId, LogMetadata, LogPerformance)]) -> Vec<LogId>
+    {
+        performances.iter().map(|(id, ..)| *id).collect()
+    }
+
+    /// Database where nothing is persisted, so tests do not affect each other.
+    fn test_db() -> SQLDb
+    {
+        SQLDb::temporary(TEST_DB_PARAMS).expect("Unable to connect to the database")
+    }
+
+    /// Options of an update of logs with 12 or 13 players.
+    fn update_options(min_ratio: f32) -> UpdateOptions
+    {
+        UpdateOptions::builder()
+            .min_ratio(min_ratio)
+            .num_players(12..=13)
+            .build()
+            .unwrap()
+    }
+
+    /// Logger that keeps every message, so tests can check what has been
+    /// logged.
+    struct CapturingLogger
+    {
+        records: Mutex<Vec<(Level, String)>>,
+    }
+
+    impl log::Log for CapturingLogger
+    {
+        fn enabled(&self, _metadata: &Metadata) -> bool { true }
+
+        fn log(&self, record: &Record)
+        {
+            self.records
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        records: Mutex::new(Vec::new()),
+    };
+
+    /// Install the capturing logger, if it has not been installed yet.
+    fn capture_logs() -> &'static Mutex<Vec<(Level, String)>>
+    {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(LevelFilter::Trace);
+        }
+
+        &LOGGER.records
+    }
+
+    fn at(timestamp: i64) -> DateTime<Utc> { Utc.timestamp_opt(timestamp, 0).unwrap() }
+
+    #[test]
+    fn connect_to_db()
+    {
+        Client::connect("host=localhost user=mixes dbname=mixes-stats", NoTls)
+            .expect("Unable to connect to the database. Make sure postgresql is set up correctly");
+    }
+
+    #[test]
+    fn start() { let db = SQLDb::start().expect("Unable to connect to SQL database"); }
+
+    #[test]
+    fn schema_version()
+    {
+        let mut db = test_db();
+        assert_eq!(db.stored_schema_version().unwrap(), Some(SCHEMA_VERSION));
+
+        // Databases migrated before the version was saved are migrated as usual.
+        db.client
+            .execute("DELETE FROM meta WHERE key = 'schema_version'", &[])
+            .unwrap();
+        assert_eq!(db.stored_schema_version().unwrap(), None);
+        db.init_tables().unwrap();
+        assert_eq!(db.stored_schema_version().unwrap(), Some(SCHEMA_VERSION));
+
+        // Connecting runs the same check as initializing the tables again.
+        db.client
+            .execute(
+                "UPDATE meta SET value = '1000' WHERE key = 'schema_version'",
+                &[],
+            )
+            .unwrap();
+        assert!(matches!(
+            db.init_tables(),
+            Err(DbError::SchemaTooNew {
+                found:     1000,
+                supported: SCHEMA_VERSION,
+            })
+        ));
+        // Nothing has been migrated back.
+        assert_eq!(db.stored_schema_version().unwrap(), Some(1000));
+    }
+
+    #[test]
+    fn migrations_run_once()
+    {
+        let mut db = test_db();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let num_missing = |db: &mut SQLDb, table: &str, column: &str| -> i64 {
+            db.client
+                .query_one(
+                    format!("SELECT count(*) FROM {} WHERE {} IS NULL", table, column).as_str(),
+                    &[],
+                )
+                .unwrap()
+                .get(0)
+        };
+        let remove = |db: &mut SQLDb, table: &str, column: &str| {
+            db.client
+                .execute(
+                    format!("UPDATE {} SET {} = NULL", table, column).as_str(),
+                    &[],
+                )
+                .unwrap();
+        };
+        let store_version = |db: &mut SQLDb, version: u32| {
+            db.client
+                .execute(
+                    "UPDATE meta SET value = $1 WHERE key = 'schema_version'",
+                    &[&version.to_string()],
+                )
+                .unwrap();
+        };
+
+        // The database is up to date, so nothing is backfilled again.
+        remove(&mut db, "overall_stats", "primary_class");
+        remove(&mut db, "dm_stats", "team");
+        db.init_tables().unwrap();
+        assert_eq!(num_missing(&mut db, "overall_stats", "primary_class"), 12);
+        assert!(num_missing(&mut db, "dm_stats", "team") > 0);
+
+        // Databases stored before the teams are only migrated from there.
+        store_version(&mut db, 25);
+        db.init_tables().unwrap();
+        assert_eq!(num_missing(&mut db, "overall_stats", "primary_class"), 12);
+        assert_eq!(num_missing(&mut db, "dm_stats", "team"), 0);
+        assert_eq!(db.stored_schema_version().unwrap(), Some(SCHEMA_VERSION));
+
+        // Databases stored before the classes are migrated once.
+        store_version(&mut db, 14);
+        db.init_tables().unwrap();
+        assert_eq!(num_missing(&mut db, "overall_stats", "primary_class"), 0);
+        assert_eq!(db.stored_schema_version().unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn conformance()
+    {
+        // The backend is chosen at runtime, like a bot does from its config.
+        let backend = |name: &str| -> DynDatabase {
+            match name {
+                "postgres" => Box::new(test_db()),
+                _ => panic!("Unknown backend {}", name),
+            }
+        };
+        run_conformance_suite(|| backend("postgres"));
+    }
+
+    #[test]
+    fn log_id_round_trip()
+    {
+        let mut db = test_db();
+        let id = LogId(3094861);
+
+        let row = db
+            .client
+            .query_one("SELECT $1::oid, $2::int4", &[&id, &id])
+            .unwrap();
+        assert_eq!(row.get::<_, LogId>(0), id);
+        assert_eq!(row.get::<_, LogId>(1), id);
+
+        let row = db.client.query_one("SELECT (-1)::int4", &[]).unwrap();
+        assert!(row.try_get::<_, LogId>(0).is_err());
+    }
+
+    #[test]
+    fn remove_external_occ()
+    {
+        let create_meta = |id| LogMetadata {
+            id:            LogId(id),
+            date_time:     DateTime::UNIX_EPOCH,
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+
+        let mut log_metas = vec![
+            create_meta(2145),
+            create_meta(1247),
+            create_meta(5),
+            create_meta(0),
+        ];
+        let check = [LogId(1247), LogId(0)];
+
+        remove_external_occurrences(&mut log_metas, &check);
+
+        assert_eq!(log_metas.len(), 2);
+    }
+
+    #[test]
+    fn select_new_logs_by_player_count()
+    {
+        let create_meta = |id, num_players| LogMetadata {
+            id: LogId(id),
+            date_time: at(0),
+            map: "cp_sunshine".to_owned(),
+            num_players,
+            title: None,
+            uploader: None,
+            duration_secs: None,
+        };
+        // Six mixes players, who have all played in every log
+        let search_results: Vec<(SteamID, Vec<LogMetadata>)> = (1..=6)
+            .map(|id| {
+                (
+                    SteamID::from_str(&format!("[U:1:{}]", id)).unwrap(),
+                    vec![create_meta(3, 0), create_meta(2, 12), create_meta(1, 300)],
+                )
+            })
+            .collect();
+        let candidate_ids = |plan: &UpdatePlan| {
+            plan.candidates
+                .iter()
+                .map(|meta| meta.id)
+                .collect::<Vec<_>>()
+        };
+
+        let plan = select_new_logs(search_results.clone(), 0.5, &(12..=13));
+        assert_eq!(candidate_ids(&plan), vec![LogId(2)]);
+        assert_eq!(
+            plan.skipped,
+            vec![
+                (LogId(3), SkipReason::NoPlayers),
+                (LogId(1), SkipReason::PlayerCount(300))
+            ]
+        );
+        assert!(plan
+            .contributions
+            .values()
+            .all(|&contribution| contribution == 1));
+
+        // Logs without players are skipped even if the range would allow them.
+        let plan = select_new_logs(search_results.clone(), 0.0, &(0..=300));
+        assert_eq!(candidate_ids(&plan), vec![LogId(2), LogId(1)]);
+        assert_eq!(plan.skipped, vec![(LogId(3), SkipReason::NoPlayers)]);
+
+        let plan = select_new_logs(search_results, 0.6, &(12..=13));
+        assert!(plan.candidates.is_empty());
+        assert_eq!(
+            plan.skipped[1],
+            (LogId(2), SkipReason::NotEnoughMixesPlayers)
+        );
+    }
+
+    #[test]
+    fn update_pages_large_histories()
+    {
+        let mut db = test_db().max_player_logs(50);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        // Thousands of pub logs, of which every hundredth is a 6v6 game.
+        let mut source = MockLogSource::new();
+        for id in (1..=10_000).rev() {
+            let meta = LogMetadata {
+                id:            LogId(id),
+                date_time:     at(id as i64),
+                map:           "cp_sunshine".to_owned(),
+                num_players:   if id % 100 == 0 { 12 } else { 24 },
+                title:         None,
+                uploader:      None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], String::new());
+        }
+
+        let plan = db.update_dry_run(&source, &update_options(0.)).unwrap();
+        assert!(source.largest_search() <= SEARCH_PAGE_SIZE as usize);
+        // Only the newest 6v6 games are kept.
+        assert_eq!(plan.candidates.len(), 50);
+        assert_eq!(plan.candidates[0].id, LogId(10_000));
+        assert_eq!(plan.candidates[49].id, LogId(5_100));
+        assert_eq!(plan.overflow, 50);
+        assert_eq!(plan.skipped.len(), 9_900);
+        assert_eq!(plan.skipped[0], (LogId(9_999), SkipReason::PlayerCount(24)));
+    }
+
+    #[test]
+    fn update_dry_run_matches_update()
+    {
+        // The players of the mock logs are only known from the searches, so
+        // they are searched one by one.
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_user(medic, 2).unwrap();
+
+        let mut source = MockLogSource::new();
+        for (id, players, num_players) in [
+            (1, vec![scout, medic], 12),
+            // Only one of the twelve players is registered
+            (2, vec![scout], 12),
+            (3, vec![scout, medic], 6),
+            (4, vec![medic], 12),
+        ] {
+            let date = at(1000 * id as i64);
+            let meta = LogMetadata {
+                id: LogId(id),
+                date_time: date,
+                map: "cp_sunshine".to_owned(),
+                num_players,
+                title: None,
+                uploader: None,
+                duration_secs: None,
+            };
+            source.add_log(meta, players, fixture_json(date).dump());
+        }
+        // Already in the database, so it is neither downloaded nor skipped
+        db.add_log(fixture_log(4, at(4000))).unwrap();
+
+        let plan = db.update_dry_run(&source, &update_options(0.1)).unwrap();
+        assert!(source.downloaded().is_empty());
+        assert_eq!(
+            plan.candidates
+                .iter()
+                .map(|meta| meta.id)
+                .collect::<Vec<_>>(),
+            vec![LogId(1)]
+        );
+        assert_eq!(plan.contributions, HashMap::from([(scout, 1), (medic, 1)]));
+        assert_eq!(
+            plan.skipped,
+            vec![
+                (LogId(3), SkipReason::PlayerCount(6)),
+                (LogId(2), SkipReason::NotEnoughMixesPlayers)
+            ]
+        );
+        assert_eq!(plan.num_found(), 3);
+
+        let report = db.update_from(&source, update_options(0.1)).unwrap();
+        assert_eq!(source.downloaded(), vec![LogId(1)]);
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(report.skipped, plan.skipped);
+
+        // Everything has been ingested, so nothing is left to do.
+        assert!(db
+            .update_dry_run(&source, &update_options(0.1))
+            .unwrap()
+            .candidates
+            .is_empty());
+    }
+
+    #[test]
+    fn update_dry_run_checks_like_update()
+    {
+        let filter = TitleFilter {
+            allow: Vec::new(),
+            deny:  vec!["free server".to_owned()],
+        };
+        let mut db = test_db().search_batch_size(1).title_filter(filter);
+        let players = [
+            SteamID::from_str("[U:1:886717065]").unwrap(),
+            SteamID::from_str("[U:1:71020853]").unwrap(),
+        ];
+        for (discord_id, player) in players.into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        let meta = |id, title: Option<&str>| LogMetadata {
+            id:            LogId(id),
+            date_time:     at(1000 * id as i64),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         title.map(str::to_owned),
+            uploader:      None,
+            duration_secs: None,
+        };
+
+        // The third log is uploaded after the first player has been searched,
+        // so it is only found with enough mixes players by the reconciliation.
+        let mut source = MockLogSource::new();
+        let json = fixture_json(at(1000)).dump();
+        source.add_log(meta(1, None), players.to_vec(), json.clone());
+        source.add_log(meta(2, Some("free server")), players.to_vec(), json.clone());
+        source.add_log_after(1, meta(3, None), players.to_vec(), json);
+        let options = || {
+            UpdateOptions::builder()
+                .min_ratio(0.15)
+                .health_check()
+                .build()
+                .unwrap()
+        };
+
+        // Nothing is searched if the source is down.
+        source.set_health(Health::Down("maintenance".to_owned()));
+        assert!(matches!(
+            db.update_dry_run(&source, &options()),
+            Err(MixesDbError::Backend(_))
+        ));
+        assert_eq!(source.num_searches(), 0);
+
+        source.set_health(Health::Degraded(Degradation::ServerError(503)));
+        let plan = db.update_dry_run(&source, &options()).unwrap();
+        assert_eq!(
+            plan.candidates
+                .iter()
+                .map(|meta| meta.id)
+                .collect::<Vec<_>>(),
+            vec![LogId(3), LogId(1)]
+        );
+        assert_eq!(
+            plan.skipped,
+            vec![(LogId(2), SkipReason::Title(Some("free server".to_owned())))]
+        );
+        assert!(source.downloaded().is_empty());
+        assert!(db.known_logs().unwrap().is_empty());
+
+        // A ratio out of range is rejected instead of searching.
+        assert!(matches!(
+            db.plan_update_since(&source, &players, Some(1.5), &(12..=13), None, None),
+            Err(DbError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn update_batched_searches()
+    {
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let other = SteamID::from_str("[U:1:1]").unwrap();
+        let mut without_medic = fixture_json(at(2000));
+        without_medic["players"].remove("[U:1:71020853]");
+
+        let mut source = MockLogSource::new();
+        for (id, players, num_players, json) in [
+            (1, vec![scout, medic], 12, fixture_json(at(1000))),
+            (2, vec![scout], 12, without_medic.clone()),
+            (3, vec![scout, medic], 6, fixture_json(at(3000))),
+            (4, vec![scout], 12, without_medic.clone()),
+        ] {
+            let meta = LogMetadata {
+                id: LogId(id),
+                date_time: at(1000 * id as i64),
+                map: "cp_sunshine".to_owned(),
+                num_players,
+                title: None,
+                uploader: None,
+                duration_secs: None,
+            };
+            source.add_log(meta, players, json.dump());
+        }
+
+        let mut db = test_db();
+        for (discord_id, player) in [scout, medic, other].into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        // Stored from the history of a player, so its players are counted
+        // without downloading it again.
+        let backfilled = || Log::from_json(LogId(4), &without_medic).unwrap();
+        db.insert_log(backfilled(), IngestReason::PersonalBackfill, None)
+            .unwrap();
+
+        // Like the update, the dry run searches once more at the end.
+        let plan = db.update_dry_run(&source, &update_options(0.15)).unwrap();
+        assert_eq!(source.num_searches(), 2);
+        assert_eq!(
+            plan.candidates
+                .iter()
+                .map(|meta| meta.id)
+                .collect::<Vec<_>>(),
+            vec![LogId(4), LogId(2), LogId(1)]
+        );
+
+        // Only the downloaded logs tell which of the players have played.
+        // The update searches once more at the end, for logs uploaded in between.
+        let report = db.update_from(&source, update_options(0.15)).unwrap();
+        assert_eq!(source.num_searches(), 4);
+        assert_eq!(source.downloaded(), vec![LogId(2), LogId(1)]);
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(
+            report.skipped,
+            vec![
+                (LogId(3), SkipReason::PlayerCount(6)),
+                (LogId(4), SkipReason::NotEnoughMixesPlayers),
+                (LogId(2), SkipReason::NotEnoughMixesPlayers)
+            ]
+        );
+        assert_eq!(db.stored_mixes_players(LogId(2)).unwrap(), None);
+        assert_eq!(db.stored_mixes_players(LogId(4)).unwrap(), Some((1, 11)));
+
+        // Searching every player on their own comes to the same result.
+        let mut db = test_db().search_batch_size(1);
+        for (discord_id, player) in [scout, medic, other].into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        db.insert_log(backfilled(), IngestReason::PersonalBackfill, None)
+            .unwrap();
+        let report = db.update_from(&source, update_options(0.15)).unwrap();
+        assert_eq!(source.num_searches(), 10);
+        assert_eq!(report.added, vec![LogId(1)]);
+    }
+
+    #[test]
+    fn update_isolates_failed_logs()
+    {
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        // The database cannot store text with NUL characters.
+        let mut poisoned = fixture_json(at(2000));
+        poisoned["info"]["title"] = "Mix\0".into();
+
+        let mut source = MockLogSource::new();
+        for (id, json) in [
+            (1, fixture_json(at(1000))),
+            (2, poisoned.clone()),
+            (3, fixture_json(at(3000))),
+        ] {
+            let meta = LogMetadata {
+                id:            LogId(id),
+                date_time:     at(1000 * id as i64),
+                map:           "cp_sunshine".to_owned(),
+                num_players:   12,
+                title:         None,
+                uploader:      None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], json.dump());
+        }
+
+        let mut db = test_db().search_batch_size(1);
+        db.add_user(scout, 1).unwrap();
+
+        let mut report = db.update_from(&source, update_options(0.)).unwrap();
+        report.added.sort();
+        assert_eq!(report.added, vec![LogId(1), LogId(3)]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, LogId(2));
+        assert!(matches!(report.skipped[0].1, SkipReason::IngestFailed(_)));
+        // Nothing of the failed log is left behind.
+        assert!(!db.has_log(LogId(2)).unwrap());
+        assert!(db.has_log(LogId(3)).unwrap());
+
+        // Only if no log can be added, the update fails.
+        let mut source = MockLogSource::new();
+        let meta = LogMetadata {
+            id:            LogId(4),
+            date_time:     at(4000),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+        source.add_log(meta, vec![scout], poisoned.dump());
+        assert!(matches!(
+            db.update_from(&source, update_options(0.)),
+            Err(DbError::Sql(_))
+        ));
+        let run = db.update_history(1).unwrap().remove(0);
+        assert!(!run.completed);
+        assert!(run.duration.is_some());
+        assert_eq!(
+            (run.num_candidates, run.num_added, run.num_failed),
+            (1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn backfill_player()
+    {
+        // The players of the mock logs are only known from the searches, so
+        // they are searched one by one.
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        let mut source = MockLogSource::new();
+        for (id, players, num_players) in [
+            (1, vec![scout, medic], 12),
+            (2, vec![scout], 6),
+            (3, vec![scout], 12),
+            (4, vec![medic], 12),
+        ] {
+            let date = at(1000 * id as i64);
+            let meta = LogMetadata {
+                id: LogId(id),
+                date_time: date,
+                map: "cp_sunshine".to_owned(),
+                num_players,
+                title: None,
+                uploader: None,
+                duration_secs: None,
+            };
+            source.add_log(meta, players, fixture_json(date).dump());
+        }
+        db.add_log(fixture_log(3, at(3000))).unwrap();
+
+        let ingest_reason = |db: &mut SQLDb, id: u32| -> IngestReason {
+            let row = db
+                .client
+                .query_one("SELECT ingest_reason FROM logs WHERE log_id = $1", &[&id])
+                .unwrap();
+            IngestReason::from_i16(row.get(0)).unwrap()
+        };
+
+        // Without any logs to look at, nothing is searched.
+        let report = db.backfill_player(&source, scout, 0, 12..=13).unwrap();
+        assert_eq!(report, UpdateReport::default());
+        assert_eq!(source.num_searches(), 0);
+
+        // Not a single other player is registered, but the log is added anyway.
+        let report = db.backfill_player(&source, scout, 10, 12..=13).unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(report.skipped, vec![(LogId(2), SkipReason::PlayerCount(6))]);
+        assert_eq!(source.downloaded(), vec![LogId(1)]);
+        assert_eq!(ingest_reason(&mut db, 1), IngestReason::PersonalBackfill);
+        assert_eq!(ingest_reason(&mut db, 3), IngestReason::Mix);
+
+        // Once enough players are registered, the log becomes a mix without being
+        // downloaded again.
+        db.add_user(scout, 1).unwrap();
+        db.add_user(medic, 2).unwrap();
+        let report = db.update_from(&source, update_options(0.1)).unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(source.downloaded(), vec![LogId(1)]);
+        assert_eq!(ingest_reason(&mut db, 1), IngestReason::Mix);
+    }
+
+    #[test]
+    fn add_users_bulk()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let demo = SteamID::from_str("[U:1:1]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        let report = db
+            .add_users_bulk(&[
+                (2, "https://steamcommunity.com/profiles/76561198031286581"),
+                (3, "[U:1:886717065]"),
+                (2, "[U:1:1]"),
+                (4, "https://steamcommunity.com/id/demo"),
+                (5, "STEAM_0:1:0"),
+                (6, "STEAM_0:1:35510426"),
+                (7, "[g:1:4]"),
+            ])
+            .unwrap();
+        assert_eq!(report.num_added(), 2);
+        assert_eq!(report.outcomes.len(), 7);
+        assert_eq!(report.outcomes[0], BulkAddOutcome::Added(medic));
+        assert_eq!(report.outcomes[1], BulkAddOutcome::DuplicateSteamId(scout));
+        assert_eq!(report.outcomes[2], BulkAddOutcome::DuplicateDiscordId(demo));
+        assert!(
+            matches!(&report.outcomes[3], BulkAddOutcome::Unparseable(reason)
+            if reason.contains("custom profile name"))
+        );
+        assert_eq!(report.outcomes[4], BulkAddOutcome::Added(demo));
+        // Added by an earlier entry of the same import
+        assert_eq!(report.outcomes[5], BulkAddOutcome::DuplicateSteamId(medic));
+        // Clans cannot be registered.
+        assert!(
+            matches!(&report.outcomes[6], BulkAddOutcome::Unparseable(reason)
+            if reason.contains("not the steam id of a player"))
+        );
+        let clan = SteamID::from_str("[g:1:4]").unwrap();
+        assert!(matches!(
+            db.add_user(clan, 8),
+            Err(MixesDbError::InvalidInput(_))
+        ));
+
+        let mut users = db.users().unwrap();
+        users.sort_unstable_by_key(|steam_id| steam_id.id64());
+        assert_eq!(users, vec![demo, medic, scout]);
+    }
+
+    #[test]
+    fn invalid_users()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.client
+            .execute(
+                "INSERT INTO users (steam_id, discord_id) VALUES (1, 2)",
+                &[],
+            )
+            .unwrap();
+
+        // The broken row is left out instead of making the lookups fail.
+        assert_eq!(db.users().unwrap(), vec![scout]);
+        let records = db.user_records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].steam_id, scout);
+
+        let error = SteamID::try_from(1).unwrap_err();
+        assert_eq!(
+            db.users_checked().unwrap(),
+            UsersReport {
+                valid:   vec![scout],
+                invalid: vec![(1, error.clone())],
+            }
+        );
+        assert_eq!(
+            db.check_integrity(false).unwrap().problems,
+            vec![IntegrityProblem::InvalidUser { steam_id: 1, error }]
+        );
+
+        // Rankings of the players leave it out as well.
+        let other_scout = SteamID::from_str("[U:1:2]").unwrap();
+        db.add_user(other_scout, 3).unwrap();
+        for id in 1..=2 {
+            let log = LogBuilder::new(LogId(id))
+                .date(at(1800 * i64::from(id)))
+                .player(scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, 9000, 1800)
+                        .team(Team::Red)
+                })
+                .player(other_scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, 6000, 1800)
+                        .team(Team::Blue)
+                })
+                .build();
+            db.add_log(log).unwrap();
+        }
+        db.client
+            .execute(
+                format!(
+                    "INSERT INTO aggregates_cache (steam_id, class, season_id, ingest_reason, \
+                     {columns}) SELECT 1, class, season_id, ingest_reason, {columns} FROM \
+                     aggregates_cache WHERE steam_id = $1",
+                    columns = AGGREGATE_COLUMNS.join(", "),
+                )
+                .as_str(),
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap();
+        let leaderboard = db
+            .normalized_leaderboard(Metric::DamagePerMinute, 1, 10)
+            .unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|&(player, ..)| player)
+                .collect::<Vec<_>>(),
+            vec![scout, other_scout]
+        );
+    }
+
+    #[test]
+    fn user_records()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let before = Utc::now() - chrono::Duration::seconds(1);
+        db.add_user(scout, 1).unwrap();
+        db.add_users_bulk(&[(2, "[U:1:71020853]")]).unwrap();
+        assert_eq!(
+            db.user_record(SteamID::from_str("[U:1:1]").unwrap())
+                .unwrap(),
+            None
+        );
+
+        let record = db.user_record(scout).unwrap().unwrap();
+        assert_eq!(record.steam_id, scout);
+        assert_eq!(record.discord_id, 1);
+        assert!(record.registered_at.unwrap() >= before);
+        assert!(record.active);
+        assert_eq!(record.username, None);
+
+        db.set_username(scout, "cklips").unwrap();
+        assert!(db.set_user_active(medic, false).unwrap());
+        assert!(!db
+            .set_user_active(SteamID::from_str("[U:1:1]").unwrap(), false)
+            .unwrap());
+
+        let records = db.user_records().unwrap();
+        assert_eq!(records.len(), 2);
+        // Ordered by steam id
+        assert_eq!(records[0].steam_id, medic);
+        assert_eq!(records[0].discord_id, 2);
+        assert!(records[0].registered_at.is_some());
+        assert!(!records[0].active);
+        assert_eq!(records[1], db.user_record(scout).unwrap().unwrap());
+        assert_eq!(records[1].username.as_deref(), Some("cklips"));
+        assert_eq!(db.users().unwrap(), vec![medic, scout]);
+    }
+
+    #[test]
+    fn ingest_log_by_ref()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        let mut source = MockLogSource::new();
+        let meta = LogMetadata {
+            id:            LogId(3094861),
+            date_time:     at(1000),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+        source.add_log(meta.clone(), vec![scout], fixture_json(at(1000)).dump());
+
+        assert!(matches!(
+            db.ingest_log_by_ref(&source, "https://logs.tf/3094861#76561198847002865"),
+            Ok(IngestOutcome::Added { players: 12 })
+        ));
+        assert!(db.has_log(LogId(3094861)).unwrap());
+        assert!(!db.has_log(LogId(3094862)).unwrap());
+
+        // Already present, so it is not downloaded again.
+        for log_ref in ["3094861", "#3094861"] {
+            assert!(matches!(
+                db.ingest_log_by_ref(&source, log_ref),
+                Ok(IngestOutcome::AlreadyPresent)
+            ));
+        }
+        assert_eq!(source.downloaded(), vec![LogId(3094861)]);
+
+        assert!(matches!(
+            db.ingest_log_by_ref(&source, "https://example.com/3094861"),
+            Err(MixesDbError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            db.ingest_log_by_ref(&source, "1"),
+            Err(MixesDbError::LogsTf(_))
+        ));
+        assert_eq!(source.downloaded(), vec![LogId(3094861), LogId(1)]);
+
+        // Stored by someone else while it has been downloaded.
+        db.client
+            .batch_execute(
+                "CREATE FUNCTION pg_temp.store_first() RETURNS trigger AS $$ BEGIN
+                    IF pg_trigger_depth() = 1 THEN
+                        INSERT INTO logs (log_id) VALUES (NEW.log_id);
+                    END IF;
+                    RETURN NEW;
+                END $$ LANGUAGE plpgsql;
+                CREATE TRIGGER store_first BEFORE INSERT ON logs
+                    FOR EACH ROW EXECUTE FUNCTION pg_temp.store_first();",
+            )
+            .unwrap();
+        let meta = LogMetadata {
+            id: LogId(2),
+            ..meta
+        };
+        source.add_log(meta, vec![scout], fixture_json(at(1000)).dump());
+        assert!(matches!(
+            db.ingest_log_by_ref(&source, "2"),
+            Ok(IngestOutcome::AlreadyPresent)
+        ));
+    }
+
+    /// Log in which only the `players`, given by their steamID3, have played,
+    /// as scouts in the given teams, ending with the given score of red and
+    /// blue.
+    fn teams_log(id: u32, (red, blue): (u8, u8), players: &[(&str, &str)]) -> Log
+    {
+        let mut json = fixture_json(at(1000 * id as i64));
+        json["teams"]["Red"]["score"] = red.into();
+        json["teams"]["Blue"]["score"] = blue.into();
+        json["players"] = JsonValue::new_object();
+        for (player, team) in players {
+            let mut class_stats = JsonValue::new_object();
+            class_stats["type"] = "scout".into();
+            for stat in ["kills", "assists", "deaths", "dmg"] {
+                class_stats[stat] = 0.into();
+            }
+            class_stats["total_time"] = 1800.into();
+
+            let mut stats = JsonValue::new_object();
+            stats["team"] = (*team).into();
+            stats["class_stats"] = JsonValue::from(vec![class_stats]);
+            json["players"][*player] = stats;
+        }
+
+        Log::from_json(LogId(id), &json).unwrap()
+    }
+
+    #[test]
+    fn match_format_stored()
+    {
+        let mut db = test_db();
+        let mut json = fixture_json(at(2000));
+        json["info"]["map"] = "pl_upward_f10".into();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+
+        let formats: Vec<(LogId, MatchFormat)> = db
+            .client
+            .query("SELECT log_id, format FROM logs ORDER BY log_id", &[])
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let format: i16 = row.get(1);
+                (row.get(0), MatchFormat::from_i16(format).unwrap())
+            })
+            .collect();
+        assert_eq!(
+            formats,
+            vec![
+                (LogId(1), MatchFormat::FiveCp),
+                (LogId(2), MatchFormat::Stopwatch)
+            ]
+        );
+    }
+
+    #[test]
+    fn damage_taken_per_minute()
+    {
+        let mut db = test_db();
+        let scouts = ["[U:1:886717065]", "[U:1:838576271]"];
+        let (first, second) = (
+            SteamID::from_str(scouts[0]).unwrap(),
+            SteamID::from_str(scouts[1]).unwrap(),
+        );
+        db.add_user(first, 1).unwrap();
+        db.add_user(second, 2).unwrap();
+        let log = |id: u32, duration_secs: u32, damage_taken: [u32; 2]| {
+            let mut json = fixture_json(at(1000 * id as i64));
+            json["info"]["total_length"] = duration_secs.into();
+            for (player, damage_taken) in scouts.into_iter().zip(damage_taken) {
+                json["players"][player]["dt"] = damage_taken.into();
+            }
+            Log::from_json(LogId(id), &json).unwrap()
+        };
+        // Per game, the second scout takes 180 and 48 damage per minute and the
+        // first 0 and 120. Weighted by the duration of the games, the first
+        // scout takes 90 damage per minute and the second only 81.
+        db.add_log(log(1, 1000, [0, 3000])).unwrap();
+        db.add_log(log(2, 3000, [6000, 2400])).unwrap();
+
+        let dtm = Metric::DamageTakenPerMinute;
+        let percentile = |db: &mut SQLDb, player, metric| {
+            db.percentile_for_metric(player, Class::Scout, metric, 2, false, 0)
+                .unwrap()
+        };
+        assert_eq!(percentile(&mut db, first, dtm), Some(1.));
+        assert_eq!(percentile(&mut db, second, dtm), Some(0.));
+        // The damage taken only counts for the primary class of the games.
+        assert_eq!(
+            db.percentile_for_metric(first, Class::Pyro, dtm, 1, false, 0)
+                .unwrap(),
+            None
+        );
+
+        // Taking less damage is better.
+        let leaderboard: Vec<(SteamID, Class)> = db
+            .normalized_leaderboard(dtm, 2, 10)
+            .unwrap()
+            .into_iter()
+            .map(|(player, class, _)| (player, class))
+            .collect();
+        assert_eq!(
+            leaderboard,
+            vec![(second, Class::Scout), (first, Class::Scout)]
+        );
+
+        // The deaths are compared the same way on either scale.
+        for player in [first, second] {
+            assert_eq!(
+                percentile(&mut db, player, Metric::DeathsPer10Min),
+                percentile(&mut db, player, Metric::DeathsPerMinute)
+            );
+        }
+    }
+
+    #[test]
+    fn damage_per_heal()
+    {
+        let mut db = test_db();
+        let medic = SteamID::from_str("[U:1:1]").unwrap();
+        let soldier = SteamID::from_str("[U:1:2]").unwrap();
+        let pocket = SteamID::from_str("[U:1:3]").unwrap();
+        for (discord_id, player) in [medic, soldier, pocket].into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        for id in [1, 2] {
+            let log = LogBuilder::new(LogId(id))
+                .date(at(1000 * i64::from(id)))
+                .medic(medic, 6430, 2, 0)
+                .player(soldier, |player| {
+                    player
+                        .class(Class::Soldier, 10, 5, 7261, 1800)
+                        .heals_received(3739)
+                })
+                .player(pocket, |player| {
+                    player
+                        .class(Class::Soldier, 10, 5, 10085, 1800)
+                        .heals_received(2691)
+                })
+                .build();
+            db.add_log(log).unwrap();
+        }
+
+        let heals_received: i64 = db
+            .client
+            .query_one(
+                "SELECT SUM(heals_received) FROM overall_stats WHERE steam_id = $1",
+                &[&(soldier.id64() as i64)],
+            )
+            .unwrap()
+            .get(0);
+        assert_eq!(heals_received, 2 * 3739);
+
+        // The soldier deals 7261 damage per 3739 healing received, the pocket
+        // 10085 per 2691. Medics are not ranked at all.
+        let dph = Metric::DamagePerHeal;
+        let leaderboard = db.normalized_leaderboard(dph, 2, 10).unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|&(player, class, _)| (player, class))
+                .collect::<Vec<_>>(),
+            vec![(pocket, Class::Soldier), (soldier, Class::Soldier)]
+        );
+        assert_eq!(
+            db.percentile_for_metric(medic, Class::Medic, dph, 1, false, 0)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn backfilled_logs_not_compared()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:1]").unwrap();
+        let other_scout = SteamID::from_str("[U:1:2]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_user(other_scout, 2).unwrap();
+        let log = |id: u32, other_damage| {
+            LogBuilder::new(LogId(id))
+                .date(at(1800 * i64::from(id)))
+                .player(scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, 9000, 1800)
+                        .team(Team::Red)
+                })
+                .player(other_scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, other_damage, 1800)
+                        .team(Team::Blue)
+                })
+                .build()
+        };
+        for id in 1..=2 {
+            db.add_log(log(id, 6000)).unwrap();
+        }
+
+        let dpm = Metric::DamagePerMinute;
+        let percentiles = |db: &mut SQLDb| {
+            [false, true].map(|include_suspect| {
+                db.percentile_for_metric(other_scout, Class::Scout, dpm, 1, include_suspect, 0)
+                    .unwrap()
+            })
+        };
+        let leaderboard = db.normalized_leaderboard(dpm, 1, 10).unwrap();
+        assert_eq!(leaderboard[0].0, scout);
+        assert_eq!(percentiles(&mut db), [Some(0.); 2]);
+
+        // A single public game where the other scout has done far more damage
+        db.insert_log(log(3, 60_000), IngestReason::PersonalBackfill, None)
+            .unwrap();
+        assert_eq!(db.normalized_leaderboard(dpm, 1, 10).unwrap(), leaderboard);
+        assert_eq!(percentiles(&mut db), [Some(0.); 2]);
+        let performances = |db: &mut SQLDb, ingest_reason| {
+            let options = QueryOptions {
+                ingest_reason,
+                ..QueryOptions::default()
+            };
+            db.get_classes_performance(other_scout, &[Class::Scout], 10, &options)
+                .unwrap()
+                .len()
+        };
+        assert_eq!(performances(&mut db, Some(IngestReason::Mix)), 2);
+        assert_eq!(
+            performances(&mut db, Some(IngestReason::PersonalBackfill)),
+            1
+        );
+        assert_eq!(performances(&mut db, None), 3);
+
+        // Once an update finds it to be a mix, it is compared like any other.
+        let mut source = MockLogSource::new();
+        let meta = LogMetadata {
+            id:            LogId(3),
+            date_time:     at(5400),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+        source.add_log(meta, vec![scout, other_scout], String::new());
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(3)]);
+        assert!(source.downloaded().is_empty());
+        assert_eq!(
+            db.normalized_leaderboard(dpm, 1, 10).unwrap()[0].0,
+            other_scout
+        );
+        assert_eq!(percentiles(&mut db), [Some(1.); 2]);
+        assert_eq!(performances(&mut db, Some(IngestReason::Mix)), 3);
+    }
+
+    #[test]
+    fn normalized_leaderboard_invalid_class()
+    {
+        let mut db = test_db();
+        let scouts = ["[U:1:1]", "[U:1:2]", "[U:1:3]"].map(|id| SteamID::from_str(id).unwrap());
+        for (discord_id, &scout) in scouts.iter().enumerate() {
+            db.add_user(scout, discord_id as u64).unwrap();
+        }
+        let log = scouts
+            .iter()
+            .zip([9000, 6000, 3000])
+            .fold(LogBuilder::new(LogId(1)), |log, (&scout, damage)| {
+                log.player(scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, damage, 1800)
+                        .team(Team::Red)
+                })
+            })
+            .build();
+        db.add_log(log).unwrap();
+
+        // The player whose class cannot be read is left out.
+        db.client
+            .execute(
+                "UPDATE aggregates_cache SET class = 100 WHERE steam_id = $1",
+                &[&(scouts[2].id64() as i64)],
+            )
+            .unwrap();
+        let leaderboard = db
+            .normalized_leaderboard(Metric::DamagePerMinute, 1, 10)
+            .unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|&(player, ..)| player)
+                .collect::<Vec<_>>(),
+            scouts[..2]
+        );
+    }
+
+    #[test]
+    fn aggregate_overflow()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:1]").unwrap();
+        let other_scout = SteamID::from_str("[U:1:2]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_user(other_scout, 2).unwrap();
+        // Close to the most a single game can store, which adds up to far more
+        // than fits into 32 bits over all games.
+        let damage = 2_000_000_000;
+        for id in 1..=50 {
+            let log = LogBuilder::new(LogId(id))
+                .date(at(1800 * i64::from(id)))
+                .player(scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, damage, 1800)
+                        .team(Team::Red)
+                })
+                .player(other_scout, |player| {
+                    player
+                        .class(Class::Scout, 10, 10, damage / 2, 1800)
+                        .team(Team::Red)
+                })
+                .build();
+            db.add_log(log).unwrap();
+        }
+
+        let cached_damage: i64 = db
+            .client
+            .query_one(
+                "SELECT SUM(damage)::bigint FROM aggregates_cache WHERE steam_id = $1",
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap()
+            .get(0);
+        assert_eq!(cached_damage, 50 * i64::from(damage));
+
+        let dpm = Metric::DamagePerMinute;
+        let leaderboard = db.normalized_leaderboard(dpm, 1, 10).unwrap();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|&(player, ..)| player)
+                .collect::<Vec<_>>(),
+            vec![scout, other_scout]
+        );
+        // Two players are one standard deviation apart.
+        assert!((leaderboard[0].2 - 0.5_f64.sqrt()).abs() < 1e-9);
+        for include_suspect in [false, true] {
+            assert_eq!(
+                db.percentile_for_metric(scout, Class::Scout, dpm, 1, include_suspect, 0)
+                    .unwrap(),
+                Some(1.)
+            );
+        }
+
+        // All games are played back to back, so they are a single session.
+        let sessions = report::session_summary(&mut db, scout, Duration::hours(1)).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].dpm, Some(damage as f32 / 30.));
+
+        // A stat that does not fit into its column is not stored wrapped around.
+        let log = LogBuilder::new(LogId(51))
+            .player(scout, |player| {
+                player.class(Class::Scout, 10, 10, u32::MAX, 1800)
+            })
+            .build();
+        match db.add_log(log) {
+            Err(MixesDbError::InvalidInput(e)) => {
+                assert!(e.contains(&format!("damage of {} is too large", u32::MAX)))
+            },
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(!db.has_log(LogId(51)).unwrap());
+    }
+
+    #[test]
+    fn suspect_durations()
+    {
+        let mut db = test_db().max_duration_secs(2000);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let mut json = fixture_json(at(2000));
+        json["info"]["total_length"] = 2001.into();
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+        json["info"].remove("total_length");
+        json["rounds"] = JsonValue::new_array();
+        db.add_log(Log::from_json(LogId(3), &json).unwrap())
+            .unwrap();
+        // Without a duration, the rounds are used.
+        let mut json = fixture_json(at(4000));
+        json["info"].remove("total_length");
+        db.add_log(Log::from_json(LogId(4), &json).unwrap())
+            .unwrap();
+
+        let suspect = |db: &mut SQLDb| -> Vec<LogId> {
+            db.client
+                .query(
+                    "SELECT log_id FROM logs WHERE duration_suspect ORDER BY log_id",
+                    &[],
+                )
+                .unwrap()
+                .iter()
+                .map(|row| row.get(0))
+                .collect()
+        };
+        assert_eq!(suspect(&mut db), vec![LogId(2), LogId(3)]);
+
+        // Suspect logs only count for the number of games when included.
+        let dpm = Metric::DamagePerMinute;
+        assert_eq!(
+            db.percentile_for_metric(scout, Class::Scout, dpm, 3, false, 0)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            db.percentile_for_metric(scout, Class::Scout, dpm, 3, true, 0)
+                .unwrap(),
+            Some(0.)
+        );
+    }
+
+    #[test]
+    fn aggregates_cache()
+    {
+        let mut db = test_db().store_unregistered(true).max_duration_secs(2000);
+        let players = [
+            "[U:1:886717065]",
+            "[U:1:838576271]",
+            "[U:1:71020853]",
+            "[U:1:142158386]",
+        ]
+        .map(|id| SteamID::from_str(id).unwrap());
+        for (discord_id, player) in players.into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        let start = Utc::now() - chrono::Duration::days(30);
+        let date = |secs| start + chrono::Duration::seconds(secs);
+        for id in 1..=4 {
+            db.add_log(fixture_log(id, date(1000 * id as i64))).unwrap();
+        }
+        let mut json = fixture_json(date(5000));
+        json["info"]["total_length"] = 2001.into();
+        db.add_log(Log::from_json(LogId(5), &json).unwrap())
+            .unwrap();
+        let old = fixture_log(6, Utc::now() - chrono::Duration::days(400));
+        db.add_log(old).unwrap();
+
+        let cached = |db: &mut SQLDb| -> Vec<String> {
+            db.client
+                .query(
+                    "SELECT aggregates_cache::text FROM aggregates_cache ORDER BY steam_id, \
+                     class, season_id",
+                    &[],
+                )
+                .unwrap()
+                .iter()
+                .map(|row| row.get(0))
+                .collect()
+        };
+        // The percentiles read from the cache match those computed from the
+        // stats, which a minimum time played forces.
+        let check = |db: &mut SQLDb| {
+            let before = cached(db);
+            assert!(!before.is_empty());
+            db.rebuild_aggregates().unwrap();
+            assert_eq!(cached(db), before);
+
+            for player in players {
+                for (class, metric) in [
+                    (Class::Scout, Metric::DamagePerMinute),
+                    (Class::Scout, Metric::DamageTakenPerMinute),
+                    (Class::Soldier, Metric::KillsPerMinute),
+                    (Class::Medic, Metric::HealingPerMinute),
+                    (Class::Medic, Metric::UberRate),
+                    (Class::Scout, Metric::DamagePerHeal),
+                ] {
+                    let percentile = |db: &mut SQLDb, min_time| {
+                        db.percentile_for_metric(player, class, metric, 2, false, min_time)
+                            .unwrap()
+                    };
+                    assert_eq!(percentile(db, 0), percentile(db, 1), "{:?}", metric);
+                }
+            }
+        };
+        check(&mut db);
+
+        db.create_season("Season", date(2500), None).unwrap();
+        db.assign_unmatched_logs_to_seasons().unwrap();
+        check(&mut db);
+        db.close_season(date(3500)).unwrap();
+        check(&mut db);
+
+        db.purge_player_data(players[3], false).unwrap();
+        check(&mut db);
+        let policy = RetentionPolicy {
+            max_age_days: Some(365),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(db.apply_retention(policy).unwrap().logs, 1);
+        check(&mut db);
+        assert!(!db
+            .normalized_leaderboard(Metric::DamagePerMinute, 2, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn duration_round_trip()
+    {
+        let mut db = test_db();
+        let mut json = fixture_json(at(2000));
+        json["info"]["total_length"] = 3661.into();
+        let long = Log::from_json(LogId(2), &json).unwrap();
+        assert_eq!(long.duration(), chrono::Duration::seconds(3661));
+        assert_eq!(long.meta().duration_secs, Some(3661));
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(long).unwrap();
+
+        // The duration can be read as an interval when querying the database.
+        let durations: Vec<(String, i32)> = db
+            .client
+            .query(
+                "SELECT duration::text, extract(epoch FROM duration)::int FROM logs ORDER BY \
+                 log_id",
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        assert_eq!(
+            durations,
+            vec![("00:28:58".to_owned(), 1738), ("01:01:01".to_owned(), 3661)]
+        );
+
+        let metas: Vec<LogMetadata> = db
+            .log_performances_between(at(0), at(3000))
+            .unwrap()
+            .into_iter()
+            .map(|(meta, _)| meta)
+            .collect();
+        assert_eq!(metas[0].duration_secs, Some(1738));
+        assert_eq!(metas[1].duration(), Some(chrono::Duration::seconds(3661)));
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let stored = db
+            .get_class_performance_structured(scout, Class::Scout, 10, None, None)
+            .unwrap();
+        assert_eq!(stored[0].1.duration_secs, Some(3661));
+
+        // Search results do not know the duration.
+        let search_result = LogMetadata {
+            duration_secs: None,
+            ..metas[0].clone()
+        };
+        assert_eq!(search_result.duration(), None);
+    }
+
+    #[test]
+    fn round_stats()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(db.round_stats(scout, 10).unwrap(), RoundStats::default());
+
+        // The team of the scout is down 0-2 and wins 3-2.
+        let mut comeback = fixture_json(at(2000));
+        comeback["teams"]["Red"]["score"] = 2.into();
+        comeback["teams"]["Blue"]["score"] = 3.into();
+        comeback["rounds"] = ["Red", "Red", "Blue", "Blue", "Blue"]
+            .iter()
+            .map(|&winner| json::object! { "winner": winner, "length": 300 })
+            .collect::<Vec<_>>()
+            .into();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(Log::from_json(LogId(2), &comeback).unwrap())
+            .unwrap();
+        // Stored before the rounds were.
+        db.add_log(fixture_log(3, at(3000))).unwrap();
+        db.client
+            .execute("UPDATE logs SET round_winners = NULL WHERE log_id = 3", &[])
+            .unwrap();
+
+        assert_eq!(
+            db.round_stats(scout, 10).unwrap(),
+            RoundStats {
+                num_games:          3,
+                round_differential: Some(1. / 3.),
+                num_timelines:      2,
+                comeback_wins:      Some(1),
+            }
+        );
+        let red = SteamID::from_str("[U:1:838576271]").unwrap();
+        assert_eq!(db.round_stats(red, 10).unwrap().comeback_wins, Some(0));
+        assert_eq!(
+            db.round_stats(scout, 1).unwrap(),
+            RoundStats {
+                num_games:          1,
+                round_differential: Some(0.),
+                num_timelines:      0,
+                comeback_wins:      None,
+            }
+        );
+
+        // A game with a team that cannot be read still counts, without its timeline.
+        db.client
+            .execute(
+                "UPDATE overall_stats SET team = 100 WHERE log_id = 2 AND steam_id = $1",
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap();
+        assert_eq!(
+            db.round_stats(scout, 10).unwrap(),
+            RoundStats {
+                num_games:          3,
+                round_differential: Some(1. / 3.),
+                num_timelines:      1,
+                comeback_wins:      Some(0),
+            }
+        );
+    }
+
+    #[test]
+    fn carry_stats()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(db.carry_stats(scout, 10).unwrap(), CarryStats::default());
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        // Stats stored before the shares were recorded do not have any.
+        db.client
+            .execute(
+                "UPDATE overall_stats SET damage_share = NULL, kill_participation = NULL WHERE \
+                 log_id = 1",
+                &[],
+            )
+            .unwrap();
+
+        // Blue has dealt 41358 damage and 111 kills, of which the scout has dealt
+        // 7090 damage and taken part in 20 kills and 16 assists.
+        let stats = db.carry_stats(scout, 10).unwrap();
+        assert_eq!(stats.num_games, 2);
+        assert!((stats.damage_share.unwrap() - 7090. / 41358.).abs() < 1e-6);
+        assert!((stats.kill_participation.unwrap() - 36. / 111.).abs() < 1e-6);
+
+        let stats = db.carry_stats(scout, 1).unwrap();
+        assert_eq!(stats.num_games, 1);
+        assert!(stats.damage_share.is_some());
+        // Without the newest game, only the one without shares is left.
+        db.client
+            .execute("DELETE FROM overall_stats WHERE log_id = 2", &[])
+            .unwrap();
+        let stats = db.carry_stats(scout, 1).unwrap();
+        assert_eq!(stats.num_games, 1);
+        assert_eq!(stats.damage_share, None);
+    }
+
+    #[test]
+    fn recent_games()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        assert_eq!(
+            db.recent_games(scout, 10, &TagFilter::default()).unwrap(),
+            Vec::new()
+        );
+
+        // Both players are on blue. The last two games have been played at the
+        // same time, the last one ending in the 3:3 of the fixture.
+        for (id, date, map, score) in [
+            (1, 1000, "cp_gullywash_f9", Some((2, 5))),
+            (2, 2000, "cp_process_f12", Some((3, 1))),
+            (3, 2000, "cp_sunshine", None),
+        ] {
+            let mut json = fixture_json(at(date));
+            json["info"]["map"] = map.into();
+            if let Some((red, blue)) = score {
+                json["teams"]["Red"]["score"] = JsonValue::from(red);
+                json["teams"]["Blue"]["score"] = JsonValue::from(blue);
+            }
+            db.add_log(Log::from_json(LogId(id), &json).unwrap())
+                .unwrap();
+        }
+
+        // The scout has played scout for 1618 seconds, dealing 6671 damage with 19
+        // kills and 16 deaths, and a bit of engineer and pyro.
+        let scout_game = |id, date, map: &str, won_rounds, lost_rounds| GameSummary {
+            log_id: LogId(id),
+            date_time: at(date),
+            map: map.to_owned(),
+            main_class: Some(Class::Scout),
+            won_rounds,
+            lost_rounds,
+            dpm: Some(6671. * 60. / 1618.),
+            kill_death_ratio: Some(19. / 16.),
+            healing: None,
+        };
+        let games = db.recent_games(scout, 10, &TagFilter::default()).unwrap();
+        assert_eq!(
+            games,
+            vec![
+                scout_game(3, 2000, "cp_sunshine", 3, 3),
+                scout_game(2, 2000, "cp_process_f12", 1, 3),
+                scout_game(1, 1000, "cp_gullywash_f9", 5, 2),
+            ]
+        );
+        assert_eq!(
+            games.iter().map(GameSummary::outcome).collect::<Vec<_>>(),
+            vec![GameOutcome::Tie, GameOutcome::Loss, GameOutcome::Win]
+        );
+        assert_eq!(
+            db.recent_games(scout, 2, &TagFilter::default()).unwrap(),
+            games[..2]
+        );
+
+        assert_eq!(
+            db.recent_games(medic, 1, &TagFilter::default()).unwrap(),
+            vec![GameSummary {
+                log_id:           LogId(3),
+                date_time:        at(2000),
+                map:              "cp_sunshine".to_owned(),
+                main_class:       Some(Class::Medic),
+                won_rounds:       3,
+                lost_rounds:      3,
+                dpm:              Some(352. * 60. / 1738.),
+                kill_death_ratio: Some(0.1),
+                healing:          Some(22732),
+            }]
+        );
+
+        // A game with a class that cannot be read is left out.
+        db.client
+            .execute(
+                "UPDATE dm_stats SET class = 100 WHERE log_id = 2 AND steam_id = $1",
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap();
+        let games = db.recent_games(scout, 10, &TagFilter::default()).unwrap();
+        assert_eq!(
+            games.iter().map(|game| game.log_id).collect::<Vec<_>>(),
+            vec![LogId(3), LogId(1)]
+        );
+    }
+
+    #[test]
+    fn log_rosters()
+    {
+        let mut db = test_db();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+
+        let rosters = db.log_rosters(LogId(1)).unwrap();
+        assert_eq!(rosters.len(), 2);
+        assert_eq!(rosters[&Team::Red].len(), 6);
+        assert_eq!(rosters[&Team::Blue].len(), 6);
+        assert!(rosters[&Team::Blue].contains(&SteamID::from_str("[U:1:886717065]").unwrap()));
+        assert!(rosters[&Team::Red].contains(&SteamID::from_str("[U:1:838576271]").unwrap()));
+        assert!(rosters[&Team::Red]
+            .windows(2)
+            .all(|pair| pair[0].id64() < pair[1].id64()));
+
+        // A player with a team that cannot be read is left out.
+        db.client
+            .execute(
+                "UPDATE overall_stats SET team = 100 WHERE steam_id = $1",
+                &[&(SteamID::from_str("[U:1:886717065]").unwrap().id64() as i64)],
+            )
+            .unwrap();
+        assert_eq!(db.log_rosters(LogId(1)).unwrap()[&Team::Blue].len(), 5);
+        assert_eq!(db.team_summary(LogId(1)).unwrap().len(), 2);
+
+        assert!(db.log_rosters(LogId(2)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn team_summary()
+    {
+        let mut db = test_db();
+        let json = fixture_json(at(1000)).dump();
+        let parsed = Log::parse(LogId(1), &json).unwrap().team_summary();
+        db.add_log_from_json(LogId(1), &json).unwrap();
+
+        let stored = db.team_summary(LogId(1)).unwrap();
+        assert_eq!(stored.len(), 2);
+        for team in [Team::Red, Team::Blue] {
+            let (stored, parsed) = (&stored[&team], &parsed[&team]);
+            assert_eq!(stored.damage, parsed.damage);
+            assert_eq!(stored.healing, parsed.healing);
+            assert_eq!(stored.num_ubers, parsed.num_ubers);
+            assert_eq!(stored.rounds, parsed.rounds);
+        }
+        assert_eq!(stored[&Team::Blue].rounds.len(), 7);
+        assert_eq!(stored[&Team::Blue].rounds[6], None);
+
+        // A tie without any rounds, like a log stored before the rounds were.
+        let scout = SteamID::from_str("[U:1:1]").unwrap();
+        let log = LogBuilder::new(LogId(2))
+            .score(0, 0)
+            .player(scout, |player| {
+                player.team(Team::Red).class(Class::Scout, 1, 1, 500, 600)
+            })
+            .build();
+        db.add_log(log).unwrap();
+        let summaries = db.team_summary(LogId(2)).unwrap();
+        assert_eq!(summaries[&Team::Red].damage, 500);
+        assert!(summaries[&Team::Red].rounds.is_empty());
+        db.client
+            .execute("UPDATE logs SET round_winners = NULL", &[])
+            .unwrap();
+        assert!(db.team_summary(LogId(1)).unwrap()[&Team::Red]
+            .rounds
+            .is_empty());
+
+        assert!(db.team_summary(LogId(3)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn class_teams()
+    {
+        let mut db = test_db();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        // The rows of the classes that have a different team than the overall
+        // stats of their player, or none at all.
+        let mismatched = |db: &mut SQLDb, table: &str| -> i64 {
+            db.client
+                .query_one(
+                    format!(
+                        "SELECT COUNT(*) FROM {0} LEFT JOIN overall_stats stats ON stats.log_id = \
+                         {0}.log_id AND stats.steam_id = {0}.steam_id WHERE {0}.team IS DISTINCT \
+                         FROM stats.team OR {0}.team IS NULL",
+                        table
+                    )
+                    .as_str(),
+                    &[],
+                )
+                .unwrap()
+                .get(0)
+        };
+
+        // New logs store the team with the classes.
+        let stored = db
+            .stored_performance(LogId(1), medic.id64() as i64)
+            .unwrap();
+        assert_eq!(stored.overall.unwrap().team, Some(Team::Blue));
+        assert!(stored.dm.iter().all(|dm| dm.team == Some(Team::Blue)));
+        assert_eq!(stored.medic.unwrap().team, Some(Team::Blue));
+        assert_eq!(mismatched(&mut db, "dm_stats"), 0);
+        assert_eq!(mismatched(&mut db, "med_stats"), 0);
+
+        // Stats stored before the team was are filled in from the overall
+        // stats. Classes without overall stats are left without a team.
+        db.client
+            .batch_execute(
+                "ALTER TABLE dm_stats DROP COLUMN team;
+                ALTER TABLE med_stats DROP COLUMN team;
+                UPDATE meta SET value = '25' WHERE key = 'schema_version';",
+            )
+            .unwrap();
+        db.client
+            .execute(
+                "DELETE FROM overall_stats WHERE steam_id = $1",
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap();
+        db.init_tables().unwrap();
+        let stored = db
+            .stored_performance(LogId(1), medic.id64() as i64)
+            .unwrap();
+        assert!(stored.dm.iter().all(|dm| dm.team == Some(Team::Blue)));
+        assert_eq!(stored.medic.unwrap().team, Some(Team::Blue));
+        let stored = db
+            .stored_performance(LogId(1), scout.id64() as i64)
+            .unwrap();
+        assert_eq!(stored.dm.len(), 3);
+        assert!(stored.dm.iter().all(|dm| dm.team.is_none()));
+        assert_eq!(mismatched(&mut db, "dm_stats"), 3);
+        assert_eq!(mismatched(&mut db, "med_stats"), 0);
+    }
+
+    #[test]
+    fn dates_round_trip()
+    {
+        // Sessions are in UTC, no matter what the connection asks for.
+        let params = format!("{} options='-c TimeZone=America/New_York'", TEST_DB_PARAMS);
+        let mut db = SQLDb::temporary(&params).unwrap();
+        let time_zone: String = db.client.query_one("SHOW TIME ZONE", &[]).unwrap().get(0);
+        assert_eq!(time_zone, "UTC");
+
+        // Dates keep their instant in other time zones too. When the clocks in
+        // Berlin have been set back on 2021-10-31, both of the first two dates
+        // were 02:30 local time. The last one is before 1970.
+        db.client
+            .batch_execute("SET TIME ZONE 'Europe/Berlin'")
+            .unwrap();
+        let dates = [
+            Utc.with_ymd_and_hms(2021, 10, 31, 0, 30, 0).unwrap(),
+            Utc.with_ymd_and_hms(2021, 10, 31, 1, 30, 0).unwrap(),
+            Utc.with_ymd_and_hms(1969, 7, 20, 20, 17, 40).unwrap(),
+        ];
+        for (id, date) in (1..).zip(dates) {
+            db.add_log(fixture_log(id, date)).unwrap();
+        }
+
+        let end = dates[1] + chrono::Duration::seconds(1);
+        let logs = db.log_performances_between(dates[2], end).unwrap();
+        let restored: Vec<(LogId, DateTime<Utc>)> = logs
+            .iter()
+            .map(|(meta, _)| (meta.id, meta.date_time))
+            .collect();
+        assert_eq!(
+            restored,
+            vec![
+                (LogId(3), dates[2]),
+                (LogId(1), dates[0]),
+                (LogId(2), dates[1])
+            ]
+        );
+        assert_eq!(logs[0].0.timestamp(), -14_182_940);
+        assert_eq!(logs[2].0.timestamp() - logs[1].0.timestamp(), 60 * 60);
+        assert_eq!(db.logs_between(dates[0], dates[1]).unwrap(), vec![LogId(1)]);
+    }
+
+    #[test]
+    fn logs_between()
+    {
+        let mut db = test_db();
+        // Log ids do not have to be in the order the logs have been played in.
+        for (id, date) in [(1, 3000), (2, 1000), (3, 2000), (4, 2000), (5, 4000)] {
+            db.add_log(fixture_log(id, at(date))).unwrap();
+        }
+
+        assert_eq!(
+            db.logs_between(at(1000), at(4000)).unwrap(),
+            vec![LogId(2), LogId(3), LogId(4), LogId(1)]
+        );
+        assert_eq!(
+            db.logs_between(at(1001), at(3000)).unwrap(),
+            vec![LogId(3), LogId(4)]
+        );
+        assert!(db.logs_between(at(4000), at(1000)).unwrap().is_empty());
+
+        let logs = db.log_performances_between(at(1001), at(3000)).unwrap();
+        let ids: Vec<LogId> = logs.iter().map(|(meta, _)| meta.id).collect();
+        assert_eq!(ids, vec![LogId(3), LogId(4)]);
+        let (meta, performances) = &logs[0];
+        assert_eq!(meta.date_time, at(2000));
+        assert_eq!(performances.len(), 12);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        assert_eq!(performances[&scout].dm.len(), 3);
+    }
+
+    #[test]
+    fn title_and_uploader()
+    {
+        let mut db = test_db();
+        let mut untitled = fixture_json(at(2000));
+        untitled["info"].remove("title");
+        untitled["info"].remove("uploader");
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(Log::from_json(LogId(2), &untitled).unwrap())
+            .unwrap();
+
+        let logs = db.log_performances_between(at(0), at(3000)).unwrap();
+        assert_eq!(
+            logs[0].0.title.as_deref(),
+            Some("serveme.tf #1221399 - BLU vs RED")
+        );
+        assert_eq!(
+            logs[0].0.uploader,
+            Some(SteamID::new_checked(76561197960497430).unwrap())
+        );
+        assert_eq!(logs[1].0.title, None);
+        assert_eq!(logs[1].0.uploader, None);
+    }
+
+    #[test]
+    fn update_title_filter()
+    {
+        let filter = TitleFilter {
+            allow: vec!["serveme.tf".to_owned()],
+            deny:  vec!["free server".to_owned()],
+        };
+        let mut db = test_db().title_filter(filter);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        let mut source = MockLogSource::new();
+        for (id, title) in [
+            (1, Some("serveme.tf #1221399 - BLU vs RED")),
+            (2, Some("serveme.tf free server")),
+            (3, None),
+        ] {
+            let date = at(1000 * id as i64);
+            let meta = LogMetadata {
+                id:            LogId(id),
+                date_time:     date,
+                map:           "cp_sunshine".to_owned(),
+                num_players:   12,
+                title:         title.map(str::to_owned),
+                uploader:      None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], fixture_json(date).dump());
+        }
+
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(
+            report.skipped,
+            vec![
+                (LogId(3), SkipReason::Title(None)),
+                (
+                    LogId(2),
+                    SkipReason::Title(Some("serveme.tf free server".to_owned()))
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_logs()
+    {
+        let mut db = test_db();
+        for (id, date) in [(1, 1000), (2, 3000), (3, 2000)] {
+            db.add_log(fixture_log(id, at(date))).unwrap();
+        }
+        let official = LogTag::new("Official").unwrap();
+        let fun = LogTag::new("fun").unwrap();
+
+        assert!(db.tag_log(LogId(1), &official).unwrap());
+        assert!(db.tag_log(LogId(2), &official).unwrap());
+        assert!(db.tag_log(LogId(2), &fun).unwrap());
+        // Tags are normalized, so this is the same tag again.
+        assert!(!db.tag_log(LogId(1), &" OFFICIAL".parse().unwrap()).unwrap());
+        assert!(!db.tag_log(LogId(4), &official).unwrap());
+
+        assert_eq!(
+            db.logs_with_tag(&official).unwrap(),
+            vec![LogId(2), LogId(1)]
+        );
+        assert!(db.untag_log(LogId(2), &official).unwrap());
+        assert!(!db.untag_log(LogId(3), &official).unwrap());
+        assert_eq!(db.logs_with_tag(&official).unwrap(), vec![LogId(1)]);
+        assert_eq!(db.logs_with_tag(&fun).unwrap(), vec![LogId(2)]);
+    }
+
+    #[test]
+    fn tag_filters()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        for (id, date) in [(1, 1000), (2, 2000), (3, 3000)] {
+            db.add_log(fixture_log(id, at(date))).unwrap();
+        }
+        let [official, fun] = ["official", "fun"].map(|tag| LogTag::new(tag).unwrap());
+        db.tag_log(LogId(1), &official).unwrap();
+        db.tag_log(LogId(2), &official).unwrap();
+        db.tag_log(LogId(2), &fun).unwrap();
+
+        let logs = |db: &mut SQLDb, tags: TagFilter| {
+            let options = QueryOptions {
+                tags,
+                ..QueryOptions::default()
+            };
+            log_ids(
+                &db.get_classes_performance(scout, &[Class::Scout], 10, &options)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            logs(&mut db, TagFilter::default()),
+            vec![LogId(3), LogId(2), LogId(1)]
+        );
+        assert_eq!(
+            logs(
+                &mut db,
+                TagFilter::include(vec![official.clone(), fun.clone()])
+            ),
+            vec![LogId(2), LogId(1)]
+        );
+        assert_eq!(
+            logs(&mut db, TagFilter::exclude(vec![fun.clone()])),
+            vec![LogId(3), LogId(1)]
+        );
+        // Excluded tags win over included ones.
+        let filter = TagFilter {
+            include: vec![official],
+            exclude: vec![fun.clone()],
+        };
+        assert_eq!(logs(&mut db, filter), vec![LogId(1)]);
+
+        let games = db
+            .recent_games(scout, 10, &TagFilter::exclude(vec![fun]))
+            .unwrap();
+        assert_eq!(
+            games.iter().map(|game| game.log_id).collect::<Vec<_>>(),
+            vec![LogId(3), LogId(1)]
+        );
+    }
+
+    #[test]
+    fn external_matches()
+    {
+        let mut db = test_db();
+        for (id, date) in [(1, 1000), (2, 3000), (3, 2000)] {
+            db.add_log(fixture_log(id, at(date))).unwrap();
+        }
+        let other = League::new("Local Cup").unwrap();
+
+        // The maps of a match each have their own log.
+        assert!(db
+            .link_external_match(LogId(1), &League::Etf2l, 1234)
+            .unwrap());
+        assert!(db
+            .link_external_match(LogId(2), &League::Etf2l, 1234)
+            .unwrap());
+        assert!(db.link_external_match(LogId(3), &other, 1234).unwrap());
+        assert!(!db
+            .link_external_match(LogId(4), &League::Etf2l, 1234)
+            .unwrap());
+        assert_eq!(
+            db.logs_for_match(&League::Etf2l, 1234).unwrap(),
+            vec![LogId(2), LogId(1)]
+        );
+        assert_eq!(db.logs_for_match(&other, 1234).unwrap(), vec![LogId(3)]);
+        assert_eq!(db.logs_for_match(&League::Rgl, 1234).unwrap(), Vec::new());
+        assert_eq!(
+            db.external_match(LogId(3)).unwrap(),
+            Some(ExternalMatch {
+                league:   other,
+                match_id: 1234,
+            })
+        );
+
+        // Linking a log again replaces its match.
+        assert!(db.link_external_match(LogId(2), &League::Rgl, 99).unwrap());
+        assert_eq!(
+            db.logs_for_match(&League::Etf2l, 1234).unwrap(),
+            vec![LogId(1)]
+        );
+        assert_eq!(db.logs_for_match(&League::Rgl, 99).unwrap(), vec![LogId(2)]);
+        assert_eq!(
+            db.external_match(LogId(2)).unwrap(),
+            Some(ExternalMatch {
+                league:   League::Rgl,
+                match_id: 99,
+            })
+        );
+        assert_eq!(db.external_match(LogId(4)).unwrap(), None);
+        // A league that cannot be read is left out.
+        db.client
+            .execute("UPDATE logs SET league = ' ' WHERE log_id = 2", &[])
+            .unwrap();
+        assert_eq!(db.external_match(LogId(2)).unwrap(), None);
+        db.client
+            .execute("UPDATE logs SET league = 'rgl' WHERE log_id = 2", &[])
+            .unwrap();
+
+        assert!(matches!(
+            db.link_external_match(LogId(1), &League::Rgl, u64::MAX),
+            Err(MixesDbError::InvalidInput(e)) if e.contains("too large")
+        ));
+        assert_eq!(
+            db.logs_for_match(&League::Rgl, u64::MAX).unwrap(),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn official_filters()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        for (id, date) in [(1, 1000), (2, 2000), (3, 3000)] {
+            db.add_log(fixture_log(id, at(date))).unwrap();
+        }
+        db.link_external_match(LogId(2), &League::Ozfortress, 7)
+            .unwrap();
+
+        let logs = |db: &mut SQLDb, officials| {
+            let options = QueryOptions {
+                officials,
+                ..QueryOptions::default()
+            };
+            log_ids(
+                &db.get_classes_performance(scout, &[Class::Scout], 10, &options)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            logs(&mut db, OfficialFilter::Include),
+            vec![LogId(3), LogId(2), LogId(1)]
+        );
+        assert_eq!(
+            logs(&mut db, OfficialFilter::Exclude),
+            vec![LogId(3), LogId(1)]
+        );
+        assert_eq!(logs(&mut db, OfficialFilter::Only), vec![LogId(2)]);
+    }
+
+    #[test]
+    fn update_tag()
+    {
+        let tag = LogTag::new("mix").unwrap();
+        let mut db = test_db().search_batch_size(1).update_tag(Some(tag.clone()));
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        // Only known from the history of the scout, so it is not downloaded again.
+        db.insert_log(
+            fixture_log(4, at(4000)),
+            IngestReason::PersonalBackfill,
+            None,
+        )
+        .unwrap();
+
+        let mut source = MockLogSource::new();
+        let meta = |id, num_players| LogMetadata {
+            id: LogId(id),
+            date_time: at(1000 * id as i64),
+            map: "cp_sunshine".to_owned(),
+            num_players,
+            title: None,
+            uploader: None,
+            duration_secs: None,
+        };
+        for (id, num_players) in [(1, 12), (2, 12), (3, 6), (4, 12)] {
+            let json = fixture_json(at(1000 * id as i64)).dump();
+            source.add_log(meta(id, num_players), vec![scout], json);
+        }
+        // Uploaded during the update, so only added by the reconciliation
+        source.add_log_after(1, meta(5, 12), vec![scout], fixture_json(at(5000)).dump());
+
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(4), LogId(2), LogId(5)]);
+        // Logs that have been added before the update are left alone.
+        let mut tagged = db.logs_with_tag(&tag).unwrap();
+        tagged.sort();
+        assert_eq!(tagged, vec![LogId(2), LogId(4), LogId(5)]);
+        assert_eq!(db.update_history(1).unwrap()[0].tag.as_ref(), Some(&tag));
+    }
+
+    #[test]
+    fn update_around_watermark()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        assert_eq!(db.max_log_id().unwrap(), None);
+
+        // Logs far below the watermark are looked up in the database, the others
+        // in the recently added mixes.
+        let watermark = 100_000;
+        let old = watermark - RECENT_LOGS_MARGIN - 1;
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.insert_log(
+            fixture_log(old, at(2000)),
+            IngestReason::PersonalBackfill,
+            None,
+        )
+        .unwrap();
+        db.add_log(fixture_log(watermark - 1, at(3000))).unwrap();
+        db.add_log(fixture_log(watermark, at(4000))).unwrap();
+        assert_eq!(db.max_log_id().unwrap(), Some(LogId(watermark)));
+
+        let mut source = MockLogSource::new();
+        for (i, id) in [
+            1,
+            2,
+            old,
+            watermark - 2,
+            watermark - 1,
+            watermark,
+            watermark + 1,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let date = at(1000 * (i as i64 + 1));
+            let meta = LogMetadata {
+                id:            LogId(id),
+                date_time:     date,
+                map:           "cp_sunshine".to_owned(),
+                num_players:   12,
+                title:         None,
+                uploader:      None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], fixture_json(date).dump());
+        }
+
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        let new_logs = [LogId(watermark + 1), LogId(watermark - 2), LogId(2)];
+        assert_eq!(source.downloaded(), new_logs);
+        // The backfilled log is marked as a mix without downloading it again.
+        assert_eq!(
+            report.added,
+            [
+                LogId(watermark + 1),
+                LogId(watermark - 2),
+                LogId(old),
+                LogId(2)
+            ]
+        );
+        assert_eq!(db.max_log_id().unwrap(), Some(LogId(watermark + 1)));
+
+        // Databases from before the watermark get it from their logs.
+        db.client
+            .execute("DELETE FROM meta WHERE key = 'max_log_id'", &[])
+            .unwrap();
+        db.init_tables().unwrap();
+        assert_eq!(db.max_log_id().unwrap(), Some(LogId(watermark + 1)));
+
+        // Removed logs still count for the watermark.
+        db.purge_player_data(scout, true).unwrap();
+        assert_eq!(db.max_log_id().unwrap(), Some(LogId(watermark + 1)));
+    }
+
+    #[test]
+    fn update_with_options()
+    {
+        let mut db = test_db()
+            .search_batch_size(1)
+            .update_tag(Some(LogTag::new("mix").unwrap()));
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        let mut source = MockLogSource::new();
+        for (id, num_players) in [(1, 12), (2, 6), (3, 6)] {
+            let date = at(1000 * id as i64);
+            let meta = LogMetadata {
+                id: LogId(id),
+                date_time: date,
+                map: "cp_sunshine".to_owned(),
+                num_players,
+                title: None,
+                uploader: None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], fixture_json(date).dump());
+        }
+
+        let fun = LogTag::new("fun").unwrap();
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let progress_copy = Arc::clone(&progress);
+        let options = UpdateOptions::builder()
+            .min_ratio(0.)
+            .num_players(6..=6)
+            .tag(fun.clone())
+            .progress(move |update| progress_copy.lock().unwrap().push(update))
+            .build()
+            .unwrap();
+        let report = db.update_with(&source, options).unwrap();
+
+        assert_eq!(report.added, vec![LogId(3), LogId(2)]);
+        assert_eq!(
+            report.skipped,
+            vec![(LogId(1), SkipReason::PlayerCount(12))]
+        );
+        // The tag of the options replaces the one of the database.
+        assert_eq!(db.logs_with_tag(&fun).unwrap(), vec![LogId(3), LogId(2)]);
+        assert_eq!(
+            db.logs_with_tag(&LogTag::new("mix").unwrap()).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            *progress.lock().unwrap(),
+            vec![
+                UpdateProgress {
+                    done:      1,
+                    total:     2,
+                    cancelled: false,
+                },
+                UpdateProgress {
+                    done:      2,
+                    total:     2,
+                    cancelled: false,
+                },
+            ]
+        );
+
+        let error = db.update(1.5, 12..=13).unwrap_err();
+        assert!(matches!(error, MixesDbError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn update_health_check()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        let mut source = MockLogSource::new();
+        let meta = LogMetadata {
+            id:            LogId(1),
+            date_time:     at(1000),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+        source.add_log(meta, vec![scout], fixture_json(at(1000)).dump());
+        let options = || {
+            UpdateOptions::builder()
+                .min_ratio(0.)
+                .health_check()
+                .build()
+                .unwrap()
+        };
+
+        // Nothing is searched or recorded if the source is down.
+        source.set_health(Health::Down("maintenance".to_owned()));
+        assert!(matches!(
+            db.update_from(&source, options()),
+            Err(DbError::UpstreamUnavailable(problem)) if problem == "maintenance"
+        ));
+        assert_eq!(source.num_searches(), 0);
+        assert!(db.update_history(10).unwrap().is_empty());
+        assert!(db.known_logs().unwrap().is_empty());
+        assert_eq!(db.status().unwrap().last_update, None);
+
+        // A degraded source is still updated from, only slower.
+        let degraded = Health::Degraded(Degradation::ServerError(503));
+        source.set_health(degraded.clone());
+        let report = db.update_from(&source, options()).unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(report.health, Some(degraded));
+
+        // Without the option, the health is not checked.
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        assert_eq!(report.health, None);
+    }
+
+    #[test]
+    fn reconcile_update()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let players = [
+            SteamID::from_str("[U:1:886717065]").unwrap(),
+            SteamID::from_str("[U:1:71020853]").unwrap(),
+        ];
+        for (discord_id, player) in players.into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        let meta = |id| LogMetadata {
+            id:            LogId(id),
+            date_time:     at(1000 * id as i64),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+
+        // The second log is uploaded after the first player has been searched,
+        // so only the second player finds it, which is not enough for the ratio.
+        let mut source = MockLogSource::new();
+        let json = fixture_json(at(1000)).dump();
+        source.add_log(meta(1), players.to_vec(), json.clone());
+        source.add_log_after(1, meta(2), players.to_vec(), json.clone());
+        let report = db.update_with(&source, update_options(0.15)).unwrap();
+        assert_eq!(report.added, vec![LogId(1), LogId(2)]);
+        assert_eq!(report.reconciled, vec![LogId(2)]);
+        assert_eq!(report.skipped, vec![]);
+        assert_eq!(source.downloaded(), vec![LogId(1), LogId(2)]);
+        let run = db.update_history(1).unwrap().remove(0);
+        assert_eq!((run.num_candidates, run.num_added), (2, 2));
+
+        // Logs found by the first searches are not added again.
+        let report = db.update_with(&source, update_options(0.15)).unwrap();
+        assert_eq!(
+            report,
+            UpdateReport {
+                run_id: report.run_id,
+                duration: report.duration,
+                ..UpdateReport::default()
+            }
+        );
+        assert_eq!(source.downloaded(), vec![LogId(1), LogId(2)]);
+
+        // Batched searches miss the log entirely until the reconciliation.
+        let mut db = test_db();
+        db.add_user(players[0], 1).unwrap();
+        let mut source = MockLogSource::new();
+        source.add_log_after(1, meta(3), players.to_vec(), json);
+        let report = db.update_with(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(3)]);
+        assert_eq!(report.reconciled, vec![LogId(3)]);
+        assert_eq!(source.downloaded(), vec![LogId(3)]);
+    }
+
+    #[test]
+    fn update_players()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        for (discord_id, player) in [scout, medic].into_iter().enumerate() {
+            db.add_user(player, discord_id as u64).unwrap();
+        }
+        let meta = |id| LogMetadata {
+            id:            LogId(id),
+            date_time:     at(1000 * id as i64),
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+
+        // Only the logs of the scout are searched, but the medic, who has not
+        // been searched, still counts towards the ratio.
+        let mut source = MockLogSource::new();
+        let json = fixture_json(at(1000)).dump();
+        source.add_log(meta(1), vec![scout, medic], json.clone());
+        source.add_log(meta(2), vec![medic], json.clone());
+        let report = db
+            .update_players(&source, &[scout, scout], update_options(0.15))
+            .unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+        assert_eq!(report.skipped, vec![]);
+        assert_eq!(source.downloaded(), vec![LogId(1)]);
+        assert_eq!(db.status().unwrap().last_update, None);
+
+        // Logs without enough registered players are skipped as usual.
+        source.add_log(meta(3), vec![scout], json);
+        let report = db
+            .update_players(&source, &[scout], update_options(0.2))
+            .unwrap();
+        assert_eq!(report.added, vec![]);
+        assert_eq!(
+            report.skipped,
+            vec![(LogId(3), SkipReason::NotEnoughMixesPlayers)]
+        );
+
+        // A full update with a lower ratio adds the skipped log, and also finds
+        // the log of the medic.
+        let report = db.update_with(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(3), LogId(2)]);
+        assert!(db.status().unwrap().last_update.is_some());
+
+        let unregistered = [
+            SteamID::from_str("[U:1:2]").unwrap(),
+            SteamID::from_str("[U:1:1]").unwrap(),
+        ];
+        let users = [scout, unregistered[0], unregistered[1]];
+        let error = db
+            .update_players(&source, &users, update_options(0.))
+            .unwrap_err();
+        assert!(matches!(&error, MixesDbError::UnregisteredPlayers(players)
+            if *players == [unregistered[1], unregistered[0]]));
+        assert_eq!(
+            error.to_string(),
+            "Not registered: 76561197960265729, 76561197960265730"
+        );
+        let error = db
+            .update_players(&source, &[], update_options(0.))
+            .unwrap_err();
+        assert!(matches!(error, MixesDbError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn cancel_update()
+    {
+        let mut db = test_db().search_batch_size(1);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        let mut source = MockLogSource::new();
+        for id in 1..=5 {
+            let date = at(1000 * id as i64);
+            let meta = LogMetadata {
+                id:            LogId(id),
+                date_time:     date,
+                map:           "cp_sunshine".to_owned(),
+                num_players:   12,
+                title:         None,
+                uploader:      None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], fixture_json(date).dump());
+        }
+
+        // Cancel the update once the second log has been added.
+        let token = CancelToken::new();
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let (progress_copy, token_copy) = (Arc::clone(&progress), token.clone());
+        let options = UpdateOptions::builder()
+            .min_ratio(0.)
+            .progress(move |update| {
+                if update.done == 2 {
+                    token_copy.cancel();
+                }
+                progress_copy.lock().unwrap().push(update);
+            })
+            .cancel_token(token.clone())
+            .build()
+            .unwrap();
+        let report = db.update_with(&source, options).unwrap();
+
+        assert!(report.cancelled);
+        assert_eq!(report.added, vec![LogId(5), LogId(4)]);
+        assert_eq!(source.downloaded(), vec![LogId(5), LogId(4)]);
+        for id in 1..=5 {
+            assert_eq!(db.has_log(LogId(id)).unwrap(), id >= 4);
+        }
+        assert_eq!(
+            progress.lock().unwrap().last(),
+            Some(&UpdateProgress {
+                done:      2,
+                total:     5,
+                cancelled: true,
+            })
+        );
+
+        let run = db.update_history(1).unwrap().remove(0);
+        assert_eq!(Some(run.id), report.run_id);
+        assert!(run.cancelled);
+        assert!(!run.completed);
+        assert!(run.duration.is_some());
+        assert_eq!((run.num_candidates, run.num_added), (5, 2));
+
+        // An update cancelled in advance does not search for logs at all.
+        let num_searches = source.num_searches();
+        let options = UpdateOptions::builder()
+            .min_ratio(0.)
+            .cancel_token(token)
+            .build()
+            .unwrap();
+        let report = db.update_with(&source, options).unwrap();
+        assert!(report.cancelled);
+        assert_eq!(report.added, vec![]);
+        assert_eq!(source.num_searches(), num_searches);
+    }
+
+    #[test]
+    fn update_history()
+    {
+        let mix = LogTag::new("mix").unwrap();
+        let mut db = test_db().search_batch_size(1).update_tag(Some(mix.clone()));
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        assert_eq!(db.update_history(10).unwrap(), vec![]);
+
+        let mut source = MockLogSource::new();
+        for (id, num_players, json) in [
+            (1, 12, fixture_json(at(1000)).dump()),
+            (2, 6, fixture_json(at(2000)).dump()),
+            // Cannot be downloaded.
+            (3, 12, "{".to_owned()),
+        ] {
+            let meta = LogMetadata {
+                id: LogId(id),
+                date_time: at(1000 * id as i64),
+                map: "cp_sunshine".to_owned(),
+                num_players,
+                title: None,
+                uploader: None,
+                duration_secs: None,
+            };
+            source.add_log(meta, vec![scout], json);
+        }
+
+        let sixes = |num_players| {
+            UpdateOptions::builder()
+                .min_ratio(0.)
+                .num_players(num_players)
+        };
+        let first = db
+            .update_with(&source, sixes(12..=12).build().unwrap())
+            .unwrap();
+        let fun = LogTag::new("fun").unwrap();
+        let options = sixes(6..=6).tag(fun.clone()).build().unwrap();
+        let second = db.update_with(&source, options).unwrap();
+        assert_eq!(first.added, vec![LogId(1)]);
+        assert_eq!(second.added, vec![LogId(2)]);
+
+        let history = db.update_history(10).unwrap();
+        assert_eq!(history.len(), 2);
+        let (second_run, first_run) = (&history[0], &history[1]);
+        assert_eq!(Some(first_run.id), first.run_id);
+        assert_eq!(Some(second_run.id), second.run_id);
+        assert!(first_run.started_at <= second_run.started_at);
+
+        assert_eq!(first_run.min_ratio, 0.);
+        assert_eq!(first_run.num_players, 12..=12);
+        assert_eq!(first_run.tag, Some(mix));
+        assert_eq!(first_run.num_candidates, 2);
+        assert_eq!(first_run.num_added, 1);
+        assert_eq!(first_run.num_failed, 1);
+        assert!(first_run.completed);
+        assert!(first_run.duration.unwrap() >= chrono::Duration::zero());
+
+        assert_eq!(second_run.num_players, 6..=6);
+        assert_eq!(second_run.tag, Some(fun));
+        assert_eq!(second_run.num_candidates, 1);
+        assert_eq!(second_run.num_added, 1);
+        assert_eq!(second_run.num_failed, 0);
+        assert!(second_run.completed);
+
+        // A run that has stopped halfway, before it could be finished.
+        let broken = db
+            .start_update_run(&sixes(12..=13).build().unwrap())
+            .unwrap();
+        let history = db.update_history(2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].id, broken);
+        assert_eq!(history[0].tag, None);
+        assert!(!history[0].completed);
+        assert_eq!(history[0].duration, None);
+        assert_eq!(history[0].num_candidates, 0);
+        assert_eq!(history[1], *second_run);
+
+        // A tag that cannot be read is left out of the run.
+        db.client
+            .execute(
+                "UPDATE update_runs SET tag = ' ' WHERE run_id = $1",
+                &[&second_run.id.0],
+            )
+            .unwrap();
+        let history = db.update_history(2).unwrap();
+        assert_eq!(history[1].tag, None);
+        assert_eq!(history[1].num_added, 1);
+    }
+
+    #[test]
+    fn teammate_winrates()
+    {
+        let mut db = test_db();
+        let (a, b, c) = ("[U:1:1]", "[U:1:2]", "[U:1:3]");
+
+        db.add_log(teams_log(1, (3, 1), &[(a, "Red"), (b, "Red"), (c, "Blue")]))
+            .unwrap();
+        db.add_log(teams_log(
+            2,
+            (2, 2),
+            &[(a, "Red"), (b, "Blue"), (c, "Blue")],
+        ))
+        .unwrap();
+        db.add_log(teams_log(
+            3,
+            (5, 0),
+            &[(a, "Blue"), (b, "Blue"), (c, "Red")],
+        ))
+        .unwrap();
+        db.add_log(teams_log(4, (1, 0), &[(a, "Red"), (c, "Red")]))
+            .unwrap();
+        let [a, b, c] = [a, b, c].map(|id3| SteamID::from_str(id3).unwrap());
+        // A duplicate row must not be counted twice.
+        db.client
+            .execute(
+                "INSERT INTO overall_stats (log_id, steam_id, won_rounds, num_rounds, team) \
+                 SELECT log_id, steam_id, won_rounds, num_rounds, team FROM overall_stats WHERE \
+                 log_id = 1",
+                &[],
+            )
+            .unwrap();
+
+        let record = |player, num_games, wins, losses, ties| TeammateRecord {
+            player,
+            num_games,
+            wins,
+            losses,
+            ties,
+        };
+        assert_eq!(
+            db.teammate_winrates(a, 1).unwrap(),
+            vec![record(b, 2, 1, 1, 0), record(c, 1, 1, 0, 0)]
+        );
+        assert_eq!(
+            db.teammate_winrates(a, 2).unwrap(),
+            vec![record(b, 2, 1, 1, 0)]
+        );
+        assert_eq!(
+            db.opponent_winrates(a, 1).unwrap(),
+            vec![record(c, 3, 1, 1, 1), record(b, 1, 0, 0, 1)]
+        );
+        assert_eq!(
+            db.opponent_winrates(c, 1).unwrap(),
+            vec![record(a, 3, 1, 1, 1), record(b, 2, 1, 1, 0)]
+        );
+        assert_eq!(db.teammate_winrates(a, 1).unwrap()[0].win_rate(), 0.5);
+    }
+
+    #[test]
+    fn participation_stats()
+    {
+        let mut db = test_db();
+        let (medic, demo) = ("[U:1:71020853]", "[U:1:142158386]");
+        for (discord_id, user) in [
+            "[U:1:886717065]",
+            "[U:1:178408897]",
+            "[U:1:838576271]",
+            medic,
+            demo,
+            "[U:1:1]",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            db.add_user(SteamID::from_str(user).unwrap(), discord_id as u64)
+                .unwrap();
+        }
+
+        // The medic and the demo have not played the last game of the window.
+        let mut third = fixture_json(at(3000));
+        third["players"].remove(medic);
+        third["players"].remove(demo);
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        db.add_log(Log::from_json(LogId(3), &third).unwrap())
+            .unwrap();
+        db.add_log(fixture_log(4, at(5000))).unwrap();
+
+        let stats = db.participation_stats(at(0), at(4000)).unwrap();
+        assert_eq!(stats.num_players, 5);
+        assert_eq!(stats.median_games, Some(3.));
+        assert_eq!(
+            stats.games_histogram,
+            vec![(1, 0), (2, 5), (5, 0), (10, 0), (20, 0), (50, 0)]
+        );
+        // The most active player has played 3 of the 13 games.
+        assert_eq!(stats.top_fifth_share, Some(3. / 13.));
+
+        let empty = db.participation_stats(at(6000), at(7000)).unwrap();
+        assert_eq!(empty.num_players, 0);
+        assert_eq!(empty.median_games, None);
+        assert_eq!(empty.top_fifth_share, None);
+    }
+
+    #[test]
+    fn data_freshness()
+    {
+        let mut db = test_db();
+        let (scout, medic, demo) = ("[U:1:886717065]", "[U:1:71020853]", "[U:1:142158386]");
+        for (discord_id, user) in [scout, medic, demo, "[U:1:1]"].into_iter().enumerate() {
+            db.add_user(SteamID::from_str(user).unwrap(), discord_id as u64)
+                .unwrap();
+        }
+
+        let freshness = db.data_freshness(chrono::Duration::days(1)).unwrap();
+        assert_eq!(freshness.newest_log, None);
+        assert_eq!(freshness.num_stale_users, 0);
+
+        // The scout has last played ten days ago, the demoman two days ago and
+        // the medic an hour ago.
+        let now = Utc::now();
+        let days_ago = |days| at((now - chrono::Duration::days(days)).timestamp());
+        let without = |date, players: &[&str]| {
+            let mut json = fixture_json(date);
+            for player in players {
+                json["players"].remove(player);
+            }
+            json
+        };
+        let newest = at((now - chrono::Duration::hours(1)).timestamp());
+        for (id, json) in [
+            (1, fixture_json(days_ago(10))),
+            (2, without(days_ago(2), &[scout, medic])),
+            (3, without(newest, &[scout, demo])),
+        ] {
+            db.add_log(Log::from_json(LogId(id), &json).unwrap())
+                .unwrap();
+        }
+
+        let freshness = db.data_freshness(chrono::Duration::days(1)).unwrap();
+        assert_eq!(freshness.newest_log, Some(newest));
+        assert_eq!(freshness.last_update, None);
+        assert_eq!(freshness.age(now), None);
+        assert_eq!(freshness.num_stale_users, 2);
+        let freshness = db.data_freshness(chrono::Duration::days(7)).unwrap();
+        assert_eq!(freshness.num_stale_users, 1);
+
+        db.update_from(&MockLogSource::new(), update_options(0.5))
+            .unwrap();
+        let freshness = db.data_freshness(chrono::Duration::days(7)).unwrap();
+        assert!(freshness.age(Utc::now()).unwrap() < chrono::Duration::minutes(1));
+    }
+
+    #[test]
+    fn status()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        let status = db.status().unwrap();
+        assert_eq!(status.num_logs, 0);
+        assert_eq!(status.newest_log, None);
+        assert_eq!(status.last_update, None);
+        assert_eq!(status.schema_version, SCHEMA_VERSION);
+        assert_eq!(status.crate_version, env!("CARGO_PKG_VERSION"));
+
+        db.add_user(scout, 1).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        let status = db.status().unwrap();
+        assert_eq!(status.num_users, 1);
+        assert_eq!(status.num_logs, 2);
+        assert_eq!(status.newest_log, Some(at(2000)));
+        assert_eq!(status.oldest_log, Some(at(1000)));
+        assert_eq!(status.num_overall_stats, 2 * 12);
+        assert_eq!(status.num_dm_stats, 2 * 17);
+        assert_eq!(status.num_med_stats, 2 * 2);
+        assert!(status.size_bytes > 0);
+        assert_eq!(status.last_update, None);
+
+        let before_update = Utc::now();
+        db.update_from(&MockLogSource::new(), update_options(0.5))
+            .unwrap();
+        let last_update = db.status().unwrap().last_update.unwrap();
+        // The database may round the time to microseconds.
+        assert!(last_update >= before_update - chrono::Duration::milliseconds(1));
+        assert!(last_update <= Utc::now());
+    }
+
+    /// The rows of the `table` as json, in order.
+    fn table_rows(db: &mut SQLDb, table: &str) -> Vec<String>
+    {
+        db.client
+            .query(
+                format!(
+                    "SELECT row_to_json(rows)::text FROM {} rows ORDER BY 1",
+                    table
+                )
+                .as_str(),
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    }
+
+    /// A database with some of everything, and a snapshot of it.
+    fn snapshot_db() -> (SQLDb, Vec<u8>)
+    {
+        let mut db = test_db().store_chat(true);
+        db.add_user(SteamID::from_str("[U:1:886717065]").unwrap(), 1)
+            .unwrap();
+        db.add_user(SteamID::from_str("[U:1:71020853]").unwrap(), 2)
+            .unwrap();
+        db.create_season("Season 1", at(1000), None).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        db.tag_log(LogId(2), &LogTag::new("official").unwrap())
+            .unwrap();
+        db.update_from(&MockLogSource::new(), update_options(0.5))
+            .unwrap();
+
+        let mut snapshot = Vec::new();
+        let info = db.export_snapshot(&mut snapshot).unwrap();
+        assert_eq!(info.schema_version, SCHEMA_VERSION);
+        assert!(info.num_rows.contains(&("logs", 2)));
+        assert!(info.num_rows.contains(&("users", 2)));
+
+        (db, snapshot)
+    }
+
+    #[test]
+    fn snapshot_round_trip()
+    {
+        let (mut db, snapshot) = snapshot_db();
+        let exported = db.export_snapshot(&mut Vec::new()).unwrap();
+
+        let mut restored = test_db();
+        restored
+            .add_user(SteamID::from_str("[U:1:1]").unwrap(), 3)
+            .unwrap();
+        let imported = restored
+            .import_snapshot(&mut snapshot.as_slice(), ImportMode::ReplaceAll)
+            .unwrap();
+        // The version of the schema is kept from the database.
+        let mut expected = exported.num_rows;
+        expected.last_mut().unwrap().1 -= 1;
+        assert_eq!(imported.num_rows, expected);
+
+        let status = |db: &mut SQLDb| DbStatus {
+            size_bytes: 0,
+            ..db.status().unwrap()
+        };
+        assert_eq!(status(&mut restored), status(&mut db));
+        for table in [
+            "users",
+            "seasons",
+            "logs",
+            "overall_stats",
+            "dm_stats",
+            "heal_spread",
+            "chat",
+            "log_tags",
+            "player_names",
+            "update_runs",
+            "meta",
+            "aggregates_cache",
+            "personal_records",
+        ] {
+            assert_eq!(
+                table_rows(&mut restored, table),
+                table_rows(&mut db, table),
+                "{}",
+                table
+            );
+        }
+
+        // Serial ids continue after the restored ones.
+        assert!(restored.close_season(at(4000)).unwrap());
+        let season = restored
+            .create_season("Season 2", at(5000), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(season.0, 2);
+    }
+
+    #[test]
+    fn snapshot_merge()
+    {
+        let (_, snapshot) = snapshot_db();
+
+        let mut db = test_db();
+        db.add_user(SteamID::from_str("[U:1:886717065]").unwrap(), 1)
+            .unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        db.add_log(fixture_log(3, at(3000))).unwrap();
+        let info = db
+            .import_snapshot(&mut snapshot.as_slice(), ImportMode::MergeSkipExisting)
+            .unwrap();
+        assert!(info.num_rows.contains(&("users", 1)));
+        assert!(info.num_rows.contains(&("logs", 1)));
+        assert!(info.num_rows.contains(&("overall_stats", 12)));
+
+        let status = db.status().unwrap();
+        assert_eq!(status.num_users, 2);
+        assert_eq!(status.num_logs, 3);
+        assert_eq!(status.num_overall_stats, 3 * 12);
+        // The tag of the log that has already been stored is not added.
+        assert!(db
+            .logs_with_tag(&LogTag::new("official").unwrap())
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.max_log_id().unwrap(), Some(LogId(3)));
+
+        // Merging again adds nothing.
+        let info = db
+            .import_snapshot(&mut snapshot.as_slice(), ImportMode::MergeSkipExisting)
+            .unwrap();
+        assert_eq!(info.total_rows(), 0);
+    }
+
+    #[test]
+    fn invalid_snapshots()
+    {
+        let (_, snapshot) = snapshot_db();
+        let snapshot = String::from_utf8(snapshot).unwrap();
+        let mut db = test_db();
+        db.add_log(fixture_log(3, at(3000))).unwrap();
+
+        let newer = snapshot.replacen(
+            &format!("\"schema_version\":{}", SCHEMA_VERSION),
+            &format!("\"schema_version\":{}", SCHEMA_VERSION + 1),
+            1,
+        );
+        assert!(matches!(
+            db.read_snapshot(&mut newer.as_bytes(), ImportMode::ReplaceAll),
+            Err(DbError::SchemaTooNew {
+                found,
+                supported: SCHEMA_VERSION,
+            }) if found == SCHEMA_VERSION + 1
+        ));
+
+        // A snapshot that has been cut off is not loaded, and nothing has been
+        // removed.
+        let truncated = &snapshot[..snapshot.len() / 2];
+        let truncated = &truncated[..truncated.rfind('\n').unwrap() + 1];
+        let not_json = snapshot.replacen("{\"row\":", "row", 1);
+        for invalid in ["", "{}", truncated, not_json.as_str()] {
+            assert!(matches!(
+                db.read_snapshot(&mut invalid.as_bytes(), ImportMode::ReplaceAll),
+                Err(DbError::InvalidInput(_))
+            ));
+        }
+        assert_eq!(db.known_logs().unwrap(), [LogId(3)]);
+    }
+
+    #[test]
+    fn overlapping_seasons_rejected()
+    {
+        let mut db = test_db();
+
+        let first = db
+            .create_season("Season 1", at(1000), Some(at(2000)))
+            .unwrap();
+        assert!(first.is_some());
+        // Touching the previous season is fine, since the end is exclusive.
+        let second = db.create_season("Season 2", at(2000), None).unwrap();
+        assert!(second.is_some());
+
+        assert_eq!(
+            db.create_season("Overlap", at(1500), Some(at(2500)))
+                .unwrap(),
+            None
+        );
+        assert_eq!(db.create_season("Overlap", at(5000), None).unwrap(), None);
+        assert!(db
+            .create_season("Before", at(0), Some(at(1000)))
+            .unwrap()
+            .is_some());
+        // A season that ends before it starts is not a season at all.
+        for end in [at(-1000), at(-2000)] {
+            assert!(matches!(
+                db.create_season("Backwards", at(-1000), Some(end)),
+                Err(MixesDbError::InvalidInput(_))
+            ));
+        }
+
+        let current = db.current_season().unwrap().expect("No current season");
+        assert_eq!(Some(current.id), second);
+        assert_eq!(current.end, None);
+
+        assert!(db.close_season(at(3000)).unwrap());
+        assert_eq!(db.current_season().unwrap(), None);
+        assert!(!db.close_season(at(4000)).unwrap());
+    }
+
+    #[test]
+    fn log_at_season_boundary()
+    {
+        let mut db = test_db();
+        let player = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        let first = db
+            .create_season("Season 1", at(1000), Some(at(2000)))
+            .unwrap();
+        let second = db.create_season("Season 2", at(2000), None).unwrap();
+
+        db.add_log(fixture_log(1, at(1999))).unwrap();
+        // Exactly at the boundary, which belongs to the second season.
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        let first_logs = db
+            .get_class_performance_structured(player, Class::Scout, 10, first, None)
+            .unwrap();
+        let second_logs = db
+            .get_class_performance_structured(player, Class::Scout, 10, second, None)
+            .unwrap();
+        assert_eq!(log_ids(&first_logs), vec![LogId(1)]);
+        assert_eq!(log_ids(&second_logs), vec![LogId(2)]);
+    }
+
+    #[test]
+    fn assign_unmatched_logs()
+    {
+        let mut db = test_db();
+        let player = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        db.add_log(fixture_log(3, at(3000))).unwrap();
+
+        let season = db.create_season("Season", at(1500), None).unwrap();
+        assert_eq!(db.assign_unmatched_logs_to_seasons().unwrap(), 2);
+        assert_eq!(db.assign_unmatched_logs_to_seasons().unwrap(), 0);
+
+        // Closing the season releases the logs after its end again.
+        assert!(db.close_season(at(2500)).unwrap());
+        assert_eq!(
+            db.get_class_performance_structured(player, Class::Scout, 10, season, None)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(db.create_season("Next", at(2500), None).unwrap().is_some());
+        assert_eq!(db.assign_unmatched_logs_to_seasons().unwrap(), 1);
+    }
+
+    #[test]
+    fn player_names()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        let renamed = |id, name: &str| {
+            let mut json = fixture_json(at(1000 * id as i64));
+            json["names"]["[U:1:886717065]"] = name.into();
+            Log::from_json(LogId(id), &json).unwrap()
+        };
+
+        assert_eq!(db.player_name(scout).unwrap(), None);
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        assert_eq!(db.player_name(scout).unwrap().as_deref(), Some("cklips"));
+        // Unregistered players are named as well, and the name is not the username.
+        let autunno = SteamID::from_str("[U:1:178408897]").unwrap();
+        assert_eq!(db.player_name(autunno).unwrap().as_deref(), Some("autunno"));
+        assert_eq!(db.username(scout).unwrap(), None);
+
+        // Only a newer log changes the name.
+        db.add_log(renamed(1, "old name")).unwrap();
+        assert_eq!(db.player_name(scout).unwrap().as_deref(), Some("cklips"));
+        db.add_log(renamed(3, "cklips2")).unwrap();
+        assert_eq!(db.player_name(scout).unwrap().as_deref(), Some("cklips2"));
+
+        // Without unregistered players, only the registered ones are named.
+        let mut db = test_db().store_unregistered(false);
+        db.add_user(scout, 1).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        assert_eq!(players_in(&mut db, "player_names", "steam_id"), vec![scout]);
+    }
+
+    #[test]
+    fn find_players_by_name()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let absent = SteamID::from_str("[U:1:1234]").unwrap();
+        let other = SteamID::from_str("[U:1:5678]").unwrap();
+
+        for (discord_id, (steam_id, name)) in [
+            (scout, "Arie"),
+            (medic, "Mariela"),
+            (absent, "ariEl"),
+            (other, "Someone_%"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert!(db.add_user(steam_id, discord_id as u64).unwrap());
+            assert!(db.set_username(steam_id, name).unwrap());
+        }
+
+        // The scout has been seen last, since the medic is missing in the newer log.
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let mut json = fixture_json(at(2000));
+        json["players"].remove("[U:1:71020853]");
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+
+        assert_eq!(
+            db.find_players_by_name("ari", 10).unwrap(),
+            vec![
+                (scout, "Arie".to_owned()),
+                (medic, "Mariela".to_owned()),
+                (absent, "ariEl".to_owned())
+            ]
+        );
+        assert_eq!(
+            db.find_players_by_name("ARI", 2).unwrap(),
+            vec![(scout, "Arie".to_owned()), (medic, "Mariela".to_owned())]
+        );
+
+        // Exact matches are preferred over substring matches.
+        assert_eq!(
+            db.find_players_by_name(" ariel ", 10).unwrap(),
+            vec![(absent, "ariEl".to_owned())]
+        );
+
+        // Wildcards are matched literally.
+        assert_eq!(
+            db.find_players_by_name("_%", 10).unwrap(),
+            vec![(other, "Someone_%".to_owned())]
+        );
+        assert_eq!(db.find_players_by_name("%", 10).unwrap().len(), 1);
+
+        assert!(matches!(
+            db.find_players_by_name("  ", 10),
+            Err(MixesDbError::InvalidInput(_))
+        ));
+        assert!(!db
+            .set_username(SteamID::from_str("[U:1:1]").unwrap(), "Nobody")
+            .unwrap());
+    }
+
+    #[test]
+    fn add_log_from_json()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let mut json = String::new();
+        File::open("test_data/log_3094861.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+
+        assert!(db.add_log_from_json(LogId(3094861), &json).unwrap());
+        assert!(!db.add_log_from_json(LogId(3094861), &json).unwrap());
+
+        let performances = db
+            .get_class_performance_structured(scout, Class::Scout, 10, None, None)
+            .unwrap();
+        let scout_performance = performances[0]
+            .2
+            .class(Class::Scout)
+            .expect("No scout performance in the log");
+        assert_eq!(scout_performance.kills, 19);
+        assert_eq!(scout_performance.assists, 14);
+        assert_eq!(scout_performance.deaths, 16);
+        assert_eq!(scout_performance.damage, 6671);
+        assert_eq!(scout_performance.time_played_secs, 1618);
+    }
+
+    #[test]
+    fn add_highlander_log()
+    {
+        // A doctored highlander game, where each team has one player of each
+        // class. The players of red have the account ids 20000001 to 20000009,
+        // in the order of `Class::ALL`, followed by the players of blue.
+        let mut json = String::new();
+        File::open("test_data/log_highlander.json")
+            .expect("Unable to open test file")
+            .read_to_string(&mut json)
+            .expect("Unable to read file to string");
+        let player = |account_id| SteamID::from_str(&format!("[U:1:{}]", account_id)).unwrap();
+
+        let mut db = test_db();
+        assert!(db.add_log_from_json(LogId(1), &json).unwrap());
+        for (account_id, class) in (20000001..).zip(Class::ALL) {
+            let performances = db
+                .get_class_performance_structured(player(account_id), class, 10, None, None)
+                .unwrap();
+            assert_eq!(log_ids(&performances), vec![LogId(1)], "{:?}", class);
+            assert_eq!(performances[0].1.num_players, 18);
+            assert_eq!(performances[0].1.game_mode(), GameMode::Highlander);
+
+            let performance = performances[0].2.class(class).unwrap();
+            assert_eq!(performance.time_played_secs, 1500);
+            assert_eq!(performances[0].2.primary_class(), Some(class));
+        }
+
+        let sniper = db
+            .get_class_performance_structured(player(20000008), Class::Sniper, 10, None, None)
+            .unwrap();
+        let sniper = sniper[0].2.class(Class::Sniper).unwrap();
+        assert_eq!(
+            (sniper.kills, sniper.assists, sniper.deaths, sniper.damage),
+            (18, 4, 11, 5403)
+        );
+        assert_eq!(sniper.sentry_kills, None);
+        let engineer = db
+            .get_class_performance_structured(player(20000006), Class::Engineer, 10, None, None)
+            .unwrap();
+        let engineer = engineer[0].2.class(Class::Engineer).unwrap();
+        assert_eq!((engineer.kills, engineer.deaths), (16, 8));
+        assert_eq!((engineer.assists, engineer.damage), (6, 4977));
+        assert_eq!(engineer.sentry_kills, Some(12));
+
+        // Updates look for sixes games unless asked for highlander.
+        let meta = LogMetadata {
+            num_players: 18,
+            // Search results do not have a duration.
+            duration_secs: None,
+            ..Log::parse(LogId(1), &json).unwrap().meta().clone()
+        };
+        let mut source = MockLogSource::new();
+        source.add_log(meta, (20000001..20000019).map(player).collect(), json);
+        let mut db = test_db();
+        for account_id in 20000001..20000012 {
+            db.add_user(player(account_id), account_id as u64).unwrap();
+        }
+        let report = db.update_from(&source, update_options(0.6)).unwrap();
+        assert_eq!(
+            report.skipped,
+            vec![(LogId(1), SkipReason::PlayerCount(18))]
+        );
+        let highlander = UpdateOptions::builder()
+            .game_mode(GameMode::Highlander)
+            .build()
+            .unwrap();
+        let report = db.update_from(&source, highlander).unwrap();
+        assert_eq!(report.added, vec![LogId(1)]);
+    }
+
+    #[test]
+    fn add_log_from_invalid_json()
+    {
+        let mut db = test_db();
+        let mut json = fixture_json(at(1000));
+        json["players"] = JsonValue::new_object();
+
+        assert!(matches!(
+            db.add_log_from_json(LogId(1), &json.dump()),
+            Err(MixesDbError::Parse(LogParseError::NoPlayers))
+        ));
+        #[cfg(not(feature = "serde-parse"))]
+        assert!(matches!(
+            db.add_log_from_json(LogId(1), "{ \"players\": "),
+            Err(MixesDbError::Parse(LogParseError::Json(_)))
+        ));
+        #[cfg(feature = "serde-parse")]
+        assert!(matches!(
+            db.add_log_from_json(LogId(1), "{ \"players\": "),
+            Err(MixesDbError::Parse(LogParseError::Typed(_)))
+        ));
+
+        // Valid json that is not a valid log is rejected instead of panicking.
+        let mut without_map = fixture_json(at(1000));
+        without_map["info"].remove("map");
+        let mut bad_player = fixture_json(at(1000));
+        let stats = bad_player["players"].remove("[U:1:886717065]");
+        bad_player["players"]["not a steam id"] = stats;
+        #[cfg(not(feature = "serde-parse"))]
+        {
+            assert!(matches!(
+                db.add_log_from_json(LogId(1), &without_map.dump()),
+                Err(MixesDbError::Parse(LogParseError::InvalidField("map")))
+            ));
+            let error = db.add_log_from_json(LogId(1), &bad_player.dump());
+            assert!(
+                matches!(error, Err(MixesDbError::Parse(LogParseError::InvalidPlayerId(id)))
+                if id == "not a steam id")
+            );
+        }
+        #[cfg(feature = "serde-parse")]
+        for json in [without_map, bad_player] {
+            assert!(matches!(
+                db.add_log_from_json(LogId(1), &json.dump()),
+                Err(MixesDbError::Parse(LogParseError::Typed(_)))
+            ));
+        }
+        assert!(db.known_logs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skip_corrupt_log()
+    {
+        let logs = capture_logs();
+        let mut db = test_db();
+
+        let download = Err(QueryError::Unsuccessful("Log not found".to_owned()));
+        let mut report = UpdateReport::default();
+        db.add_downloaded_log(LogId(31337), download, IngestReason::Mix, None, &mut report)
+            .unwrap();
+
+        assert!(report.added.is_empty());
+        assert!(matches!(
+            report.skipped.as_slice(),
+            [(LogId(31337), SkipReason::DownloadFailed(_))]
+        ));
+
+        assert!(logs.lock().unwrap().iter().any(|(level, message)| {
+            *level == Level::Warn && message.starts_with("Skipping log 31337")
+        }));
+    }
+
+    #[test]
+    fn heal_spread()
+    {
+        let mut db = test_db();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let pocket = SteamID::from_str("[U:1:142158386]").unwrap();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        let heal_spread = db.get_heal_spread(medic, 10).unwrap();
+        assert_eq!(heal_spread.len(), 5);
+        assert_eq!(heal_spread[0], (pocket, 2 * 7081));
+        assert_eq!(heal_spread[1], (scout, 2 * 4934));
+
+        let heal_spread = db.get_heal_spread(medic, 1).unwrap();
+        assert_eq!(heal_spread[0], (pocket, 7081));
+
+        // The heal targets are restored when reading the medic performance.
+        let performances = db
+            .get_class_performance_structured(medic, Class::Medic, 1, None, None)
+            .unwrap();
+        let heal_targets = &performances[0]
+            .2
+            .medic
+            .as_ref()
+            .expect("No medic performance in the log")
+            .heal_targets;
+        assert_eq!(heal_targets.len(), 5);
+        assert_eq!(heal_targets[0], (pocket, 7081));
+
+        assert!(db.get_heal_spread(scout, 10).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "danger-raw-sql")]
+    #[test]
+    fn raw_queries()
+    {
+        use super::{row_to_meta, row_to_overall, META_COLUMNS, OVERALL_COLUMNS};
+
+        let mut db = test_db();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        // A custom aggregate comes to the same result as the built-in one.
+        let mut heal_spread: Vec<(SteamID, u64)> = db
+            .query_raw(
+                "SELECT target_id, sum(healing)::bigint FROM heal_spread WHERE medic_id = $1 \
+                 GROUP BY target_id",
+                &[&(medic.id64() as i64)],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let target_id: i64 = row.get(0);
+                let healing: i64 = row.get(1);
+                (
+                    SteamID::new_checked(target_id as u64).unwrap(),
+                    healing as u64,
+                )
+            })
+            .collect();
+        let mut expected = db.get_heal_spread(medic, 10).unwrap();
+        heal_spread.sort_by_key(|&(target, _)| target.id64());
+        expected.sort_by_key(|&(target, _)| target.id64());
+        assert_eq!(heal_spread.len(), 5);
+        assert_eq!(heal_spread, expected);
+
+        // Custom queries can decode rows like the database does.
+        let stored = &db
+            .get_class_performance_structured(medic, Class::Medic, 1, None, None)
+            .unwrap()[0];
+        let overall = db
+            .query_raw(
+                format!(
+                    "SELECT {} FROM overall_stats WHERE log_id = $1 AND steam_id = $2",
+                    OVERALL_COLUMNS
+                )
+                .as_str(),
+                &[&stored.0, &(medic.id64() as i64)],
+            )
+            .unwrap();
+        assert_eq!(Some(row_to_overall(&overall[0])), stored.2.overall);
+        let meta = db
+            .query_raw(
+                format!("SELECT {} FROM logs WHERE log_id = $1", META_COLUMNS).as_str(),
+                &[&stored.0],
+            )
+            .unwrap();
+        assert_eq!(row_to_meta(&meta[0]), stored.1);
+    }
+
+    #[test]
+    fn medic_stats_round_trip()
+    {
+        let mut db = test_db();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        let mut json = fixture_json(at(1000));
+        json["players"]["[U:1:71020853]"]["ubertypes"]["kritzkrieg"] = 2.into();
+        let log = Log::from_json(LogId(1), &json).unwrap();
+        let expected = LogPerformance::from(log.performances()[&medic].clone()).medic;
+        assert_eq!(expected.as_ref().map(|med| med.num_kritz), Some(2));
+        db.add_log(log).unwrap();
+
+        let performances = db
+            .get_class_performance_structured(medic, Class::Medic, 1, None, None)
+            .unwrap();
+        assert_eq!(performances[0].2.medic, expected);
+    }
+
+    #[test]
+    fn long_names()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        // Workshop maps can have long names.
+        let map = format!("workshop/{}.ugc12345678", "cp_".repeat(23));
+        assert_eq!(map.len(), 90);
+        let mut json = fixture_json(at(1000));
+        json["info"]["map"] = map.as_str().into();
+        assert!(db
+            .add_log(Log::from_json(LogId(1), &json).unwrap())
+            .unwrap());
+        let stored: String = db
+            .client
+            .query_one("SELECT map FROM logs WHERE log_id = 1", &[])
+            .unwrap()
+            .get(0);
+        assert_eq!(stored, map);
+
+        let longest = "a".repeat(MAX_NAME_LEN);
+        assert!(db.set_username(scout, &longest).unwrap());
+        match db.set_username(scout, &format!("{}a", longest)) {
+            Err(MixesDbError::InvalidInput(e)) => assert!(e.contains("username")),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(db.username(scout).unwrap(), Some(longest));
+
+        match db.create_season(&"s".repeat(MAX_NAME_LEN + 1), at(0), None) {
+            Err(MixesDbError::InvalidInput(e)) => assert!(e.contains("season name")),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn event_counts_round_trip()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        let mut json = fixture_json(at(1000));
+        json["players"]["[U:1:886717065]"]["class_stats"][1]["sentries"] = 3.into();
+        let log = Log::from_json(LogId(1), &json).unwrap();
+        let expected = LogPerformance::from(log.performances()[&scout].clone());
+        db.add_log(log).unwrap();
+
+        let performances = db
+            .get_class_performance_structured(scout, Class::Scout, 1, None, None)
+            .unwrap();
+        let stored = &performances[0].2;
+        assert_eq!(
+            stored.overall.as_ref().map(|overall| overall.captures),
+            Some(9)
+        );
+        assert_eq!(stored.overall, expected.overall);
+        let engineer = stored.dm.iter().find(|dm| dm.class == Class::Engineer);
+        assert_eq!(engineer.and_then(|dm| dm.sentry_kills), Some(3));
+        assert_eq!(stored.dm, expected.dm);
+    }
+
+    #[test]
+    fn structured_performances_match_log()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        let log = fixture_log(1, at(1000));
+        let expected = LogPerformance::from(log.performance_of(scout).unwrap().clone());
+        assert!(
+            expected.dm.len() > 1,
+            "The scout must have played several classes"
+        );
+        db.add_log(log).unwrap();
+
+        let performances = db
+            .get_class_performance_structured(scout, Class::Scout, 1, None, None)
+            .unwrap();
+        let (_, _, performance) = &performances[0];
+        assert_eq!(performance.dm, expected.dm);
+        assert!(performance
+            .dm
+            .windows(2)
+            .all(|dm| dm[0].time_played_secs >= dm[1].time_played_secs));
+        assert_eq!(
+            performance.class(Class::Scout),
+            expected.class(Class::Scout)
+        );
+    }
+
+    #[test]
+    fn classes_performance()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        // The scout has played scout, engineer and pyro in both logs.
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        let classes = [Class::Scout, Class::Engineer];
+        let options = QueryOptions::default();
+        let performances = db
+            .get_classes_performance(scout, &classes, 1, &options)
+            .unwrap();
+        assert_eq!(log_ids(&performances), vec![LogId(2)]);
+        let (_, _, performance) = &performances[0];
+        assert!(performance.class(Class::Scout).is_some());
+        assert!(performance.class(Class::Engineer).is_some());
+
+        assert_eq!(
+            db.get_classes_performance(scout, &classes, 10, &options)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            db.get_classes_performance(scout, &[Class::Pyro], 10, &options)
+                .unwrap(),
+            db.get_class_performance_structured(scout, Class::Pyro, 10, None, None)
+                .unwrap()
+        );
+        assert!(db
+            .get_classes_performance(scout, &[], 10, &options)
+            .unwrap()
+            .is_empty());
+        assert!(db
+            .get_classes_performance(scout, &[Class::Medic, Class::Spy], 10, &options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn min_class_time()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+
+        // A newer log in which the player has only been on scout for 30 seconds.
+        let mut json = fixture_json(at(2000));
+        for class_stats in json["players"]["[U:1:886717065]"]["class_stats"].members_mut() {
+            if class_stats["type"] == "scout" {
+                class_stats["total_time"] = 30.into();
+            }
+        }
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+
+        // Scout is not the primary class of the newer log.
+        let with_min_time = |min_class_time_secs| QueryOptions {
+            min_class_time_secs,
+            only_primary_class: false,
+            ..QueryOptions::default()
+        };
+        let mut scout_logs = |limit, min_class_time_secs| {
+            log_ids(
+                &db.get_classes_performance(
+                    scout,
+                    &[Class::Scout],
+                    limit,
+                    &with_min_time(min_class_time_secs),
+                )
+                .unwrap(),
+            )
+        };
+        assert_eq!(scout_logs(10, 0), vec![LogId(2), LogId(1)]);
+        assert_eq!(scout_logs(10, 30), vec![LogId(2), LogId(1)]);
+        assert_eq!(scout_logs(10, 31), vec![LogId(1)]);
+        // The limit only applies to the logs that are long enough.
+        assert_eq!(scout_logs(1, 60), vec![LogId(1)]);
+
+        // The player has been on pyro for 8 seconds in both logs.
+        let dpm = Metric::DamagePerMinute;
+        assert!(db
+            .percentile_for_metric(scout, Class::Pyro, dpm, 1, true, 0)
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            db.percentile_for_metric(scout, Class::Pyro, dpm, 1, true, 30)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn max_offclass_fraction()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        // Second log where the scout has played more engineer than scout.
+        let mut json = fixture_json(at(2000));
+        json["players"]["[U:1:886717065]"]["class_stats"][1]["total_time"] = 2000.into();
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+
+        // Scout is not the primary class of the second log, which would leave it
+        // out on its own.
+        let logs = |db: &mut SQLDb, max_offclass_fraction| {
+            let options = QueryOptions {
+                max_offclass_fraction,
+                only_primary_class: false,
+                ..QueryOptions::default()
+            };
+            let performances = db
+                .get_classes_performance(scout, &[Class::Scout], 10, &options)
+                .unwrap();
+            log_ids(&performances)
+        };
+        assert_eq!(logs(&mut db, None), vec![LogId(2), LogId(1)]);
+        assert_eq!(logs(&mut db, Some(0.5)), vec![LogId(1)]);
+        assert_eq!(logs(&mut db, Some(0.0)), Vec::<LogId>::new());
+    }
+
+    #[test]
+    fn only_primary_class()
+    {
+        let mut db = test_db();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        // Second log where the medic has played scout for the last round.
+        let mut json = fixture_json(at(2000));
+        json["players"]["[U:1:71020853]"]["class_stats"]
+            .push(json::object! {
+                type: "scout",
+                kills: 3,
+                assists: 0,
+                deaths: 1,
+                dmg: 900,
+                total_time: 240,
+            })
+            .unwrap();
+        db.add_log(Log::from_json(LogId(2), &json).unwrap())
+            .unwrap();
+
+        let logs = |db: &mut SQLDb, class, only_primary_class| {
+            let options = QueryOptions {
+                only_primary_class,
+                ..QueryOptions::default()
+            };
+            let performances = db
+                .get_classes_performance(medic, &[class], 10, &options)
+                .unwrap();
+            for (_, _, performance) in &performances {
+                assert_eq!(performance.primary_class(), Some(Class::Medic));
+            }
+            log_ids(&performances)
+        };
+        assert_eq!(logs(&mut db, Class::Scout, true), Vec::<LogId>::new());
+        assert_eq!(logs(&mut db, Class::Scout, false), vec![LogId(2)]);
+        assert_eq!(logs(&mut db, Class::Medic, true), vec![LogId(2), LogId(1)]);
+        assert_eq!(logs(&mut db, Class::Medic, false), vec![LogId(2), LogId(1)]);
+    }
+
+    /// Database that records every event it emits.
+    fn recording_db() -> (SQLDb, Arc<Mutex<Vec<DbEvent>>>)
+    {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut db = test_db();
+        let recorded = Arc::clone(&events);
+        db.set_event_handler(Box::new(move |event| recorded.lock().unwrap().push(event)));
+
+        (db, events)
+    }
+
+    #[test]
+    fn events()
+    {
+        let (mut db, events) = recording_db();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        let log = fixture_log(1, at(1000));
+        let mut players: Vec<SteamID> = log.performances().keys().copied().collect();
+        players.sort_unstable_by_key(|steam_id| steam_id.id64());
+
+        assert!(db.add_user(medic, 1).unwrap());
+        assert!(!db.add_user(medic, 1).unwrap());
+        assert!(db.add_log(log).unwrap());
+        assert!(!db.add_log(fixture_log(1, at(1000))).unwrap());
+        db.purge_player_data(medic, true).unwrap();
+        db.update_from(&MockLogSource::new(), update_options(0.1))
+            .unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                DbEvent::UserAdded(medic),
+                DbEvent::LogAdded {
+                    log_id: LogId(1),
+                    players,
+                    records_broken: Vec::new(),
+                },
+                DbEvent::UserRemoved(medic),
+                DbEvent::LogRemoved(LogId(1)),
+                DbEvent::UpdateCompleted { added: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn personal_records()
+    {
+        let (mut db, events) = recording_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let record = |db: &mut SQLDb, metric| {
+            let records = db.personal_records(scout).unwrap();
+            let record = records
+                .iter()
+                .find(|record| record.metric == metric)
+                .unwrap();
+            assert_eq!(record.class, Class::Scout);
+            (record.log_id, record.value)
+        };
+        assert_eq!(db.personal_records(scout).unwrap(), vec![]);
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let records = db.personal_records(scout).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| record.metric)
+                .collect::<Vec<_>>(),
+            [
+                Metric::DamagePerMinute,
+                Metric::KillsPerMinute,
+                Metric::AssistsPerMinute,
+                Metric::DeathsPerMinute,
+                Metric::DeathsPer10Min,
+                Metric::DamageTakenPerMinute,
+                Metric::KillsPerGame,
+                Metric::KillDeathRatio,
+                Metric::KillsAssistsPerDeath,
+                Metric::DamagePerHeal,
+            ]
+        );
+        assert_eq!(
+            record(&mut db, Metric::DamagePerMinute),
+            (LogId(1), 7090. * 60. / 1738.)
+        );
+        assert_eq!(record(&mut db, Metric::KillsPerGame), (LogId(1), 20.));
+
+        // The second log beats the damage of the first, but not its kills. The
+        // stats of everyone else are the same, which is no new record.
+        let now = Utc::now();
+        let mut second = fixture_json(now);
+        second["players"]["[U:1:886717065]"]["dmg"] = 8000.into();
+        second["players"]["[U:1:886717065]"]["kills"] = 10.into();
+        db.add_log(Log::from_json(LogId(2), &second).unwrap())
+            .unwrap();
+        assert_eq!(
+            record(&mut db, Metric::DamagePerMinute),
+            (LogId(2), 8000. * 60. / 1738.)
+        );
+        assert_eq!(record(&mut db, Metric::KillsPerGame), (LogId(1), 20.));
+        let records = db.personal_records(scout).unwrap();
+        assert!(records
+            .iter()
+            .filter(|record| record.log_id == LogId(2))
+            .all(|record| record.date.timestamp() == now.timestamp()));
+
+        let events = events.lock().unwrap().clone();
+        let records_broken = match events.last() {
+            Some(DbEvent::LogAdded {
+                log_id: LogId(2),
+                records_broken,
+                ..
+            }) => records_broken.clone(),
+            event => panic!("Unexpected event {:?}", event),
+        };
+        assert_eq!(
+            records_broken
+                .iter()
+                .map(|broken| (broken.player, broken.record.metric, broken.previous.log_id))
+                .collect::<Vec<_>>(),
+            [
+                (scout, Metric::DamagePerMinute, LogId(1)),
+                (scout, Metric::DamagePerHeal, LogId(1)),
+            ]
+        );
+        assert_eq!(records_broken[0].record.value, 8000. * 60. / 1738.);
+        assert_eq!(records_broken[0].previous.value, 7090. * 60. / 1738.);
+
+        // Added by an update, the records are part of the report.
+        let mut third = fixture_json(now);
+        third["players"]["[U:1:886717065]"]["kills"] = 30.into();
+        let mut source = MockLogSource::new();
+        let meta = LogMetadata {
+            id:            LogId(3),
+            date_time:     now,
+            map:           "cp_sunshine".to_owned(),
+            num_players:   12,
+            title:         None,
+            uploader:      None,
+            duration_secs: None,
+        };
+        source.add_log(meta, vec![scout], third.dump());
+        db.add_user(scout, 1).unwrap();
+        let report = db.update_from(&source, update_options(0.)).unwrap();
+        assert_eq!(report.added, vec![LogId(3)]);
+        assert!(report
+            .records_broken
+            .iter()
+            .any(|broken| broken.record.metric == Metric::KillsPerGame
+                && broken.previous.log_id == LogId(1)));
+
+        // Rebuilding the records from the stats gives the same records.
+        let stored = table_rows(&mut db, "personal_records");
+        assert_eq!(db.rebuild_records().unwrap(), stored.len() as u64);
+        assert_eq!(table_rows(&mut db, "personal_records"), stored);
+
+        // The records set in removed logs are replaced by the best that is left.
+        let policy = RetentionPolicy {
+            max_age_days: Some(365),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(db.apply_retention(policy).unwrap().logs, 1);
+        assert_eq!(record(&mut db, Metric::KillsPerGame), (LogId(3), 30.));
+        assert_eq!(
+            record(&mut db, Metric::KillDeathRatio),
+            (LogId(3), 30. / 17.)
+        );
+        assert!(db
+            .personal_records(scout)
+            .unwrap()
+            .iter()
+            .all(|record| record.log_id != LogId(1)));
+
+        // A game with a class that cannot be read does not count.
+        db.client
+            .execute(
+                "UPDATE overall_stats SET primary_class = 100 WHERE log_id = 3 AND steam_id = $1",
+                &[&(scout.id64() as i64)],
+            )
+            .unwrap();
+        db.rebuild_records().unwrap();
+        let records = db.personal_records(scout).unwrap();
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|record| record.log_id != LogId(3)));
+    }
+
+    #[test]
+    fn no_events_on_rollback()
+    {
+        let (mut db, events) = recording_db();
+        // Adding the medic stats fails, so the whole log is rolled back.
+        db.client.batch_execute("DROP TABLE med_stats").unwrap();
+
+        assert!(db.add_log(fixture_log(1, at(1000))).is_err());
+        assert!(db.known_logs().unwrap().is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn chat_not_stored_by_default()
+    {
+        let mut db = test_db();
+        // Adding the log must not touch the chat table at all.
+        db.client.batch_execute("DROP TABLE chat").unwrap();
+
+        assert!(db.add_log(fixture_log(1, at(1000))).unwrap());
+    }
+
+    /// The players that have stats in the `table` of the database, which has
+    /// the steam ids in the column `steam_id`. Rows without a steam id are
+    /// ignored.
+    fn players_in(db: &mut SQLDb, table: &str, steam_id: &str) -> Vec<SteamID>
+    {
+        db.client
+            .query(
+                format!(
+                    "SELECT DISTINCT {0} FROM {1} WHERE {0} IS NOT NULL ORDER BY {0}",
+                    steam_id, table
+                )
+                .as_str(),
+                &[],
+            )
+            .unwrap()
+            .iter()
+            .map(|row| {
+                let steam_id: i64 = row.get(0);
+                SteamID::new_checked(steam_id as u64).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prune_unregistered()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_user(medic, 2).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let heal_spread = db.get_heal_spread(medic, 10).unwrap();
+
+        // 12 players with overall stats, 2 of them registered
+        assert!(db.prune_unregistered().unwrap() >= 10);
+        assert_eq!(db.prune_unregistered().unwrap(), 0);
+
+        let mut registered = vec![scout, medic];
+        registered.sort_unstable_by_key(|steam_id| steam_id.id64());
+        assert_eq!(players_in(&mut db, "overall_stats", "steam_id"), registered);
+        assert_eq!(players_in(&mut db, "dm_stats", "steam_id"), registered);
+        assert_eq!(players_in(&mut db, "med_stats", "steam_id"), vec![medic]);
+        assert_eq!(players_in(&mut db, "heal_spread", "medic_id"), vec![medic]);
+        assert_eq!(players_in(&mut db, "player_names", "steam_id"), registered);
+        assert_eq!(db.get_heal_spread(medic, 10).unwrap(), heal_spread);
+        assert_eq!(db.known_logs().unwrap(), vec![LogId(1)]);
+        assert_eq!(
+            db.get_class_performance_structured(scout, Class::Scout, 10, None, None)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    /// Every column of the database that contains steam ids.
+    const STEAM_ID_COLUMNS: [(&str, &str); 9] = [
+        ("users", "steam_id"),
+        ("logs", "uploader"),
+        ("overall_stats", "steam_id"),
+        ("dm_stats", "steam_id"),
+        ("med_stats", "steam_id"),
+        ("heal_spread", "medic_id"),
+        ("heal_spread", "target_id"),
+        ("chat", "steam_id"),
+        ("player_names", "steam_id"),
+    ];
+
+    #[test]
+    fn purge_player_data()
+    {
+        let mut db = test_db().store_chat(true);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        db.add_user(medic, 2).unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        let mut uploaded = fixture_json(at(2000));
+        uploaded["info"]["uploader"]["id"] = medic.id64().to_string().into();
+        db.add_log(Log::from_json(LogId(2), &uploaded).unwrap())
+            .unwrap();
+
+        // The targets of the medic are only removed from their heal spread.
+        let other_columns = || {
+            STEAM_ID_COLUMNS
+                .into_iter()
+                .filter(|&column| column != ("heal_spread", "target_id"))
+        };
+        let others_before: Vec<Vec<SteamID>> = other_columns()
+            .map(|(table, column)| {
+                let mut players = players_in(&mut db, table, column);
+                players.retain(|&player| player != medic);
+                players
+            })
+            .collect();
+        let other_medic = SteamID::from_str("[U:1:125648953]").unwrap();
+        let heal_spread = db.get_heal_spread(other_medic, 10).unwrap();
+        let mut scout_performance = db
+            .get_class_performance_structured(scout, Class::Scout, 10, None, None)
+            .unwrap();
+        // The log the medic has uploaded is kept without its uploader.
+        assert_eq!(scout_performance[0].1.uploader, Some(medic));
+        scout_performance[0].1.uploader = None;
+
+        // The scout is registered as well, so no log is removed.
+        let report = db.purge_player_data(medic, true).unwrap();
+        assert_eq!(
+            report,
+            PurgeReport {
+                user:          true,
+                logs:          0,
+                overall_stats: 2,
+                dm_stats:      2,
+                med_stats:     2,
+                heal_spread:   10,
+                chat:          2,
+            }
+        );
+        assert!(db.purge_player_data(medic, true).unwrap().is_empty());
+
+        let others_after: Vec<Vec<SteamID>> = other_columns()
+            .map(|(table, column)| players_in(&mut db, table, column))
+            .collect();
+        assert_eq!(others_after, others_before);
+        assert!(!players_in(&mut db, "heal_spread", "target_id").contains(&medic));
+        assert_eq!(db.get_heal_spread(other_medic, 10).unwrap(), heal_spread);
+        assert_eq!(db.known_logs().unwrap(), vec![LogId(2), LogId(1)]);
+        assert_eq!(
+            db.get_class_performance_structured(scout, Class::Scout, 10, None, None)
+                .unwrap(),
+            scout_performance
+        );
+    }
+
+    #[test]
+    fn purge_player_data_with_sole_logs()
+    {
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        let db_with_logs = || {
+            let mut db = test_db().store_chat(true);
+            db.add_user(medic, 1).unwrap();
+            db.add_log(fixture_log(1, at(1000))).unwrap();
+            db.add_log(fixture_log(2, at(2000))).unwrap();
+            db
+        };
+
+        // Without the flag, the logs are kept for the other players.
+        let mut db = db_with_logs();
+        assert_eq!(db.purge_player_data(medic, false).unwrap().logs, 0);
+        assert_eq!(db.known_logs().unwrap(), vec![LogId(2), LogId(1)]);
+
+        // The medic is the only registered player, so the logs are removed.
+        let mut db = db_with_logs();
+        let report = db.purge_player_data(medic, true).unwrap();
+        assert_eq!(report.logs, 2);
+        assert!(report.overall_stats > 2);
+        assert!(db.known_logs().unwrap().is_empty());
+        for (table, column) in STEAM_ID_COLUMNS {
+            assert_eq!(players_in(&mut db, table, column), Vec::new(), "{}", table);
+        }
+    }
+
+    #[test]
+    fn apply_retention()
+    {
+        let mut db = test_db().store_chat(true);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        db.add_user(scout, 1).unwrap();
+        let days_ago = |days| Utc::now() - chrono::Duration::days(days);
+        let official = LogTag::new("official").unwrap();
+        let awards = LogTag::new("awards").unwrap();
+        for (id, days) in [(1, 400), (2, 400), (3, 400), (4, 10)] {
+            db.add_log(fixture_log(id, days_ago(days))).unwrap();
+        }
+        db.tag_log(LogId(2), &official).unwrap();
+        db.tag_log(LogId(3), &awards).unwrap();
+        for (id, days) in [(10, 30), (11, 20), (12, 15)] {
+            let log = fixture_log(id, days_ago(days));
+            db.insert_log(log, IngestReason::PersonalBackfill, None)
+                .unwrap();
+        }
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(365),
+            max_backfill_logs: Some(2),
+            protected_tags: vec![official, awards],
+            batch_size: 1,
+            ..RetentionPolicy::default()
+        };
+        let all_logs = vec![
+            LogId(12),
+            LogId(11),
+            LogId(10),
+            LogId(4),
+            LogId(3),
+            LogId(2),
+            LogId(1),
+        ];
+
+        // A dry run counts the same rows, but keeps them.
+        let dry_run = db
+            .apply_retention(RetentionPolicy {
+                dry_run: true,
+                ..policy.clone()
+            })
+            .unwrap();
+        assert_eq!(db.known_logs().unwrap(), all_logs);
+
+        let report = db.apply_retention(policy.clone()).unwrap();
+        assert_eq!(report, dry_run);
+        assert_eq!(report.logs, 2);
+        assert_eq!(report.overall_stats, 24);
+        assert_eq!(report.med_stats, 4);
+        assert!(report.dm_stats > 0 && report.heal_spread > 0 && report.chat > 0);
+        assert_eq!(report.log_tags, 0);
+        assert!(!db.has_log(LogId(1)).unwrap());
+        assert!(!db.has_log(LogId(10)).unwrap());
+        assert_eq!(
+            db.known_logs().unwrap(),
+            vec![LogId(12), LogId(11), LogId(4), LogId(3), LogId(2)]
+        );
+
+        assert!(db.apply_retention(policy).unwrap().is_empty());
+        // The default policy does not remove anything.
+        assert!(db
+            .apply_retention(RetentionPolicy::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn apply_retention_events()
+    {
+        let (mut db, events) = recording_db();
+        for id in [1, 2] {
+            let log = fixture_log(id, Utc::now() - chrono::Duration::days(400));
+            db.add_log(log).unwrap();
+        }
+        // The second log is still referenced, so its batch fails.
+        db.client
+            .batch_execute(
+                "CREATE TABLE kept (log_id bigint REFERENCES logs (log_id));
+                INSERT INTO kept VALUES (2);",
+            )
+            .unwrap();
+        events.lock().unwrap().clear();
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(365),
+            batch_size: 1,
+            ..RetentionPolicy::default()
+        };
+        assert!(db.apply_retention(policy).is_err());
+        // The first batch has been removed all the same.
+        assert_eq!(db.known_logs().unwrap(), vec![LogId(2)]);
+        assert_eq!(*events.lock().unwrap(), vec![DbEvent::LogRemoved(LogId(1))]);
+    }
+
+    #[test]
+    fn apply_retention_linked_matches()
+    {
+        let mut db = test_db();
+        let days_ago = |days| Utc::now() - chrono::Duration::days(days);
+        for id in [1, 2] {
+            db.add_log(fixture_log(id, days_ago(400))).unwrap();
+        }
+        for id in [10, 11] {
+            let log = fixture_log(id, days_ago(30));
+            db.insert_log(log, IngestReason::PersonalBackfill, None)
+                .unwrap();
+        }
+        // Neither log is tagged, but both belong to an official match.
+        for id in [1, 10] {
+            assert!(db
+                .link_external_match(LogId(id), &League::Etf2l, 1234)
+                .unwrap());
+        }
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(365),
+            max_backfill_logs: Some(0),
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(db.apply_retention(policy.clone()).unwrap().logs, 2);
+        assert_eq!(db.known_logs().unwrap(), vec![LogId(10), LogId(1)]);
+
+        let report = db
+            .apply_retention(RetentionPolicy {
+                protect_linked_matches: false,
+                ..policy
+            })
+            .unwrap();
+        assert_eq!(report.logs, 2);
+        assert!(db.known_logs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_integrity()
+    {
+        let mut db = test_db();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+        assert_eq!(
+            db.check_integrity(false).unwrap(),
+            IntegrityReport::default()
+        );
+
+        for statement in [
+            // Stats of a log that is not stored
+            "INSERT INTO med_stats (log_id, steam_id, healing) VALUES (3, 1, 100)".to_owned(),
+            "INSERT INTO heal_spread VALUES (3, 1, 2, 100), (3, 1, 3, 50)".to_owned(),
+            // A log without stats
+            "INSERT INTO logs (log_id, date, map, duration_secs, num_players) VALUES (4, \
+             to_timestamp(4000), 'cp_granary_pro_rc8', 1800, 12)"
+                .to_owned(),
+            "UPDATE logs SET duration_secs = -1 WHERE log_id = 2".to_owned(),
+            // Everyone else has played the 6 rounds of the fixture.
+            format!(
+                "UPDATE overall_stats SET num_rounds = 20 WHERE log_id = 1 AND steam_id = {}",
+                scout.id64()
+            ),
+            format!(
+                "UPDATE dm_stats SET class = 12 WHERE log_id = 1 AND steam_id = {} AND class = {}",
+                scout.id64(),
+                Class::Engineer as i16
+            ),
+            format!(
+                "INSERT INTO med_stats SELECT * FROM med_stats WHERE log_id = 2 AND steam_id = {}",
+                medic.id64()
+            ),
+        ] {
+            db.client.execute(statement.as_str(), &[]).unwrap();
+        }
+
+        let remaining = vec![
+            IntegrityProblem::EmptyLog(LogId(4)),
+            IntegrityProblem::InvalidDuration {
+                log_id:        LogId(2),
+                duration_secs: Some(-1),
+            },
+            IntegrityProblem::InconsistentRounds {
+                log_id:     LogId(1),
+                min_rounds: 6,
+                max_rounds: 20,
+            },
+            IntegrityProblem::InvalidClass {
+                log_id: LogId(1),
+                player: scout.id64(),
+                class:  12,
+            },
+            IntegrityProblem::DuplicateRows {
+                table:  "med_stats",
+                log_id: LogId(2),
+                player: medic.id64(),
+                class:  None,
+                rows:   2,
+            },
+        ];
+        let mut problems = vec![
+            IntegrityProblem::OrphanRows {
+                table:  "med_stats",
+                log_id: LogId(3),
+                rows:   1,
+            },
+            IntegrityProblem::OrphanRows {
+                table:  "heal_spread",
+                log_id: LogId(3),
+                rows:   2,
+            },
+        ];
+        problems.extend(remaining.iter().cloned());
+
+        let report = db.check_integrity(false).unwrap();
+        assert_eq!(report.problems, problems);
+        assert_eq!(report.repaired_rows, 0);
+        // Checking does not change anything.
+        assert_eq!(db.check_integrity(false).unwrap(), report);
+
+        let report = db.check_integrity(true).unwrap();
+        assert_eq!(report.problems, problems);
+        assert_eq!(report.repaired_rows, 3);
+        assert_eq!(db.check_integrity(false).unwrap().problems, remaining);
+    }
+
+    #[test]
+    fn store_unregistered()
+    {
+        let mut pruned = test_db();
+        let mut db = test_db().store_unregistered(false);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+        for db in [&mut pruned, &mut db] {
+            db.add_user(scout, 1).unwrap();
+            db.add_user(medic, 2).unwrap();
+            db.add_log(fixture_log(1, at(1000))).unwrap();
+        }
+        pruned.prune_unregistered().unwrap();
+
+        assert_eq!(db.prune_unregistered().unwrap(), 0);
+        for (table, steam_id) in [
+            ("overall_stats", "steam_id"),
+            ("dm_stats", "steam_id"),
+            ("med_stats", "steam_id"),
+            ("heal_spread", "medic_id"),
+            ("player_names", "steam_id"),
+        ] {
+            assert_eq!(
+                players_in(&mut db, table, steam_id),
+                players_in(&mut pruned, table, steam_id)
+            );
+        }
+    }
+
+    #[test]
+    fn search_chat()
+    {
+        let mut db = test_db().store_chat(true);
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        db.add_log(fixture_log(1, at(1000))).unwrap();
+        db.add_log(fixture_log(2, at(2000))).unwrap();
+
+        let results = db.search_chat("rOoStEr", 3).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.log_id == LogId(2)));
+        assert_eq!(
+            results[0],
+            ChatSearchResult {
+                log_id:    LogId(2),
+                date_time: at(2000),
+                map:       "cp_sunshine".to_owned(),
+                message:   ChatMessage {
+                    steam_id: Some(scout),
+                    name:     "cklips".to_owned(),
+                    message:  "full rooster?".to_owned(),
+                },
+            }
+        );
+
+        let results = db.search_chat("rooster", 10).unwrap();
+        assert_eq!(results.len(), 8);
+        assert_eq!(results[7].log_id, LogId(1));
+        assert_eq!(
+            results[7].message.message,
+            "WE WERE PROMISED A FULL ROOSTER!"
+        );
+
+        // Wildcards are matched literally.
+        assert!(db.search_chat("%", 10).unwrap().is_empty());
+        assert!(matches!(
+            db.search_chat("", 10),
+            Err(MixesDbError::InvalidInput(_))
+        ));
+    }
+}
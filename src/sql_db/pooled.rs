@@ -0,0 +1,789 @@
+//! A [`Database`] backed by a pool of connections instead of a single one, so
+//! that it can be used from several threads at once, like by a bot that
+//! handles commands concurrently.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use postgres as sql;
+use r2d2::{CustomizeConnection, Pool};
+use r2d2_postgres::PostgresConnectionManager;
+
+use super::{
+    Connection, DbResult, SQLDb, DEFAULT_CONNECTION_PARAMS, DEFAULT_MAX_DURATION_SECS,
+    DEFAULT_MAX_PLAYER_LOGS, DEFAULT_SEARCH_BATCH_SIZE, SESSION_SETUP,
+};
+use crate::database::{
+    BulkAddReport, CarryStats, ChatSearchResult, Database, DbStatus, EventHandler, ExternalMatch,
+    Freshness, GameSummary, ImportMode, IngestOutcome, IntegrityReport, League, LogTag,
+    LogWithPerformances, Metric, ParticipationStats, PurgeReport, QueryOptions, RecordEntry,
+    RetentionPolicy, RetentionReport, RoundStats, SnapshotInfo, TagFilter, TeammateRecord,
+    TitleFilter, UpdateOptions, UpdatePlan, UpdateReport, UpdateRunRecord, UserRecord, UsersReport,
+};
+use crate::error::MixesDbResult;
+use crate::logs_tf::{Log, LogMetadata, LogSource, TeamSummary};
+use crate::score::Team;
+use crate::season::{Season, SeasonId};
+use crate::{Class, LogId, LogPerformance, SteamID};
+
+type ConnectionManager = PostgresConnectionManager<sql::NoTls>;
+/// A connection that has been checked out of the pool. It is returned to the
+/// pool when dropped.
+pub(super) type PooledConnection = r2d2::PooledConnection<ConnectionManager>;
+
+/// The number of connections in the pool by default, see
+/// [`PooledSqlDbBuilder::max_size`].
+pub const DEFAULT_POOL_SIZE: u32 = 8;
+/// How long to wait for a free connection by default, see
+/// [`PooledSqlDbBuilder::connection_timeout`].
+pub const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The same database as [`SQLDb`], but every method checks out a connection of
+/// a pool for the duration of the call, instead of working with a single
+/// connection. Clones share the pool, so every thread can have its own clone
+/// and run queries while another thread is adding logs or updating. Methods
+/// that write do so in a transaction on their connection, just like with
+/// [`SQLDb`].
+#[derive(Clone)]
+pub struct PooledSqlDb
+{
+    pool:               Pool<ConnectionManager>,
+    store_chat:         bool,
+    store_unregistered: bool,
+    max_duration_secs:  u32,
+    max_player_logs:    usize,
+    search_batch_size:  usize,
+    title_filter:       TitleFilter,
+    update_tag:         Option<LogTag>,
+    event_handler:      Option<Arc<Mutex<EventHandler>>>,
+}
+
+/// Settings of the pool of a [`PooledSqlDb`], created with
+/// [`PooledSqlDb::builder`].
+pub struct PooledSqlDbBuilder
+{
+    config:             sql::Config,
+    max_size:           u32,
+    connection_timeout: Duration,
+    idle_timeout:       Option<Duration>,
+}
+
+impl PooledSqlDbBuilder
+{
+    /// Set the largest number of connections the pool opens, which is the
+    /// number of calls that can run at the same time. Defaults to
+    /// [`DEFAULT_POOL_SIZE`].
+    pub fn max_size(mut self, max_size: u32) -> Self
+    {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set how long a call waits for a connection to become free before it
+    /// fails. Defaults to [`DEFAULT_CONNECTION_TIMEOUT`].
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Self
+    {
+        self.connection_timeout = connection_timeout;
+        self
+    }
+
+    /// Set how long a connection may be unused before it is closed, or `None`
+    /// to keep connections open. Defaults to 10 minutes.
+    pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self
+    {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Connect to the database and create the tables needed, in case they are
+    /// not present yet.
+    pub fn build(self) -> DbResult<PooledSqlDb>
+    {
+        let manager = PostgresConnectionManager::new(self.config, sql::NoTls);
+        let pool = Pool::builder()
+            .max_size(self.max_size)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+            .connection_customizer(Box::new(SessionSetup))
+            .build(manager)?;
+        SQLDb::with_connection(Connection::Pooled(pool.get()?)).init_tables()?;
+
+        Ok(PooledSqlDb {
+            pool,
+            store_chat: false,
+            store_unregistered: true,
+            max_duration_secs: DEFAULT_MAX_DURATION_SECS,
+            max_player_logs: DEFAULT_MAX_PLAYER_LOGS,
+            search_batch_size: DEFAULT_SEARCH_BATCH_SIZE,
+            title_filter: TitleFilter::default(),
+            update_tag: None,
+            event_handler: None,
+        })
+    }
+}
+
+/// Sets up every connection the pool opens like the connection of a [`SQLDb`].
+#[derive(Debug)]
+struct SessionSetup;
+
+impl CustomizeConnection<sql::Client, sql::Error> for SessionSetup
+{
+    fn on_acquire(&self, client: &mut sql::Client) -> Result<(), sql::Error>
+    {
+        client.batch_execute(SESSION_SETUP)
+    }
+}
+
+impl PooledSqlDb
+{
+    /// Connect to the local database with
+    /// [`DEFAULT_CONNECTION_PARAMS`](super::DEFAULT_CONNECTION_PARAMS), with
+    /// the default settings of the pool.
+    pub fn start() -> DbResult<Self> { Self::builder(DEFAULT_CONNECTION_PARAMS.parse()?).build() }
+
+    /// Start building a database that connects with the `config`.
+    pub fn builder(config: sql::Config) -> PooledSqlDbBuilder
+    {
+        PooledSqlDbBuilder {
+            config,
+            max_size: DEFAULT_POOL_SIZE,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+
+    /// See [`SQLDb::store_chat`].
+    pub fn store_chat(mut self, store_chat: bool) -> Self
+    {
+        self.store_chat = store_chat;
+        self
+    }
+
+    /// See [`SQLDb::store_unregistered`].
+    pub fn store_unregistered(mut self, store_unregistered: bool) -> Self
+    {
+        self.store_unregistered = store_unregistered;
+        self
+    }
+
+    /// See [`SQLDb::max_duration_secs`].
+    pub fn max_duration_secs(mut self, max_duration_secs: u32) -> Self
+    {
+        self.max_duration_secs = max_duration_secs;
+        self
+    }
+
+    /// See [`SQLDb::max_player_logs`].
+    pub fn max_player_logs(mut self, max_player_logs: usize) -> Self
+    {
+        self.max_player_logs = max_player_logs;
+        self
+    }
+
+    /// See [`SQLDb::search_batch_size`].
+    pub fn search_batch_size(mut self, search_batch_size: usize) -> Self
+    {
+        self.search_batch_size = search_batch_size;
+        self
+    }
+
+    /// See [`SQLDb::title_filter`].
+    pub fn title_filter(mut self, title_filter: TitleFilter) -> Self
+    {
+        self.title_filter = title_filter;
+        self
+    }
+
+    /// See [`SQLDb::update_tag`].
+    pub fn update_tag(mut self, update_tag: Option<LogTag>) -> Self
+    {
+        self.update_tag = update_tag;
+        self
+    }
+
+    /// The number of connections that are currently open and the number of
+    /// them that are not in use.
+    pub fn connections(&self) -> (u32, u32)
+    {
+        let state = self.pool.state();
+        (state.connections, state.idle_connections)
+    }
+
+    /// Check out a connection of the pool and wrap it with the settings of this
+    /// database. The connection is returned when the database is dropped.
+    fn checkout(&self) -> DbResult<SQLDb>
+    {
+        let mut db = SQLDb::with_connection(Connection::Pooled(self.pool.get()?));
+        db.store_chat = self.store_chat;
+        db.store_unregistered = self.store_unregistered;
+        db.max_duration_secs = self.max_duration_secs;
+        db.max_player_logs = self.max_player_logs;
+        db.search_batch_size = self.search_batch_size;
+        db.title_filter = self.title_filter.clone();
+        db.update_tag = self.update_tag.clone();
+        if let Some(handler) = &self.event_handler {
+            let handler = Arc::clone(handler);
+            db.event_handler = Some(Box::new(move |event| {
+                (handler.lock().unwrap_or_else(PoisonError::into_inner))(event)
+            }));
+        }
+
+        Ok(db)
+    }
+}
+
+impl Database for PooledSqlDb
+{
+    fn set_event_handler(&mut self, handler: EventHandler)
+    {
+        self.event_handler = Some(Arc::new(Mutex::new(handler)));
+    }
+
+    fn add_user(&mut self, steam_id: SteamID, discord_id: u64) -> MixesDbResult<bool>
+    {
+        self.checkout()?.add_user(steam_id, discord_id)
+    }
+
+    fn add_users_bulk(&mut self, entries: &[(u64, &str)]) -> MixesDbResult<BulkAddReport>
+    {
+        self.checkout()?.add_users_bulk(entries)
+    }
+
+    fn remove_user(&mut self, steam_id: SteamID) -> MixesDbResult<bool>
+    {
+        self.checkout()?.remove_user(steam_id)
+    }
+
+    fn purge_player_data(
+        &mut self,
+        steam_id: SteamID,
+        remove_sole_logs: bool,
+    ) -> MixesDbResult<PurgeReport>
+    {
+        self.checkout()?
+            .purge_player_data(steam_id, remove_sole_logs)
+    }
+
+    fn apply_retention(&mut self, policy: RetentionPolicy) -> MixesDbResult<RetentionReport>
+    {
+        self.checkout()?.apply_retention(policy)
+    }
+
+    fn check_integrity(&mut self, repair: bool) -> MixesDbResult<IntegrityReport>
+    {
+        self.checkout()?.check_integrity(repair)
+    }
+
+    fn users_checked(&mut self) -> MixesDbResult<UsersReport> { self.checkout()?.users_checked() }
+
+    fn user_records(&mut self) -> MixesDbResult<Vec<UserRecord>> { self.checkout()?.user_records() }
+
+    fn user_record(&mut self, steam_id: SteamID) -> MixesDbResult<Option<UserRecord>>
+    {
+        self.checkout()?.user_record(steam_id)
+    }
+
+    fn set_user_active(&mut self, steam_id: SteamID, active: bool) -> MixesDbResult<bool>
+    {
+        self.checkout()?.set_user_active(steam_id, active)
+    }
+
+    fn username(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>
+    {
+        self.checkout()?.username(steam_id)
+    }
+
+    fn set_username(&mut self, steam_id: SteamID, username: &str) -> MixesDbResult<bool>
+    {
+        self.checkout()?.set_username(steam_id, username)
+    }
+
+    fn find_players_by_name(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, String)>>
+    {
+        self.checkout()?.find_players_by_name(query, limit)
+    }
+
+    fn player_name(&mut self, steam_id: SteamID) -> MixesDbResult<Option<String>>
+    {
+        self.checkout()?.player_name(steam_id)
+    }
+
+    fn add_log(&mut self, log: Log) -> MixesDbResult<bool> { self.checkout()?.add_log(log) }
+
+    fn add_log_from_json(&mut self, id: LogId, json: &str) -> MixesDbResult<bool>
+    {
+        self.checkout()?.add_log_from_json(id, json)
+    }
+
+    fn has_log(&mut self, id: LogId) -> MixesDbResult<bool> { self.checkout()?.has_log(id) }
+
+    fn max_log_id(&mut self) -> MixesDbResult<Option<LogId>> { self.checkout()?.max_log_id() }
+
+    fn tag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>
+    {
+        self.checkout()?.tag_log(id, tag)
+    }
+
+    fn untag_log(&mut self, id: LogId, tag: &LogTag) -> MixesDbResult<bool>
+    {
+        self.checkout()?.untag_log(id, tag)
+    }
+
+    fn logs_with_tag(&mut self, tag: &LogTag) -> MixesDbResult<Vec<LogId>>
+    {
+        self.checkout()?.logs_with_tag(tag)
+    }
+
+    fn link_external_match(
+        &mut self,
+        id: LogId,
+        league: &League,
+        match_id: u64,
+    ) -> MixesDbResult<bool>
+    {
+        self.checkout()?.link_external_match(id, league, match_id)
+    }
+
+    fn external_match(&mut self, id: LogId) -> MixesDbResult<Option<ExternalMatch>>
+    {
+        self.checkout()?.external_match(id)
+    }
+
+    fn logs_for_match(&mut self, league: &League, match_id: u64) -> MixesDbResult<Vec<LogId>>
+    {
+        self.checkout()?.logs_for_match(league, match_id)
+    }
+
+    fn ingest_log_by_ref(
+        &mut self,
+        source: &dyn LogSource,
+        log_ref: &str,
+    ) -> MixesDbResult<IngestOutcome>
+    {
+        self.checkout()?.ingest_log_by_ref(source, log_ref)
+    }
+
+    fn update_with(
+        &mut self,
+        source: &dyn LogSource,
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        self.checkout()?.update_with(source, options)
+    }
+
+    fn update_players(
+        &mut self,
+        source: &dyn LogSource,
+        users: &[SteamID],
+        options: UpdateOptions,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        self.checkout()?.update_players(source, users, options)
+    }
+
+    fn update(
+        &mut self,
+        min_ratio: f32,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        self.checkout()?.update(min_ratio, num_players)
+    }
+
+    fn update_dry_run(
+        &mut self,
+        source: &dyn LogSource,
+        options: &UpdateOptions,
+    ) -> MixesDbResult<UpdatePlan>
+    {
+        self.checkout()?.update_dry_run(source, options)
+    }
+
+    fn update_history(&mut self, limit: usize) -> MixesDbResult<Vec<UpdateRunRecord>>
+    {
+        self.checkout()?.update_history(limit)
+    }
+
+    fn backfill_player(
+        &mut self,
+        source: &dyn LogSource,
+        user: SteamID,
+        max_logs: usize,
+        num_players: RangeInclusive<u16>,
+    ) -> MixesDbResult<UpdateReport>
+    {
+        self.checkout()?
+            .backfill_player(source, user, max_logs, num_players)
+    }
+
+    fn status(&mut self) -> MixesDbResult<DbStatus> { self.checkout()?.status() }
+
+    fn export_snapshot(&mut self, writer: &mut dyn Write) -> MixesDbResult<SnapshotInfo>
+    {
+        self.checkout()?.export_snapshot(writer)
+    }
+
+    fn import_snapshot(
+        &mut self,
+        reader: &mut dyn Read,
+        mode: ImportMode,
+    ) -> MixesDbResult<SnapshotInfo>
+    {
+        self.checkout()?.import_snapshot(reader, mode)
+    }
+
+    fn data_freshness(&mut self, stale_after: chrono::Duration) -> MixesDbResult<Freshness>
+    {
+        self.checkout()?.data_freshness(stale_after)
+    }
+
+    fn get_player_log_performance(
+        &mut self,
+        log_id: LogId,
+        user: SteamID,
+    ) -> MixesDbResult<Option<LogPerformance>>
+    {
+        self.checkout()?.get_player_log_performance(log_id, user)
+    }
+
+    fn log_rosters(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, Vec<SteamID>>>
+    {
+        self.checkout()?.log_rosters(log_id)
+    }
+
+    fn team_summary(&mut self, log_id: LogId) -> MixesDbResult<HashMap<Team, TeamSummary>>
+    {
+        self.checkout()?.team_summary(log_id)
+    }
+
+    fn logs_between(&mut self, from: DateTime<Utc>, to: DateTime<Utc>)
+        -> MixesDbResult<Vec<LogId>>
+    {
+        self.checkout()?.logs_between(from, to)
+    }
+
+    fn log_performances_between(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<Vec<LogWithPerformances>>
+    {
+        self.checkout()?.log_performances_between(from, to)
+    }
+
+    fn participation_stats(
+        &mut self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> MixesDbResult<ParticipationStats>
+    {
+        self.checkout()?.participation_stats(from, to)
+    }
+
+    fn get_classes_performance(
+        &mut self,
+        user: SteamID,
+        classes: &[Class],
+        limit: usize,
+        options: &QueryOptions,
+    ) -> MixesDbResult<Vec<(LogId, LogMetadata, LogPerformance)>>
+    {
+        self.checkout()?
+            .get_classes_performance(user, classes, limit, options)
+    }
+
+    fn percentile_for_metric(
+        &mut self,
+        user: SteamID,
+        class: Class,
+        metric: Metric,
+        min_games: u32,
+        include_suspect: bool,
+        min_class_time_secs: u32,
+    ) -> MixesDbResult<Option<f32>>
+    {
+        self.checkout()?.percentile_for_metric(
+            user,
+            class,
+            metric,
+            min_games,
+            include_suspect,
+            min_class_time_secs,
+        )
+    }
+
+    fn normalized_leaderboard(
+        &mut self,
+        metric: Metric,
+        min_games: u32,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, Class, f64)>>
+    {
+        self.checkout()?
+            .normalized_leaderboard(metric, min_games, limit)
+    }
+
+    fn get_heal_spread(
+        &mut self,
+        medic: SteamID,
+        limit: usize,
+    ) -> MixesDbResult<Vec<(SteamID, u64)>>
+    {
+        self.checkout()?.get_heal_spread(medic, limit)
+    }
+
+    fn carry_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<CarryStats>
+    {
+        self.checkout()?.carry_stats(user, limit)
+    }
+
+    fn round_stats(&mut self, user: SteamID, limit: usize) -> MixesDbResult<RoundStats>
+    {
+        self.checkout()?.round_stats(user, limit)
+    }
+
+    fn personal_records(&mut self, user: SteamID) -> MixesDbResult<Vec<RecordEntry>>
+    {
+        self.checkout()?.personal_records(user)
+    }
+
+    fn recent_games(
+        &mut self,
+        user: SteamID,
+        limit: usize,
+        tags: &TagFilter,
+    ) -> MixesDbResult<Vec<GameSummary>>
+    {
+        self.checkout()?.recent_games(user, limit, tags)
+    }
+
+    fn teammate_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>
+    {
+        self.checkout()?.teammate_winrates(user, min_shared_games)
+    }
+
+    fn opponent_winrates(
+        &mut self,
+        user: SteamID,
+        min_shared_games: u32,
+    ) -> MixesDbResult<Vec<TeammateRecord>>
+    {
+        self.checkout()?.opponent_winrates(user, min_shared_games)
+    }
+
+    fn create_season(
+        &mut self,
+        name: &str,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> MixesDbResult<Option<SeasonId>>
+    {
+        self.checkout()?.create_season(name, start, end)
+    }
+
+    fn close_season(&mut self, end: DateTime<Utc>) -> MixesDbResult<bool>
+    {
+        self.checkout()?.close_season(end)
+    }
+
+    fn current_season(&mut self) -> MixesDbResult<Option<Season>>
+    {
+        self.checkout()?.current_season()
+    }
+
+    fn assign_unmatched_logs_to_seasons(&mut self) -> MixesDbResult<u64>
+    {
+        self.checkout()?.assign_unmatched_logs_to_seasons()
+    }
+
+    fn search_chat(&mut self, pattern: &str, limit: usize) -> MixesDbResult<Vec<ChatSearchResult>>
+    {
+        self.checkout()?.search_chat(pattern, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::str::FromStr;
+    use std::thread;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::MixesDbError;
+
+    const TEST_DB_PARAMS: &str = "host=localhost user=mixes dbname=mixes-stats";
+
+    /// A schema that is shared by all connections of a pool, unlike the
+    /// temporary schema of a single connection. It is dropped with everything
+    /// in it when the test is done.
+    struct TestSchema
+    {
+        name: String,
+    }
+
+    impl TestSchema
+    {
+        fn create(name: &str) -> Self
+        {
+            let name = format!("{}_{}", name, std::process::id());
+            let mut client = sql::Client::connect(TEST_DB_PARAMS, sql::NoTls).unwrap();
+            client
+                .batch_execute(&format!(
+                    "DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}",
+                    name
+                ))
+                .unwrap();
+
+            Self { name }
+        }
+
+        fn config(&self) -> sql::Config
+        {
+            let mut config: sql::Config = TEST_DB_PARAMS.parse().unwrap();
+            config.options(&format!("-c search_path={}", self.name));
+
+            config
+        }
+    }
+
+    impl Drop for TestSchema
+    {
+        fn drop(&mut self)
+        {
+            if let Ok(mut client) = sql::Client::connect(TEST_DB_PARAMS, sql::NoTls) {
+                let _ = client.batch_execute(&format!("DROP SCHEMA {} CASCADE", self.name));
+            }
+        }
+    }
+
+    fn fixture_log(id: u32) -> Log
+    {
+        let json = std::fs::read_to_string("test_data/log_3094861.json").unwrap();
+        let mut json = json::parse(&json).unwrap();
+        json["info"]["date"] = (id as i64 * 1000).into();
+
+        Log::from_json(LogId(id), &json).unwrap()
+    }
+
+    #[test]
+    fn concurrent_reads_and_writes()
+    {
+        let schema = TestSchema::create("pooled_concurrent");
+        let mut db = PooledSqlDb::builder(schema.config())
+            .max_size(6)
+            .connection_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+        let classes_performance = move |db: &mut PooledSqlDb| {
+            db.get_classes_performance(scout, &[Class::Scout], 100, &QueryOptions::default())
+                .unwrap()
+        };
+
+        db.add_log(fixture_log(1)).unwrap();
+        let expected = classes_performance(&mut db).remove(0).2;
+
+        let writer = {
+            let mut db = db.clone();
+            thread::spawn(move || {
+                for id in 2..=10 {
+                    assert!(db.add_log(fixture_log(id)).unwrap());
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut db = db.clone();
+                let expected = expected.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        // Every log is either there with all of its stats or not at all.
+                        let performances = classes_performance(&mut db);
+                        assert!(!performances.is_empty());
+                        for (id, meta, performance) in performances {
+                            assert_eq!(meta.id, id);
+                            assert_eq!(
+                                meta.date_time,
+                                Utc.timestamp_opt(id.0 as i64 * 1000, 0).unwrap()
+                            );
+                            assert_eq!(performance, expected);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(classes_performance(&mut db).len(), 10);
+        let (connections, idle) = db.connections();
+        assert!(connections <= 6);
+        assert_eq!(idle, connections);
+    }
+
+    #[test]
+    fn shared_between_clones()
+    {
+        let schema = TestSchema::create("pooled_clones");
+        let mut db = PooledSqlDb::builder(schema.config()).build().unwrap();
+        let mut clone = db.clone();
+        let scout = SteamID::from_str("[U:1:886717065]").unwrap();
+
+        assert!(clone.add_user(scout, 1).unwrap());
+        assert!(!db.add_user(scout, 1).unwrap());
+        assert_eq!(db.users().unwrap(), vec![scout]);
+    }
+
+    #[test]
+    fn utc_sessions()
+    {
+        let schema = TestSchema::create("pooled_utc");
+        let mut config = schema.config();
+        config.options(&format!(
+            "-c search_path={} -c TimeZone=Asia/Tokyo",
+            schema.name
+        ));
+        let db = PooledSqlDb::builder(config).max_size(2).build().unwrap();
+
+        let (mut first, mut second) = (db.checkout().unwrap(), db.checkout().unwrap());
+        for checked_out in [&mut first, &mut second] {
+            let row = checked_out.client.query_one("SHOW TIME ZONE", &[]).unwrap();
+            assert_eq!(row.get::<_, String>(0), "UTC");
+        }
+    }
+
+    #[test]
+    fn connection_timeout()
+    {
+        let schema = TestSchema::create("pooled_timeout");
+        let db = PooledSqlDb::builder(schema.config())
+            .max_size(1)
+            .connection_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let _busy = db.checkout().unwrap();
+        match db.clone().status() {
+            Err(MixesDbError::Backend(e)) => assert!(e.is::<r2d2::Error>()),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
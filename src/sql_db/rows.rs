@@ -0,0 +1,195 @@
+//! Decoding of the rows of the tables of a [`SQLDb`](super::SQLDb). Each
+//! function expects the row to consist of the columns listed in the matching
+//! constant, in that order, so custom queries can select them with the
+//! constant and decode the result the same way the database does.
+
+use std::str::FromStr;
+
+use num_traits::FromPrimitive;
+use postgres::Row;
+
+use super::stored_user_id;
+use crate::database::{Metric, RecordEntry};
+use crate::dm_performance::DMPerformance;
+use crate::logs_tf::LogMetadata;
+use crate::medic_performance::MedicPerformance;
+use crate::overall_performance::OverallPerformance;
+use crate::score::Team;
+use crate::Class;
+
+/// The columns of the `overall_stats` table read by [`row_to_overall`].
+pub const OVERALL_COLUMNS: &str = "won_rounds, num_rounds, damage, damage_taken, kills, deaths, \
+                                   num_medkits, medkits_hp, team, captures, heals_received";
+/// The columns of the `dm_stats` table read by [`row_to_dm`].
+pub const DM_COLUMNS: &str =
+    "class, damage, kills, assists, deaths, time_played_secs, sentry_kills, team";
+/// The columns of the `med_stats` table read by [`row_to_med`].
+pub const MED_COLUMNS: &str = "healing, average_uber_length_secs, num_ubers, num_drops, deaths, \
+                               time_played_secs, num_kritz, advantages_lost, \
+                               biggest_advantage_lost_secs, team";
+/// The columns of the `logs` table read by [`row_to_meta`].
+pub const META_COLUMNS: &str = "log_id, date, map, num_players, title, uploader, duration_secs";
+/// The columns of the `personal_records` table read by [`row_to_record`].
+pub const RECORD_COLUMNS: &str = "class, metric, value, log_id, date";
+
+/// Read a row of the `overall_stats` table consisting of the
+/// [`OVERALL_COLUMNS`].
+///
+/// # Panics
+/// If the row does not have these columns or the team is invalid.
+pub fn row_to_overall(row: &Row) -> OverallPerformance
+{
+    let won_rounds: i16 = row.get(0);
+    let num_rounds: i16 = row.get(1);
+    let damage: i32 = row.get(2);
+    let damage_taken: i32 = row.get(3);
+    let kills: i16 = row.get(4);
+    let deaths: i16 = row.get(5);
+    let num_medkits: i16 = row.get(6);
+    let medkits_hp: i32 = row.get(7);
+    let team: Option<i16> = row.get(8);
+    let captures: i16 = row.get(9);
+    let heals_received: i32 = row.get(10);
+    let team = team.map(read_team);
+
+    OverallPerformance {
+        team,
+        won_rounds: won_rounds as u8,
+        num_rounds: num_rounds as u8,
+        damage: damage as u32,
+        damage_taken: damage_taken as u32,
+        kills: kills as u16,
+        deaths: deaths as u16,
+        num_medkits: num_medkits as u16,
+        medkits_hp: medkits_hp as u32,
+        captures: captures as u16,
+        heals_received: heals_received as u32,
+    }
+}
+
+/// Read a row of the `dm_stats` table consisting of the [`DM_COLUMNS`].
+///
+/// # Panics
+/// If the row does not have these columns or the class or team is invalid.
+pub fn row_to_dm(row: &Row) -> DMPerformance
+{
+    let class: i16 = row.get(0);
+    let damage: i32 = row.get(1);
+    let kills: i16 = row.get(2);
+    let assists: i16 = row.get(3);
+    let deaths: i16 = row.get(4);
+    let time_played_secs: i32 = row.get(5);
+    let sentry_kills: Option<i16> = row.get(6);
+    let team: Option<i16> = row.get(7);
+
+    DMPerformance {
+        class:            Class::from_i16(class).expect("Invalid class in the database"),
+        kills:            kills as u16,
+        assists:          assists as u16,
+        deaths:           deaths as u16,
+        damage:           damage as u32,
+        time_played_secs: time_played_secs as u32,
+        sentry_kills:     sentry_kills.map(|kills| kills as u16),
+        team:             team.map(read_team),
+    }
+}
+
+/// Read a row of the `med_stats` table consisting of the [`MED_COLUMNS`]. The
+/// heal targets are stored in the `heal_spread` table, so they are left empty.
+///
+/// # Panics
+/// If the row does not have these columns or the team is invalid.
+pub fn row_to_med(row: &Row) -> MedicPerformance
+{
+    let healing: i32 = row.get(0);
+    let average_uber_length_secs: f32 = row.get(1);
+    let num_ubers: i16 = row.get(2);
+    let num_drops: i16 = row.get(3);
+    let deaths: i16 = row.get(4);
+    let time_played_secs: i32 = row.get(5);
+    let num_kritz: i16 = row.get(6);
+    let advantages_lost: i16 = row.get(7);
+    let biggest_advantage_lost_secs: f32 = row.get(8);
+    let team: Option<i16> = row.get(9);
+
+    MedicPerformance {
+        healing: healing as u32,
+        average_uber_length_secs,
+        num_ubers: num_ubers as u8,
+        num_drops: num_drops as u8,
+        num_kritz: num_kritz as u8,
+        advantages_lost: advantages_lost as u8,
+        biggest_advantage_lost_secs,
+        deaths: deaths as u16,
+        time_played_secs: time_played_secs as u32,
+        heal_targets: Vec::new(),
+        team: team.map(read_team),
+    }
+}
+
+/// Read a row of the `personal_records` table consisting of the
+/// [`RECORD_COLUMNS`].
+///
+/// # Panics
+/// If the row does not have these columns or the class or metric is invalid.
+pub fn row_to_record(row: &Row) -> RecordEntry
+{
+    let class: i16 = row.get(0);
+    let metric: &str = row.get(1);
+
+    RecordEntry {
+        class:  Class::from_i16(class).expect("Invalid class in the database"),
+        metric: Metric::from_str(metric).expect("Invalid metric in the database"),
+        value:  row.get(2),
+        log_id: row.get(3),
+        date:   row.get(4),
+    }
+}
+
+/// Read the `round_winners` of the `logs` table, which are in the column of
+/// the row at `idx`. Logs stored before the rounds were do not have them.
+///
+/// # Panics
+/// If the row does not have the column or a team is invalid.
+pub fn row_to_round_winners(row: &Row, idx: usize) -> Option<Vec<Option<Team>>>
+{
+    let winners: Option<Vec<Option<i16>>> = row.get(idx);
+
+    winners.map(|winners| {
+        winners
+            .into_iter()
+            .map(|winner| {
+                winner.map(|team| Team::from_i16(team).expect("Invalid team in the database"))
+            })
+            .collect()
+    })
+}
+
+/// Read a row of the `logs` table consisting of the [`META_COLUMNS`]. An
+/// invalid uploader is logged and left out.
+///
+/// # Panics
+/// If the row does not have these columns.
+pub fn row_to_meta(row: &Row) -> LogMetadata
+{
+    let num_players: i16 = row.get(3);
+    let uploader: Option<i64> = row.get(5);
+    let duration_secs: Option<i32> = row.get(6);
+
+    LogMetadata {
+        id:            row.get(0),
+        date_time:     row.get(1),
+        map:           row.get(2),
+        num_players:   num_players as u16,
+        title:         row.get(4),
+        uploader:      uploader.and_then(|uploader| stored_user_id(uploader).ok()),
+        // Broken rows may have a negative duration, which is as good as none.
+        duration_secs: duration_secs.and_then(|secs| u32::try_from(secs).ok()),
+    }
+}
+
+/// Read a team stored as a number.
+///
+/// # Panics
+/// If the team is invalid.
+fn read_team(team: i16) -> Team { Team::from_i16(team).expect("Invalid team in the database") }
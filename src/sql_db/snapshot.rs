@@ -0,0 +1,401 @@
+//! Snapshots of everything stored in a [`SQLDb`], see
+//! [`Database::export_snapshot`](crate::Database::export_snapshot).
+//!
+//! A snapshot is written as json lines. The first line is a header with the
+//! format, its version and the version of the schema of the database. Each
+//! table starts with a line naming it and its columns, followed by a line for
+//! each of its rows. The last line marks the end of the snapshot, so that a
+//! snapshot that has been cut off is not loaded:
+//!
+//! ```text
+//! {"format":"mixes-db-snapshot","version":1,"schema_version":24,"created_at":"..."}
+//! {"table":"users","columns":["steam_id","discord_id",...]}
+//! {"row":{"steam_id":76561198846982793,"discord_id":1,...}}
+//! ...
+//! {"end":true,"num_rows":1234}
+//! ```
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use chrono::{DateTime, Utc};
+use json::JsonValue;
+use postgres as sql;
+
+use super::{replace_aggregates, replace_records, DbError, DbResult, SQLDb, SCHEMA_VERSION};
+use crate::database::{ImportMode, SnapshotInfo};
+
+/// Names the format in the header of a snapshot.
+const SNAPSHOT_FORMAT: &str = "mixes-db-snapshot";
+/// The version of the format of snapshots, which changes whenever snapshots
+/// have to be read differently. Changes of the schema do not change it.
+const SNAPSHOT_VERSION: u32 = 1;
+/// The number of rows that are read from or loaded into a table at once.
+const SNAPSHOT_BATCH_SIZE: usize = 1000;
+
+/// Skips rows whose key is already stored when merging.
+const SKIP_EXISTING: &str = "ON CONFLICT DO NOTHING";
+/// Skips the rows of logs that have been stored before the import when
+/// merging.
+const SKIP_KNOWN_LOGS: &str = "WHERE log_id NOT IN (SELECT log_id FROM snapshot_known_logs)";
+
+/// A table that is part of a snapshot.
+struct SnapshotTable
+{
+    name:    &'static str,
+    /// The end of the query that loads the rows of the table when the
+    /// database is replaced, after the rows to load are selected.
+    replace: &'static str,
+    /// The end of the query that loads the rows when merging.
+    merge:   &'static str,
+}
+
+/// The tables in a snapshot, in the order they are written in. The aggregates
+/// are left out, since they are rebuilt from the stats.
+const SNAPSHOT_TABLES: [SnapshotTable; 12] = [
+    SnapshotTable::skip_existing("users"),
+    SnapshotTable::skip_existing("seasons"),
+    SnapshotTable::skip_existing("logs"),
+    SnapshotTable::skip_known_logs("heal_spread"),
+    SnapshotTable::skip_known_logs("overall_stats"),
+    SnapshotTable::skip_known_logs("dm_stats"),
+    SnapshotTable::skip_known_logs("med_stats"),
+    SnapshotTable::skip_known_logs("chat"),
+    SnapshotTable::skip_known_logs("log_tags"),
+    SnapshotTable::skip_existing("player_names"),
+    SnapshotTable::skip_existing("update_runs"),
+    // The version of the schema is the one of the database, not the one of
+    // the snapshot. The highest log id that has been seen is kept when merging.
+    SnapshotTable {
+        name:    "meta",
+        replace: "WHERE key <> 'schema_version'",
+        merge:   "WHERE key <> 'schema_version' ON CONFLICT (key) DO UPDATE SET value = \
+                  EXCLUDED.value WHERE meta.key = 'max_log_id' AND EXCLUDED.value::bigint > \
+                  meta.value::bigint",
+    },
+];
+
+/// The tables with a serial column, which continues after the highest value
+/// that has been loaded.
+const SERIAL_COLUMNS: [(&str, &str); 2] = [("seasons", "season_id"), ("update_runs", "run_id")];
+
+impl SnapshotTable
+{
+    const fn skip_existing(name: &'static str) -> Self
+    {
+        Self {
+            name,
+            replace: "",
+            merge: SKIP_EXISTING,
+        }
+    }
+
+    const fn skip_known_logs(name: &'static str) -> Self
+    {
+        Self {
+            name,
+            replace: "",
+            merge: SKIP_KNOWN_LOGS,
+        }
+    }
+}
+
+impl SQLDb
+{
+    /// Write a snapshot of all tables to the `writer`, see
+    /// [`Database::export_snapshot`](crate::Database::export_snapshot). The
+    /// tables are read in a single transaction, so they are consistent with
+    /// each other.
+    pub(super) fn write_snapshot(&mut self, writer: &mut dyn Write) -> DbResult<SnapshotInfo>
+    {
+        let mut transaction = self
+            .client
+            .build_transaction()
+            .isolation_level(sql::IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()?;
+
+        let created_at = Utc::now();
+        let header = json::object! {
+            format: SNAPSHOT_FORMAT,
+            version: SNAPSHOT_VERSION,
+            schema_version: SCHEMA_VERSION,
+            created_at: created_at.to_rfc3339(),
+        };
+        writeln!(writer, "{}", header.dump())?;
+
+        let mut num_rows = Vec::with_capacity(SNAPSHOT_TABLES.len());
+        for table in &SNAPSHOT_TABLES {
+            let columns = table_columns(&mut transaction, table.name)?;
+            let table_header = json::object! {
+                table: table.name,
+                columns: columns.clone(),
+            };
+            writeln!(writer, "{}", table_header.dump())?;
+
+            let portal = transaction.bind(
+                format!(
+                    "SELECT row_to_json(snapshot)::text FROM (SELECT {} FROM {}) snapshot",
+                    columns.join(", "),
+                    table.name
+                )
+                .as_str(),
+                &[],
+            )?;
+            let mut num_table_rows = 0;
+            loop {
+                let rows = transaction.query_portal(&portal, SNAPSHOT_BATCH_SIZE as i32)?;
+                if rows.is_empty() {
+                    break;
+                }
+                for row in &rows {
+                    let row: &str = row.get(0);
+                    writeln!(writer, "{{\"row\":{}}}", row)?;
+                }
+                num_table_rows += rows.len() as u64;
+            }
+            num_rows.push((table.name, num_table_rows));
+        }
+
+        let total_rows: u64 = num_rows.iter().map(|(_, num_rows)| num_rows).sum();
+        let end = json::object! {
+            end: true,
+            num_rows: total_rows,
+        };
+        writeln!(writer, "{}", end.dump())?;
+        writer.flush()?;
+        transaction.commit()?;
+
+        Ok(SnapshotInfo {
+            schema_version: SCHEMA_VERSION,
+            created_at,
+            num_rows,
+        })
+    }
+
+    /// Load a snapshot from the `reader`, see
+    /// [`Database::import_snapshot`](crate::Database::import_snapshot).
+    pub(super) fn read_snapshot(
+        &mut self,
+        reader: &mut dyn Read,
+        mode: ImportMode,
+    ) -> DbResult<SnapshotInfo>
+    {
+        let mut lines = BufReader::new(reader).lines().enumerate();
+        let mut next_line = || -> DbResult<(usize, JsonValue)> {
+            match lines.next() {
+                Some((idx, line)) => {
+                    let json = json::parse(&line?).map_err(|_| {
+                        invalid(format!("Line {} of the snapshot is not json", idx + 1))
+                    })?;
+                    Ok((idx + 1, json))
+                },
+                None => Err(invalid("The snapshot ends before its end has been marked")),
+            }
+        };
+
+        let (_, header) = next_line()?;
+        if header["format"] != SNAPSHOT_FORMAT {
+            return Err(invalid("The file is not a snapshot of a mixes database"));
+        }
+        if header["version"] != SNAPSHOT_VERSION {
+            return Err(invalid(format!(
+                "Version {} of the snapshot format is not supported",
+                header["version"]
+            )));
+        }
+        let schema_version = match header["schema_version"].as_u32() {
+            Some(schema_version) => schema_version,
+            None => return Err(invalid("The snapshot lacks the version of its schema")),
+        };
+        if schema_version > SCHEMA_VERSION {
+            return Err(DbError::SchemaTooNew {
+                found:     schema_version,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        let created_at = header["created_at"]
+            .as_str()
+            .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok())
+            .ok_or_else(|| invalid("The snapshot lacks when it has been taken"))?
+            .with_timezone(&Utc);
+
+        let mut transaction = self.client.transaction()?;
+        match mode {
+            ImportMode::ReplaceAll => {
+                let tables: Vec<&str> = SNAPSHOT_TABLES
+                    .iter()
+                    .map(|table| table.name)
+                    .filter(|&name| name != "meta")
+                    .collect();
+                transaction.batch_execute(
+                    format!(
+                        "TRUNCATE {}; DELETE FROM meta WHERE key <> 'schema_version'",
+                        tables.join(", ")
+                    )
+                    .as_str(),
+                )?;
+            },
+            ImportMode::MergeSkipExisting => transaction.batch_execute(
+                "CREATE TEMPORARY TABLE snapshot_known_logs ON COMMIT DROP AS SELECT log_id FROM \
+                 logs",
+            )?,
+        }
+
+        let mut loader: Option<TableLoader> = None;
+        let mut num_rows = Vec::with_capacity(SNAPSHOT_TABLES.len());
+        loop {
+            let (line_number, line) = next_line()?;
+            if line.has_key("row") {
+                let loader = loader.as_mut().ok_or_else(|| {
+                    invalid(format!(
+                        "Line {} of the snapshot is a row outside of any table",
+                        line_number
+                    ))
+                })?;
+                loader.rows.push(line["row"].dump());
+                if loader.rows.len() == SNAPSHOT_BATCH_SIZE {
+                    loader.load(&mut transaction, mode)?;
+                }
+                continue;
+            }
+
+            if let Some(loader) = loader.take() {
+                num_rows.push(loader.finish(&mut transaction, mode)?);
+            }
+            if line["end"] == true {
+                break;
+            }
+            match line["table"].as_str() {
+                Some(name) => loader = Some(TableLoader::new(&mut transaction, name, &line)?),
+                None => {
+                    return Err(invalid(format!(
+                        "Line {} of the snapshot is neither a table nor a row",
+                        line_number
+                    )))
+                },
+            }
+        }
+
+        for (table, column) in SERIAL_COLUMNS {
+            transaction.execute(
+                format!(
+                    "SELECT setval(pg_get_serial_sequence('{0}', '{1}'), COALESCE(MAX({1}), 0) + \
+                     1, false) FROM {0}",
+                    table, column
+                )
+                .as_str(),
+                &[],
+            )?;
+        }
+        replace_aggregates(&mut transaction)?;
+        replace_records(&mut transaction, None)?;
+        transaction.commit()?;
+
+        Ok(SnapshotInfo {
+            schema_version,
+            created_at,
+            num_rows,
+        })
+    }
+}
+
+/// Loads the rows of a table of a snapshot in batches.
+struct TableLoader
+{
+    table:    &'static SnapshotTable,
+    /// The columns of the snapshot that the table has as well.
+    columns:  String,
+    /// The rows that have not been loaded yet, as json objects.
+    rows:     Vec<String>,
+    num_rows: u64,
+}
+
+impl TableLoader
+{
+    /// Start loading the table named in the `header` of its rows. Columns of
+    /// the snapshot that the table does not have are left out, and columns
+    /// that the snapshot does not have, since it has been taken of an older
+    /// schema, get their default.
+    fn new(transaction: &mut sql::Transaction, name: &str, header: &JsonValue) -> DbResult<Self>
+    {
+        let table = SNAPSHOT_TABLES
+            .iter()
+            .find(|table| table.name == name)
+            .ok_or_else(|| invalid(format!("The snapshot has the unknown table {}", name)))?;
+        let columns: Vec<String> = table_columns(transaction, table.name)?
+            .into_iter()
+            .filter(|column| header["columns"].contains(column.as_str()))
+            .collect();
+        if columns.is_empty() {
+            return Err(invalid(format!(
+                "The table {} of the snapshot has none of the columns of the database",
+                name
+            )));
+        }
+
+        Ok(Self {
+            table,
+            columns: columns.join(", "),
+            rows: Vec::with_capacity(SNAPSHOT_BATCH_SIZE),
+            num_rows: 0,
+        })
+    }
+
+    /// Load the rows that have been collected so far.
+    fn load(&mut self, transaction: &mut sql::Transaction, mode: ImportMode) -> DbResult<()>
+    {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = format!("[{}]", self.rows.join(","));
+        self.num_rows += transaction.execute(
+            format!(
+                "INSERT INTO {table} ({columns}) SELECT {columns} FROM \
+                 json_populate_recordset(NULL::{table}, $1::text::json) {end}",
+                table = self.table.name,
+                columns = self.columns,
+                end = match mode {
+                    ImportMode::ReplaceAll => self.table.replace,
+                    ImportMode::MergeSkipExisting => self.table.merge,
+                },
+            )
+            .as_str(),
+            &[&rows],
+        )?;
+        self.rows.clear();
+
+        Ok(())
+    }
+
+    /// Load the remaining rows.
+    ///
+    /// # Returns
+    /// The name of the table and the number of rows that have been added to it.
+    fn finish(
+        mut self,
+        transaction: &mut sql::Transaction,
+        mode: ImportMode,
+    ) -> DbResult<(&'static str, u64)>
+    {
+        self.load(transaction, mode)?;
+        Ok((self.table.name, self.num_rows))
+    }
+}
+
+/// The columns of the `table` in their order, leaving out generated ones that
+/// cannot be written to.
+fn table_columns(transaction: &mut sql::Transaction, table: &str) -> DbResult<Vec<String>>
+{
+    Ok(transaction
+        .query(
+            "SELECT attname::text FROM pg_attribute WHERE attrelid = to_regclass($1) AND attnum > \
+             0 AND NOT attisdropped AND attgenerated = '' ORDER BY attnum",
+            &[&table],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+fn invalid(problem: impl Into<String>) -> DbError { DbError::InvalidInput(problem.into()) }
@@ -0,0 +1,240 @@
+//! Small statistics over lists of counts, like the number of games each player
+//! has played, used to sum up how the players take part in the mixes, over
+//! the metrics of players, used to compare them with each other, and over the
+//! winners of the rounds of a game, used to tell how close it has been.
+
+use crate::score::Team;
+
+/// How many rounds a team must have been behind for a win to count as a
+/// comeback, see [`is_comeback`].
+pub const COMEBACK_DEFICIT: u32 = 2;
+
+/// The median of the `values`. For an even number of values, it is the mean of
+/// the two in the middle.
+///
+/// # Returns
+/// `None` if there are no values.
+pub fn median(values: &[u32]) -> Option<f32>
+{
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[middle - 1] as f32 + sorted[middle] as f32) / 2.),
+        _ => Some(sorted[middle] as f32),
+    }
+}
+
+/// Count how many of the `values` fall into each bucket. The buckets are given
+/// by their `lower_bounds`, which have to be ascending. Each bucket contains
+/// the values from its lower bound up to the lower bound of the next bucket,
+/// the last one everything from its lower bound on. Values below the first
+/// bound are not counted.
+///
+/// # Returns
+/// Each lower bound together with the number of values in its bucket.
+pub fn histogram(values: &[u32], lower_bounds: &[u32]) -> Vec<(u32, u32)>
+{
+    let mut counts = vec![0; lower_bounds.len()];
+    for &value in values {
+        if let Some(bucket) = lower_bounds.iter().rposition(|&bound| bound <= value) {
+            counts[bucket] += 1;
+        }
+    }
+
+    lower_bounds.iter().copied().zip(counts).collect()
+}
+
+/// The share of the sum of all `values` that the largest ones make up, taking
+/// the given `fraction` of the values, rounded up. With a fraction of `0.2`,
+/// this is how concentrated the values are on the top 20%: `0.2` if all values
+/// are the same, close to `1.0` if a few values make up almost everything.
+///
+/// # Returns
+/// A value between `0.0` and `1.0`, or `None` if the values sum up to zero.
+pub fn top_share(values: &[u32], fraction: f32) -> Option<f32>
+{
+    let total: u64 = values.iter().map(|&value| value as u64).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    let num_top = (values.len() as f32 * fraction).ceil() as usize;
+    let top: u64 = sorted.iter().take(num_top).map(|&value| value as u64).sum();
+
+    Some(top as f32 / total as f32)
+}
+
+/// The arithmetic mean of the `values`.
+///
+/// # Returns
+/// `None` if there are no values.
+pub fn mean(values: &[f64]) -> Option<f64>
+{
+    match values.len() {
+        0 => None,
+        len => Some(values.iter().sum::<f64>() / len as f64),
+    }
+}
+
+/// The standard deviation of the `values` as a sample of all possible values,
+/// dividing by one less than the number of values (Bessel's correction).
+///
+/// # Returns
+/// `None` if there are fewer than two values, since a single value says
+/// nothing about how far values spread.
+pub fn std_dev(values: &[f64]) -> Option<f64>
+{
+    if values.len() < 2 {
+        return None;
+    }
+
+    let mean = mean(values)?;
+    let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    Some((squares / (values.len() - 1) as f64).sqrt())
+}
+
+/// How many standard deviations each of the `values` is above their mean.
+///
+/// # Returns
+/// The z-score of each value, in the same order, or `None` if the standard
+/// deviation is unknown or zero, because there are fewer than two values or
+/// they are all the same.
+pub fn z_scores(values: &[f64]) -> Option<Vec<f64>>
+{
+    let mean = mean(values)?;
+    let std_dev = std_dev(values).filter(|&std_dev| std_dev > 0.)?;
+
+    Some(
+        values
+            .iter()
+            .map(|value| (value - mean) / std_dev)
+            .collect(),
+    )
+}
+
+/// The most rounds the `team` has been behind the other team at any point of
+/// a game with the round `winners`, in the order the rounds were played.
+/// Rounds won by neither team do not change the score.
+pub fn largest_deficit(winners: &[Option<Team>], team: Team) -> u32
+{
+    let mut lead: i32 = 0;
+    let mut largest = 0;
+    for winner in winners.iter().flatten() {
+        lead += if *winner == team { 1 } else { -1 };
+        largest = largest.max(-lead);
+    }
+
+    largest as u32
+}
+
+/// Whether the `team` has won a game with the round `winners` after having been
+/// at least [`COMEBACK_DEFICIT`] rounds behind, in the order the rounds were
+/// played. The game is won if the team has won more of the rounds.
+pub fn is_comeback(winners: &[Option<Team>], team: Team) -> bool
+{
+    let won = winners
+        .iter()
+        .filter(|&&winner| winner == Some(team))
+        .count();
+    let lost = winners
+        .iter()
+        .filter(|&&winner| winner == Some(team.other()))
+        .count();
+
+    won > lost && largest_deficit(winners, team) >= COMEBACK_DEFICIT
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn median_of_counts()
+    {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[4]), Some(4.));
+        assert_eq!(median(&[7, 1, 3]), Some(3.));
+        assert_eq!(median(&[1, 8, 2, 4]), Some(3.));
+    }
+
+    #[test]
+    fn histogram_buckets()
+    {
+        let values = [1, 1, 2, 4, 5, 9, 10, 30, 0];
+        assert_eq!(
+            histogram(&values, &[1, 2, 5, 10]),
+            vec![(1, 2), (2, 2), (5, 2), (10, 2)]
+        );
+        assert_eq!(histogram(&[], &[1, 2]), vec![(1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn top_share_of_counts()
+    {
+        assert_eq!(top_share(&[], 0.2), None);
+        assert_eq!(top_share(&[0, 0], 0.2), None);
+        assert_eq!(top_share(&[3; 10], 0.2), Some(0.2));
+        // The top 20% of 6 values are the two largest.
+        assert_eq!(top_share(&[10, 1, 1, 6, 1, 1], 0.2), Some(0.8));
+        assert_eq!(top_share(&[5], 0.2), Some(1.));
+    }
+
+    #[test]
+    fn mean_and_std_dev()
+    {
+        assert_eq!(mean(&[]), None);
+        assert_eq!(mean(&[3.]), Some(3.));
+        assert_eq!(mean(&[1., 2., 6.]), Some(3.));
+
+        assert_eq!(std_dev(&[]), None);
+        assert_eq!(std_dev(&[3.]), None);
+        assert_eq!(std_dev(&[5., 5.]), Some(0.));
+        // The squared deviations sum up to 14, divided by 2 instead of 3.
+        assert_eq!(std_dev(&[1., 2., 6.]), Some(7_f64.sqrt()));
+        assert_eq!(
+            std_dev(&[2., 4., 4., 4., 5., 5., 7., 9.]),
+            Some((32. / 7_f64).sqrt())
+        );
+    }
+
+    #[test]
+    fn z_scores_of_values()
+    {
+        assert_eq!(z_scores(&[]), None);
+        assert_eq!(z_scores(&[3.]), None);
+        assert_eq!(z_scores(&[5., 5., 5.]), None);
+        assert_eq!(z_scores(&[2., 4., 6.]), Some(vec![-1., 0., 1.]));
+        assert_eq!(z_scores(&[6., 2., 4.]), Some(vec![1., -1., 0.]));
+    }
+
+    #[test]
+    fn comebacks()
+    {
+        let (red, blue) = (Some(Team::Red), Some(Team::Blue));
+        // Down 0-2, won 3-2.
+        let comeback = [blue, blue, red, red, red];
+        assert_eq!(largest_deficit(&comeback, Team::Red), 2);
+        assert!(is_comeback(&comeback, Team::Red));
+        assert!(!is_comeback(&comeback, Team::Blue));
+
+        // Rounds won by neither team do not count, and a stomp is no comeback.
+        assert!(is_comeback(
+            &[blue, None, blue, None, red, red, red],
+            Team::Red
+        ));
+        assert_eq!(largest_deficit(&[red; 5], Team::Red), 0);
+        assert!(!is_comeback(&[red; 5], Team::Red));
+
+        // Only down by one at a time, or down by two without winning.
+        assert!(!is_comeback(&[blue, red, blue, red, red], Team::Red));
+        assert!(!is_comeback(&[blue, blue, red, red], Team::Red));
+        assert!(!is_comeback(&[blue, blue, red, red, blue], Team::Red));
+        assert_eq!(largest_deficit(&[], Team::Blue), 0);
+    }
+}
@@ -2,6 +2,8 @@
 //! uses steamID64 for lookups but has steamID3s in the log files, a safe
 //! conversion and type safety between these two is critical.
 
+use std::error::Error;
+use std::fmt;
 use std::str::FromStr;
 
 use num_derive::FromPrimitive;
@@ -11,9 +13,19 @@ const ACCOUNT_INSTANCE_OFFSET_BITS: u64 = 32;
 // Account instance is located at ACCOUNT_INSTANCE_OFFSET_BITS and 20 bits long
 const ACCOUNT_INSTANCE_MASK: u64 = 0xfffff << ACCOUNT_INSTANCE_OFFSET_BITS;
 const ACCOUNT_TYPE_OFFSET_BITS: u64 = 52;
+// The instance of individual accounts used by the desktop client
+const DESKTOP_INSTANCE: u32 = 1;
+// Flags in the instance of chat ids that mark the chat of a clan, a lobby and a
+// matchmaking lobby. Plain chat rooms have none of them set.
+const CHAT_INSTANCE_FLAGS: u32 = 0x80000 | 0x40000 | 0x20000;
 const UNIVERSE_OFFSET_BITS: u64 = 56;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct SteamID
 {
     id64: u64,
@@ -29,35 +41,67 @@ impl SteamID
 
     /// Create a steam id from its steamID64 representation.
     ///
-    /// Checks if the id is in a sane format. In case it is not, an `Err(())` is
-    /// returned.
+    /// Checks if the id is in a sane format, see [`SteamID::validate`]. In case
+    /// it is not, an `Err(())` is returned.
     ///
     /// # Warning
     /// It does not actually make a request to check if there is a profile
     /// connected to this steam id, so lookups for the profile may still fail.
     pub fn new_checked(id64: u64) -> Result<Self, ()>
     {
-        if Self::try_for_universe(id64).is_some()
-            && Self::try_for_account_type(id64).is_some()
-            // Check for normal user account, the only one currently supported
-            && (id64 & ACCOUNT_INSTANCE_MASK == 1 << ACCOUNT_INSTANCE_OFFSET_BITS)
-        {
-            Ok(Self { id64 })
+        let steam_id = Self { id64 };
+        steam_id.validate().map_err(|_| ())?;
+
+        Ok(steam_id)
+    }
+
+    /// Check if the steam id is in a sane format. The universe and account type
+    /// have to be known and the instance has to fit the account type:
+    /// individual accounts have the desktop instance, clans have none and chats
+    /// may only have the flags for the kind of chat set. The instance of the
+    /// other account types is not checked.
+    pub fn validate(&self) -> Result<(), SteamIDError>
+    {
+        if Self::try_for_universe(self.id64).is_none() {
+            return Err(SteamIDError::Universe(
+                (self.id64 >> UNIVERSE_OFFSET_BITS) as u8,
+            ));
         }
-        else {
-            Err(())
+
+        let account_type = match Self::try_for_account_type(self.id64) {
+            None | Some(AccountType::Invalid) => {
+                return Err(SteamIDError::AccountType(
+                    (self.id64 >> ACCOUNT_TYPE_OFFSET_BITS) as u8 & 0xf,
+                ));
+            },
+            Some(account_type) => account_type,
+        };
+
+        let instance = self.instance();
+        let valid_instance = match account_type {
+            AccountType::Individual => instance == DESKTOP_INSTANCE,
+            AccountType::Clan => instance == 0,
+            AccountType::Chat => instance & !CHAT_INSTANCE_FLAGS == 0,
+            _ => true,
+        };
+        if !valid_instance {
+            return Err(SteamIDError::Instance(account_type, instance));
         }
+
+        Ok(())
     }
 
-    /// Create a steam id from the parts usually present. The account type will
-    /// always be set to a user account.
+    /// Create a steam id from the parts usually present. The instance is set
+    /// to the desktop instance for individual accounts and left empty for all
+    /// other account types.
     pub fn from_parts(universe: Universe, account_type: AccountType, id: u32) -> Self
     {
         let mut id64 = 0;
         id64 |= id as u64;
 
-        // Assume user account
-        id64 |= 1 << ACCOUNT_INSTANCE_OFFSET_BITS;
+        if let AccountType::Individual = account_type {
+            id64 |= (DESKTOP_INSTANCE as u64) << ACCOUNT_INSTANCE_OFFSET_BITS;
+        }
 
         id64 |= (account_type as u64) << ACCOUNT_TYPE_OFFSET_BITS;
         id64 |= (universe as u64) << UNIVERSE_OFFSET_BITS;
@@ -78,6 +122,45 @@ impl SteamID
         AccountType::from_u8(account_type_nibble)
     }
 
+    /// Read a steam id as users tend to provide it. Besides the formats
+    /// accepted by [`SteamID::from_str`], this accepts links to steam profiles
+    /// that contain the steamID64, like
+    /// `https://steamcommunity.com/profiles/76561198031286581`.
+    ///
+    /// # Returns
+    /// The steam id or a description of why it could not be read. Links with a
+    /// custom profile name cannot be read, since the name would have to be
+    /// looked up on steam.
+    pub fn from_reference(reference: &str) -> Result<Self, String>
+    {
+        let reference = reference.trim();
+        if let Some((_, path)) = reference.split_once("steamcommunity.com/") {
+            let path = path.trim_end_matches('/');
+            if let Some(id64) = path.strip_prefix("profiles/") {
+                Self::from_str(id64).map_err(|_| format!("`{}` is not a valid steamID64", id64))
+            }
+            else if path.starts_with("id/") {
+                Err(format!(
+                    "`{}` links to a custom profile name, which cannot be looked up. Use the link \
+                     with the steamID64 instead",
+                    reference
+                ))
+            }
+            else {
+                Err(format!("`{}` is not a link to a steam profile", reference))
+            }
+        }
+        else {
+            Self::from_str(reference).map_err(|_| {
+                format!(
+                    "`{}` is neither a steam id (steamID64, steamID3 or steamID) nor a link to a \
+                     steam profile",
+                    reference
+                )
+            })
+        }
+    }
+
     /// Get the universe this account is part of.
     ///
     /// # Panics
@@ -101,6 +184,39 @@ impl SteamID
 
     pub fn id64(self) -> u64 { self.id64 }
 
+    /// Whether this is the steam id of an individual account, so of a player,
+    /// in contrast to a clan or a chat, for instance.
+    pub fn is_individual(self) -> bool
+    {
+        matches!(
+            Self::try_for_account_type(self.id64),
+            Some(AccountType::Individual)
+        )
+    }
+
+    /// The instance of the account, which tells which client an individual
+    /// account uses or which kind of chat a chat id is for.
+    fn instance(self) -> u32
+    {
+        ((self.id64 & ACCOUNT_INSTANCE_MASK) >> ACCOUNT_INSTANCE_OFFSET_BITS) as u32
+    }
+
+    /// Get the account id, which is the part of the steam id that identifies
+    /// the account within its universe. It is the number in the steamID3.
+    pub fn account_id(self) -> u32 { self.id64 as u32 }
+
+    /// Bring the steam id of an individual account into the form used in logs,
+    /// which is in the public universe with the desktop instance. The same
+    /// account may show up with a different universe, for instance in
+    /// steamID64s from other tools, which would make it compare as a different
+    /// player. Other account types are left as they are.
+    pub fn normalize(&mut self)
+    {
+        if let AccountType::Individual = self.account_type() {
+            *self = Self::from_parts(Universe::Public, AccountType::Individual, self.account_id());
+        }
+    }
+
     pub fn to_id64_string(self) -> String { self.id64.to_string() }
 
     pub fn to_id3_string(self) -> String
@@ -133,42 +249,183 @@ impl SteamID
 
 impl FromStr for SteamID
 {
-    type Err = ();
+    type Err = SteamIdParseError;
 
+    /// Read a steam id in any of the common formats: a steamID64, a steamID3
+    /// with or without brackets, like `[U:1:71020853]`, a legacy steamID like
+    /// `STEAM_0:1:35510426` or a link to a logs.tf or steam profile that
+    /// contains the steamID64. Surrounding whitespace is ignored.
     fn from_str(s: &str) -> Result<Self, Self::Err>
     {
+        let error = || SteamIdParseError {
+            input: s.to_owned(),
+        };
+        let id = s.trim();
+        let id = profile_link_id(id).unwrap_or(id);
+
         // Try known conversions
         // Starting with steamid64 if it's just a number.
-        if let Ok(id64) = s.parse::<u64>() {
-            Self::new_checked(id64)
+        if let Ok(id64) = id.parse::<u64>() {
+            Self::new_checked(id64).map_err(|()| error())
         }
-        // Check for ID3
-        else if s.starts_with('[') && s.ends_with(']') {
-            let parts: Vec<&str> = s.split(':').collect();
-            if parts.len() == 3 && parts[0].len() == 2 && parts[1].len() == 1 {
-                let account_id = parts[2][..parts[2].len() - 1]
-                    .parse::<u32>()
-                    .map_err(|_| ())?;
-                let account_type: AccountType = parts[0].chars().nth(1).unwrap().try_into()?;
-                let universe: Universe = Universe::Public;
-
-                Ok(Self::from_parts(universe, account_type, account_id))
-            }
-            else {
-                Err(())
-            }
+        // Check for legacy ID format, STEAM_X:Y:Z with the account id being 2Z + Y
+        else if let Some(id1) = id.strip_prefix("STEAM_") {
+            Self::from_id1(id1).ok_or_else(error)
         }
-        // Check for legacy ID format
-        else if s.starts_with("STEAM_") {
-            todo!()
-        }
-        // Not a known format
+        // Otherwise it can only be an ID3
         else {
-            Err(())
+            Self::from_id3(id).ok_or_else(error)
+        }
+    }
+}
+
+impl TryFrom<u64> for SteamID
+{
+    type Error = SteamIDError;
+
+    /// Create a steam id from its steamID64 representation, like
+    /// [`SteamID::new_checked`], but tell what is wrong with an invalid one.
+    fn try_from(id64: u64) -> Result<Self, Self::Error>
+    {
+        let steam_id = Self { id64 };
+        steam_id.validate()?;
+
+        Ok(steam_id)
+    }
+}
+
+impl SteamID
+{
+    /// Read a steamID3 like `[U:1:71020853]`. The brackets are optional, the
+    /// universe must be the public one.
+    fn from_id3(id3: &str) -> Option<Self>
+    {
+        let id3 = match id3.strip_prefix('[') {
+            Some(bracketed) => bracketed.strip_suffix(']')?,
+            None => id3,
+        };
+
+        let mut parts = id3.split(':');
+        let (account_type, universe, account_id) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || universe != "1" {
+            return None;
+        }
+
+        let mut account_type = account_type.chars();
+        let account_type: AccountType = match (account_type.next(), account_type.next()) {
+            (Some(account_type), None) => account_type.try_into().ok()?,
+            _ => return None,
+        };
+        let account_id = account_id.parse::<u32>().ok()?;
+
+        Some(Self::from_parts(Universe::Public, account_type, account_id))
+    }
+
+    /// Read a legacy steamID like `STEAM_0:1:35510426` without the `STEAM_`
+    /// prefix.
+    fn from_id1(id1: &str) -> Option<Self>
+    {
+        let parts: Vec<&str> = id1.split(':').collect();
+        if parts.len() != 3 {
+            return None;
         }
+
+        // Universe 0 has been used for public accounts in older games
+        let universe = match parts[0].parse::<u8>().ok()? {
+            0 => Universe::Public,
+            universe => Universe::from_u8(universe)?,
+        };
+        let y = match parts[1] {
+            "0" => 0,
+            "1" => 1,
+            _ => return None,
+        };
+        let z = parts[2].parse::<u32>().ok()?;
+        let account_id = z.checked_mul(2).and_then(|id| id.checked_add(y))?;
+
+        Some(Self::from_parts(
+            universe,
+            AccountType::Individual,
+            account_id,
+        ))
     }
 }
 
+/// Get the steamID64 out of a link to a profile on logs.tf, like
+/// `https://logs.tf/profile/76561198031286581`, or on steam, like
+/// `https://steamcommunity.com/profiles/76561198031286581`.
+///
+/// # Returns
+/// What is in the place of the steamID64, or `None` if it is not a link to a
+/// profile.
+fn profile_link_id(link: &str) -> Option<&str>
+{
+    let link = link
+        .strip_prefix("https://")
+        .or_else(|| link.strip_prefix("http://"))
+        .unwrap_or(link);
+    let link = link.strip_prefix("www.").unwrap_or(link);
+    let id = link
+        .strip_prefix("logs.tf/profile/")
+        .or_else(|| link.strip_prefix("steamcommunity.com/profiles/"))?;
+
+    // Links may point to a part of the profile or contain a query.
+    let id = id.split(['?', '#']).next().unwrap_or(id);
+    Some(id.trim_end_matches('/'))
+}
+
+/// A string could not be read as a steam id. Contains the string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SteamIdParseError
+{
+    input: String,
+}
+
+impl fmt::Display for SteamIdParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(
+            f,
+            "`{}` is not a steam id, expected a steamID64, steamID3 or steamID, or a link to a \
+             profile",
+            self.input
+        )
+    }
+}
+
+impl Error for SteamIdParseError {}
+
+/// The reason a steamID64 is not a valid steam id, see [`SteamID::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SteamIDError
+{
+    /// The universe is unknown. Contains the universe.
+    Universe(u8),
+    /// The account type is unknown or invalid. Contains the account type.
+    AccountType(u8),
+    /// The instance does not fit the account type.
+    Instance(AccountType, u32),
+}
+
+impl fmt::Display for SteamIDError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            Self::Universe(universe) => write!(f, "Unknown universe {}", universe),
+            Self::AccountType(account_type) => write!(f, "Invalid account type {}", account_type),
+            Self::Instance(account_type, instance) => write!(
+                f,
+                "The instance {:#x} is not valid for {:?} accounts",
+                instance, account_type
+            ),
+        }
+    }
+}
+
+impl Error for SteamIDError {}
+
 #[derive(Copy, Clone, Debug, FromPrimitive)]
 pub enum Universe
 {
@@ -180,7 +437,7 @@ pub enum Universe
     RC = 5,
 }
 
-#[derive(Copy, Clone, Debug, FromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum AccountType
 {
     Invalid = 0,
@@ -196,21 +453,21 @@ pub enum AccountType
     AnonUser = 10,
 }
 
-impl Into<char> for AccountType
+impl From<AccountType> for char
 {
-    fn into(self) -> char
+    fn from(account_type: AccountType) -> Self
     {
-        match self {
-            Self::Invalid => 'I',
-            Self::Individual => 'U',
-            Self::Multiseat => 'M',
-            Self::GameServer => 'G',
-            Self::AnonGameServer => 'A',
-            Self::Pending => 'P',
-            Self::ContentServer => 'C',
-            Self::Clan => 'g',
-            Self::Chat => 'c',
-            Self::AnonUser => 'a',
+        match account_type {
+            AccountType::Invalid => 'I',
+            AccountType::Individual => 'U',
+            AccountType::Multiseat => 'M',
+            AccountType::GameServer => 'G',
+            AccountType::AnonGameServer => 'A',
+            AccountType::Pending => 'P',
+            AccountType::ContentServer => 'C',
+            AccountType::Clan => 'g',
+            AccountType::Chat => 'c',
+            AccountType::AnonUser => 'a',
         }
     }
 }
@@ -242,7 +499,7 @@ mod test
 {
     use std::str::FromStr;
 
-    use crate::SteamID;
+    use crate::{AccountType, SteamID, SteamIDError, SteamIdParseError};
 
     #[test]
     fn from_id3()
@@ -261,4 +518,188 @@ mod test
             76561198247447256
         );
     }
+
+    #[test]
+    fn from_id3_without_brackets()
+    {
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        assert_eq!(SteamID::from_str("U:1:71020853"), Ok(medic));
+        assert_eq!(SteamID::from_str(" [U:1:71020853]\n"), Ok(medic));
+        assert_eq!(SteamID::from_str("\tU:1:71020853 "), Ok(medic));
+    }
+
+    #[test]
+    fn from_profile_link()
+    {
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        for link in [
+            "http://logs.tf/profile/76561198031286581",
+            "https://logs.tf/profile/76561198031286581?p=2",
+            "logs.tf/profile/76561198031286581",
+            "https://steamcommunity.com/profiles/76561198031286581",
+            "https://www.steamcommunity.com/profiles/76561198031286581/",
+            " steamcommunity.com/profiles/76561198031286581#comments ",
+        ] {
+            assert_eq!(SteamID::from_str(link), Ok(medic), "{}", link);
+        }
+
+        for link in [
+            "https://logs.tf/profile/",
+            "https://logs.tf/profile/medic",
+            "https://steamcommunity.com/id/medic",
+        ] {
+            assert!(SteamID::from_str(link).is_err(), "{}", link);
+        }
+    }
+
+    #[test]
+    fn invalid()
+    {
+        for id in [
+            "",
+            "   ",
+            "medic",
+            "U:3:123",
+            "[U:3:123]",
+            "[U:1:123",
+            "U:1:123]",
+            "[]",
+            "[U:1:]",
+            "[U:1:123:4]",
+            "[UU:1:123]",
+            "[X:1:123]",
+            "[Ü:1:123]",
+            "STEAM_0:0:",
+            "STEAM_0:1",
+            "0",
+        ] {
+            assert_eq!(
+                SteamID::from_str(id),
+                Err(SteamIdParseError {
+                    input: id.to_owned(),
+                }),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn from_id1()
+    {
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        assert_eq!(SteamID::from_str("STEAM_0:1:35510426"), Ok(medic));
+        assert_eq!(SteamID::from_str("STEAM_1:1:35510426"), Ok(medic));
+        assert!(SteamID::from_str("STEAM_0:2:35510426").is_err());
+        assert!(SteamID::from_str("STEAM_0:1").is_err());
+        assert!(SteamID::from_str("STEAM_0:1:x").is_err());
+    }
+
+    #[test]
+    fn from_reference()
+    {
+        let medic = SteamID::from_str("[U:1:71020853]").unwrap();
+
+        for reference in [
+            "76561198031286581",
+            "[U:1:71020853]",
+            "STEAM_0:1:35510426",
+            " https://steamcommunity.com/profiles/76561198031286581/ ",
+            "steamcommunity.com/profiles/76561198031286581",
+        ] {
+            assert_eq!(
+                SteamID::from_reference(reference),
+                Ok(medic),
+                "{}",
+                reference
+            );
+        }
+
+        for reference in [
+            "",
+            "medic",
+            "https://steamcommunity.com/id/medic",
+            "https://steamcommunity.com/profiles/medic",
+            "https://steamcommunity.com/groups/mixes",
+        ] {
+            assert!(SteamID::from_reference(reference).is_err(), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn validate()
+    {
+        for (id64, result, is_individual) in [
+            // [U:1:71020853]
+            (76561198031286581, Ok(()), true),
+            // The same account without an instance
+            (
+                76561193736319285,
+                Err(SteamIDError::Instance(AccountType::Individual, 0)),
+                true,
+            ),
+            // The clan of Valve, [g:1:4]
+            (103582791429521412, Ok(()), false),
+            (
+                103582795724488708,
+                Err(SteamIDError::Instance(AccountType::Clan, 1)),
+                false,
+            ),
+            // A chat room, the chat of a clan and a lobby
+            (108086391056891905, Ok(()), false),
+            (110338190870577156, Ok(()), false),
+            (109212290963734529, Ok(()), false),
+            (
+                108086395351859201,
+                Err(SteamIDError::Instance(AccountType::Chat, 1)),
+                false,
+            ),
+            // The instance of game servers is not checked.
+            (85568414394876027, Ok(()), false),
+            (436849168149905409, Err(SteamIDError::Universe(6)), true),
+            (72057598332895233, Err(SteamIDError::AccountType(0)), false),
+            (112589990684262401, Err(SteamIDError::AccountType(9)), false),
+        ] {
+            let steam_id = unsafe { SteamID::new(id64) };
+            assert_eq!(steam_id.validate(), result, "{}", id64);
+            assert_eq!(
+                SteamID::new_checked(id64).is_ok(),
+                result.is_ok(),
+                "{}",
+                id64
+            );
+            assert_eq!(SteamID::try_from(id64).map(|_| ()), result, "{}", id64);
+            assert_eq!(steam_id.is_individual(), is_individual, "{}", id64);
+        }
+
+        // Steam ids read from other formats have the right instance.
+        for id3 in ["[U:1:71020853]", "[g:1:4]", "[T:1:1]"] {
+            assert_eq!(
+                SteamID::from_str(id3).unwrap().validate(),
+                Ok(()),
+                "{}",
+                id3
+            );
+        }
+    }
+
+    #[test]
+    fn normalize()
+    {
+        let from_id3 = SteamID::from_str("[U:1:71020853]").unwrap();
+        // The same account, but in the unspecified universe
+        let mut from_id64 = SteamID::from_str("4503603993358645").unwrap();
+        assert_ne!(from_id64, from_id3);
+        assert_eq!(from_id64.account_id(), from_id3.account_id());
+
+        from_id64.normalize();
+        assert_eq!(from_id64, from_id3);
+
+        let mut normalized = from_id3;
+        normalized.normalize();
+        assert_eq!(normalized, from_id3);
+    }
 }
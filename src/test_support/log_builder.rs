@@ -0,0 +1,676 @@
+use chrono::{DateTime, TimeZone, Utc};
+use json::JsonValue;
+
+use crate::score::{MatchFormat, Team};
+use crate::{Class, Log, LogId, SteamID};
+
+/// The unix timestamp of a built log, unless it is set with
+/// [`LogBuilder::date`].
+pub const DEFAULT_LOG_TIMESTAMP: i64 = 1_600_000_000;
+/// The duration of a built log in seconds, unless it is set with
+/// [`LogBuilder::duration`] or given by its rounds.
+pub const DEFAULT_LOG_DURATION_SECS: u32 = 1800;
+
+/// Builder of a [`Log`] that only contains the players and stats a test needs.
+/// Everything that follows from the stats of the players is derived from
+/// them, like their totals over all classes or the number of players, so the
+/// log is always consistent. Stats that contradict each other make the
+/// builder panic, naming the contradiction.
+///
+/// The log is read from the json logs.tf would return for it, see
+/// [`LogBuilder::to_json`], so it goes through the same parser as a
+/// downloaded log.
+#[derive(Clone, Debug)]
+pub struct LogBuilder
+{
+    id:          LogId,
+    date_time:   DateTime<Utc>,
+    map:         String,
+    title:       Option<String>,
+    duration:    Option<u32>,
+    score:       Option<(u8, u8)>,
+    rounds:      Vec<(Option<Team>, u32)>,
+    players:     Vec<(SteamID, PlayerBuilder)>,
+    num_players: Option<usize>,
+    chat:        Vec<(Option<SteamID>, String, String)>,
+}
+
+/// The stats of a player of a [`LogBuilder`], set in the function passed to
+/// [`LogBuilder::player`]. The stats over the whole game, like the kills, are
+/// summed up from the classes played.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerBuilder
+{
+    team:           Option<Team>,
+    name:           Option<String>,
+    classes:        Vec<ClassStats>,
+    damage_taken:   u32,
+    heals_received: u32,
+    captures:       u16,
+    medic:          Option<MedicStats>,
+    heal_targets:   Vec<(SteamID, u32)>,
+}
+
+#[derive(Clone, Debug)]
+struct ClassStats
+{
+    class:            Class,
+    kills:            u16,
+    assists:          u16,
+    deaths:           u16,
+    damage:           u32,
+    /// `None` if the class has been played for the whole game.
+    time_played_secs: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+struct MedicStats
+{
+    healing:   u32,
+    num_ubers: u8,
+    num_drops: u8,
+}
+
+impl LogBuilder
+{
+    /// Start building a 5cp log on `cp_process_final` without any players,
+    /// played at [`DEFAULT_LOG_TIMESTAMP`] for
+    /// [`DEFAULT_LOG_DURATION_SECS`] and ending in a 0:0 tie.
+    pub fn new(id: LogId) -> Self
+    {
+        Self {
+            id,
+            date_time: Utc.timestamp_opt(DEFAULT_LOG_TIMESTAMP, 0).unwrap(),
+            map: "cp_process_final".to_owned(),
+            title: None,
+            duration: None,
+            score: None,
+            rounds: Vec::new(),
+            players: Vec::new(),
+            num_players: None,
+            chat: Vec::new(),
+        }
+    }
+
+    /// Set the map, which also decides how the score is counted, see
+    /// [`MatchFormat::from_map`].
+    pub fn map(mut self, map: &str) -> Self
+    {
+        self.map = map.to_owned();
+        self
+    }
+
+    pub fn date(mut self, date_time: DateTime<Utc>) -> Self
+    {
+        self.date_time = date_time;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self
+    {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    /// Set the duration of the game. Without it, the duration is the sum of
+    /// the lengths of the rounds, like for a log that does not report its
+    /// duration.
+    pub fn duration(mut self, duration_secs: u32) -> Self
+    {
+        self.duration = Some(duration_secs);
+        self
+    }
+
+    /// Set the score of the red and the blue team. Without it, the score is
+    /// the number of rounds each team has won.
+    pub fn score(mut self, red: u8, blue: u8) -> Self
+    {
+        self.score = Some((red, blue));
+        self
+    }
+
+    /// Add a round that has lasted `length_secs` and has been won by the
+    /// `winner`, `None` if it has ended in a stalemate.
+    pub fn round(mut self, winner: Option<Team>, length_secs: u32) -> Self
+    {
+        self.rounds.push((winner, length_secs));
+        self
+    }
+
+    /// Add a player with the stats set by `stats`.
+    ///
+    /// # Panics
+    /// If the player has already been added.
+    pub fn player(
+        mut self,
+        steam_id: SteamID,
+        stats: impl FnOnce(PlayerBuilder) -> PlayerBuilder,
+    ) -> Self
+    {
+        assert!(
+            self.players.iter().all(|(player, _)| *player != steam_id),
+            "Player {} has been added to log {} twice",
+            id3(steam_id),
+            self.id
+        );
+        self.players
+            .push((steam_id, stats(PlayerBuilder::default())));
+        self
+    }
+
+    /// Add a player who has played medic for the whole game without any
+    /// kills, deaths or damage, with the `healing` done and the number of
+    /// ubers used and dropped.
+    pub fn medic(self, steam_id: SteamID, healing: u32, num_ubers: u8, num_drops: u8) -> Self
+    {
+        self.player(steam_id, |player| {
+            let mut player = player.medic(healing, num_ubers, num_drops);
+            player.classes.push(ClassStats {
+                class:            Class::Medic,
+                kills:            0,
+                assists:          0,
+                deaths:           0,
+                damage:           0,
+                time_played_secs: None,
+            });
+            player
+        })
+    }
+
+    /// Expect the log to end up with `num_players` players, to catch a test
+    /// that forgets a player. Players who have not played any class for any
+    /// time do not count, they are spectators.
+    pub fn num_players(mut self, num_players: usize) -> Self
+    {
+        self.num_players = Some(num_players);
+        self
+    }
+
+    /// Add a message to the chat, written by `steam_id` or by the console if
+    /// it is `None`.
+    pub fn chat(mut self, steam_id: Option<SteamID>, name: &str, message: &str) -> Self
+    {
+        self.chat
+            .push((steam_id, name.to_owned(), message.to_owned()));
+        self
+    }
+
+    /// The json logs.tf would return for the log, for instance to test how it
+    /// is parsed.
+    ///
+    /// # Panics
+    /// If the stats contradict each other, see [`LogBuilder`].
+    pub fn to_json(&self) -> JsonValue
+    {
+        let duration_secs = self.duration_secs();
+        let (red, blue) = self.checked_score();
+
+        let mut json = json::object! {
+            info: {
+                map: self.map.as_str(),
+                date: self.date_time.timestamp(),
+            },
+            teams: {
+                Red: { score: red },
+                Blue: { score: blue },
+            },
+            players: {},
+            names: {},
+            healspread: {},
+            rounds: [],
+            chat: [],
+        };
+        // Without a duration, logs.tf reports the lengths of the rounds.
+        if self.duration.is_some() || self.rounds.is_empty() {
+            json["info"]["total_length"] = duration_secs.into();
+        }
+        if let Some(title) = &self.title {
+            json["info"]["title"] = title.as_str().into();
+        }
+        for &(winner, length_secs) in &self.rounds {
+            let winner = winner.map(team_name);
+            json["rounds"]
+                .push(json::object! { winner: winner, length: length_secs })
+                .expect("The rounds are an array");
+        }
+
+        let mut num_players = 0;
+        let mut team_sizes = [0, 0];
+        for (steam_id, player) in &self.players {
+            // Players without a team fill up the smaller team.
+            let team = player.team.unwrap_or(match team_sizes[0] <= team_sizes[1] {
+                true => Team::Red,
+                false => Team::Blue,
+            });
+            team_sizes[team as usize] += 1;
+            let id = id3(*steam_id);
+
+            json["players"][id.as_str()] = player.to_json(*steam_id, team, duration_secs);
+            if let Some(name) = &player.name {
+                json["names"][id.as_str()] = name.as_str().into();
+            }
+            for &(target, healing) in &player.heal_targets {
+                json["healspread"][id.as_str()][id3(target).as_str()] = healing.into();
+            }
+            let has_played = player
+                .classes
+                .iter()
+                .any(|class| class.time_played_secs.unwrap_or(duration_secs) > 0);
+            num_players += usize::from(has_played);
+        }
+        if let Some(expected) = self.num_players {
+            assert_eq!(
+                num_players, expected,
+                "Log {} is expected to have {} players, but {} have played",
+                self.id, expected, num_players
+            );
+        }
+
+        for (steam_id, name, message) in &self.chat {
+            let steam_id = match steam_id {
+                Some(steam_id) => id3(*steam_id),
+                None => "Console".to_owned(),
+            };
+            json["chat"]
+                .push(
+                    json::object! { steamid: steam_id, name: name.as_str(), msg: message.as_str() },
+                )
+                .expect("The chat is an array");
+        }
+
+        json
+    }
+
+    /// Build the log.
+    ///
+    /// # Panics
+    /// If the stats contradict each other, see [`LogBuilder`].
+    pub fn build(self) -> Log
+    {
+        Log::from_json(self.id, &self.to_json()).expect("The built log is valid")
+    }
+
+    fn duration_secs(&self) -> u32
+    {
+        match (self.duration, self.rounds.is_empty()) {
+            (Some(duration_secs), _) => duration_secs,
+            (None, true) => DEFAULT_LOG_DURATION_SECS,
+            (None, false) => self
+                .rounds
+                .iter()
+                .map(|&(_, length_secs)| length_secs)
+                .sum(),
+        }
+    }
+
+    /// The score of the red and the blue team, checked against the rounds
+    /// they have won unless the score does not count rounds.
+    fn checked_score(&self) -> (u8, u8)
+    {
+        let won = |team| {
+            let won = self
+                .rounds
+                .iter()
+                .filter(|(winner, _)| *winner == Some(team));
+            won.count() as u8
+        };
+        let from_rounds = (won(Team::Red), won(Team::Blue));
+
+        match self.score {
+            None => from_rounds,
+            Some(score) => {
+                let counts_rounds = MatchFormat::from_map(&self.map) != MatchFormat::Stopwatch;
+                if counts_rounds && !self.rounds.is_empty() {
+                    assert_eq!(
+                        score, from_rounds,
+                        "The score of log {} does not match the rounds won by red and blue",
+                        self.id
+                    );
+                }
+                score
+            },
+        }
+    }
+}
+
+impl PlayerBuilder
+{
+    pub fn team(mut self, team: Team) -> Self
+    {
+        self.team = Some(team);
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self
+    {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Add a class the player has played for `time_played_secs`, with the
+    /// kills, deaths and damage on it.
+    ///
+    /// # Panics
+    /// If the class has already been added.
+    pub fn class(
+        mut self,
+        class: Class,
+        kills: u16,
+        deaths: u16,
+        damage: u32,
+        time_played_secs: u32,
+    ) -> Self
+    {
+        assert!(
+            self.classes.iter().all(|stats| stats.class != class),
+            "{:?} has been added to a player twice",
+            class
+        );
+        self.classes.push(ClassStats {
+            class,
+            kills,
+            assists: 0,
+            deaths,
+            damage,
+            time_played_secs: Some(time_played_secs),
+        });
+        self
+    }
+
+    /// Set the assists on the class added last.
+    ///
+    /// # Panics
+    /// If no class has been added yet.
+    pub fn assists(mut self, assists: u16) -> Self
+    {
+        self.classes
+            .last_mut()
+            .expect("Assists can only be set after adding a class")
+            .assists = assists;
+        self
+    }
+
+    pub fn damage_taken(mut self, damage_taken: u32) -> Self
+    {
+        self.damage_taken = damage_taken;
+        self
+    }
+
+    pub fn heals_received(mut self, heals_received: u32) -> Self
+    {
+        self.heals_received = heals_received;
+        self
+    }
+
+    pub fn captures(mut self, captures: u16) -> Self
+    {
+        self.captures = captures;
+        self
+    }
+
+    /// Set the stats of the player as medic, the healing done and the number
+    /// of ubers used and dropped. The player has to play medic, see
+    /// [`PlayerBuilder::class`].
+    pub fn medic(mut self, healing: u32, num_ubers: u8, num_drops: u8) -> Self
+    {
+        self.medic = Some(MedicStats {
+            healing,
+            num_ubers,
+            num_drops,
+        });
+        self
+    }
+
+    /// Add `healing` the player has done to the `target` as medic.
+    pub fn heal(mut self, target: SteamID, healing: u32) -> Self
+    {
+        self.heal_targets.push((target, healing));
+        self
+    }
+
+    /// The json of the player as listed in the players of a log.
+    fn to_json(&self, steam_id: SteamID, team: Team, duration_secs: u32) -> JsonValue
+    {
+        let id = id3(steam_id);
+        let plays_medic = self.classes.iter().any(|stats| stats.class == Class::Medic);
+        assert!(
+            plays_medic || (self.medic.is_none() && self.heal_targets.is_empty()),
+            "Player {} has medic stats without playing medic",
+            id
+        );
+
+        let mut class_stats = JsonValue::new_array();
+        for stats in &self.classes {
+            let time_played_secs = stats.time_played_secs.unwrap_or(duration_secs);
+            assert!(
+                time_played_secs <= duration_secs,
+                "Player {} has played {:?} for {}s in a game of {}s",
+                id,
+                stats.class,
+                time_played_secs,
+                duration_secs
+            );
+            class_stats
+                .push(json::object! {
+                    "type": class_name(stats.class),
+                    kills: stats.kills,
+                    assists: stats.assists,
+                    deaths: stats.deaths,
+                    dmg: stats.damage,
+                    total_time: time_played_secs,
+                })
+                .expect("The class stats are an array");
+        }
+
+        let sum = |stat: fn(&ClassStats) -> u32| self.classes.iter().map(stat).sum::<u32>();
+        let mut json = json::object! {
+            team: team_name(team),
+            kills: sum(|stats| stats.kills as u32),
+            assists: sum(|stats| stats.assists as u32),
+            deaths: sum(|stats| stats.deaths as u32),
+            dmg: sum(|stats| stats.damage),
+            dt: self.damage_taken,
+            hr: self.heals_received,
+            cpc: self.captures,
+            class_stats: class_stats,
+        };
+        if let Some(medic) = &self.medic {
+            json["heal"] = medic.healing.into();
+            json["ubers"] = medic.num_ubers.into();
+            json["drops"] = medic.num_drops.into();
+            json["ubertypes"] = json::object! { medigun: medic.num_ubers };
+            json["medicstats"] = JsonValue::new_object();
+        }
+
+        json
+    }
+}
+
+/// The name of the `class` in the logs of logs.tf.
+fn class_name(class: Class) -> &'static str
+{
+    match class {
+        Class::Demoman => "demoman",
+        Class::Engineer => "engineer",
+        Class::Heavy => "heavyweapons",
+        Class::Medic => "medic",
+        Class::Pyro => "pyro",
+        Class::Scout => "scout",
+        Class::Sniper => "sniper",
+        Class::Soldier => "soldier",
+        Class::Spy => "spy",
+        Class::Unknown => "unknown",
+    }
+}
+
+/// The steamID3 of the player, which identifies them in a log.
+fn id3(steam_id: SteamID) -> String { format!("[U:1:{}]", steam_id.account_id()) }
+
+fn team_name(team: Team) -> &'static str
+{
+    match team {
+        Team::Red => "Red",
+        Team::Blue => "Blue",
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::score::MatchFormat;
+
+    fn steam_id(id3: &str) -> SteamID { SteamID::from_str(id3).unwrap() }
+
+    #[test]
+    fn build()
+    {
+        let (scout, soldier, medic) = (
+            steam_id("[U:1:1]"),
+            steam_id("[U:1:2]"),
+            steam_id("[U:1:3]"),
+        );
+        let builder = LogBuilder::new(LogId(7))
+            .map("koth_product_final")
+            .duration(900)
+            .score(2, 1)
+            .player(scout, |player| {
+                player
+                    .class(Class::Scout, 10, 5, 4000, 600)
+                    .assists(3)
+                    .class(Class::Heavy, 2, 1, 500, 300)
+                    .damage_taken(3000)
+                    .heals_received(1500)
+                    .name("scout")
+            })
+            .player(soldier, |player| {
+                player
+                    .class(Class::Soldier, 7, 6, 5000, 900)
+                    .team(Team::Blue)
+            })
+            .medic(medic, 12000, 4, 1)
+            .num_players(3)
+            .chat(Some(scout), "scout", "gg");
+        let json = builder.to_json();
+        let log = builder.build();
+
+        assert_eq!(log.meta().id, LogId(7));
+        assert_eq!(log.meta().num_players, 3);
+        assert_eq!(log.duration_secs(), 900);
+        assert_eq!(log.meta().format(), MatchFormat::Koth);
+        assert_eq!(
+            log.class_times(scout),
+            vec![(Class::Scout, 600), (Class::Heavy, 300)]
+        );
+        assert_eq!(log.primary_class(medic), Some(Class::Medic));
+        assert_eq!(log.chat()[0].message, "gg");
+
+        let performance = log.performance_of(scout).unwrap();
+        let overall = performance.iter().find_map(|p| p.as_overall()).unwrap();
+        assert_eq!(
+            (overall.kills, overall.deaths, overall.damage),
+            (12, 6, 4500)
+        );
+        assert_eq!((overall.won_rounds, overall.num_rounds), (2, 3));
+        assert_eq!(overall.heals_received, 1500);
+        // Players without a team fill up the smaller team.
+        assert_eq!(json["players"]["[U:1:1]"]["team"], "Red");
+        assert_eq!(json["players"]["[U:1:3]"]["team"], "Red");
+        assert_eq!(json["players"]["[U:1:1]"]["assists"], 3);
+
+        let medic_performance = log
+            .performance_of(medic)
+            .unwrap()
+            .iter()
+            .find_map(|p| p.as_med())
+            .unwrap();
+        assert_eq!(medic_performance.healing, 12000);
+        assert_eq!(medic_performance.time_played_secs, 900);
+        assert_eq!(
+            (medic_performance.num_ubers, medic_performance.num_drops),
+            (4, 1)
+        );
+
+        // The json is parsed the same way as a downloaded log.
+        let parsed = Log::parse(LogId(7), &json.dump()).unwrap();
+        assert_eq!(parsed.performance_of(scout), log.performance_of(scout));
+    }
+
+    #[test]
+    fn rounds()
+    {
+        let log = LogBuilder::new(LogId(1))
+            .round(Some(Team::Red), 300)
+            .round(None, 200)
+            .round(Some(Team::Blue), 100)
+            .player(steam_id("[U:1:1]"), |player| {
+                player.class(Class::Scout, 0, 0, 0, 600)
+            })
+            .build();
+        // Without a duration, the rounds make up the game.
+        assert_eq!(log.duration_secs(), 600);
+        let overall = log
+            .performance_of(steam_id("[U:1:1]"))
+            .unwrap()
+            .iter()
+            .find_map(|p| p.as_overall())
+            .unwrap();
+        assert_eq!((overall.won_rounds, overall.num_rounds), (1, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match the rounds")]
+    fn score_against_rounds()
+    {
+        LogBuilder::new(LogId(1))
+            .round(Some(Team::Red), 300)
+            .score(2, 0)
+            .to_json();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected to have 2 players, but 1 have played")]
+    fn missing_player()
+    {
+        LogBuilder::new(LogId(1))
+            .player(steam_id("[U:1:1]"), |player| {
+                player.class(Class::Scout, 0, 0, 0, 600)
+            })
+            .player(steam_id("[U:1:2]"), |player| player)
+            .num_players(2)
+            .to_json();
+    }
+
+    #[test]
+    #[should_panic(expected = "added to log 1 twice")]
+    fn duplicate_player()
+    {
+        LogBuilder::new(LogId(1))
+            .player(steam_id("[U:1:1]"), |player| player)
+            .player(steam_id("[U:1:1]"), |player| player);
+    }
+
+    #[test]
+    #[should_panic(expected = "for 2000s in a game of 1800s")]
+    fn class_longer_than_game()
+    {
+        LogBuilder::new(LogId(1))
+            .player(steam_id("[U:1:1]"), |player| {
+                player.class(Class::Scout, 0, 0, 0, 2000)
+            })
+            .to_json();
+    }
+
+    #[test]
+    #[should_panic(expected = "medic stats without playing medic")]
+    fn medic_stats_without_medic()
+    {
+        LogBuilder::new(LogId(1))
+            .player(steam_id("[U:1:1]"), |player| {
+                player.class(Class::Scout, 0, 0, 0, 600).medic(100, 0, 0)
+            })
+            .to_json();
+    }
+}
@@ -0,0 +1,6 @@
+//! Helpers for writing tests against the crate, like building logs with just
+//! the stats a test cares about instead of editing the bundled test log.
+
+mod log_builder;
+
+pub use log_builder::*;
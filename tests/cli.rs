@@ -0,0 +1,40 @@
+//! Drive the `mixes-cli` binary. Only invalid invocations are tested, which are
+//! rejected before connecting to the database.
+#![cfg(feature = "cli")]
+
+use assert_cmd::Command;
+
+fn cli() -> Command { Command::cargo_bin("mixes-cli").expect("Binary not built") }
+
+#[test]
+fn help()
+{
+    let output = cli().arg("--help").output().unwrap();
+    assert!(output.status.success());
+
+    let help = String::from_utf8(output.stdout).unwrap();
+    for command in ["register", "update", "stats", "export", "backup", "restore"] {
+        assert!(help.contains(command), "{}", command);
+    }
+}
+
+#[test]
+fn invalid_arguments()
+{
+    for args in [
+        &["register", "not-a-steam-id", "1"][..],
+        &["register", "https://steamcommunity.com/id/medic", "1"],
+        &["register", "[U:1:71020853]", "-1"],
+        &["update", "--ratio", "1.5"],
+        &["update", "--players", "13..12"],
+        &["stats", "[U:1:71020853]", "bard"],
+        &["export", "--table", "chat", "--out", "chat.csv"],
+        &["backup"],
+        &["restore"],
+        &["sync"],
+    ] {
+        let output = cli().args(args).output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(!output.stderr.is_empty(), "{:?}", args);
+    }
+}
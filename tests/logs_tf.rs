@@ -0,0 +1,319 @@
+//! Talk to a mock of the logs.tf API that answers with recorded responses, to
+//! test searching, downloading and updating the database without network
+//! access.
+
+mod support;
+
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use mixes_db::logs_tf::search_params::SearchParams;
+use mixes_db::logs_tf::{
+    health_check, Degradation, Health, LogSource, LogsTf, QueryError, SearchParamsError,
+};
+use mixes_db::{LogId, SteamID};
+use support::{MockServer, Response};
+
+/// The player whose logs are searched.
+fn medic() -> SteamID { SteamID::new_checked(76561198031286581).unwrap() }
+
+#[test]
+fn search_logs()
+{
+    let server = MockServer::start();
+    server.route("/", [Response::fixture("search_page_1.json")]);
+
+    let logs = server
+        .source(0)
+        .search_logs(SearchParams::player_id(medic()).add_limit(2))
+        .unwrap();
+    assert_eq!(
+        server.requests(),
+        vec!["/?player=76561198031286581&limit=2"]
+    );
+
+    let ids: Vec<LogId> = logs.iter().map(|meta| meta.id).collect();
+    assert_eq!(ids, vec![LogId(3094861), LogId(3094790)]);
+    assert_eq!(logs[0].map, "cp_sunshine");
+    assert_eq!(logs[0].num_players, 12);
+    assert_eq!(
+        logs[0].title.as_deref(),
+        Some("serveme.tf #1221399 - BLU vs RED")
+    );
+    assert_eq!(logs[0].date_time, Utc.timestamp_opt(1640206126, 0).unwrap());
+    assert_eq!(logs[1].num_players, 13);
+}
+
+#[test]
+fn search_with_all_params()
+{
+    let server = MockServer::start();
+    server.route(
+        "/",
+        [
+            Response::fixture("search_page_1.json"),
+            Response::fixture("search_page_1.json"),
+        ],
+    );
+
+    let search_params = SearchParams::player_id(medic())
+        .add_player_id(SteamID::new_checked(76561198847982793).unwrap())
+        .add_limit(2)
+        .add_offset(4);
+    server.source(0).search_logs(search_params).unwrap();
+    let search_params = SearchParams::log_title(" serveme.tf #1221399 ".to_owned())
+        .add_limit(2)
+        .add_offset(4);
+    server.source(0).search_logs(search_params).unwrap();
+    assert_eq!(
+        server.requests(),
+        vec![
+            "/?player=76561198031286581%2C76561198847982793&limit=2&offset=4",
+            "/?title=serveme.tf+%231221399&limit=2&offset=4",
+        ]
+    );
+
+    // Searches that make no sense are not made.
+    let result = server.source(0).search_logs(SearchParams::limit(0));
+    assert!(matches!(
+        result,
+        Err(QueryError::InvalidParams(
+            SearchParamsError::LimitOutOfRange(0)
+        ))
+    ));
+    let result = server
+        .source(0)
+        .search_logs(SearchParams::player_id(medic()).add_log_title("serveme.tf".to_owned()));
+    assert!(matches!(
+        result,
+        Err(QueryError::InvalidParams(
+            SearchParamsError::PlayersAndTitle
+        ))
+    ));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn search_pages()
+{
+    let server = MockServer::start();
+    server.route(
+        "/",
+        [
+            Response::fixture("search_page_1.json"),
+            Response::fixture("search_page_2.json"),
+        ],
+    );
+
+    let mut pages = Vec::new();
+    server
+        .source(0)
+        .search_logs_paged(SearchParams::player_id(medic()), 2, &mut |page| {
+            pages.push(page.iter().map(|meta| meta.id).collect::<Vec<_>>());
+            true
+        })
+        .unwrap();
+
+    // The second page is not full, so it is the last one.
+    assert_eq!(
+        pages,
+        vec![vec![LogId(3094861), LogId(3094790)], vec![LogId(3094702)]]
+    );
+    assert_eq!(
+        server.requests(),
+        vec![
+            "/?player=76561198031286581&limit=2&offset=0",
+            "/?player=76561198031286581&limit=2&offset=2"
+        ]
+    );
+}
+
+#[test]
+fn download_with_retries()
+{
+    let server = MockServer::start();
+    server.route(
+        "/3094861",
+        [
+            Response::rate_limited(),
+            Response::fixture("malformed.json"),
+            Response::fixture("maintenance.json"),
+            Response::test_log(),
+        ],
+    );
+
+    let log = server.source(3).download_log(LogId(3094861)).unwrap();
+    assert_eq!(log.meta().id, LogId(3094861));
+    assert_eq!(log.meta().map, "cp_sunshine");
+    assert_eq!(log.performances().len(), 12);
+    assert_eq!(server.requests(), vec!["/3094861"; 4]);
+}
+
+#[test]
+fn rate_limit_exhausts_retries()
+{
+    let server = MockServer::start();
+    server.route("/3094861", [Response::rate_limited()]);
+
+    // The first try and one retry, then the error is given up on.
+    let result = server.source(1).download_log(LogId(3094861));
+    assert!(matches!(result, Err(QueryError::JsonParseError(_))));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn error_classification()
+{
+    let server = MockServer::start();
+    server
+        .route("/1", [Response::rate_limited()])
+        .route("/2", [Response::fixture("malformed.json")])
+        .route("/3", [Response::fixture("maintenance.json")]);
+    let source = server.source(0);
+
+    assert!(matches!(
+        source.download_log(LogId(1)),
+        Err(QueryError::JsonParseError(_))
+    ));
+    assert!(matches!(
+        source.download_log(LogId(2)),
+        Err(QueryError::JsonParseError(_))
+    ));
+    assert!(matches!(
+        source.download_log(LogId(3)),
+        Err(QueryError::MalformedResponse(response)) if response.contains("maintenance")
+    ));
+    // Logs without a recorded response are missing.
+    assert!(matches!(
+        source.download_log(LogId(4)),
+        Err(QueryError::Unsuccessful(error)) if error == "Log not found."
+    ));
+}
+
+#[test]
+fn health_classification()
+{
+    let server = MockServer::start();
+    let health = |response: Response| {
+        server.route("/", [response]);
+        let source = LogsTf {
+            slow_after: Duration::from_millis(200),
+            ..server.source(0)
+        };
+        health_check(&source).unwrap()
+    };
+
+    assert_eq!(health(Response::fixture("search_page_1.json")), Health::Up);
+    assert!(matches!(
+        health(Response::fixture("search_page_1.json").delayed(Duration::from_millis(400))),
+        Health::Degraded(Degradation::Slow(latency)) if latency >= Duration::from_millis(400)
+    ));
+    assert_eq!(
+        health(Response::service_unavailable()),
+        Health::Degraded(Degradation::ServerError(503))
+    );
+    assert_eq!(
+        health(Response::rate_limited()),
+        Health::Degraded(Degradation::RateLimited)
+    );
+    assert!(matches!(
+        health(Response::fixture("maintenance.json")),
+        Health::Down(problem) if problem.contains("maintenance")
+    ));
+    assert!(matches!(
+        health(Response::fixture("malformed.json")),
+        Health::Down(_)
+    ));
+    // The health check is a single search for one log.
+    assert_eq!(server.requests()[0], "/?limit=1");
+
+    // Nothing listens on port 1, so connecting fails right away.
+    let unreachable = LogsTf {
+        api_base: "http://127.0.0.1:1".to_owned(),
+        ..server.source(0)
+    };
+    assert!(matches!(
+        health_check(&unreachable).unwrap(),
+        Health::Down(_)
+    ));
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn update_aborts_when_down()
+{
+    use std::error::Error;
+
+    use mixes_db::sql_db::{DbError, SQLDb};
+    use mixes_db::{Database, MixesDbError, UpdateOptions};
+
+    let server = MockServer::start();
+    server.route("/", [Response::fixture("maintenance.json")]);
+
+    let mut db = SQLDb::temporary("host=localhost user=mixes dbname=mixes-stats")
+        .expect("Unable to connect to the database");
+    db.add_user(medic(), 1).unwrap();
+
+    let options = UpdateOptions::builder().health_check().build().unwrap();
+    let error = db.update_with(&server.source(1), options).unwrap_err();
+    assert!(matches!(error, MixesDbError::Backend(_)));
+    assert!(matches!(
+        error.source().unwrap().downcast_ref::<DbError>(),
+        Some(DbError::UpstreamUnavailable(_))
+    ));
+    // Only the health check has been made.
+    assert_eq!(server.requests(), vec!["/?limit=1"]);
+    assert!(db.known_logs().unwrap().is_empty());
+    assert!(db.update_history(10).unwrap().is_empty());
+}
+
+#[cfg(feature = "test-util")]
+#[test]
+fn update()
+{
+    use std::str::FromStr;
+
+    use mixes_db::sql_db::SQLDb;
+    use mixes_db::{Database, SkipReason, UpdateOptions};
+
+    let server = MockServer::start();
+    server
+        .route("/", [Response::fixture("search_update.json")])
+        .route("/3094861", [Response::rate_limited(), Response::test_log()]);
+
+    let mut db = SQLDb::temporary("host=localhost user=mixes dbname=mixes-stats")
+        .expect("Unable to connect to the database");
+    // 8 of the 12 players of the log are registered.
+    for (discord_id, player) in [
+        "[U:1:71020853]",
+        "[U:1:178408897]",
+        "[U:1:886717065]",
+        "[U:1:838576271]",
+        "[U:1:142158386]",
+        "[U:1:125648953]",
+        "[U:1:1028703238]",
+        "[U:1:57603258]",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        db.add_user(SteamID::from_str(player).unwrap(), discord_id as u64)
+            .unwrap();
+    }
+
+    let options = UpdateOptions::builder().build().unwrap();
+    let report = db.update_with(&server.source(1), options).unwrap();
+    assert_eq!(report.added, vec![LogId(3094861)]);
+    assert_eq!(
+        report.skipped,
+        vec![(LogId(3094702), SkipReason::PlayerCount(4))]
+    );
+    assert!(db.has_log(LogId(3094861)).unwrap());
+
+    let downloads = server
+        .requests()
+        .into_iter()
+        .filter(|request| request == "/3094861")
+        .count();
+    assert_eq!(downloads, 2);
+}
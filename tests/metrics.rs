@@ -0,0 +1,66 @@
+//! Run an update against a mock of the logs.tf API and check the metrics that
+//! are rendered for it. The metrics are counted for the whole process, so this
+//! is the only test of its binary.
+
+#![cfg(all(feature = "metrics", feature = "test-util"))]
+
+mod support;
+
+use std::str::FromStr;
+
+use mixes_db::sql_db::SQLDb;
+use mixes_db::{metrics, Database, LogId, SteamID, UpdateOptions};
+use support::{MockServer, Response};
+
+#[test]
+fn update_metrics()
+{
+    let server = MockServer::start();
+    server
+        .route("/", [Response::fixture("search_update.json")])
+        .route("/3094861", [Response::rate_limited(), Response::test_log()]);
+
+    let mut db = SQLDb::temporary("host=localhost user=mixes dbname=mixes-stats")
+        .expect("Unable to connect to the database");
+    for (discord_id, player) in ["[U:1:71020853]", "[U:1:886717065]"]
+        .into_iter()
+        .enumerate()
+    {
+        db.add_user(SteamID::from_str(player).unwrap(), discord_id as u64)
+            .unwrap();
+    }
+
+    let options = UpdateOptions::builder().min_ratio(0.).build().unwrap();
+    let report = db.update_with(&server.source(1), options).unwrap();
+    assert_eq!(report.added, vec![LogId(3094861)]);
+    assert!(db.add_log_from_json(LogId(1), "{}").is_err());
+
+    // A single search finds the log, which is downloaded on the second try. The
+    // newest logs are searched again at the end, which finds nothing new.
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests[0].starts_with("/?player="));
+    assert_eq!(requests[1..3], ["/3094861", "/3094861"]);
+    assert!(requests[3].starts_with("/?player="));
+
+    let rendered = metrics::gather();
+    for line in [
+        "mixes_db_logs_tf_requests_total{kind=\"search\",outcome=\"success\"} 2",
+        "mixes_db_logs_tf_requests_total{kind=\"download\",outcome=\"success\"} 1",
+        // The rate limit is answered with a page that is not json.
+        "mixes_db_logs_tf_requests_total{kind=\"download\",outcome=\"invalid_json\"} 1",
+        "mixes_db_logs_tf_requests_total{kind=\"download\",outcome=\"timeout\"} 0",
+        "mixes_db_retries_total 1",
+        "mixes_db_logs_ingested_total 1",
+        "mixes_db_log_parse_failures_total 1",
+        "mixes_db_update_duration_seconds_count 1",
+        "mixes_db_update_duration_seconds_bucket{le=\"+Inf\"} 1",
+    ] {
+        assert!(
+            rendered.lines().any(|rendered| rendered == line),
+            "{} is missing from:\n{}",
+            line,
+            rendered
+        );
+    }
+}
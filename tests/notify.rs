@@ -0,0 +1,160 @@
+//! Post notifications to a mock of a Discord webhook and check what has been
+//! posted.
+
+#![cfg(feature = "webhook")]
+
+mod support;
+
+use std::time::Duration;
+
+use json::JsonValue;
+use mixes_db::notify::{DiscordWebhook, NotifyError, MAX_DESCRIPTION_CHARS};
+use mixes_db::{DbEvent, LogId, SkipReason, UpdateReport};
+use support::{MockServer, Response};
+
+/// A webhook on the mock server that does not retry failed posts.
+fn webhook(server: &MockServer) -> DiscordWebhook
+{
+    DiscordWebhook {
+        num_retries: 0,
+        ..DiscordWebhook::new(&server.url("/webhook"))
+    }
+}
+
+/// The single embed of each message posted to the server.
+fn posted_embeds(server: &MockServer) -> Vec<JsonValue>
+{
+    server
+        .bodies()
+        .iter()
+        .map(|body| {
+            let mut message = json::parse(body).unwrap();
+            assert_eq!(message["embeds"].len(), 1);
+            message["embeds"].array_remove(0)
+        })
+        .collect()
+}
+
+/// The names and values of the fields of the `embed`.
+fn fields(embed: &JsonValue) -> Vec<(String, String)>
+{
+    embed["fields"]
+        .members()
+        .map(|field| (field["name"].to_string(), field["value"].to_string()))
+        .collect()
+}
+
+#[test]
+fn notify_update()
+{
+    let server = MockServer::start();
+    server.route("/webhook", [Response::no_content()]);
+    let report = UpdateReport {
+        added: vec![LogId(1), LogId(2)],
+        skipped: vec![
+            (LogId(3), SkipReason::NotEnoughMixesPlayers),
+            (LogId(4), SkipReason::DownloadFailed("timed out".to_owned())),
+        ],
+        duration: Some(Duration::from_secs(75)),
+        ..UpdateReport::default()
+    };
+
+    webhook(&server).notify_update(&report).unwrap();
+    assert_eq!(server.requests(), vec!["/webhook"]);
+    let embeds = posted_embeds(&server);
+    assert_eq!(embeds[0]["title"], "Update completed with failures");
+    assert_eq!(
+        fields(&embeds[0]),
+        vec![
+            ("Added".to_owned(), "2".to_owned()),
+            ("Failed".to_owned(), "1".to_owned()),
+            ("Duration".to_owned(), "1m 15s".to_owned()),
+        ]
+    );
+    // Logs that are not wanted are not failures.
+    assert_eq!(
+        embeds[0]["description"],
+        "Log 4: The log could not be downloaded: timed out"
+    );
+}
+
+#[test]
+fn many_failures_are_truncated()
+{
+    let server = MockServer::start();
+    server.route("/webhook", [Response::no_content()]);
+    let reason = "x".repeat(1000);
+    let report = UpdateReport {
+        skipped: (1..=500)
+            .map(|id| (LogId(id), SkipReason::IngestFailed(reason.clone())))
+            .collect(),
+        ..UpdateReport::default()
+    };
+
+    webhook(&server).notify_update(&report).unwrap();
+    let embeds = posted_embeds(&server);
+    assert!(embeds[0]["fields"]
+        .members()
+        .all(|field| field["name"] != "Duration"));
+
+    let description = embeds[0]["description"].as_str().unwrap();
+    assert!(description.chars().count() <= MAX_DESCRIPTION_CHARS);
+    let lines: Vec<&str> = description.lines().collect();
+    // Each failure is cut off, so that more of them fit.
+    assert!(lines[0].starts_with("Log 1: The log could not be stored: xxx"));
+    assert_eq!(lines[0].chars().count(), 200);
+    assert!(lines[0].ends_with('…'));
+    let num_listed = lines.len() - 1;
+    assert!(num_listed > 10);
+    assert_eq!(
+        lines[num_listed],
+        format!("… and {} more", 500 - num_listed)
+    );
+}
+
+#[test]
+fn rejected_posts()
+{
+    let server = MockServer::start();
+    // The mock answers unknown paths with a 404, like Discord does for a
+    // deleted webhook.
+    let mut webhook = webhook(&server);
+    webhook.num_retries = 2;
+
+    let error = webhook.notify_update(&UpdateReport::default()).unwrap_err();
+    assert!(matches!(error, NotifyError::Rejected(404, _)));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn event_handler()
+{
+    let server = MockServer::start();
+    server.route("/webhook", [Response::no_content()]);
+    let handler = webhook(&server).into_event_handler();
+
+    handler(DbEvent::LogRemoved(LogId(1)));
+    assert!(server.requests().is_empty());
+
+    handler(DbEvent::UpdateCompleted { added: 3 });
+    handler(DbEvent::UpdateCancelled { added: 1 });
+    let embeds = posted_embeds(&server);
+    assert_eq!(embeds[0]["title"], "Update completed");
+    assert_eq!(embeds[1]["title"], "Update cancelled");
+    assert_eq!(
+        fields(&embeds[1]),
+        vec![
+            ("Added".to_owned(), "1".to_owned()),
+            ("Failed".to_owned(), "0".to_owned()),
+        ]
+    );
+
+    // A failed post does not make the handler fail.
+    let handler = DiscordWebhook {
+        num_retries: 0,
+        ..DiscordWebhook::new(&server.url("/deleted"))
+    }
+    .into_event_handler();
+    handler(DbEvent::UpdateCompleted { added: 3 });
+    assert_eq!(server.requests().len(), 3);
+}
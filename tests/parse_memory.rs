@@ -0,0 +1,74 @@
+//! Measure how much memory reading a log takes at most, with an allocator that
+//! counts the bytes allocated. This is the only test of its binary, so that no
+//! other test allocates while a log is being read.
+
+#![cfg(feature = "serde-parse")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::{self, File};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mixes_db::{Log, LogId};
+
+const TEST_LOG: &str = "test_data/log_3094861.json";
+
+/// The system allocator, counting the bytes currently allocated and the most
+/// that have been allocated at once.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8
+    {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout)
+    {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The most bytes allocated at once while running the `action`, on top of
+/// what has been allocated before.
+fn peak_allocation<R>(action: impl FnOnce() -> R) -> usize
+{
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = action();
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+    drop(result);
+    peak
+}
+
+#[test]
+fn streaming_peak_allocation()
+{
+    let document = peak_allocation(|| {
+        let text = fs::read_to_string(TEST_LOG).unwrap();
+        Log::from_json(LogId(1), &json::parse(&text).unwrap()).unwrap()
+    });
+    let typed = peak_allocation(|| {
+        let text = fs::read_to_string(TEST_LOG).unwrap();
+        Log::parse_typed(LogId(1), &text).unwrap()
+    });
+    let streamed = peak_allocation(|| Log::from_reader(LogId(1), File::open(TEST_LOG).unwrap()));
+
+    // The text of the log alone is larger than everything streaming needs.
+    let text_len = fs::metadata(TEST_LOG).unwrap().len() as usize;
+    assert!(streamed < text_len, "{} >= {}", streamed, text_len);
+    assert!(streamed < typed, "{} >= {}", streamed, typed);
+    assert!(typed < document, "{} >= {}", typed, document);
+}
@@ -0,0 +1,225 @@
+//! Mock of the logs.tf API for the integration tests. It answers the requests
+//! made to it with responses recorded in `tests/http_fixtures`, so everything
+//! that talks to logs.tf can be tested without network access.
+
+// Every test crate only uses some of the responses.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{fs, thread};
+
+use mixes_db::logs_tf::LogsTf;
+
+/// A response of the mock server.
+#[derive(Clone, Debug)]
+pub struct Response
+{
+    pub status: u16,
+    pub body:   String,
+    /// How long the server waits before answering.
+    pub delay:  Duration,
+}
+
+impl Response
+{
+    /// Answer with the recorded response in `tests/http_fixtures/{name}`.
+    pub fn fixture(name: &str) -> Self { Self::ok(read(&format!("tests/http_fixtures/{}", name))) }
+
+    /// Answer with the test log of `test_data`, like logs.tf does for a
+    /// download.
+    pub fn test_log() -> Self { Self::ok(read("test_data/log_3094861.json")) }
+
+    /// Answer like logs.tf does when too many requests have been made.
+    pub fn rate_limited() -> Self
+    {
+        Self {
+            status: 429,
+            body:   read("tests/http_fixtures/rate_limited.txt"),
+            delay:  Duration::ZERO,
+        }
+    }
+
+    /// Answer like logs.tf does when its server is overloaded.
+    pub fn service_unavailable() -> Self
+    {
+        Self {
+            status: 503,
+            body:   read("tests/http_fixtures/service_unavailable.html"),
+            delay:  Duration::ZERO,
+        }
+    }
+
+    /// Answer without a body, like Discord does for a message posted to a
+    /// webhook.
+    pub fn no_content() -> Self
+    {
+        Self {
+            status: 204,
+            body:   String::new(),
+            delay:  Duration::ZERO,
+        }
+    }
+
+    /// Wait for the `delay` before answering.
+    pub fn delayed(self, delay: Duration) -> Self { Self { delay, ..self } }
+
+    fn ok(body: String) -> Self
+    {
+        Self {
+            status: 200,
+            body,
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+fn read(path: &str) -> String
+{
+    fs::read_to_string(path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e))
+}
+
+/// The responses of the mock server, by the path of the requests they answer.
+/// Searches are made on `/`, downloads on `/{id}`.
+type Routes = HashMap<String, VecDeque<Response>>;
+
+/// A request made to the mock server, by its path and query, and its body.
+type Request = (String, String);
+
+/// Server that pretends to be the logs.tf API. Each path answers with its
+/// responses in order, the last one is repeated for all further requests.
+/// Paths without responses answer like logs.tf does for a missing log.
+pub struct MockServer
+{
+    address:  String,
+    routes:   Arc<Mutex<Routes>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer
+{
+    /// Start the server on a free local port. It runs until the test ends.
+    pub fn start() -> Self
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = Self {
+            address:  format!("http://{}", listener.local_addr().unwrap()),
+            routes:   Arc::default(),
+            requests: Arc::default(),
+        };
+
+        let routes = Arc::clone(&server.routes);
+        let requests = Arc::clone(&server.requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                answer(stream.unwrap(), &routes, &requests);
+            }
+        });
+
+        server
+    }
+
+    /// Answer the requests made on the `path` with the `responses`.
+    pub fn route(&self, path: &str, responses: impl IntoIterator<Item = Response>) -> &Self
+    {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), responses.into_iter().collect());
+        self
+    }
+
+    /// A source that uses this server instead of logs.tf. Searches are not
+    /// cached, so each one reaches the server.
+    pub fn source(&self, num_retries: u8) -> LogsTf
+    {
+        LogsTf {
+            num_retries,
+            timeout: Duration::from_secs(5),
+            api_base: self.address.clone(),
+            search_cache_ttl: None,
+            ..LogsTf::default()
+        }
+    }
+
+    /// The url of the `path` on this server.
+    pub fn url(&self, path: &str) -> String { format!("{}{}", self.address, path) }
+
+    /// The paths and queries of the requests made so far, in order.
+    pub fn requests(&self) -> Vec<String>
+    {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(target, _)| target.clone()).collect()
+    }
+
+    /// The bodies of the requests made so far, in order. Requests without a
+    /// body, like searches and downloads, have an empty one.
+    pub fn bodies(&self) -> Vec<String>
+    {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(_, body)| body.clone()).collect()
+    }
+}
+
+fn answer(mut stream: TcpStream, routes: &Mutex<Routes>, requests: &Mutex<Vec<Request>>)
+{
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    // The headers end with an empty line, which is followed by the body of the
+    // length they announce, if there is one.
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    let target = request_line
+        .split(' ')
+        .nth(1)
+        .unwrap_or_default()
+        .to_owned();
+    let path = target.split('?').next().unwrap_or_default().to_owned();
+    requests
+        .lock()
+        .unwrap()
+        .push((target, String::from_utf8(body).unwrap()));
+
+    let response = {
+        let mut routes = routes.lock().unwrap();
+        match routes.get_mut(&path) {
+            Some(responses) if responses.len() > 1 => responses.pop_front(),
+            Some(responses) => responses.front().cloned(),
+            None => None,
+        }
+    }
+    .unwrap_or_else(|| Response {
+        status: 404,
+        body:   read("tests/http_fixtures/not_found.json"),
+        delay:  Duration::ZERO,
+    });
+
+    thread::sleep(response.delay);
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )
+    .unwrap();
+}